@@ -0,0 +1,110 @@
+//! CBOR and MessagePack output with length-prefixed framing
+//!
+//! Binary documents cannot be separated by newlines, so every document is written as a frame:
+//! its length in bytes as a big-endian `u32`, followed by the encoded document. A stream of
+//! frames is read back with `read_frame` until it returns `None`.
+use crate::error::{AstgenError, Result};
+use serde::Serialize;
+use std::io::{self, Read, Write};
+
+/// Binary encoding to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    Cbor,
+    MsgPack,
+}
+
+/// Encode `value` and write it to `out` as one frame
+///
+/// Structs are encoded as maps keyed by field name in both encodings, so documents carry the
+/// same keys as the JSON output.
+pub fn write_frame(
+    out: &mut dyn Write,
+    encoding: BinaryEncoding,
+    value: &impl Serialize,
+) -> Result<()> {
+    let mut document = Vec::new();
+    match encoding {
+        BinaryEncoding::Cbor => ciborium::into_writer(value, &mut document)
+            .map_err(|e| AstgenError::SerializationError(e.to_string()))?,
+        BinaryEncoding::MsgPack => rmp_serde::encode::write_named(&mut document, value)
+            .map_err(|e| AstgenError::SerializationError(e.to_string()))?,
+    }
+    let length = u32::try_from(document.len()).map_err(|_| {
+        AstgenError::SerializationError(format!(
+            "Document of {} bytes is too large for a binary frame. Limit the tree with --max-nodes or --max-output-bytes.",
+            document.len()
+        ))
+    })?;
+    out.write_all(&length.to_be_bytes())?;
+    out.write_all(&document)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Read the next frame's document bytes, or `None` at the end of the stream
+pub fn read_frame(input: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut document = vec![0; u32::from_be_bytes(length) as usize];
+    input.read_exact(&mut document)?;
+    Ok(Some(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Document {
+        filename: String,
+        has_error: bool,
+    }
+
+    fn document(filename: &str) -> Document {
+        Document {
+            filename: filename.to_string(),
+            has_error: false,
+        }
+    }
+
+    #[test]
+    fn test_frames_round_trip_in_both_encodings() {
+        for encoding in [BinaryEncoding::Cbor, BinaryEncoding::MsgPack] {
+            let mut out = Vec::new();
+            write_frame(&mut out, encoding, &document("a.rs")).unwrap();
+            write_frame(&mut out, encoding, &document("b.rs")).unwrap();
+
+            let mut input = out.as_slice();
+            let mut decoded = Vec::new();
+            while let Some(frame) = read_frame(&mut input).unwrap() {
+                decoded.push(match encoding {
+                    BinaryEncoding::Cbor => ciborium::from_reader(frame.as_slice()).unwrap(),
+                    BinaryEncoding::MsgPack => rmp_serde::from_slice::<Document>(&frame).unwrap(),
+                });
+            }
+            assert_eq!(decoded, vec![document("a.rs"), document("b.rs")]);
+        }
+    }
+
+    #[test]
+    fn test_msgpack_encodes_structs_as_maps() {
+        let mut out = Vec::new();
+        write_frame(&mut out, BinaryEncoding::MsgPack, &document("a.rs")).unwrap();
+        // A two-entry fixmap, rather than the two-element fixarray rmp_serde writes by default
+        assert_eq!(out[4], 0x82);
+    }
+
+    #[test]
+    fn test_truncated_frame_is_an_error() {
+        let mut out = Vec::new();
+        write_frame(&mut out, BinaryEncoding::Cbor, &document("a.rs")).unwrap();
+        out.pop();
+        assert!(read_frame(&mut out.as_slice()).is_err());
+    }
+}
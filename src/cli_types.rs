@@ -1,4 +1,9 @@
-use crate::error::{AstgenError, Result};
+use astgen::error::{AstgenError, Result};
+use astgen::json::{ColumnEncoding, Position, TreeOptions};
+use astgen::languages::supported_languages;
+use astgen::output::OutputFormat;
+use astgen::query::QuerySet;
+use astgen::schema::AstSchema;
 use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
 
@@ -6,15 +11,42 @@ use std::path::PathBuf;
 #[command(
     color = clap::ColorChoice::Auto,
     author = "Graham Brooks",
-    version = crate::VERSION,
+    version = astgen::VERSION,
     about = "Generate Abstract Syntax Trees from source code using Tree-sitter",
     long_about = "astgen parses source code files using Tree-sitter grammars and outputs ASTs in JSON format.\n\nSupported languages: Rust, Java, C#, Go, Python, TypeScript, JavaScript, Ruby"
 )]
 pub struct Args {
     /// Input files or directories to process
-    #[arg(value_name = "FILES", help = "Files or directories to parse")]
+    #[arg(
+        value_name = "FILES",
+        help = "Files or directories to parse, or - to read source from stdin"
+    )]
     pub files: Vec<PathBuf>,
 
+    /// Language of every input, instead of detecting it from file names and content
+    #[arg(
+        long,
+        value_name = "LANGUAGE",
+        help = "Parse every input as this language (e.g. Python, C++) instead of detecting it from the file name or content"
+    )]
+    pub language: Option<String>,
+
+    /// Languages for files matching glob patterns
+    #[arg(
+        long,
+        value_name = "PATTERN=LANGUAGE",
+        help = "Parse files matching a glob pattern as a language, e.g. '*.h=C++', ahead of extension detection and .astgenrc mappings (can be used multiple times)"
+    )]
+    pub language_map: Vec<String>,
+
+    /// File name reported for source read from stdin
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "File name for source read from stdin, written as its filename and used to detect its language"
+    )]
+    pub stdin_filename: Option<String>,
+
     /// Output format
     #[arg(
         short,
@@ -25,10 +57,124 @@ pub struct Args {
     )]
     pub format: OutputFormat,
 
-    /// Truncate output to specified length
-    #[arg(long, help = "Truncate JSON output to specified number of characters")]
+    /// Unit used for node column positions
+    #[arg(
+        long,
+        value_enum,
+        default_value = "utf8",
+        help = "Count node columns in UTF-8 bytes or UTF-16 code units"
+    )]
+    pub column_encoding: ColumnEncodingArg,
+
+    /// Group field children by field name
+    #[arg(
+        long,
+        help = "Group children that fill a grammar field under a \"fields\" object keyed by field name, each holding an array of nodes"
+    )]
+    pub group_fields: bool,
+
+    /// Keep only named nodes
+    #[arg(
+        long,
+        help = "Omit anonymous tokens such as punctuation and keywords, keeping only named nodes"
+    )]
+    pub named_only: bool,
+
+    /// Include the is_named flag on every node
+    #[arg(
+        long,
+        help = "Mark every node with \"is_named\" to distinguish named nodes from anonymous tokens"
+    )]
+    pub is_named: bool,
+
+    /// Label S-expression children with field names
+    #[arg(
+        long,
+        help = "With --format sexp, prefix children with their grammar field name, as in \"name: (identifier)\""
+    )]
+    pub sexp_fields: bool,
+
+    /// Add ranges to S-expression nodes
+    #[arg(
+        long,
+        help = "With --format sexp, add \"[row, column] - [row, column]\" ranges to every node"
+    )]
+    pub sexp_ranges: bool,
+
+    /// Subtree to draw in graph output
+    #[arg(
+        long,
+        value_name = "ROW:COLUMN",
+        help = "With --format dot or mermaid, draw only the smallest named node containing this zero-based position (byte column)"
+    )]
+    pub graph_root: Option<Position>,
+
+    /// Schema of the written trees
+    #[arg(
+        long,
+        value_enum,
+        default_value = "tree-sitter",
+        help = "Write trees as Tree-sitter nodes or in a language's own AST schema (estree for JavaScript, TypeScript and TSX, python-ast for Python)"
+    )]
+    pub schema: AstSchema,
+
+    /// Treat files with syntax errors as failures
+    #[arg(
+        long,
+        help = "Exit with a non-zero status if any file contains syntax errors"
+    )]
+    pub fail_on_syntax_error: bool,
+
+    /// Maximum depth of nodes in the output tree
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        help = "Prune the children of nodes at this tree depth (the root is depth 0)"
+    )]
+    pub max_depth_nodes: Option<usize>,
+
+    /// Maximum number of nodes per file
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Stop adding nodes to a file's tree after this many"
+    )]
+    pub max_nodes: Option<usize>,
+
+    /// Approximate output size budget per file
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Stop adding nodes that would take a file's tree past about this many bytes of compact JSON"
+    )]
+    pub max_output_bytes: Option<usize>,
+
+    /// Former spelling of `--max-output-bytes`, which cut the output text rather than pruning
+    /// the tree
+    #[arg(
+        long,
+        value_name = "BYTES",
+        hide = true,
+        conflicts_with = "max_output_bytes"
+    )]
     pub truncate: Option<usize>,
 
+    /// Query files to run against each parsed tree
+    #[arg(
+        long,
+        value_name = "[LANGUAGE=]FILE",
+        help = "Output only the captures of a Tree-sitter query file (.scm), optionally scoped to one language (can be used multiple times)"
+    )]
+    pub query: Vec<String>,
+
+    /// Inline queries to run against each parsed tree
+    #[arg(
+        long,
+        value_name = "[LANGUAGE=]QUERY",
+        help = "Output only the captures of an inline Tree-sitter query, optionally scoped to one language (can be used multiple times)"
+    )]
+    pub query_string: Vec<String>,
+
     /// Enable verbose output
     #[arg(short, long, help = "Show detailed processing information")]
     pub verbose: bool,
@@ -107,45 +253,121 @@ pub struct Args {
     )]
     pub output: Option<PathBuf>,
 
+    /// Wrap every file's document in one aggregate document
+    #[arg(
+        long,
+        help = "Write a single document with a run header, every file's output under \"files\", and a summary"
+    )]
+    pub aggregate: bool,
+
+    /// End JSON Lines output with a summary record
+    #[arg(
+        long,
+        help = "With --format jsonl, finish with a {\"summary\": ...} line counting processed, failed and syntax error files"
+    )]
+    pub summary_record: bool,
+
     /// Show progress bar
     #[arg(long, help = "Show progress bar for directory processing")]
     pub progress: bool,
 }
 
-#[derive(ValueEnum, Clone, Debug)]
-pub enum OutputFormat {
-    Json,
-    PrettyJson,
-    Yaml,
+/// Values of `--column-encoding`
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColumnEncodingArg {
+    /// Columns are UTF-8 byte offsets from the start of the line
+    #[default]
+    Utf8,
+    /// Columns are UTF-16 code units from the start of the line, as used by LSP editors
+    Utf16,
 }
 
-pub fn format_output(json_str: &str, format: &OutputFormat) -> Result<String> {
-    match format {
-        OutputFormat::Json => Ok(json_str.to_string()),
-        OutputFormat::PrettyJson => {
-            let value: serde_json::Value = serde_json::from_str(json_str)?;
-            Ok(serde_json::to_string_pretty(&value)?)
+impl From<ColumnEncodingArg> for ColumnEncoding {
+    fn from(arg: ColumnEncodingArg) -> Self {
+        match arg {
+            ColumnEncodingArg::Utf8 => ColumnEncoding::Utf8,
+            ColumnEncodingArg::Utf16 => ColumnEncoding::Utf16,
         }
-        OutputFormat::Yaml => {
-            let value: serde_json::Value = serde_json::from_str(json_str)?;
-            serde_yaml::to_string(&value).map_err(|e| {
-                AstgenError::SerializationError(format!("YAML serialization failed: {}", e))
-            })
+    }
+}
+
+/// Split an optional `LANGUAGE=` scope off a query argument
+fn split_language_scope(value: &str) -> (Option<&str>, &str) {
+    if let Some((language, rest)) = value.split_once('=') {
+        if supported_languages()
+            .iter()
+            .any(|info| info.name.eq_ignore_ascii_case(language.trim()))
+        {
+            return (Some(language.trim()), rest);
         }
     }
+    (None, value)
 }
 
 impl Args {
-    pub fn validate(&self) -> crate::error::Result<()> {
+    /// Whether `-` asks for source to be read from stdin
+    pub fn reads_stdin(&self) -> bool {
+        self.files.iter().any(|file| file.as_os_str() == "-")
+    }
+
+    /// Whether the run may write more than one file, i.e. it names several inputs or a directory
+    pub fn reads_many_files(&self) -> bool {
+        self.files.len() > 1 || self.files.iter().any(|file| file.is_dir())
+    }
+
+    /// The `--language-map` patterns and languages, in the order given
+    pub fn language_map(&self) -> Vec<(String, String)> {
+        self.language_map
+            .iter()
+            .filter_map(|value| value.rsplit_once('='))
+            .map(|(pattern, language)| (pattern.trim().to_string(), language.trim().to_string()))
+            .collect()
+    }
+
+    /// Load the queries given with --query and --query-string, if any
+    pub fn query_set(&self) -> Result<Option<QuerySet>> {
+        let mut queries = QuerySet::new();
+        for value in &self.query {
+            let (language, path) = split_language_scope(value);
+            let source = std::fs::read_to_string(path).map_err(|e| {
+                AstgenError::InvalidInput(format!("Cannot read query file {}: {}", path, e))
+            })?;
+            queries.add(language, &source);
+        }
+        for value in &self.query_string {
+            let (language, source) = split_language_scope(value);
+            queries.add(language, source);
+        }
+        Ok((!queries.is_empty()).then_some(queries))
+    }
+
+    /// Options for converting parsed trees, derived from the command line
+    pub fn tree_options(&self) -> TreeOptions {
+        TreeOptions {
+            column_encoding: self.column_encoding.into(),
+            group_fields: self.group_fields,
+            named_only: self.named_only,
+            include_is_named: self.is_named,
+            max_depth: self.max_depth_nodes,
+            max_nodes: self.max_nodes,
+            max_output_bytes: self.max_output_bytes.or(self.truncate),
+            sexp_fields: self.sexp_fields,
+            sexp_ranges: self.sexp_ranges,
+            graph_root: self.graph_root,
+            schema: self.schema,
+        }
+    }
+
+    pub fn validate(&self) -> astgen::error::Result<()> {
         // Validate thread count
         if let Some(threads) = self.parallel {
             if threads == 0 {
-                return Err(crate::error::AstgenError::InvalidInput(
+                return Err(astgen::error::AstgenError::InvalidInput(
                     "Thread count must be at least 1. Try using --parallel 1 or omit the flag to use default.".to_string()
                 ));
             }
             if threads > 64 {
-                return Err(crate::error::AstgenError::InvalidInput(
+                return Err(astgen::error::AstgenError::InvalidInput(
                     "Thread count cannot exceed 64. Try using a smaller number like --parallel 8."
                         .to_string(),
                 ));
@@ -154,26 +376,26 @@ impl Args {
 
         // Validate file size limit
         if self.max_file_size == 0 {
-            return Err(crate::error::AstgenError::InvalidInput(
+            return Err(astgen::error::AstgenError::InvalidInput(
                 "Max file size must be at least 1 MB. Try using --max-file-size 1.".to_string(),
             ));
         }
         if self.max_file_size > 1000 {
-            return Err(crate::error::AstgenError::InvalidInput(
+            return Err(astgen::error::AstgenError::InvalidInput(
                 "Max file size cannot exceed 1000 MB. Try using a smaller limit like --max-file-size 100.".to_string()
             ));
         }
 
         // Validate max depth
         if self.max_depth == 0 {
-            return Err(crate::error::AstgenError::InvalidInput(
+            return Err(astgen::error::AstgenError::InvalidInput(
                 "Max depth must be at least 1. Try using --max-depth 1 or omit the flag to use default.".to_string()
             ));
         }
 
         // Validate conflicting flags
         if self.verbose && self.quiet {
-            return Err(crate::error::AstgenError::InvalidInput(
+            return Err(astgen::error::AstgenError::InvalidInput(
                 "Cannot use both --verbose and --quiet flags together. Choose one or neither."
                     .to_string(),
             ));
@@ -183,7 +405,7 @@ impl Args {
         if let Some(output_path) = &self.output {
             if let Some(parent) = output_path.parent() {
                 if !parent.exists() {
-                    return Err(crate::error::AstgenError::InvalidInput(format!(
+                    return Err(astgen::error::AstgenError::InvalidInput(format!(
                         "Output directory does not exist: {}. Create the directory first.",
                         parent.display()
                     )));
@@ -194,7 +416,7 @@ impl Args {
         // Validate include/exclude patterns
         for pattern in &self.include {
             if pattern.is_empty() {
-                return Err(crate::error::AstgenError::InvalidInput(
+                return Err(astgen::error::AstgenError::InvalidInput(
                     "Include pattern cannot be empty. Use a valid glob pattern like '*.rs'."
                         .to_string(),
                 ));
@@ -203,13 +425,164 @@ impl Args {
 
         for pattern in &self.exclude {
             if pattern.is_empty() {
-                return Err(crate::error::AstgenError::InvalidInput(
+                return Err(astgen::error::AstgenError::InvalidInput(
                     "Exclude pattern cannot be empty. Use a valid glob pattern like 'target/*'."
                         .to_string(),
                 ));
             }
         }
 
+        if self.summary_record && self.format != OutputFormat::Jsonl {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "--summary-record only applies to JSON Lines output. Add --format jsonl."
+                    .to_string(),
+            ));
+        }
+        if self.format.is_tree_only() && !(self.query.is_empty() && self.query_string.is_empty()) {
+            return Err(astgen::error::AstgenError::InvalidInput(format!(
+                "--format {} only holds syntax trees, not query captures. Use --format json or yaml with queries.",
+                self.format.to_possible_value().expect("formats are not skipped").get_name()
+            )));
+        }
+        if (self.sexp_fields || self.sexp_ranges) && self.format != OutputFormat::Sexp {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "--sexp-fields and --sexp-ranges only apply to S-expression output. Add --format sexp.".to_string(),
+            ));
+        }
+        if self.graph_root.is_some()
+            && !matches!(self.format, OutputFormat::Dot | OutputFormat::Mermaid)
+        {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "--graph-root only applies to graph output. Add --format dot or --format mermaid."
+                    .to_string(),
+            ));
+        }
+        // One diagram holds one tree, and two concatenated diagrams are not a valid document
+        if matches!(self.format, OutputFormat::Dot | OutputFormat::Mermaid)
+            && self.reads_many_files()
+        {
+            return Err(astgen::error::AstgenError::InvalidInput(format!(
+                "--format {} draws one file's tree. Give a single file, or run astgen once per file.",
+                self.format.to_possible_value().expect("formats are not skipped").get_name()
+            )));
+        }
+        if self.schema != AstSchema::TreeSitter {
+            if self.format.is_tree_only() {
+                return Err(astgen::error::AstgenError::InvalidInput(format!(
+                    "--format {} renders Tree-sitter trees and cannot use --schema. Use --format json, yaml, cbor or msgpack.",
+                    self.format.to_possible_value().expect("formats are not skipped").get_name()
+                )));
+            }
+            if !(self.query.is_empty() && self.query_string.is_empty()) {
+                return Err(astgen::error::AstgenError::InvalidInput(
+                    "--schema changes how trees are written and cannot be combined with queries."
+                        .to_string(),
+                ));
+            }
+        }
+        if self.format == OutputFormat::Sqlite && self.output.is_none() {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "SQLite output needs a database path. Add --output repo.db.".to_string(),
+            ));
+        }
+        if self.aggregate
+            && !matches!(
+                self.format,
+                OutputFormat::Json | OutputFormat::PrettyJson | OutputFormat::Yaml
+            )
+        {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "--aggregate writes a single text document and only supports --format json, pretty-json or yaml.".to_string(),
+            ));
+        }
+
+        if let Some(language) = &self.language {
+            let languages = supported_languages();
+            if !languages
+                .iter()
+                .any(|info| info.name.eq_ignore_ascii_case(language))
+            {
+                return Err(astgen::error::AstgenError::InvalidInput(format!(
+                    "Unknown language: {}. Use one of {}.",
+                    language,
+                    languages
+                        .iter()
+                        .map(|info| info.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )));
+            }
+        }
+        for value in &self.language_map {
+            if !value.rsplit_once('=').is_some_and(|(pattern, language)| {
+                !pattern.trim().is_empty() && !language.trim().is_empty()
+            }) {
+                return Err(astgen::error::AstgenError::InvalidInput(format!(
+                    "Invalid language mapping: {}. Use PATTERN=LANGUAGE, e.g. --language-map '*.h=C++'.",
+                    value
+                )));
+            }
+        }
+        if self
+            .files
+            .iter()
+            .filter(|file| file.as_os_str() == "-")
+            .count()
+            > 1
+        {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "stdin can only be read once. Pass - a single time.".to_string(),
+            ));
+        }
+        if self.stdin_filename.is_some() && !self.reads_stdin() {
+            return Err(astgen::error::AstgenError::InvalidInput(
+                "--stdin-filename only applies when reading from stdin. Pass - as an input."
+                    .to_string(),
+            ));
+        }
+
+        for value in self.query.iter().chain(&self.query_string) {
+            if split_language_scope(value).1.trim().is_empty() {
+                return Err(astgen::error::AstgenError::InvalidInput(
+                    "Query cannot be empty. Use a Tree-sitter query like '(function_item) @function'."
+                        .to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_language_scope() {
+        assert_eq!(
+            split_language_scope("rust=queries/functions.scm"),
+            (Some("rust"), "queries/functions.scm")
+        );
+        assert_eq!(
+            split_language_scope("C++=(class_specifier) @class"),
+            (Some("C++"), "(class_specifier) @class")
+        );
+        assert_eq!(
+            split_language_scope("((identifier) @id (#eq? @id \"a=b\"))"),
+            (None, "((identifier) @id (#eq? @id \"a=b\"))")
+        );
+        assert_eq!(
+            split_language_scope("functions.scm"),
+            (None, "functions.scm")
+        );
+    }
+
+    #[test]
+    fn test_column_encoding_reaches_tree_options() {
+        let args = Args::parse_from(["astgen", "a.rs"]);
+        assert_eq!(args.tree_options().column_encoding, ColumnEncoding::Utf8);
+        let args = Args::parse_from(["astgen", "--column-encoding", "utf16", "a.rs"]);
+        assert_eq!(args.tree_options().column_encoding, ColumnEncoding::Utf16);
+    }
+}
@@ -1,25 +1,118 @@
 use crate::error::{AstgenError, Result};
-use serde::{Deserialize, Serialize};
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Config {
     pub patterns: Option<PatternConfig>,
+    /// File patterns mapped to the language their files are parsed as, e.g. `"*.h" = "C++"`
+    pub languages: Option<LanguageTable>,
     pub ignore: Option<IgnoreConfig>,
     pub output: Option<OutputConfig>,
     pub performance: Option<PerformanceConfig>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// File patterns for each language, e.g. `cpp = ["*.inc", "*.ipp"]`
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct PatternConfig {
     pub rust: Option<Vec<String>>,
-    pub python: Option<Vec<String>>,
-    pub javascript: Option<Vec<String>>,
     pub java: Option<Vec<String>>,
+    pub csharp: Option<Vec<String>>,
     pub go: Option<Vec<String>>,
+    pub python: Option<Vec<String>>,
     pub typescript: Option<Vec<String>>,
-    pub csharp: Option<Vec<String>>,
+    pub tsx: Option<Vec<String>>,
+    pub javascript: Option<Vec<String>>,
     pub ruby: Option<Vec<String>>,
+    pub c: Option<Vec<String>>,
+    pub cpp: Option<Vec<String>>,
+    pub bash: Option<Vec<String>>,
+    pub json: Option<Vec<String>>,
+    pub html: Option<Vec<String>>,
+    pub css: Option<Vec<String>>,
+    pub yaml: Option<Vec<String>>,
+    pub swift: Option<Vec<String>>,
+    pub scala: Option<Vec<String>>,
+    pub lua: Option<Vec<String>>,
+    pub hcl: Option<Vec<String>>,
+    pub graphql: Option<Vec<String>>,
+}
+
+impl PatternConfig {
+    /// Every language's name with its patterns, in registry order
+    pub fn language_patterns(&self) -> Vec<(&'static str, &[String])> {
+        [
+            ("Rust", &self.rust),
+            ("Java", &self.java),
+            ("C#", &self.csharp),
+            ("Go", &self.go),
+            ("Python", &self.python),
+            ("TypeScript", &self.typescript),
+            ("TSX", &self.tsx),
+            ("JavaScript", &self.javascript),
+            ("Ruby", &self.ruby),
+            ("C", &self.c),
+            ("C++", &self.cpp),
+            ("Bash", &self.bash),
+            ("JSON", &self.json),
+            ("HTML", &self.html),
+            ("CSS", &self.css),
+            ("YAML", &self.yaml),
+            ("Swift", &self.swift),
+            ("Scala", &self.scala),
+            ("Lua", &self.lua),
+            ("HCL", &self.hcl),
+            ("GraphQL", &self.graphql),
+        ]
+        .into_iter()
+        .map(|(language, patterns)| (language, patterns.as_deref().unwrap_or_default()))
+        .collect()
+    }
+}
+
+/// The `[languages]` table: pattern and language pairs in the order they are written, since
+/// the first matching pattern wins
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LanguageTable(pub Vec<(String, String)>);
+
+impl Serialize for LanguageTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (pattern, language) in &self.0 {
+            map.serialize_entry(pattern, language)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for LanguageTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct TableVisitor;
+
+        impl<'de> Visitor<'de> for TableVisitor {
+            type Value = LanguageTable;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a table of file patterns and language names")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut access: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = access.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(LanguageTable(entries))
+            }
+        }
+
+        deserializer.deserialize_map(TableVisitor)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,19 +132,63 @@ pub struct OutputConfig {
 pub struct PerformanceConfig {
     pub max_threads: Option<usize>,
     pub max_file_size_mb: Option<usize>,
+    /// Parsers each worker thread keeps for reuse, one per language (0 disables reuse)
     pub parser_pool_size: Option<usize>,
 }
 
 impl Config {
+    /// File patterns and the language each selects, from `[languages]` and then `[patterns]`
+    pub fn language_map(&self) -> Vec<(String, String)> {
+        let mut map = self
+            .languages
+            .as_ref()
+            .map_or_else(Vec::new, |table| table.0.clone());
+        if let Some(patterns) = &self.patterns {
+            for (language, patterns) in patterns.language_patterns() {
+                map.extend(
+                    patterns
+                        .iter()
+                        .map(|pattern| (pattern.clone(), language.to_string())),
+                );
+            }
+        }
+        map
+    }
+
     pub fn load(path: &PathBuf) -> Result<Self> {
         let content = std::fs::read_to_string(path).map_err(|e| {
             AstgenError::ConfigError(format!("Cannot read config file {}: {}", path.display(), e))
         })?;
         let config: Config = toml::from_str(&content)
             .map_err(|e| AstgenError::ConfigError(format!("Invalid config file {}: {}\n\nCheck the TOML syntax and ensure all required fields are present.", path.display(), e)))?;
+        config.check_patterns().map_err(|e| {
+            AstgenError::ConfigError(format!("Invalid config file {}: {}", path.display(), e))
+        })?;
         Ok(config)
     }
 
+    /// Reject `[patterns]` entries written as the regular expressions older versions took, such
+    /// as `"rs$"`, which as globs would never match a file
+    fn check_patterns(&self) -> std::result::Result<(), String> {
+        let Some(patterns) = &self.patterns else {
+            return Ok(());
+        };
+        for (language, patterns) in patterns.language_patterns() {
+            if let Some(pattern) = patterns
+                .iter()
+                .find(|pattern| pattern.starts_with('^') || pattern.ends_with('$'))
+            {
+                return Err(format!(
+                    "[patterns] entry \"{}\" for {} is a regular expression, but patterns are globs matched against file names. Write e.g. \"*.{}\" instead.",
+                    pattern,
+                    language,
+                    pattern.trim_start_matches('^').trim_end_matches('$')
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn find_default() -> Option<PathBuf> {
         // Look for .astgenrc in current directory, then home directory
         if let Ok(current_dir) = std::env::current_dir() {
@@ -83,7 +220,8 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
+    use crate::detection::Detection;
+    use std::fs::{self, File};
     use std::io::Write;
     use tempfile::tempdir;
 
@@ -112,6 +250,92 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_load_performance_config() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("perf.astgenrc");
+        let mut file = File::create(&file_path).unwrap();
+        file.write_all(b"[performance]\nmax_threads = 4\nparser_pool_size = 3\n")
+            .unwrap();
+        let config = Config::load(&file_path).unwrap();
+        let performance = config.performance.unwrap();
+        assert_eq!(performance.max_threads, Some(4));
+        assert_eq!(performance.parser_pool_size, Some(3));
+    }
+
+    #[test]
+    fn test_load_language_map() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("languages.astgenrc");
+        let mut file = File::create(&file_path).unwrap();
+        file.write_all(
+            b"[languages]\n\"*.inc\" = \"C++\"\n\"*.h\" = \"C++\"\n\"*.cgi\" = \"Python\"\n\n[patterns]\ngraphql = [\"*.schema\"]\nc = [\"*.x\"]\n",
+        )
+        .unwrap();
+        let config = Config::load(&file_path).unwrap();
+        // Table entries keep their written order, ahead of the per-language patterns
+        assert_eq!(
+            config.language_map(),
+            [
+                ("*.inc", "C++"),
+                ("*.h", "C++"),
+                ("*.cgi", "Python"),
+                ("*.x", "C"),
+                ("*.schema", "GraphQL"),
+            ]
+            .map(|(pattern, language)| (pattern.to_string(), language.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_load_example_config() {
+        let example = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".astgenrc.example");
+        let config = Config::load(&example).unwrap();
+        let mut encodings = crate::languages::create_encodings();
+        for (pattern, language) in config.language_map() {
+            encodings.map_pattern(&pattern, &language).unwrap();
+        }
+        let detected = |path| {
+            encodings
+                .detect(path, None)
+                .map(|(encoding, detection)| (encoding.name().to_string(), detection))
+        };
+        assert_eq!(
+            detected("types/stubs.pyi"),
+            Some(("Python".to_string(), Detection::Override))
+        );
+        assert_eq!(
+            detected("src/main.rs"),
+            Some(("Rust".to_string(), Detection::Extension))
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_regex_patterns() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("old.astgenrc");
+        fs::write(&file_path, "[patterns]\nrust = [\"rs$\"]\n").unwrap();
+        let err = Config::load(&file_path).unwrap_err();
+        assert!(err.to_string().contains("\"*.rs\""), "{}", err);
+    }
+
+    #[test]
+    fn test_pattern_config_covers_every_language() {
+        let config = PatternConfig::default();
+        let names = config
+            .language_patterns()
+            .into_iter()
+            .map(|(language, _)| language)
+            .collect::<Vec<_>>();
+        let encodings = crate::languages::create_encodings();
+        let mut registered = encodings
+            .iter()
+            .map(|encoding| encoding.name())
+            .collect::<Vec<_>>();
+        registered.dedup();
+        assert_eq!(names, registered);
+    }
+
     #[test]
     fn test_find_default_none() {
         // Should not find a config in a temp dir with none present
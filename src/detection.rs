@@ -0,0 +1,209 @@
+//! How the language of a file was chosen, and the content sniffing used for files whose name
+//! does not give it away
+//!
+//! Scripts without an extension usually say what they are in their first lines: a shebang
+//! (`#!/usr/bin/env python3`), an Emacs mode line (`# -*- mode: ruby -*-`) or a Vim modeline
+//! (`# vim: set ft=sh:`). Some well-known files, such as `Rakefile`, are recognized by name.
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// What decided a file's language, written to the envelope as `detection`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Detection {
+    /// The file extension, e.g. `.py`
+    Extension,
+    /// The whole file name, e.g. `Rakefile`
+    Filename,
+    /// The interpreter named by a `#!` line
+    Shebang,
+    /// An Emacs or Vim modeline
+    Modeline,
+    /// `--language`, `--language-map` or a language mapping in the configuration
+    Override,
+}
+
+impl Detection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Detection::Extension => "extension",
+            Detection::Filename => "filename",
+            Detection::Shebang => "shebang",
+            Detection::Modeline => "modeline",
+            Detection::Override => "override",
+        }
+    }
+}
+
+/// Lines at the start and end of a file searched for a Vim modeline, as Vim's `modelines`
+const MODELINE_LINES: usize = 5;
+
+/// The language of a well-known file that has no extension
+pub fn language_from_filename(file_name: &str) -> Option<&'static str> {
+    Some(match file_name {
+        "Rakefile" | "Gemfile" | "Guardfile" | "Vagrantfile" | "Podfile" | "Brewfile"
+        | "Capfile" | "Berksfile" | "Fastfile" | "Dangerfile" => "Ruby",
+        ".bashrc" | ".bash_profile" | ".bash_login" | ".bash_logout" | ".profile" | "PKGBUILD"
+        | "APKBUILD" => "Bash",
+        "SConstruct" | "SConscript" | "Snakefile" => "Python",
+        ".babelrc" | ".eslintrc" | ".jshintrc" => "JSON",
+        ".clang-format" | ".clang-tidy" => "YAML",
+        _ => return None,
+    })
+}
+
+/// The language of the interpreter on a `#!` first line, following `/usr/bin/env`
+pub fn language_from_shebang(source: &str) -> Option<&'static str> {
+    let line = source.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Skip options such as `-S` and variable assignments
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    // `python3.12` and `lua5.4` run the same language as `python` and `lua`
+    let interpreter = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match interpreter {
+        "python" | "pypy" => "Python",
+        "sh" | "bash" | "dash" | "ksh" | "ash" => "Bash",
+        "node" | "nodejs" => "JavaScript",
+        "ts-node" => "TypeScript",
+        "ruby" | "jruby" => "Ruby",
+        "lua" | "luajit" => "Lua",
+        "scala" => "Scala",
+        "swift" => "Swift",
+        _ => return None,
+    })
+}
+
+/// The language named by an Emacs mode line in the first two lines, or a Vim modeline in the
+/// first or last five
+pub fn language_from_modeline(source: &str) -> Option<&'static str> {
+    static VIM: OnceLock<Regex> = OnceLock::new();
+    let vim = VIM.get_or_init(|| {
+        Regex::new(r"(?:^|\s)(?:vi|vim?[<=>]?\d*|ex):.*?\b(?:ft|filetype|syn|syntax)=([\w+.-]+)")
+            .expect("Invalid modeline pattern")
+    });
+
+    let emacs = source.lines().take(2).find_map(|line| {
+        let (_, rest) = line.split_once("-*-")?;
+        let (variables, _) = rest.split_once("-*-")?;
+        if !variables.contains(':') {
+            return Some(variables.trim());
+        }
+        variables.split(';').find_map(|variable| {
+            let (name, value) = variable.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("mode")
+                .then_some(value.trim())
+        })
+    });
+    if let Some(mode) = emacs {
+        return mode_language(mode);
+    }
+
+    let lines = source.lines().collect::<Vec<_>>();
+    let tail = lines
+        .len()
+        .saturating_sub(MODELINE_LINES)
+        .max(MODELINE_LINES);
+    lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().skip(tail))
+        .find_map(|line| vim.captures(line))
+        .and_then(|captures| mode_language(&captures[1]))
+}
+
+/// The language of an Emacs major mode or Vim filetype
+fn mode_language(mode: &str) -> Option<&'static str> {
+    Some(match mode.to_ascii_lowercase().as_str() {
+        "python" => "Python",
+        "ruby" => "Ruby",
+        "sh" | "bash" | "shell-script" => "Bash",
+        "js" | "javascript" => "JavaScript",
+        "ts" | "typescript" => "TypeScript",
+        "tsx" | "typescriptreact" => "TSX",
+        "rust" => "Rust",
+        "go" => "Go",
+        "java" => "Java",
+        "c" => "C",
+        "cpp" | "c++" => "C++",
+        "cs" | "csharp" => "C#",
+        "json" => "JSON",
+        "html" => "HTML",
+        "css" => "CSS",
+        "yaml" => "YAML",
+        "swift" => "Swift",
+        "scala" => "Scala",
+        "lua" => "Lua",
+        "hcl" | "terraform" => "HCL",
+        "graphql" => "GraphQL",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shebangs() {
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/env python3\n"),
+            Some("Python")
+        );
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/python3.12 -u\n"),
+            Some("Python")
+        );
+        assert_eq!(language_from_shebang("#!/bin/bash\nset -e\n"), Some("Bash"));
+        assert_eq!(language_from_shebang("#! /bin/sh"), Some("Bash"));
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/env -S NODE_ENV=production node --trace\n"),
+            Some("JavaScript")
+        );
+        assert_eq!(language_from_shebang("#!/usr/bin/env perl\n"), None);
+        assert_eq!(language_from_shebang("# not a shebang\n#!/bin/sh"), None);
+    }
+
+    #[test]
+    fn test_modelines() {
+        assert_eq!(
+            language_from_modeline("# -*- mode: ruby; coding: utf-8 -*-\n"),
+            Some("Ruby")
+        );
+        assert_eq!(
+            language_from_modeline("#!/bin/tool\n// -*- C++ -*-\n"),
+            Some("C++")
+        );
+        assert_eq!(
+            language_from_modeline("x\n\n\n\n\n\n\n# vim: set ft=sh ts=4:\n"),
+            Some("Bash")
+        );
+        assert_eq!(
+            language_from_modeline("/* vim: filetype=javascript */\n"),
+            Some("JavaScript")
+        );
+        // Vim only looks at the first and last five lines
+        let buried = format!("{}# vim: ft=python\n{}", "x\n".repeat(6), "x\n".repeat(6));
+        assert_eq!(language_from_modeline(&buried), None);
+        assert_eq!(language_from_modeline("see index: ft=python"), None);
+    }
+
+    #[test]
+    fn test_well_known_filenames() {
+        assert_eq!(language_from_filename("Rakefile"), Some("Ruby"));
+        assert_eq!(language_from_filename(".bashrc"), Some("Bash"));
+        assert_eq!(language_from_filename("Makefile"), None);
+    }
+
+    #[test]
+    fn test_detection_names() {
+        assert_eq!(
+            serde_json::to_string(&Detection::Modeline).unwrap(),
+            format!("\"{}\"", Detection::Modeline.as_str())
+        );
+    }
+}
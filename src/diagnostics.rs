@@ -0,0 +1,180 @@
+//! Syntax error reporting for parsed trees
+use crate::json::{ColumnEncoding, Position};
+use serde::Serialize;
+use tree_sitter::Node;
+
+/// Longest snippet of surrounding source included in a diagnostic
+const MAX_SNIPPET_CHARS: usize = 120;
+
+/// What Tree-sitter did to recover from a syntax error
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticKind {
+    /// Source that could not be parsed was wrapped in an `ERROR` node
+    Error,
+    /// An expected token was absent and a zero-width `MISSING` node was inserted
+    Missing,
+}
+
+/// A syntax error found in a parsed file
+#[derive(Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_point: Position,
+    pub end_point: Position,
+    /// The source line on which the error starts
+    pub snippet: String,
+}
+
+/// Collect a diagnostic for every `ERROR` and `MISSING` node under `root`
+pub fn collect_diagnostics(
+    source_code: &str,
+    root: Node,
+    column_encoding: ColumnEncoding,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if !root.has_error() {
+        return diagnostics;
+    }
+
+    let mut cursor = root.walk();
+    loop {
+        let node = cursor.node();
+        let kind = if node.is_error() {
+            Some(DiagnosticKind::Error)
+        } else if node.is_missing() {
+            Some(DiagnosticKind::Missing)
+        } else {
+            None
+        };
+
+        if let Some(kind) = kind {
+            diagnostics.push(diagnostic_for(source_code, node, kind, column_encoding));
+        }
+
+        // Only descend into subtrees that still contain errors; an ERROR node is
+        // reported once rather than once per token it swallowed
+        let descended =
+            kind != Some(DiagnosticKind::Error) && node.has_error() && cursor.goto_first_child();
+        if !descended {
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return diagnostics;
+                }
+            }
+        }
+    }
+}
+
+fn diagnostic_for(
+    source_code: &str,
+    node: Node,
+    kind: DiagnosticKind,
+    column_encoding: ColumnEncoding,
+) -> Diagnostic {
+    let message = match kind {
+        DiagnosticKind::Error => {
+            let text = source_code[node.start_byte()..node.end_byte()].trim();
+            match text.lines().next() {
+                Some(line) if !line.is_empty() => {
+                    format!("Unexpected syntax: {}", truncate_chars(line, 40))
+                }
+                _ => "Unexpected syntax".to_string(),
+            }
+        }
+        DiagnosticKind::Missing => format!("Missing {}", node.kind()),
+    };
+
+    Diagnostic {
+        kind,
+        message,
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        start_point: Position::from_point(
+            source_code,
+            node.start_position(),
+            node.start_byte(),
+            column_encoding,
+        ),
+        end_point: Position::from_point(
+            source_code,
+            node.end_position(),
+            node.end_byte(),
+            column_encoding,
+        ),
+        snippet: line_snippet(source_code, node.start_byte()),
+    }
+}
+
+/// The full line of source containing `byte`, without its line terminator
+fn line_snippet(source_code: &str, byte: usize) -> String {
+    let line_start = source_code[..byte].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source_code[byte..]
+        .find('\n')
+        .map_or(source_code.len(), |i| byte + i);
+    truncate_chars(
+        source_code[line_start..line_end].trim_end_matches('\r'),
+        MAX_SNIPPET_CHARS,
+    )
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Parser;
+
+    fn diagnostics_for(code: &str, language: tree_sitter::Language) -> Vec<Diagnostic> {
+        let mut parser = Parser::new();
+        parser.set_language(&language).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        collect_diagnostics(code, tree.root_node(), ColumnEncoding::Utf8)
+    }
+
+    #[test]
+    fn test_no_diagnostics_for_valid_code() {
+        let diagnostics = diagnostics_for("fn main() {}", tree_sitter_rust::LANGUAGE.into());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_reports_missing_node() {
+        let diagnostics = diagnostics_for(
+            "fn main() {\n    let x = 1\n}",
+            tree_sitter_rust::LANGUAGE.into(),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::Missing);
+        assert_eq!(diagnostics[0].message, "Missing ;");
+        assert_eq!(diagnostics[0].start_point.row, 1);
+        assert_eq!(diagnostics[0].snippet, "    let x = 1");
+    }
+
+    #[test]
+    fn test_reports_error_node() {
+        let diagnostics = diagnostics_for("x = 1\ny = (1 +\n", tree_sitter_python::LANGUAGE.into());
+        assert!(!diagnostics.is_empty());
+        let error = diagnostics
+            .iter()
+            .find(|d| d.kind == DiagnosticKind::Error)
+            .unwrap();
+        assert_eq!(error.start_point.row, 1);
+        assert_eq!(error.message, "Unexpected syntax: y = (1 +");
+        assert_eq!(error.snippet, "y = (1 +");
+    }
+
+    #[test]
+    fn test_truncate_chars_respects_char_boundaries() {
+        assert_eq!(truncate_chars("héllo", 2), "hé...");
+        assert_eq!(truncate_chars("hi", 5), "hi");
+    }
+}
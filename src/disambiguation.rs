@@ -0,0 +1,289 @@
+//! Choosing between the languages an ambiguous extension is registered for, such as C and C++
+//! for `.h`
+//!
+//! The header's own content is the best evidence, so C++-only tokens are looked for first. When
+//! the code would be valid in either language, both grammars parse it and the one with fewer
+//! errors wins; failing that, the sources next to the header decide. The first language
+//! registered for the extension is the fallback.
+use crate::diagnostics::collect_diagnostics;
+use crate::encoding::Encoding;
+use crate::encodings::Encodings;
+use crate::json::ColumnEncoding;
+use crate::parsing;
+use regex::{Regex, RegexSet};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How an ambiguous file's language was chosen, written to the envelope as `disambiguation`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Disambiguation {
+    pub strategy: Strategy,
+    pub confidence: Confidence,
+}
+
+/// The evidence that settled the language
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Tokens only one of the languages has, e.g. `namespace` or `::` for C++
+    Tokens,
+    /// One grammar parsed the file with fewer errors than the others
+    Grammar,
+    /// Sources in the same directory are written in only one of the languages
+    Siblings,
+    /// Nothing told the languages apart, so the first registered for the extension was kept
+    Default,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+/// Tokens of C++ code that C does not have, matched with comments, literals and preprocessor
+/// lines removed
+const CPP_MARKERS: &[&str] = &[
+    r"\bnamespace\b",
+    r"\btemplate\s*<",
+    r"\bclass\s+\w+\s*(?:final\s*)?[:{;]",
+    r"(?m)^\s*(?:public|private|protected)\s*:",
+    r"::",
+    r"\b(?:virtual|typename|nullptr)\b",
+    r"\boperator\s*(?:\(\)|[^\w\s(])",
+];
+
+/// Choose the language of `source` from `candidates`, the languages its extension is
+/// registered for in matching order
+pub fn disambiguate<'e, 'a>(
+    encodings: &'e Encodings<'a>,
+    candidates: &[&'e Encoding<'a>],
+    file_path: &str,
+    source: &str,
+) -> (&'e Encoding<'a>, Disambiguation) {
+    let chosen = |encoding, strategy, confidence| {
+        (
+            encoding,
+            Disambiguation {
+                strategy,
+                confidence,
+            },
+        )
+    };
+
+    if let Some(cpp) = candidates.iter().find(|encoding| encoding.name == "C++") {
+        let markers = cpp_markers(source);
+        if markers > 0 {
+            let confidence = if markers > 1 {
+                Confidence::High
+            } else {
+                Confidence::Medium
+            };
+            return chosen(*cpp, Strategy::Tokens, confidence);
+        }
+    }
+
+    let errors = candidates
+        .iter()
+        .map(|encoding| {
+            parsing::parse_tree(source, encoding.language).map_or(usize::MAX, |tree| {
+                collect_diagnostics(source, tree.root_node(), ColumnEncoding::default()).len()
+            })
+        })
+        .collect::<Vec<_>>();
+    let fewest = errors.iter().copied().min().unwrap_or_default();
+    if let [best] = positions(&errors, |count| count == fewest)[..] {
+        let confidence = if fewest == 0 {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        };
+        return chosen(candidates[best], Strategy::Grammar, confidence);
+    }
+
+    if let Some(sibling) = sibling_language(encodings, candidates, file_path) {
+        return chosen(sibling, Strategy::Siblings, Confidence::Medium);
+    }
+    chosen(candidates[0], Strategy::Default, Confidence::Low)
+}
+
+/// How many different C++-only tokens appear in `source`, counting `#include` of a standard
+/// header without an extension, e.g. `<vector>`, as one
+fn cpp_markers(source: &str) -> usize {
+    static MARKERS: OnceLock<RegexSet> = OnceLock::new();
+    static INCLUDE: OnceLock<Regex> = OnceLock::new();
+    let markers = MARKERS.get_or_init(|| RegexSet::new(CPP_MARKERS).expect("Invalid C++ marker"));
+    let include =
+        INCLUDE.get_or_init(|| Regex::new(r"^\s*#\s*include\s*<[\w/]+>").expect("Invalid include"));
+
+    let mut code = String::with_capacity(source.len());
+    let mut includes_cpp_header = false;
+    let mut in_directive = false;
+    for line in strip_comments_and_literals(source).lines() {
+        if in_directive || line.trim_start().starts_with('#') {
+            includes_cpp_header |= include.is_match(line);
+            in_directive = line.ends_with('\\');
+        } else {
+            code.push_str(line);
+            code.push('\n');
+        }
+    }
+    markers.matches(&code).iter().count() + usize::from(includes_cpp_header)
+}
+
+/// `source` with comments and the contents of string and character literals blanked out,
+/// keeping its lines
+fn strip_comments_and_literals(source: &str) -> String {
+    let mut code = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&next| next != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    if next == '\n' {
+                        code.push('\n');
+                    }
+                    previous = next;
+                }
+                code.push(' ');
+            }
+            '"' | '\'' => {
+                while let Some(next) = chars.next_if(|&next| next != '\n') {
+                    if next == '\\' {
+                        chars.next_if(|&escaped| escaped != '\n');
+                    } else if next == c {
+                        break;
+                    }
+                }
+                code.push(c);
+                code.push(c);
+            }
+            _ => code.push(c),
+        }
+    }
+    code
+}
+
+/// The one candidate that files next to `file_path` are unambiguously written in, if any
+fn sibling_language<'e, 'a>(
+    encodings: &'e Encodings<'a>,
+    candidates: &[&'e Encoding<'a>],
+    file_path: &str,
+) -> Option<&'e Encoding<'a>> {
+    let directory = match Path::new(file_path).parent()? {
+        parent if parent.as_os_str().is_empty() => Path::new("."),
+        parent => parent,
+    };
+    let mut found = vec![false; candidates.len()];
+    for entry in fs::read_dir(directory).ok()?.flatten() {
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_file()) {
+            continue;
+        }
+        let sibling = entry.path();
+        if let [language] = encodings.candidates(&sibling.to_string_lossy())[..] {
+            if let Some(index) = candidates
+                .iter()
+                .position(|candidate| candidate.name == language.name)
+            {
+                found[index] = true;
+            }
+        }
+    }
+    match positions(&found, |found| found)[..] {
+        [only] => Some(candidates[only]),
+        _ => None,
+    }
+}
+
+fn positions<T: Copy>(values: &[T], predicate: impl Fn(T) -> bool) -> Vec<usize> {
+    (0..values.len())
+        .filter(|&index| predicate(values[index]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use tempfile::TempDir;
+
+    fn choose(file_path: &str, source: &str) -> (String, Disambiguation) {
+        let encodings = create_encodings();
+        let candidates = encodings.candidates(file_path);
+        assert_eq!(candidates.len(), 2);
+        let (encoding, disambiguation) = disambiguate(&encodings, &candidates, file_path, source);
+        (encoding.name().to_string(), disambiguation)
+    }
+
+    #[test]
+    fn test_cpp_tokens() {
+        let (language, disambiguation) = choose(
+            "widget.h",
+            "#include <vector>\nnamespace ui {\nstd::vector<int> sizes();\n}\n",
+        );
+        assert_eq!(language, "C++");
+        assert_eq!(disambiguation.strategy, Strategy::Tokens);
+        assert_eq!(disambiguation.confidence, Confidence::High);
+
+        // Comments, strings and `#ifdef __cplusplus` guards are not C++ code
+        assert_eq!(
+            cpp_markers(
+                "/* a C++ class */\n#ifdef __cplusplus\nextern \"C\" {\n#endif\nconst char *s = \"a::b\";\n"
+            ),
+            0
+        );
+        assert_eq!(
+            cpp_markers("class Widget {\n public:\n  Widget();\n};\n"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_grammar_errors() {
+        // `new` is an identifier in C but an operator in C++
+        let (language, disambiguation) = choose(
+            "list.h",
+            "static inline int grow(int n) { int new = n * 2; return new; }\n",
+        );
+        assert_eq!(language, "C");
+        assert_eq!(disambiguation.strategy, Strategy::Grammar);
+
+        let (language, _) = choose("math.h", "double norm(const double &x);\n");
+        assert_eq!(language, "C++");
+    }
+
+    #[test]
+    fn test_siblings_and_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let header = temp_dir.path().join("api.h");
+        let header = header.to_str().unwrap();
+        let source = "int api_version(void);\n";
+
+        let (language, disambiguation) = choose(header, source);
+        assert_eq!(language, "C");
+        assert_eq!(disambiguation.strategy, Strategy::Default);
+        assert_eq!(disambiguation.confidence, Confidence::Low);
+
+        fs::write(temp_dir.path().join("api.cpp"), "").unwrap();
+        fs::write(temp_dir.path().join("other.h"), "").unwrap();
+        let (language, disambiguation) = choose(header, source);
+        assert_eq!(language, "C++");
+        assert_eq!(disambiguation.strategy, Strategy::Siblings);
+
+        fs::write(temp_dir.path().join("main.c"), "").unwrap();
+        let (_, disambiguation) = choose(header, source);
+        assert_eq!(disambiguation.strategy, Strategy::Default);
+    }
+}
@@ -2,6 +2,7 @@ use regex::Regex;
 use std::path::Path;
 use tree_sitter::Language;
 
+/// Associates a file name pattern with the Tree-sitter language used to parse it
 pub struct Encoding<'a> {
     extension_pattern: Regex,
     pub(crate) language: &'a Language,
@@ -18,6 +19,16 @@ impl<'a> Encoding<'a> {
         }
     }
 
+    /// Display name of the language, e.g. "Rust"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tree-sitter grammar used to parse matching files
+    pub fn language(&self) -> &Language {
+        self.language
+    }
+
     pub(crate) fn matches(&self, file_path: &str) -> bool {
         if let Some(extension) = Path::new(file_path).extension().and_then(|e| e.to_str()) {
             return self.extension_pattern.is_match(extension);
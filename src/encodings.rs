@@ -1,14 +1,32 @@
+use crate::detection::{self, Detection};
+use crate::disambiguation::{self, Disambiguation};
 use crate::encoding::Encoding;
+use crate::error::{AstgenError, Result};
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
 use tree_sitter::Language;
 
+/// Ordered registry of encodings; the first encoding matching a file wins
+///
+/// File patterns mapped to a language with `map_pattern` are consulted before the extension
+/// patterns, again in the order they were added.
 pub struct Encodings<'a> {
     encodings: Vec<Encoding<'a>>,
+    /// File patterns and the index of the encoding they select
+    overrides: Vec<(Pattern, usize)>,
+}
+
+impl Default for Encodings<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Encodings<'a> {
     pub fn new() -> Self {
         Self {
             encodings: Vec::new(),
+            overrides: Vec::new(),
         }
     }
 
@@ -22,10 +40,155 @@ impl<'a> Encodings<'a> {
             .push(Encoding::new(extension_pattern, language, name));
         self
     }
+
+    /// All registered encodings, in matching order
+    pub fn iter(&self) -> impl Iterator<Item = &Encoding<'a>> {
+        self.encodings.iter()
+    }
+
+    /// Parse files matching the glob `pattern` as the language called `language`, whatever
+    /// their extension
+    ///
+    /// A pattern without a `/`, such as `*.h`, is matched against the file name; one with a `/`
+    /// against the whole path, where `*` stays within a directory and `**` crosses them.
+    pub fn map_pattern(&mut self, pattern: &str, language: &str) -> Result<&mut Self> {
+        let index = self
+            .encodings
+            .iter()
+            .position(|encoding| encoding.name.eq_ignore_ascii_case(language))
+            .ok_or_else(|| {
+                AstgenError::InvalidInput(format!(
+                    "Unknown language {} for files matching {}",
+                    language, pattern
+                ))
+            })?;
+        let compiled = Pattern::new(pattern).map_err(|e| {
+            AstgenError::InvalidInput(format!("Invalid file pattern {}: {}", pattern, e))
+        })?;
+        self.overrides.push((compiled, index));
+        Ok(self)
+    }
+
+    /// Find the encoding for a file based on the mapped file patterns, then its extension or
+    /// file name
     pub fn match_file(&self, file_path: &str) -> Option<&Encoding<'_>> {
+        self.detect(file_path, None).map(|(encoding, _)| encoding)
+    }
+
+    /// Find the encoding for a file and what decided it
+    ///
+    /// Mapped file patterns come first, then the extension, or for a file without one its name.
+    /// When none of those match, the shebang or a modeline in `source`, if given, names the
+    /// language.
+    pub fn detect(
+        &self,
+        file_path: &str,
+        source: Option<&str>,
+    ) -> Option<(&Encoding<'_>, Detection)> {
+        if let Some(encoding) = self.match_pattern(file_path) {
+            return Some((encoding, Detection::Override));
+        }
+        let by_path = self
+            .encodings
+            .iter()
+            .find(|encoding| encoding.matches(file_path));
+        let path = Path::new(file_path);
+        if path.extension().is_some() {
+            if let Some(encoding) = by_path {
+                return Some((encoding, Detection::Extension));
+            }
+        } else {
+            let well_known = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(detection::language_from_filename)
+                .and_then(|language| self.match_name(language));
+            if let Some(encoding) = well_known.or(by_path) {
+                return Some((encoding, Detection::Filename));
+            }
+        }
+
+        let source = source?;
+        let sniffed = |language: Option<&str>, detection| {
+            language
+                .and_then(|language| self.match_name(language))
+                .map(|encoding| (encoding, detection))
+        };
+        sniffed(detection::language_from_shebang(source), Detection::Shebang).or_else(|| {
+            sniffed(
+                detection::language_from_modeline(source),
+                Detection::Modeline,
+            )
+        })
+    }
+
+    /// Find the encoding for a file from its name and content, and what decided it
+    ///
+    /// Detection is the same as `detect` given the source, except that an extension several
+    /// languages share, such as `.h`, is settled by the content, and how is returned as well.
+    pub fn detect_with_source(
+        &self,
+        file_path: &str,
+        source: &str,
+    ) -> Option<(&Encoding<'_>, Detection, Option<Disambiguation>)> {
+        let (encoding, detection) = self.detect(file_path, Some(source))?;
+        if detection == Detection::Extension {
+            let candidates = self.candidates(file_path);
+            if candidates.len() > 1 {
+                let (chosen, how) =
+                    disambiguation::disambiguate(self, &candidates, file_path, source);
+                return Some((chosen, detection, Some(how)));
+            }
+        }
+        Some((encoding, detection, None))
+    }
+
+    /// The encoding a mapped file pattern selects for a file, if any
+    pub fn match_pattern(&self, file_path: &str) -> Option<&Encoding<'_>> {
+        let path = file_path.strip_prefix("./").unwrap_or(file_path);
+        let file_name = Path::new(path)
+            .file_name()
+            .map_or(path.into(), |name| name.to_string_lossy());
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        self.overrides
+            .iter()
+            .find(|(pattern, _)| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_with(path, options)
+                } else {
+                    pattern.matches_with(&file_name, options)
+                }
+            })
+            .map(|(_, index)| &self.encodings[*index])
+    }
+
+    /// Every language whose extension pattern matches a file, in matching order; more than one
+    /// means the extension alone cannot tell them apart, as for `.h`
+    pub fn candidates(&self, file_path: &str) -> Vec<&Encoding<'a>> {
+        let mut candidates: Vec<&Encoding<'a>> = Vec::new();
+        for encoding in self
+            .encodings
+            .iter()
+            .filter(|encoding| encoding.matches(file_path))
+        {
+            if !candidates
+                .iter()
+                .any(|candidate| candidate.name == encoding.name)
+            {
+                candidates.push(encoding);
+            }
+        }
+        candidates
+    }
+
+    /// Find the encoding for a language by its name, ignoring case, e.g. "python" or "C++"
+    pub fn match_name(&self, name: &str) -> Option<&Encoding<'_>> {
         self.encodings
             .iter()
-            .find(|encoding| encoding.matches(file_path))
+            .find(|encoding| encoding.name.eq_ignore_ascii_case(name))
     }
 }
 
@@ -119,6 +282,90 @@ mod tests {
         assert_eq!(result.unwrap().name, "Rust"); // First match wins
     }
 
+    #[test]
+    fn match_name_ignores_case() {
+        let mut encodings = Encodings::new();
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let cpp_language = tree_sitter_cpp::LANGUAGE.into();
+
+        encodings
+            .add("rs$", &rust_language, "Rust")
+            .add("cpp$", &cpp_language, "C++");
+
+        assert_eq!(encodings.match_name("rust").unwrap().name, "Rust");
+        assert_eq!(encodings.match_name("c++").unwrap().name, "C++");
+        assert!(encodings.match_name("rs").is_none());
+    }
+
+    #[test]
+    fn map_pattern_wins_over_extensions() {
+        let mut encodings = Encodings::new();
+        let c_language = tree_sitter_c::LANGUAGE.into();
+        let cpp_language = tree_sitter_cpp::LANGUAGE.into();
+
+        encodings
+            .add("c$", &c_language, "C")
+            .add("h$", &c_language, "C")
+            .add("cpp$", &cpp_language, "C++");
+        encodings
+            .map_pattern("*.h", "c++")
+            .unwrap()
+            .map_pattern("legacy/**/*.inc", "C")
+            .unwrap()
+            .map_pattern("*.inc", "C++")
+            .unwrap();
+
+        assert_eq!(encodings.match_file("include/api.h").unwrap().name, "C++");
+        assert_eq!(encodings.match_file("./api.h").unwrap().name, "C++");
+        assert_eq!(encodings.match_file("src/main.c").unwrap().name, "C");
+        // Earlier mappings win, and path patterns only match whole paths
+        assert_eq!(encodings.match_file("legacy/a/b.inc").unwrap().name, "C");
+        assert_eq!(
+            encodings.match_file("src/legacy/b.inc").unwrap().name,
+            "C++"
+        );
+
+        assert!(encodings.map_pattern("*.x", "Cobol").is_err());
+        assert!(encodings.map_pattern("[", "C").is_err());
+    }
+
+    #[test]
+    fn detect_sniffs_files_without_an_extension() {
+        let mut encodings = Encodings::new();
+        let python_language = tree_sitter_python::LANGUAGE.into();
+        let ruby_language = tree_sitter_ruby::LANGUAGE.into();
+
+        encodings
+            .add("^py$", &python_language, "Python")
+            .add("^rb$", &ruby_language, "Ruby");
+        encodings.map_pattern("bin/*", "ruby").unwrap();
+
+        let detect = |path, source| {
+            encodings
+                .detect(path, source)
+                .map(|(encoding, detection)| (encoding.name.as_str(), detection))
+        };
+        let script = Some("#!/usr/bin/env python3\n# vim: ft=ruby\n");
+        assert_eq!(
+            detect("tool.py", None),
+            Some(("Python", Detection::Extension))
+        );
+        assert_eq!(
+            detect("Rakefile", None),
+            Some(("Ruby", Detection::Filename))
+        );
+        assert_eq!(detect("tool", script), Some(("Python", Detection::Shebang)));
+        assert_eq!(
+            detect("tool", Some("# vim: ft=ruby\n")),
+            Some(("Ruby", Detection::Modeline))
+        );
+        assert_eq!(
+            detect("bin/tool", script),
+            Some(("Ruby", Detection::Override))
+        );
+        assert_eq!(detect("tool", None), None);
+    }
+
     #[test]
     fn match_file_with_complex_path() {
         let mut encodings = Encodings::new();
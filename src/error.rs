@@ -14,6 +14,7 @@ pub enum AstgenError {
         limit: usize,
     },
     UnsupportedFileType(String),
+    QueryError(String),
 }
 
 impl fmt::Display for AstgenError {
@@ -28,6 +29,7 @@ impl fmt::Display for AstgenError {
             AstgenError::FileTooLarge { path, size, limit } => {
                 write!(f, "File too large: {} ({} bytes)\nMaximum allowed size: {} bytes\nUse --max-file-size to increase the limit.", path, size, limit)
             }
+            AstgenError::QueryError(msg) => write!(f, "Query error: {}", msg),
             AstgenError::UnsupportedFileType(path) => {
                 write!(f, "Cannot determine language for file: {}\nSupported extensions: .rs, .java, .cs, .go, .py, .ts, .tsx, .js, .rb", path)
             }
@@ -0,0 +1,3015 @@
+//! ESTree output for JavaScript, TypeScript and TSX
+//!
+//! Nodes follow the layout Acorn writes with `locations` and `ranges` enabled: `type`, `start`,
+//! `end`, `loc` (1-based lines) and `range`, then the node's own properties. TypeScript syntax
+//! uses the node types of typescript-estree 8, and JSX those of the JSX extension to ESTree.
+//! Offsets and columns count UTF-8 bytes, or UTF-16 code units (as JavaScript strings do) with
+//! `ColumnEncoding::Utf16`.
+//!
+//! Syntax without an ESTree counterpart, including the `ERROR` nodes of files with syntax
+//! errors, becomes an `Unknown` node that records the Tree-sitter `kind`.
+use crate::json::{
+    ColumnEncoding, Position, TreeOptions, STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES,
+};
+use crate::schema::{AstValue, Span};
+use tree_sitter::{Node, Point};
+
+/// Encodings whose trees can be converted
+pub const LANGUAGES: &[&str] = &["JavaScript", "TypeScript", "TSX"];
+
+type Props = Vec<(&'static str, AstValue)>;
+
+/// Convert the tree of a JavaScript, TypeScript or TSX file into an ESTree `Program`
+pub fn to_estree(language: &str, source_code: &str, root: Node, options: &TreeOptions) -> AstValue {
+    let utf16_line_starts = (options.column_encoding == ColumnEncoding::Utf16).then(|| {
+        let mut starts = vec![0];
+        let mut offset = 0;
+        for line in source_code.split_inclusive('\n') {
+            offset += line.encode_utf16().count();
+            starts.push(offset);
+        }
+        starts
+    });
+    let converter = Converter {
+        source: source_code,
+        typescript: language != "JavaScript",
+        encoding: options.column_encoding,
+        utf16_line_starts,
+    };
+    converter.program(root)
+}
+
+struct Converter<'a> {
+    source: &'a str,
+    typescript: bool,
+    encoding: ColumnEncoding,
+    /// Offset of every line in UTF-16 code units, when offsets are counted in them
+    utf16_line_starts: Option<Vec<usize>>,
+}
+
+impl Converter<'_> {
+    fn text(&self, node: Node) -> &str {
+        &self.source[node.start_byte()..node.end_byte()]
+    }
+
+    fn offset(&self, byte: usize, point: Point) -> usize {
+        match &self.utf16_line_starts {
+            Some(starts) => starts[point.row] + self.column(byte, point),
+            None => byte,
+        }
+    }
+
+    fn column(&self, byte: usize, point: Point) -> usize {
+        Position::from_point(self.source, point, byte, self.encoding).column
+    }
+
+    fn location(&self, byte: usize, point: Point) -> AstValue {
+        AstValue::Map(vec![
+            ("line", (point.row + 1).into()),
+            ("column", self.column(byte, point).into()),
+        ])
+    }
+
+    /// A node of ESTree type `ty` covering `span`
+    fn make(&self, ty: &'static str, span: impl Into<Span>, props: Props) -> AstValue {
+        let span = span.into();
+        let start = self.offset(span.start_byte, span.start);
+        let end = self.offset(span.end_byte, span.end);
+        let mut entries = vec![
+            ("type", ty.into()),
+            ("start", start.into()),
+            ("end", end.into()),
+            (
+                "loc",
+                AstValue::Map(vec![
+                    ("start", self.location(span.start_byte, span.start)),
+                    ("end", self.location(span.end_byte, span.end)),
+                ]),
+            ),
+            ("range", vec![start.into(), end.into()].into()),
+        ];
+        entries.extend(props);
+        AstValue::Map(entries)
+    }
+
+    /// Move the end of a converted node to the end of `span`
+    fn extend(&self, value: &mut AstValue, span: Span) {
+        let end = self.offset(span.end_byte, span.end);
+        set(value, "end", end.into());
+        if let AstValue::Map(entries) = value {
+            for (key, entry) in entries.iter_mut() {
+                match (*key, entry) {
+                    ("loc", loc) => set(loc, "end", self.location(span.end_byte, span.end)),
+                    ("range", AstValue::List(range)) => range[1] = end.into(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn unknown(&self, node: Node) -> AstValue {
+        self.make("Unknown", node, vec![("kind", node.kind().into())])
+    }
+
+    fn identifier(&self, node: Node) -> AstValue {
+        let name = match node.kind() {
+            "private_property_identifier" => {
+                return self.make(
+                    "PrivateIdentifier",
+                    node,
+                    vec![("name", self.text(node)[1..].into())],
+                )
+            }
+            "undefined" => "undefined",
+            _ => self.text(node),
+        };
+        self.make("Identifier", node, vec![("name", name.into())])
+    }
+
+    fn optional(&self, node: Option<Node>) -> AstValue {
+        node.map_or(AstValue::Null, |node| self.convert(node))
+    }
+
+    fn list<'tree>(&self, nodes: impl IntoIterator<Item = Node<'tree>>) -> AstValue {
+        nodes
+            .into_iter()
+            .map(|node| self.convert(node))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn program(&self, root: Node) -> AstValue {
+        let end_row = self.source.matches('\n').count();
+        let end_column = self.source.len() - self.source.rfind('\n').map_or(0, |i| i + 1);
+        let span = Span {
+            start_byte: 0,
+            end_byte: self.source.len(),
+            start: Point::new(0, 0),
+            end: Point::new(end_row, end_column),
+        };
+        let statements = named_children(root);
+        let is_module = statements
+            .iter()
+            .any(|node| matches!(node.kind(), "import_statement" | "export_statement"));
+        self.make(
+            "Program",
+            span,
+            vec![
+                ("body", self.statements(&statements, true)),
+                (
+                    "sourceType",
+                    if is_module { "module" } else { "script" }.into(),
+                ),
+                ("comments", self.comments(root)),
+            ],
+        )
+    }
+
+    /// Convert a statement list, marking the directive prologue (`"use strict"` and the like)
+    /// when `directives` is set
+    fn statements(&self, nodes: &[Node], directives: bool) -> AstValue {
+        let mut in_prologue = directives;
+        let mut body = Vec::new();
+        for &node in nodes {
+            if node.kind() == "hash_bang_line" {
+                continue;
+            }
+            let mut statement = self.convert(node);
+            let directive = named_children(node)
+                .first()
+                .filter(|expression| {
+                    node.kind() == "expression_statement" && expression.kind() == "string"
+                })
+                .map(|expression| {
+                    let raw = self.text(*expression);
+                    raw[1..raw.len() - 1].to_string()
+                });
+            match directive {
+                Some(directive) if in_prologue => {
+                    set(&mut statement, "directive", directive.into())
+                }
+                _ => in_prologue = false,
+            }
+            body.push(statement);
+        }
+        body.into()
+    }
+
+    fn comments(&self, root: Node) -> AstValue {
+        let mut comments = Vec::new();
+        let mut cursor = root.walk();
+        'walk: loop {
+            let node = cursor.node();
+            if node.kind() == "comment" {
+                let text = self.text(node);
+                let comment = if let Some(line) = text.strip_prefix("//") {
+                    self.make("Line", node, vec![("value", line.into())])
+                } else {
+                    let block = text.strip_prefix("/*").unwrap_or(text);
+                    let block = block.strip_suffix("*/").unwrap_or(block);
+                    self.make("Block", node, vec![("value", block.into())])
+                };
+                comments.push(comment);
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        comments.into()
+    }
+
+    fn convert(&self, node: Node) -> AstValue {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.convert_node(node)
+        })
+    }
+
+    fn convert_node(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let first = || named_children(node).into_iter().next();
+        match node.kind() {
+            // Statements
+            "expression_statement" => match first() {
+                // Tree-sitter parses `namespace a {}` as an expression
+                Some(module) if module.kind() == "internal_module" => self.module(module),
+                expression => self.make(
+                    "ExpressionStatement",
+                    node,
+                    vec![("expression", self.optional(expression))],
+                ),
+            },
+            "statement_block" => self.block(node, false),
+            "empty_statement" => self.make("EmptyStatement", node, vec![]),
+            "debugger_statement" => self.make("DebuggerStatement", node, vec![]),
+            "return_statement" => self.make(
+                "ReturnStatement",
+                node,
+                vec![("argument", self.optional(first()))],
+            ),
+            "throw_statement" => self.make(
+                "ThrowStatement",
+                node,
+                vec![("argument", self.optional(first()))],
+            ),
+            "if_statement" => self.make(
+                "IfStatement",
+                node,
+                vec![
+                    ("test", self.optional(field("condition"))),
+                    ("consequent", self.optional(field("consequence"))),
+                    (
+                        "alternate",
+                        self.optional(
+                            field("alternative")
+                                .and_then(|clause| named_children(clause).into_iter().next()),
+                        ),
+                    ),
+                ],
+            ),
+            "while_statement" => self.make(
+                "WhileStatement",
+                node,
+                vec![
+                    ("test", self.optional(field("condition"))),
+                    ("body", self.optional(field("body"))),
+                ],
+            ),
+            "do_statement" => self.make(
+                "DoWhileStatement",
+                node,
+                vec![
+                    ("body", self.optional(field("body"))),
+                    ("test", self.optional(field("condition"))),
+                ],
+            ),
+            "with_statement" => self.make(
+                "WithStatement",
+                node,
+                vec![
+                    ("object", self.optional(field("object"))),
+                    ("body", self.optional(field("body"))),
+                ],
+            ),
+            "for_statement" => self.for_statement(node),
+            "for_in_statement" => self.for_in_statement(node),
+            "labeled_statement" => self.make(
+                "LabeledStatement",
+                node,
+                vec![
+                    ("body", self.optional(field("body"))),
+                    ("label", self.optional(field("label"))),
+                ],
+            ),
+            "break_statement" => self.make(
+                "BreakStatement",
+                node,
+                vec![("label", self.optional(field("label")))],
+            ),
+            "continue_statement" => self.make(
+                "ContinueStatement",
+                node,
+                vec![("label", self.optional(field("label")))],
+            ),
+            "switch_statement" => self.make(
+                "SwitchStatement",
+                node,
+                vec![
+                    ("discriminant", self.optional(field("value"))),
+                    (
+                        "cases",
+                        self.list(field("body").map(named_children).unwrap_or_default()),
+                    ),
+                ],
+            ),
+            "switch_case" | "switch_default" => {
+                let consequent: Vec<_> = node
+                    .children_by_field_name("body", &mut node.walk())
+                    .collect();
+                self.make(
+                    "SwitchCase",
+                    node,
+                    vec![
+                        ("consequent", self.statements(&consequent, false)),
+                        ("test", self.optional(field("value"))),
+                    ],
+                )
+            }
+            "try_statement" => self.make(
+                "TryStatement",
+                node,
+                vec![
+                    ("block", self.optional(field("body"))),
+                    ("handler", self.optional(field("handler"))),
+                    (
+                        "finalizer",
+                        self.optional(
+                            field("finalizer").and_then(|f| f.child_by_field_name("body")),
+                        ),
+                    ),
+                ],
+            ),
+            "catch_clause" => {
+                let mut param = self.optional(field("parameter"));
+                if let Some(annotation) = field("type") {
+                    self.annotate(&mut param, annotation);
+                }
+                self.make(
+                    "CatchClause",
+                    node,
+                    vec![("param", param), ("body", self.optional(field("body")))],
+                )
+            }
+            "variable_declaration" | "lexical_declaration" | "using_declaration" => {
+                self.variable_declaration(node, Span::from(node))
+            }
+            "variable_declarator" => {
+                let mut id = self.optional(field("name"));
+                if let Some(annotation) = field("type") {
+                    self.annotate(&mut id, annotation);
+                }
+                let mut props = vec![("id", id), ("init", self.optional(field("value")))];
+                if self.typescript && has_token(node, "!") {
+                    props.push(("definite", true.into()));
+                }
+                self.make("VariableDeclarator", node, props)
+            }
+            "function_declaration" | "generator_function_declaration" => {
+                self.function(node, "FunctionDeclaration", node.into())
+            }
+            "function_expression" | "generator_function" => {
+                self.function(node, "FunctionExpression", node.into())
+            }
+            "arrow_function" => self.function(node, "ArrowFunctionExpression", node.into()),
+            "function_signature" => self.function(node, "TSDeclareFunction", node.into()),
+            "class_declaration" | "abstract_class_declaration" => {
+                self.class(node, "ClassDeclaration", &[])
+            }
+            "class" => self.class(node, "ClassExpression", &[]),
+            "import_statement" => self.import_statement(node),
+            "export_statement" => self.export_statement(node),
+
+            // Expressions
+            "identifier"
+            | "type_identifier"
+            | "property_identifier"
+            | "shorthand_property_identifier"
+            | "shorthand_property_identifier_pattern"
+            | "statement_identifier"
+            | "private_property_identifier"
+            | "undefined" => self.identifier(node),
+            // `default` as an export name, as in `export { a as default }`
+            "default" => self.make("Identifier", node, vec![("name", "default".into())]),
+            "this" => self.make("ThisExpression", node, vec![]),
+            "super" => self.make("Super", node, vec![]),
+            "number" | "string" | "true" | "false" | "null" | "regex" => self.literal(node),
+            "template_string" => self.template_literal(node),
+            "parenthesized_expression" => self.optional(first()),
+            "array" => self.make(
+                "ArrayExpression",
+                node,
+                vec![("elements", self.elements(node))],
+            ),
+            "object" => self.make(
+                "ObjectExpression",
+                node,
+                vec![(
+                    "properties",
+                    named_children(node)
+                        .into_iter()
+                        .map(|property| self.property(property))
+                        .collect::<Vec<_>>()
+                        .into(),
+                )],
+            ),
+            "member_expression" | "subscript_expression" | "call_expression" => {
+                let (value, optional) = self.chain_element(node);
+                if optional {
+                    self.make("ChainExpression", node, vec![("expression", value)])
+                } else {
+                    value
+                }
+            }
+            "new_expression" => {
+                let mut props = vec![
+                    ("callee", self.optional(field("constructor"))),
+                    (
+                        "arguments",
+                        self.list(field("arguments").map(named_children).unwrap_or_default()),
+                    ),
+                ];
+                self.push_type_arguments(&mut props, field("type_arguments"));
+                self.make("NewExpression", node, props)
+            }
+            "binary_expression" => {
+                let operator = field("operator").map_or("", |op| op.kind());
+                let ty = if matches!(operator, "&&" | "||" | "??") {
+                    "LogicalExpression"
+                } else {
+                    "BinaryExpression"
+                };
+                self.make(
+                    ty,
+                    node,
+                    vec![
+                        ("left", self.optional(field("left"))),
+                        ("operator", operator.into()),
+                        ("right", self.optional(field("right"))),
+                    ],
+                )
+            }
+            "unary_expression" => self.make(
+                "UnaryExpression",
+                node,
+                vec![
+                    (
+                        "operator",
+                        field("operator").map_or("", |op| op.kind()).into(),
+                    ),
+                    ("prefix", true.into()),
+                    ("argument", self.optional(field("argument"))),
+                ],
+            ),
+            "update_expression" => {
+                let operator = field("operator");
+                let argument = field("argument");
+                let prefix = match (operator, argument) {
+                    (Some(operator), Some(argument)) => {
+                        operator.start_byte() < argument.start_byte()
+                    }
+                    _ => false,
+                };
+                self.make(
+                    "UpdateExpression",
+                    node,
+                    vec![
+                        ("operator", operator.map_or("", |op| op.kind()).into()),
+                        ("prefix", prefix.into()),
+                        ("argument", self.optional(argument)),
+                    ],
+                )
+            }
+            "assignment_expression" | "augmented_assignment_expression" => self.make(
+                "AssignmentExpression",
+                node,
+                vec![
+                    (
+                        "operator",
+                        field("operator").map_or("=", |op| op.kind()).into(),
+                    ),
+                    ("left", self.optional(field("left"))),
+                    ("right", self.optional(field("right"))),
+                ],
+            ),
+            "ternary_expression" => self.make(
+                "ConditionalExpression",
+                node,
+                vec![
+                    ("test", self.optional(field("condition"))),
+                    ("consequent", self.optional(field("consequence"))),
+                    ("alternate", self.optional(field("alternative"))),
+                ],
+            ),
+            "sequence_expression" => {
+                let mut expressions = Vec::new();
+                let mut pending = vec![node];
+                while let Some(sequence) = pending.pop() {
+                    for child in named_children(sequence).into_iter().rev() {
+                        if child.kind() == "sequence_expression" {
+                            pending.push(child);
+                        } else {
+                            expressions.push(child);
+                        }
+                    }
+                }
+                expressions.sort_by_key(|child| child.start_byte());
+                self.make(
+                    "SequenceExpression",
+                    node,
+                    vec![("expressions", self.list(expressions))],
+                )
+            }
+            "await_expression" => self.make(
+                "AwaitExpression",
+                node,
+                vec![("argument", self.optional(first()))],
+            ),
+            "yield_expression" => self.make(
+                "YieldExpression",
+                node,
+                vec![
+                    ("delegate", has_token(node, "*").into()),
+                    ("argument", self.optional(first())),
+                ],
+            ),
+            "spread_element" => self.make(
+                "SpreadElement",
+                node,
+                vec![("argument", self.optional(first()))],
+            ),
+            "meta_property" => {
+                let tokens = children(node);
+                match (tokens.first(), tokens.last()) {
+                    (Some(&meta), Some(&property)) => self.make(
+                        "MetaProperty",
+                        node,
+                        vec![
+                            ("meta", self.identifier(meta)),
+                            ("property", self.identifier(property)),
+                        ],
+                    ),
+                    _ => self.unknown(node),
+                }
+            }
+            "decorator" => self.make(
+                "Decorator",
+                node,
+                vec![("expression", self.optional(first()))],
+            ),
+
+            // Patterns
+            "array_pattern" => self.make(
+                "ArrayPattern",
+                node,
+                vec![("elements", self.elements(node))],
+            ),
+            "object_pattern" => self.make(
+                "ObjectPattern",
+                node,
+                vec![(
+                    "properties",
+                    named_children(node)
+                        .into_iter()
+                        .map(|property| self.property(property))
+                        .collect::<Vec<_>>()
+                        .into(),
+                )],
+            ),
+            "assignment_pattern" => self.make(
+                "AssignmentPattern",
+                node,
+                vec![
+                    ("left", self.optional(field("left"))),
+                    ("right", self.optional(field("right"))),
+                ],
+            ),
+            "rest_pattern" => self.make(
+                "RestElement",
+                node,
+                vec![("argument", self.optional(first()))],
+            ),
+
+            // JSX
+            "jsx_element" | "jsx_self_closing_element" => self.jsx_element(node),
+            "jsx_expression" => self.jsx_expression(node, false),
+
+            // TypeScript expressions and declarations
+            "as_expression" | "satisfies_expression" => {
+                let children = named_children(node);
+                let ty = if node.kind() == "as_expression" {
+                    "TSAsExpression"
+                } else {
+                    "TSSatisfiesExpression"
+                };
+                self.make(
+                    ty,
+                    node,
+                    vec![
+                        ("expression", self.optional(children.first().copied())),
+                        (
+                            "typeAnnotation",
+                            children
+                                .get(1)
+                                .map_or(AstValue::Null, |annotation| self.ts_type(*annotation)),
+                        ),
+                    ],
+                )
+            }
+            "non_null_expression" => self.make(
+                "TSNonNullExpression",
+                node,
+                vec![("expression", self.optional(first()))],
+            ),
+            "type_assertion" => {
+                let children = named_children(node);
+                let type_annotation = children
+                    .first()
+                    .and_then(|arguments| named_children(*arguments).into_iter().next())
+                    .map_or(AstValue::Null, |ty| self.ts_type(ty));
+                self.make(
+                    "TSTypeAssertion",
+                    node,
+                    vec![
+                        ("typeAnnotation", type_annotation),
+                        ("expression", self.optional(children.get(1).copied())),
+                    ],
+                )
+            }
+            "instantiation_expression" => {
+                let expression = field("function").or_else(|| {
+                    named_children(node)
+                        .into_iter()
+                        .find(|child| child.kind() != "type_arguments")
+                });
+                let mut props = vec![("expression", self.optional(expression))];
+                self.push_type_arguments(&mut props, field("type_arguments"));
+                self.make("TSInstantiationExpression", node, props)
+            }
+            "interface_declaration" => self.interface(node),
+            "type_alias_declaration" => {
+                let mut props = vec![
+                    ("id", self.optional(field("name"))),
+                    (
+                        "typeAnnotation",
+                        field("value").map_or(AstValue::Null, |ty| self.ts_type(ty)),
+                    ),
+                ];
+                self.push_type_parameters(&mut props, field("type_parameters"));
+                self.make("TSTypeAliasDeclaration", node, props)
+            }
+            "enum_declaration" => self.enum_declaration(node),
+            "module" | "internal_module" => self.module(node),
+            "ambient_declaration" => self.ambient_declaration(node),
+            "import_alias" => {
+                let children = named_children(node);
+                self.make(
+                    "TSImportEqualsDeclaration",
+                    node,
+                    vec![
+                        ("id", self.optional(children.first().copied())),
+                        (
+                            "moduleReference",
+                            children
+                                .get(1)
+                                .map_or(AstValue::Null, |name| self.entity_name(*name)),
+                        ),
+                        ("importKind", "value".into()),
+                    ],
+                )
+            }
+            _ => self.unknown(node),
+        }
+    }
+
+    fn block(&self, node: Node, directives: bool) -> AstValue {
+        self.make(
+            "BlockStatement",
+            node,
+            vec![("body", self.statements(&named_children(node), directives))],
+        )
+    }
+
+    fn variable_declaration(&self, node: Node, span: Span) -> AstValue {
+        let kind = match node.kind() {
+            "variable_declaration" => "var".to_string(),
+            _ => node
+                .children_by_field_name("kind", &mut node.walk())
+                .map(|kind| kind.kind())
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        let declarations: Vec<_> = named_children(node)
+            .into_iter()
+            .filter(|child| child.kind() == "variable_declarator")
+            .collect();
+        self.make(
+            "VariableDeclaration",
+            span,
+            vec![
+                ("declarations", self.list(declarations)),
+                ("kind", kind.into()),
+            ],
+        )
+    }
+
+    fn for_statement(&self, node: Node) -> AstValue {
+        let init = node
+            .child_by_field_name("initializer")
+            .and_then(|init| match init.kind() {
+                "empty_statement" | ";" => None,
+                "variable_declaration" | "lexical_declaration" => {
+                    Some(self.variable_declaration(init, without_semicolon(init)))
+                }
+                "expression_statement" => named_children(init)
+                    .into_iter()
+                    .next()
+                    .map(|expression| self.convert(expression)),
+                _ => Some(self.convert(init)),
+            });
+        let test = node
+            .children_by_field_name("condition", &mut node.walk())
+            .find(|condition| condition.is_named() && condition.kind() != "empty_statement")
+            .map(|condition| match condition.kind() {
+                "expression_statement" => {
+                    self.optional(named_children(condition).into_iter().next())
+                }
+                _ => self.convert(condition),
+            });
+        self.make(
+            "ForStatement",
+            node,
+            vec![
+                ("init", init.into()),
+                ("test", test.into()),
+                (
+                    "update",
+                    self.optional(node.child_by_field_name("increment")),
+                ),
+                ("body", self.optional(node.child_by_field_name("body"))),
+            ],
+        )
+    }
+
+    fn for_in_statement(&self, node: Node) -> AstValue {
+        let tokens = children(node);
+        let open = tokens
+            .iter()
+            .position(|token| token.kind() == "(")
+            .unwrap_or(0);
+        let is_await = tokens[..open].iter().any(|token| token.kind() == "await");
+        let kinds: Vec<_> = tokens[open..]
+            .iter()
+            .filter(|token| matches!(token.kind(), "var" | "let" | "const" | "using" | "await"))
+            .take_while(|token| {
+                node.child_by_field_name("left")
+                    .is_none_or(|left| token.start_byte() < left.start_byte())
+            })
+            .copied()
+            .collect();
+        let left_node = node.child_by_field_name("left");
+        let left = match (kinds.first(), left_node) {
+            (Some(&kind), Some(left)) => {
+                let declarator = self.make(
+                    "VariableDeclarator",
+                    left,
+                    vec![("id", self.convert(left)), ("init", AstValue::Null)],
+                );
+                let kind_text = kinds
+                    .iter()
+                    .map(|kind| kind.kind())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.make(
+                    "VariableDeclaration",
+                    Span::from(kind).to(left),
+                    vec![
+                        ("declarations", vec![declarator].into()),
+                        ("kind", kind_text.into()),
+                    ],
+                )
+            }
+            _ => self.optional(left_node),
+        };
+        let is_of = node
+            .child_by_field_name("operator")
+            .is_some_and(|operator| operator.kind() == "of");
+        let mut props = vec![
+            ("left", left),
+            ("right", self.optional(node.child_by_field_name("right"))),
+            ("body", self.optional(node.child_by_field_name("body"))),
+        ];
+        if is_of {
+            props.insert(0, ("await", is_await.into()));
+        }
+        self.make(
+            if is_of {
+                "ForOfStatement"
+            } else {
+                "ForInStatement"
+            },
+            node,
+            props,
+        )
+    }
+
+    /// Functions, arrow functions and TypeScript function signatures; `span` is the range of the
+    /// function itself, which for methods starts at the parameters
+    fn function(&self, node: Node, ty: &'static str, span: Span) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let body = field("body");
+        let is_expression = body.is_some_and(|body| body.kind() != "statement_block");
+        let mut props = vec![
+            (
+                "id",
+                self.optional(field("name").filter(|_| !is_method(node))),
+            ),
+            ("expression", is_expression.into()),
+            (
+                "generator",
+                (node.kind().starts_with("generator_") || has_token(node, "*")).into(),
+            ),
+            ("async", has_token(node, "async").into()),
+            ("params", self.params(node)),
+        ];
+        match body {
+            Some(body) if body.kind() == "statement_block" => {
+                props.push(("body", self.block(body, true)))
+            }
+            Some(body) => props.push(("body", self.convert(body))),
+            None if ty == "TSEmptyBodyFunctionExpression" => props.push(("body", AstValue::Null)),
+            None => {}
+        }
+        if ty == "TSDeclareFunction" {
+            props.push(("declare", false.into()));
+        }
+        self.push_type_parameters(&mut props, field("type_parameters"));
+        if let Some(return_type) = field("return_type") {
+            props.push(("returnType", self.type_annotation(return_type)));
+        }
+        self.make(ty, span, props)
+    }
+
+    fn params(&self, node: Node) -> AstValue {
+        if let Some(parameter) = node.child_by_field_name("parameter") {
+            return vec![self.convert(parameter)].into();
+        }
+        let Some(parameters) = node.child_by_field_name("parameters") else {
+            return AstValue::List(Vec::new());
+        };
+        named_children(parameters)
+            .into_iter()
+            .map(|parameter| match parameter.kind() {
+                "required_parameter" | "optional_parameter" => self.ts_parameter(parameter),
+                _ => self.convert(parameter),
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn ts_parameter(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let Some(pattern) = field("pattern").or_else(|| field("name")) else {
+            return self.unknown(node);
+        };
+        let mut parameter = if pattern.kind() == "this" {
+            self.make("Identifier", pattern, vec![("name", "this".into())])
+        } else {
+            self.convert(pattern)
+        };
+        if node.kind() == "optional_parameter" {
+            set(&mut parameter, "optional", true.into());
+            if let Some(question) = children(node).into_iter().find(|token| token.kind() == "?") {
+                self.extend(&mut parameter, question.into());
+            }
+        }
+        if let Some(annotation) = field("type") {
+            self.annotate(&mut parameter, annotation);
+        }
+        let decorators = self.decorators(node);
+        if !decorators.is_empty() {
+            set(&mut parameter, "decorators", decorators.into());
+        }
+        if let Some(value) = field("value") {
+            parameter = self.make(
+                "AssignmentPattern",
+                Span::from(pattern).to(value),
+                vec![("left", parameter), ("right", self.convert(value))],
+            );
+        }
+
+        let accessibility = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "accessibility_modifier");
+        let is_readonly = has_token(node, "readonly");
+        let is_override = named_children(node)
+            .iter()
+            .any(|child| child.kind() == "override_modifier");
+        if accessibility.is_none() && !is_readonly && !is_override {
+            return parameter;
+        }
+        let mut props = Vec::new();
+        if let Some(accessibility) = accessibility {
+            props.push(("accessibility", self.text(accessibility).into()));
+        }
+        props.push(("override", is_override.into()));
+        props.push(("readonly", is_readonly.into()));
+        props.push(("parameter", parameter));
+        self.make("TSParameterProperty", node, props)
+    }
+
+    /// Give a binding the type annotation `annotation`, widening it to cover the annotation as
+    /// typescript-estree does
+    fn annotate(&self, binding: &mut AstValue, annotation: Node) {
+        set(binding, "typeAnnotation", self.type_annotation(annotation));
+        self.extend(binding, annotation.into());
+    }
+
+    fn decorators(&self, node: Node) -> Vec<AstValue> {
+        node.children_by_field_name("decorator", &mut node.walk())
+            .chain(named_children(node).into_iter().filter(|child| {
+                child.kind() == "decorator" && node.child_by_field_name("decorator").is_none()
+            }))
+            .map(|decorator| self.convert(decorator))
+            .collect()
+    }
+
+    fn class(&self, node: Node, ty: &'static str, extra_decorators: &[AstValue]) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let heritage = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "class_heritage");
+        let mut super_class = AstValue::Null;
+        let mut super_type_arguments = None;
+        let mut implements = Vec::new();
+        if let Some(heritage) = heritage {
+            for clause in named_children(heritage) {
+                match clause.kind() {
+                    "extends_clause" => {
+                        super_class = self.optional(clause.child_by_field_name("value"));
+                        super_type_arguments = clause.child_by_field_name("type_arguments");
+                    }
+                    "implements_clause" => {
+                        implements.extend(
+                            named_children(clause)
+                                .into_iter()
+                                .map(|ty| self.heritage("TSClassImplements", ty)),
+                        );
+                    }
+                    _ => super_class = self.convert(clause),
+                }
+            }
+        }
+        let mut props = vec![
+            ("id", self.optional(field("name"))),
+            ("superClass", super_class),
+            ("body", self.class_body(field("body"))),
+        ];
+        if self.typescript {
+            if node.kind() == "abstract_class_declaration" {
+                props.push(("abstract", true.into()));
+            }
+            self.push_type_parameters(&mut props, field("type_parameters"));
+            if let Some(arguments) = super_type_arguments {
+                props.push(("superTypeArguments", self.type_arguments(arguments)));
+            }
+            if !implements.is_empty() {
+                props.push(("implements", implements.into()));
+            }
+        }
+        let mut decorators = extra_decorators.to_vec();
+        decorators.extend(self.decorators(node));
+        if !decorators.is_empty() {
+            props.push(("decorators", decorators.into()));
+        }
+        self.make(ty, node, props)
+    }
+
+    fn class_body(&self, body: Option<Node>) -> AstValue {
+        let Some(body) = body else {
+            return AstValue::Null;
+        };
+        let members = children(body);
+        let mut converted = Vec::new();
+        let mut decorators = Vec::new();
+        let mut first_decorator = None;
+        for (index, &member) in members.iter().enumerate() {
+            if !member.is_named() {
+                continue;
+            }
+            if member.kind() == "decorator" {
+                first_decorator.get_or_insert(member);
+                decorators.push(self.convert(member));
+                continue;
+            }
+            let mut value = self.class_member(member);
+            // Field definitions end with their semicolon, which Tree-sitter leaves in the body
+            if matches!(
+                member.kind(),
+                "field_definition" | "public_field_definition"
+            ) {
+                if let Some(&semicolon) = members.get(index + 1).filter(|next| next.kind() == ";") {
+                    self.extend(&mut value, semicolon.into());
+                }
+            }
+            if !decorators.is_empty() {
+                let mut all = std::mem::take(&mut decorators);
+                if let Some(AstValue::List(own)) = value.get("decorators") {
+                    all.extend(own.iter().cloned());
+                }
+                set(&mut value, "decorators", all.into());
+            }
+            // Members start at their first decorator
+            if let Some(decorator) = first_decorator.take() {
+                value = self.rebase(value, decorator);
+            }
+            converted.push(value);
+        }
+        self.make("ClassBody", body, vec![("body", converted.into())])
+    }
+
+    fn class_member(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        match node.kind() {
+            "method_definition" | "method_signature" | "abstract_method_signature" => {
+                let (key, computed) = self.property_key(field("name"));
+                let is_static = has_token(node, "static");
+                let kind = if has_token(node, "get") {
+                    "get"
+                } else if has_token(node, "set") {
+                    "set"
+                } else if !is_static
+                    && key.get("name").and_then(AstValue::as_str) == Some("constructor")
+                    || key.get("value").and_then(AstValue::as_str) == Some("constructor")
+                {
+                    "constructor"
+                } else {
+                    "method"
+                };
+                let (ty, value_ty) = match node.kind() {
+                    "method_definition" => ("MethodDefinition", "FunctionExpression"),
+                    "method_signature" => ("MethodDefinition", "TSEmptyBodyFunctionExpression"),
+                    _ => (
+                        "TSAbstractMethodDefinition",
+                        "TSEmptyBodyFunctionExpression",
+                    ),
+                };
+                let function_start = field("type_parameters")
+                    .or_else(|| field("parameters"))
+                    .map_or(Span::from(node), Span::from);
+                let value =
+                    self.function(node, value_ty, function_start.to(without_semicolon(node)));
+                let mut props = vec![
+                    ("static", is_static.into()),
+                    ("computed", computed.into()),
+                    ("key", key),
+                    ("kind", kind.into()),
+                    ("value", value),
+                ];
+                self.push_member_modifiers(&mut props, node);
+                let decorators = self.decorators(node);
+                if !decorators.is_empty() {
+                    props.push(("decorators", decorators.into()));
+                }
+                self.make(ty, without_semicolon(node), props)
+            }
+            "field_definition" | "public_field_definition" => {
+                let (key, computed) =
+                    self.property_key(field("property").or_else(|| field("name")));
+                let is_abstract = has_token(node, "abstract");
+                let mut props = vec![
+                    ("static", has_token(node, "static").into()),
+                    ("computed", computed.into()),
+                    ("key", key),
+                    ("value", self.optional(field("value"))),
+                ];
+                if let Some(annotation) = field("type") {
+                    props.push(("typeAnnotation", self.type_annotation(annotation)));
+                }
+                self.push_member_modifiers(&mut props, node);
+                if self.typescript {
+                    props.push(("declare", has_token(node, "declare").into()));
+                    props.push(("definite", has_token(node, "!").into()));
+                }
+                let decorators = self.decorators(node);
+                if !decorators.is_empty() {
+                    props.push(("decorators", decorators.into()));
+                }
+                let ty = if is_abstract {
+                    "TSAbstractPropertyDefinition"
+                } else {
+                    "PropertyDefinition"
+                };
+                self.make(ty, node, props)
+            }
+            "class_static_block" => self.make(
+                "StaticBlock",
+                node,
+                vec![(
+                    "body",
+                    self.statements(
+                        &field("body").map(named_children).unwrap_or_default(),
+                        false,
+                    ),
+                )],
+            ),
+            "index_signature" => self.index_signature(node),
+            _ => self.convert(node),
+        }
+    }
+
+    fn push_member_modifiers(&self, props: &mut Props, node: Node) {
+        if !self.typescript {
+            return;
+        }
+        if let Some(accessibility) = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "accessibility_modifier")
+        {
+            props.push(("accessibility", self.text(accessibility).into()));
+        }
+        props.push(("optional", has_token(node, "?").into()));
+        props.push((
+            "override",
+            named_children(node)
+                .iter()
+                .any(|child| child.kind() == "override_modifier")
+                .into(),
+        ));
+        props.push(("readonly", has_token(node, "readonly").into()));
+    }
+
+    /// A property name and whether it is computed (`[key]`)
+    fn property_key(&self, node: Option<Node>) -> (AstValue, bool) {
+        match node {
+            Some(node) if node.kind() == "computed_property_name" => {
+                (self.optional(named_children(node).into_iter().next()), true)
+            }
+            Some(node) => (self.convert(node), false),
+            None => (AstValue::Null, false),
+        }
+    }
+
+    /// A member of an object literal or object pattern
+    fn property(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let property =
+            |key: AstValue, computed: bool, value: AstValue, shorthand: bool, kind: &str| {
+                vec![
+                    ("method", false.into()),
+                    ("shorthand", shorthand.into()),
+                    ("computed", computed.into()),
+                    ("key", key),
+                    ("value", value),
+                    ("kind", kind.into()),
+                ]
+            };
+        match node.kind() {
+            "pair" | "pair_pattern" => {
+                let (key, computed) = self.property_key(field("key"));
+                let value = self.optional(field("value"));
+                self.make(
+                    "Property",
+                    node,
+                    property(key, computed, value, false, "init"),
+                )
+            }
+            "shorthand_property_identifier" | "shorthand_property_identifier_pattern" => {
+                let key = self.identifier(node);
+                self.make(
+                    "Property",
+                    node,
+                    property(key.clone(), false, key, true, "init"),
+                )
+            }
+            "object_assignment_pattern" => {
+                let left = field("left");
+                let key = self.optional(left);
+                let value = self.make(
+                    "AssignmentPattern",
+                    node,
+                    vec![
+                        ("left", key.clone()),
+                        ("right", self.optional(field("right"))),
+                    ],
+                );
+                self.make("Property", node, property(key, false, value, true, "init"))
+            }
+            "method_definition" => {
+                let (key, computed) = self.property_key(field("name"));
+                let kind = if has_token(node, "get") {
+                    "get"
+                } else if has_token(node, "set") {
+                    "set"
+                } else {
+                    "init"
+                };
+                let function_start = field("type_parameters")
+                    .or_else(|| field("parameters"))
+                    .map_or(Span::from(node), Span::from);
+                let value = self.function(node, "FunctionExpression", function_start.to(node));
+                let mut props = property(key, computed, value, false, kind);
+                props[0].1 = (kind == "init").into();
+                self.make("Property", node, props)
+            }
+            _ => self.convert(node),
+        }
+    }
+
+    /// Elements of an array literal or pattern, with `null` for holes such as `[a, , b]`
+    fn elements(&self, node: Node) -> AstValue {
+        let mut elements = Vec::new();
+        let mut since_separator = false;
+        for child in children(node) {
+            match child.kind() {
+                "[" | "]" => {}
+                "," => {
+                    if !since_separator {
+                        elements.push(AstValue::Null);
+                    }
+                    since_separator = false;
+                }
+                _ => {
+                    elements.push(self.convert(child));
+                    since_separator = true;
+                }
+            }
+        }
+        elements.into()
+    }
+
+    /// A member access or call, and whether it or anything before it in the chain is optional
+    /// (`?.`), which makes the whole chain a `ChainExpression`
+    fn chain_element(&self, node: Node) -> (AstValue, bool) {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            let field = |name| node.child_by_field_name(name);
+            let inner = field("object").or_else(|| field("function"));
+            let (object, inner_optional) = match inner {
+                Some(inner)
+                    if matches!(
+                        inner.kind(),
+                        "member_expression" | "subscript_expression" | "call_expression"
+                    ) =>
+                {
+                    self.chain_element(inner)
+                }
+                _ => (self.optional(inner), false),
+            };
+            let optional = children(node)
+                .iter()
+                .any(|child| child.kind() == "optional_chain" || child.kind() == "?.");
+            let value = match node.kind() {
+                "member_expression" => self.make(
+                    "MemberExpression",
+                    node,
+                    vec![
+                        ("object", object),
+                        ("property", self.optional(field("property"))),
+                        ("computed", false.into()),
+                        ("optional", optional.into()),
+                    ],
+                ),
+                "subscript_expression" => self.make(
+                    "MemberExpression",
+                    node,
+                    vec![
+                        ("object", object),
+                        ("property", self.optional(field("index"))),
+                        ("computed", true.into()),
+                        ("optional", optional.into()),
+                    ],
+                ),
+                _ => {
+                    let arguments = field("arguments");
+                    if inner.is_some_and(|inner| inner.kind() == "import") {
+                        let mut arguments = arguments.map(named_children).unwrap_or_default();
+                        let source = (!arguments.is_empty()).then(|| arguments.remove(0));
+                        let mut props = vec![("source", self.optional(source))];
+                        if let Some(&options) = arguments.first() {
+                            props.push(("options", self.convert(options)));
+                        }
+                        return (self.make("ImportExpression", node, props), false);
+                    }
+                    if let Some(quasi) = arguments.filter(|a| a.kind() == "template_string") {
+                        let mut props = vec![("tag", object), ("quasi", self.convert(quasi))];
+                        self.push_type_arguments(&mut props, field("type_arguments"));
+                        return (
+                            self.make("TaggedTemplateExpression", node, props),
+                            inner_optional,
+                        );
+                    }
+                    let mut props = vec![
+                        ("callee", object),
+                        (
+                            "arguments",
+                            self.list(arguments.map(named_children).unwrap_or_default()),
+                        ),
+                        ("optional", optional.into()),
+                    ];
+                    self.push_type_arguments(&mut props, field("type_arguments"));
+                    self.make("CallExpression", node, props)
+                }
+            };
+            (value, inner_optional || optional)
+        })
+    }
+
+    fn literal(&self, node: Node) -> AstValue {
+        let raw = self.text(node);
+        let mut props = Vec::new();
+        match node.kind() {
+            "number" => match raw.strip_suffix('n') {
+                Some(digits) => {
+                    props.push(("value", AstValue::Null));
+                    props.push(("raw", raw.into()));
+                    props.push(("bigint", bigint_digits(digits).into()));
+                    return self.make("Literal", node, props);
+                }
+                None => props.push(("value", number_value(raw))),
+            },
+            "string" => {
+                let content = &raw[1..raw.len().max(2) - 1];
+                props.push((
+                    "value",
+                    cook(content, true)
+                        .unwrap_or_else(|| content.to_string())
+                        .into(),
+                ));
+            }
+            "true" => props.push(("value", true.into())),
+            "false" => props.push(("value", false.into())),
+            "regex" => {
+                props.push(("value", AstValue::Null));
+                props.push(("raw", raw.into()));
+                let text = |name| {
+                    node.child_by_field_name(name)
+                        .map_or("", |part| self.text(part))
+                };
+                props.push((
+                    "regex",
+                    AstValue::Map(vec![
+                        ("pattern", text("pattern").into()),
+                        ("flags", text("flags").into()),
+                    ]),
+                ));
+                return self.make("Literal", node, props);
+            }
+            _ => props.push(("value", AstValue::Null)),
+        }
+        props.push(("raw", raw.into()));
+        self.make("Literal", node, props)
+    }
+
+    fn template_literal(&self, node: Node) -> AstValue {
+        let substitutions: Vec<_> = named_children(node)
+            .into_iter()
+            .filter(|child| child.kind() == "template_substitution")
+            .collect();
+        let span = Span::from(node);
+        let mut quasis = Vec::new();
+        let mut start = self.inner_start(node, 1);
+        for substitution in &substitutions {
+            quasis.push(self.template_element(start, Span::from(*substitution), false));
+            start = Span::from(*substitution).end();
+        }
+        let closing = Span {
+            start_byte: span.end_byte.saturating_sub(1).max(start.start_byte),
+            start: Point::new(span.end.row, span.end.column.saturating_sub(1)),
+            ..span
+        };
+        quasis.push(self.template_element(start, closing, true));
+        self.make(
+            "TemplateLiteral",
+            node,
+            vec![
+                (
+                    "expressions",
+                    self.list(substitutions.iter().filter_map(|substitution| {
+                        named_children(*substitution).into_iter().next()
+                    })),
+                ),
+                ("quasis", quasis.into()),
+            ],
+        )
+    }
+
+    /// The empty span `offset` bytes into `node`, which must all be on its first line
+    fn inner_start(&self, node: Node, offset: usize) -> Span {
+        let point = Point::new(
+            node.start_position().row,
+            node.start_position().column + offset,
+        );
+        Span {
+            start_byte: node.start_byte() + offset,
+            end_byte: node.start_byte() + offset,
+            start: point,
+            end: point,
+        }
+    }
+
+    /// The template text between the end of `start` and the start of `next`
+    fn template_element(&self, start: Span, next: Span, tail: bool) -> AstValue {
+        let span = Span {
+            start_byte: start.end_byte,
+            start: start.end,
+            end_byte: next.start_byte,
+            end: next.start,
+        };
+        let raw = &self.source[span.start_byte..span.end_byte];
+        self.make(
+            "TemplateElement",
+            span,
+            vec![
+                (
+                    "value",
+                    AstValue::Map(vec![
+                        ("raw", raw.into()),
+                        ("cooked", cook(raw, false).into()),
+                    ]),
+                ),
+                ("tail", tail.into()),
+            ],
+        )
+    }
+
+    fn import_statement(&self, node: Node) -> AstValue {
+        let parts = named_children(node);
+        if let Some(require) = parts
+            .iter()
+            .find(|part| part.kind() == "import_require_clause")
+        {
+            let source = require.child_by_field_name("source");
+            let reference = self.make(
+                "TSExternalModuleReference",
+                Span::from(
+                    children(*require)
+                        .into_iter()
+                        .find(|token| token.kind() == "require")
+                        .unwrap_or(*require),
+                )
+                .to(*require),
+                vec![("expression", self.optional(source))],
+            );
+            return self.make(
+                "TSImportEqualsDeclaration",
+                node,
+                vec![
+                    (
+                        "id",
+                        self.optional(
+                            named_children(*require)
+                                .into_iter()
+                                .find(|c| c.kind() == "identifier"),
+                        ),
+                    ),
+                    ("moduleReference", reference),
+                    ("importKind", "value".into()),
+                ],
+            );
+        }
+        let mut specifiers = Vec::new();
+        for clause in parts.iter().filter(|part| part.kind() == "import_clause") {
+            for item in named_children(*clause) {
+                match item.kind() {
+                    "identifier" => specifiers.push(self.make(
+                        "ImportDefaultSpecifier",
+                        item,
+                        vec![("local", self.identifier(item))],
+                    )),
+                    "namespace_import" => specifiers.push(self.make(
+                        "ImportNamespaceSpecifier",
+                        item,
+                        vec![(
+                            "local",
+                            self.optional(named_children(item).into_iter().next()),
+                        )],
+                    )),
+                    "named_imports" => {
+                        for specifier in named_children(item) {
+                            let imported = self.optional(specifier.child_by_field_name("name"));
+                            let local = specifier
+                                .child_by_field_name("alias")
+                                .map_or_else(|| imported.clone(), |alias| self.convert(alias));
+                            let mut props = vec![("imported", imported), ("local", local)];
+                            if self.typescript {
+                                props.push(("importKind", type_or_value(specifier).into()));
+                            }
+                            specifiers.push(self.make("ImportSpecifier", specifier, props));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut props = vec![
+            ("specifiers", specifiers.into()),
+            ("source", self.optional(node.child_by_field_name("source"))),
+        ];
+        if self.typescript {
+            props.push(("importKind", type_or_value(node).into()));
+        }
+        self.make("ImportDeclaration", node, props)
+    }
+
+    fn export_statement(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let decorators = self.decorators(node);
+        let declaration = |declaration: Node| match declaration.kind() {
+            "class_declaration" | "abstract_class_declaration" => {
+                self.class(declaration, "ClassDeclaration", &decorators)
+            }
+            _ => self.convert(declaration),
+        };
+        let source = self.optional(field("source"));
+
+        if has_token(node, "default") {
+            let value = field("declaration").or_else(|| field("value")).or_else(|| {
+                named_children(node)
+                    .into_iter()
+                    .find(|child| child.kind() != "decorator")
+            });
+            let declaration = match value {
+                Some(value)
+                    if matches!(value.kind(), "function_expression" | "generator_function") =>
+                {
+                    let mut function = self.convert(value);
+                    set(&mut function, "type", "FunctionDeclaration".into());
+                    function
+                }
+                Some(value) if value.kind() == "class" => {
+                    self.class(value, "ClassDeclaration", &decorators)
+                }
+                Some(value) => declaration(value),
+                None => AstValue::Null,
+            };
+            let mut props = vec![("declaration", declaration)];
+            if self.typescript {
+                props.push(("exportKind", "value".into()));
+            }
+            return self.make("ExportDefaultDeclaration", node, props);
+        }
+        if self.typescript && has_token(node, "=") {
+            return self.make(
+                "TSExportAssignment",
+                node,
+                vec![(
+                    "expression",
+                    self.optional(named_children(node).into_iter().next()),
+                )],
+            );
+        }
+        if self.typescript && has_token(node, "namespace") && has_token(node, "as") {
+            return self.make(
+                "TSNamespaceExportDeclaration",
+                node,
+                vec![("id", self.optional(named_children(node).into_iter().next()))],
+            );
+        }
+        let namespace = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "namespace_export");
+        if has_token(node, "*") || namespace.is_some() {
+            let exported =
+                namespace.and_then(|namespace| named_children(namespace).into_iter().next());
+            let mut props = vec![("exported", self.optional(exported)), ("source", source)];
+            if self.typescript {
+                props.push(("exportKind", type_or_value(node).into()));
+            }
+            return self.make("ExportAllDeclaration", node, props);
+        }
+
+        let mut specifiers = Vec::new();
+        if let Some(clause) = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "export_clause")
+        {
+            for specifier in named_children(clause) {
+                let local = self.optional(specifier.child_by_field_name("name"));
+                let exported = specifier
+                    .child_by_field_name("alias")
+                    .map_or_else(|| local.clone(), |alias| self.convert(alias));
+                let mut props = vec![("local", local), ("exported", exported)];
+                if self.typescript {
+                    props.push(("exportKind", type_or_value(specifier).into()));
+                }
+                specifiers.push(self.make("ExportSpecifier", specifier, props));
+            }
+        }
+        let mut props = vec![
+            (
+                "declaration",
+                field("declaration").map_or(AstValue::Null, declaration),
+            ),
+            ("specifiers", specifiers.into()),
+            ("source", source),
+        ];
+        if self.typescript {
+            props.push(("exportKind", type_or_value(node).into()));
+        }
+        self.make("ExportNamedDeclaration", node, props)
+    }
+
+    fn jsx_element(&self, node: Node) -> AstValue {
+        if node.kind() == "jsx_self_closing_element" {
+            let opening = self.jsx_opening(node, true);
+            return self.make(
+                "JSXElement",
+                node,
+                vec![
+                    ("openingElement", opening),
+                    ("closingElement", AstValue::Null),
+                    ("children", AstValue::List(Vec::new())),
+                ],
+            );
+        }
+        let open = node.child_by_field_name("open_tag");
+        let close = node.child_by_field_name("close_tag");
+        let (Some(open), Some(close)) = (open, close) else {
+            return self.unknown(node);
+        };
+
+        // Text between child elements, including whitespace, becomes JSXText
+        let mut children_values = Vec::new();
+        let mut text_start = Span::from(open).end();
+        for child in named_children(node) {
+            if child.id() == open.id()
+                || child.id() == close.id()
+                || matches!(child.kind(), "jsx_text" | "html_character_reference")
+            {
+                continue;
+            }
+            if child.start_byte() > text_start.start_byte {
+                children_values.push(self.jsx_text(text_start.to(Span::from(child).start())));
+            }
+            children_values.push(match child.kind() {
+                "jsx_expression" => self.jsx_expression(child, true),
+                _ => self.convert(child),
+            });
+            text_start = Span::from(child).end();
+        }
+        if close.start_byte() > text_start.start_byte {
+            children_values.push(self.jsx_text(text_start.to(Span::from(close).start())));
+        }
+
+        if open.child_by_field_name("name").is_none() {
+            return self.make(
+                "JSXFragment",
+                node,
+                vec![
+                    (
+                        "openingFragment",
+                        self.make("JSXOpeningFragment", open, vec![]),
+                    ),
+                    (
+                        "closingFragment",
+                        self.make("JSXClosingFragment", close, vec![]),
+                    ),
+                    ("children", children_values.into()),
+                ],
+            );
+        }
+        self.make(
+            "JSXElement",
+            node,
+            vec![
+                ("openingElement", self.jsx_opening(open, false)),
+                (
+                    "closingElement",
+                    self.make(
+                        "JSXClosingElement",
+                        close,
+                        vec![(
+                            "name",
+                            close
+                                .child_by_field_name("name")
+                                .map_or(AstValue::Null, |name| self.jsx_name(name)),
+                        )],
+                    ),
+                ),
+                ("children", children_values.into()),
+            ],
+        )
+    }
+
+    fn jsx_opening(&self, node: Node, self_closing: bool) -> AstValue {
+        let attributes = node
+            .children_by_field_name("attribute", &mut node.walk())
+            .map(|attribute| match attribute.kind() {
+                "jsx_expression" => self.make(
+                    "JSXSpreadAttribute",
+                    attribute,
+                    vec![(
+                        "argument",
+                        named_children(attribute)
+                            .into_iter()
+                            .next()
+                            .map_or(AstValue::Null, |spread| {
+                                self.optional(named_children(spread).into_iter().next())
+                            }),
+                    )],
+                ),
+                _ => {
+                    let parts = named_children(attribute);
+                    let value = parts
+                        .get(1)
+                        .map_or(AstValue::Null, |value| match value.kind() {
+                            "string" => {
+                                let raw = self.text(*value);
+                                self.make(
+                                    "Literal",
+                                    *value,
+                                    vec![
+                                        ("value", decode_entities(&raw[1..raw.len() - 1]).into()),
+                                        ("raw", raw.into()),
+                                    ],
+                                )
+                            }
+                            "jsx_expression" => self.jsx_expression(*value, true),
+                            _ => self.convert(*value),
+                        });
+                    self.make(
+                        "JSXAttribute",
+                        attribute,
+                        vec![
+                            (
+                                "name",
+                                parts
+                                    .first()
+                                    .map_or(AstValue::Null, |name| self.jsx_name(*name)),
+                            ),
+                            ("value", value),
+                        ],
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut props = vec![
+            ("attributes", attributes.into()),
+            (
+                "name",
+                node.child_by_field_name("name")
+                    .map_or(AstValue::Null, |name| self.jsx_name(name)),
+            ),
+            ("selfClosing", self_closing.into()),
+        ];
+        self.push_type_arguments(
+            &mut props,
+            named_children(node)
+                .into_iter()
+                .find(|child| child.kind() == "type_arguments"),
+        );
+        self.make("JSXOpeningElement", node, props)
+    }
+
+    fn jsx_name(&self, node: Node) -> AstValue {
+        match node.kind() {
+            "member_expression" => self.make(
+                "JSXMemberExpression",
+                node,
+                vec![
+                    (
+                        "object",
+                        node.child_by_field_name("object")
+                            .map_or(AstValue::Null, |object| self.jsx_name(object)),
+                    ),
+                    (
+                        "property",
+                        node.child_by_field_name("property")
+                            .map_or(AstValue::Null, |property| self.jsx_name(property)),
+                    ),
+                ],
+            ),
+            "jsx_namespace_name" => {
+                let parts = named_children(node);
+                self.make(
+                    "JSXNamespacedName",
+                    node,
+                    vec![
+                        (
+                            "namespace",
+                            parts
+                                .first()
+                                .map_or(AstValue::Null, |part| self.jsx_name(*part)),
+                        ),
+                        (
+                            "name",
+                            parts
+                                .get(1)
+                                .map_or(AstValue::Null, |part| self.jsx_name(*part)),
+                        ),
+                    ],
+                )
+            }
+            _ => self.make(
+                "JSXIdentifier",
+                node,
+                vec![("name", self.text(node).into())],
+            ),
+        }
+    }
+
+    /// `{...}` in JSX; `as_child` distinguishes children from attribute values and spreads
+    fn jsx_expression(&self, node: Node, as_child: bool) -> AstValue {
+        match named_children(node).into_iter().next() {
+            Some(spread) if spread.kind() == "spread_element" && as_child => self.make(
+                "JSXSpreadChild",
+                node,
+                vec![(
+                    "expression",
+                    self.optional(named_children(spread).into_iter().next()),
+                )],
+            ),
+            Some(expression) => self.make(
+                "JSXExpressionContainer",
+                node,
+                vec![("expression", self.convert(expression))],
+            ),
+            None => {
+                let inner = Span {
+                    start_byte: node.start_byte() + 1,
+                    start: Point::new(node.start_position().row, node.start_position().column + 1),
+                    end_byte: node.end_byte().saturating_sub(1),
+                    end: Point::new(
+                        node.end_position().row,
+                        node.end_position().column.saturating_sub(1),
+                    ),
+                };
+                self.make(
+                    "JSXExpressionContainer",
+                    node,
+                    vec![("expression", self.make("JSXEmptyExpression", inner, vec![]))],
+                )
+            }
+        }
+    }
+
+    fn jsx_text(&self, span: Span) -> AstValue {
+        let raw = &self.source[span.start_byte..span.end_byte];
+        self.make(
+            "JSXText",
+            span,
+            vec![("value", decode_entities(raw).into()), ("raw", raw.into())],
+        )
+    }
+
+    // TypeScript
+
+    fn push_type_parameters(&self, props: &mut Props, node: Option<Node>) {
+        if let Some(node) = node {
+            let params = named_children(node)
+                .into_iter()
+                .map(|parameter| self.type_parameter(parameter))
+                .collect::<Vec<_>>();
+            props.push((
+                "typeParameters",
+                self.make(
+                    "TSTypeParameterDeclaration",
+                    node,
+                    vec![("params", params.into())],
+                ),
+            ));
+        }
+    }
+
+    fn push_type_arguments(&self, props: &mut Props, node: Option<Node>) {
+        if let Some(node) = node {
+            props.push(("typeArguments", self.type_arguments(node)));
+        }
+    }
+
+    fn type_arguments(&self, node: Node) -> AstValue {
+        let params = named_children(node)
+            .into_iter()
+            .map(|ty| self.ts_type(ty))
+            .collect::<Vec<_>>();
+        self.make(
+            "TSTypeParameterInstantiation",
+            node,
+            vec![("params", params.into())],
+        )
+    }
+
+    fn type_parameter(&self, node: Node) -> AstValue {
+        let inner_type = |name| {
+            node.child_by_field_name(name)
+                .and_then(|wrapper| named_children(wrapper).into_iter().next())
+                .map_or(AstValue::Null, |ty| self.ts_type(ty))
+        };
+        let name = node
+            .child_by_field_name("name")
+            .map_or(AstValue::Null, |name| self.identifier(name));
+        self.make(
+            "TSTypeParameter",
+            node,
+            vec![
+                ("name", name),
+                ("constraint", inner_type("constraint")),
+                ("default", inner_type("value")),
+                ("in", has_token(node, "in").into()),
+                ("out", has_token(node, "out").into()),
+                ("const", has_token(node, "const").into()),
+            ],
+        )
+    }
+
+    /// `: T` and the other annotation forms, as a `TSTypeAnnotation` covering the colon
+    fn type_annotation(&self, node: Node) -> AstValue {
+        let ty = named_children(node)
+            .into_iter()
+            .next()
+            .map_or(AstValue::Null, |ty| self.ts_type(ty));
+        self.make("TSTypeAnnotation", node, vec![("typeAnnotation", ty)])
+    }
+
+    fn ts_type(&self, node: Node) -> AstValue {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.ts_type_node(node)
+        })
+    }
+
+    fn ts_type_node(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let types = || {
+            named_children(node)
+                .into_iter()
+                .map(|ty| self.ts_type(ty))
+                .collect::<Vec<_>>()
+        };
+        let first_type = || {
+            named_children(node)
+                .into_iter()
+                .next()
+                .map_or(AstValue::Null, |ty| self.ts_type(ty))
+        };
+        match node.kind() {
+            "predefined_type" => {
+                let ty = match self.text(node) {
+                    "any" => "TSAnyKeyword",
+                    "number" => "TSNumberKeyword",
+                    "string" => "TSStringKeyword",
+                    "boolean" => "TSBooleanKeyword",
+                    "bigint" => "TSBigIntKeyword",
+                    "symbol" => "TSSymbolKeyword",
+                    "object" => "TSObjectKeyword",
+                    "never" => "TSNeverKeyword",
+                    "unknown" => "TSUnknownKeyword",
+                    "void" => "TSVoidKeyword",
+                    "undefined" => "TSUndefinedKeyword",
+                    "unique symbol" => "TSSymbolKeyword",
+                    _ => return self.unknown(node),
+                };
+                self.make(ty, node, vec![])
+            }
+            // Tree-sitter has no keyword for `bigint`
+            "type_identifier" if self.text(node) == "bigint" => {
+                self.make("TSBigIntKeyword", node, vec![])
+            }
+            "type_identifier" | "nested_type_identifier" | "identifier" => self.make(
+                "TSTypeReference",
+                node,
+                vec![("typeName", self.entity_name(node))],
+            ),
+            "generic_type" => {
+                let mut props = vec![(
+                    "typeName",
+                    field("name").map_or(AstValue::Null, |name| self.entity_name(name)),
+                )];
+                self.push_type_arguments(&mut props, field("type_arguments"));
+                self.make("TSTypeReference", node, props)
+            }
+            "this_type" => self.make("TSThisType", node, vec![]),
+            "array_type" => self.make("TSArrayType", node, vec![("elementType", first_type())]),
+            "union_type" | "intersection_type" => {
+                let mut members = Vec::new();
+                let mut pending = vec![node];
+                while let Some(set) = pending.pop() {
+                    for child in named_children(set).into_iter().rev() {
+                        if child.kind() == node.kind() {
+                            pending.push(child);
+                        } else {
+                            members.push(child);
+                        }
+                    }
+                }
+                members.sort_by_key(|member| member.start_byte());
+                let ty = if node.kind() == "union_type" {
+                    "TSUnionType"
+                } else {
+                    "TSIntersectionType"
+                };
+                self.make(
+                    ty,
+                    node,
+                    vec![(
+                        "types",
+                        members
+                            .into_iter()
+                            .map(|member| self.ts_type(member))
+                            .collect::<Vec<_>>()
+                            .into(),
+                    )],
+                )
+            }
+            "parenthesized_type" => first_type(),
+            "literal_type" => match named_children(node).into_iter().next() {
+                Some(literal) if literal.kind() == "null" => {
+                    self.make("TSNullKeyword", node, vec![])
+                }
+                Some(literal) if literal.kind() == "undefined" => {
+                    self.make("TSUndefinedKeyword", node, vec![])
+                }
+                Some(literal) => self.make(
+                    "TSLiteralType",
+                    node,
+                    vec![("literal", self.convert(literal))],
+                ),
+                None => self.unknown(node),
+            },
+            "template_literal_type" => {
+                let parts = named_children(node);
+                let placeholders: Vec<_> = parts
+                    .iter()
+                    .filter(|part| part.kind() == "template_type")
+                    .copied()
+                    .collect();
+                let mut quasis = Vec::new();
+                let mut start = self.inner_start(node, 1);
+                for placeholder in &placeholders {
+                    quasis.push(self.template_element(start, Span::from(*placeholder), false));
+                    start = Span::from(*placeholder).end();
+                }
+                let span = Span::from(node);
+                let closing = Span {
+                    start_byte: span.end_byte - 1,
+                    start: Point::new(span.end.row, span.end.column.saturating_sub(1)),
+                    ..span
+                };
+                quasis.push(self.template_element(start, closing, true));
+                self.make(
+                    "TSTemplateLiteralType",
+                    node,
+                    vec![
+                        ("quasis", quasis.into()),
+                        (
+                            "types",
+                            placeholders
+                                .iter()
+                                .filter_map(|placeholder| {
+                                    named_children(*placeholder).into_iter().next()
+                                })
+                                .map(|ty| self.ts_type(ty))
+                                .collect::<Vec<_>>()
+                                .into(),
+                        ),
+                    ],
+                )
+            }
+            "function_type" | "constructor_type" => {
+                let return_node = field("return_type").or_else(|| field("type"));
+                let arrow = children(node)
+                    .into_iter()
+                    .find(|token| token.kind() == "=>");
+                let mut props = vec![("params", self.params(node))];
+                if let Some(return_node) = return_node {
+                    let annotation_span = arrow
+                        .map_or(Span::from(return_node), Span::from)
+                        .to(return_node);
+                    props.push((
+                        "returnType",
+                        self.make(
+                            "TSTypeAnnotation",
+                            annotation_span,
+                            vec![("typeAnnotation", self.ts_type(return_node))],
+                        ),
+                    ));
+                }
+                self.push_type_parameters(&mut props, field("type_parameters"));
+                if node.kind() == "constructor_type" {
+                    props.insert(0, ("abstract", has_token(node, "abstract").into()));
+                    self.make("TSConstructorType", node, props)
+                } else {
+                    self.make("TSFunctionType", node, props)
+                }
+            }
+            "object_type" => {
+                let members = named_children(node);
+                if let [member] = members.as_slice() {
+                    if let Some(mapped) = self.mapped_type(node, *member) {
+                        return mapped;
+                    }
+                }
+                self.make(
+                    "TSTypeLiteral",
+                    node,
+                    vec![(
+                        "members",
+                        members
+                            .into_iter()
+                            .map(|member| self.type_member(member))
+                            .collect::<Vec<_>>()
+                            .into(),
+                    )],
+                )
+            }
+            "tuple_type" => self.make(
+                "TSTupleType",
+                node,
+                vec![(
+                    "elementTypes",
+                    named_children(node)
+                        .into_iter()
+                        .map(|element| match element.kind() {
+                            "required_parameter" | "optional_parameter" => {
+                                self.named_tuple_member(element)
+                            }
+                            _ => self.ts_type(element),
+                        })
+                        .collect::<Vec<_>>()
+                        .into(),
+                )],
+            ),
+            "optional_type" => self.make(
+                "TSOptionalType",
+                node,
+                vec![("typeAnnotation", first_type())],
+            ),
+            "rest_type" => self.make("TSRestType", node, vec![("typeAnnotation", first_type())]),
+            "type_query" => self.make(
+                "TSTypeQuery",
+                node,
+                vec![(
+                    "exprName",
+                    named_children(node)
+                        .into_iter()
+                        .next()
+                        .map_or(AstValue::Null, |name| self.entity_name(name)),
+                )],
+            ),
+            "index_type_query" => self.make(
+                "TSTypeOperator",
+                node,
+                vec![
+                    ("operator", "keyof".into()),
+                    ("typeAnnotation", first_type()),
+                ],
+            ),
+            "readonly_type" => self.make(
+                "TSTypeOperator",
+                node,
+                vec![
+                    ("operator", "readonly".into()),
+                    ("typeAnnotation", first_type()),
+                ],
+            ),
+            "lookup_type" => {
+                let parts = types();
+                let mut parts = parts.into_iter();
+                self.make(
+                    "TSIndexedAccessType",
+                    node,
+                    vec![
+                        ("objectType", parts.next().unwrap_or(AstValue::Null)),
+                        ("indexType", parts.next().unwrap_or(AstValue::Null)),
+                    ],
+                )
+            }
+            "conditional_type" => {
+                let ty = |name| field(name).map_or(AstValue::Null, |ty| self.ts_type(ty));
+                self.make(
+                    "TSConditionalType",
+                    node,
+                    vec![
+                        ("checkType", ty("left")),
+                        ("extendsType", ty("right")),
+                        ("trueType", ty("consequence")),
+                        ("falseType", ty("alternative")),
+                    ],
+                )
+            }
+            "infer_type" => {
+                let parts = named_children(node);
+                let parameter_span = parts.first().map_or(Span::from(node), |name| {
+                    Span::from(*name).to(*parts.last().unwrap_or(name))
+                });
+                let parameter = self.make(
+                    "TSTypeParameter",
+                    parameter_span,
+                    vec![
+                        (
+                            "name",
+                            parts
+                                .first()
+                                .map_or(AstValue::Null, |name| self.identifier(*name)),
+                        ),
+                        (
+                            "constraint",
+                            parts
+                                .get(1)
+                                .map_or(AstValue::Null, |constraint| self.ts_type(*constraint)),
+                        ),
+                        ("in", false.into()),
+                        ("out", false.into()),
+                        ("const", false.into()),
+                    ],
+                );
+                self.make("TSInferType", node, vec![("typeParameter", parameter)])
+            }
+            "type_predicate" | "asserts" => {
+                let (parameter, ty) = if node.kind() == "asserts" {
+                    match named_children(node).into_iter().next() {
+                        Some(predicate) if predicate.kind() == "type_predicate" => (
+                            predicate.child_by_field_name("name"),
+                            predicate.child_by_field_name("type"),
+                        ),
+                        other => (other, None),
+                    }
+                } else {
+                    (field("name"), field("type"))
+                };
+                let parameter_name = match parameter {
+                    Some(this) if this.kind() == "this" => self.make("TSThisType", this, vec![]),
+                    other => self.optional(other),
+                };
+                let type_annotation = ty.map_or(AstValue::Null, |ty| {
+                    self.make(
+                        "TSTypeAnnotation",
+                        ty,
+                        vec![("typeAnnotation", self.ts_type(ty))],
+                    )
+                });
+                self.make(
+                    "TSTypePredicate",
+                    node,
+                    vec![
+                        ("asserts", (node.kind() == "asserts").into()),
+                        ("parameterName", parameter_name),
+                        ("typeAnnotation", type_annotation),
+                    ],
+                )
+            }
+            "type_predicate_annotation" | "asserts_annotation" | "type_annotation" => first_type(),
+            _ => self.unknown(node),
+        }
+    }
+
+    /// `name: T` in a tuple type; `...name: T[]` is a rest type around the named member
+    fn named_tuple_member(&self, node: Node) -> AstValue {
+        let label = node
+            .child_by_field_name("pattern")
+            .or_else(|| node.child_by_field_name("name"));
+        let element_type = node
+            .child_by_field_name("type")
+            .and_then(|annotation| named_children(annotation).into_iter().next())
+            .map_or(AstValue::Null, |ty| self.ts_type(ty));
+        let optional = node.kind() == "optional_parameter";
+        match label.filter(|label| label.kind() == "rest_pattern") {
+            Some(rest) => {
+                let name = named_children(rest).into_iter().next();
+                let member = self.make(
+                    "TSNamedTupleMember",
+                    name.map_or(Span::from(node), Span::from).to(node),
+                    vec![
+                        ("elementType", element_type),
+                        ("label", self.optional(name)),
+                        ("optional", optional.into()),
+                    ],
+                );
+                self.make("TSRestType", node, vec![("typeAnnotation", member)])
+            }
+            None => self.make(
+                "TSNamedTupleMember",
+                node,
+                vec![
+                    ("elementType", element_type),
+                    ("label", self.optional(label)),
+                    ("optional", optional.into()),
+                ],
+            ),
+        }
+    }
+
+    /// `{ [K in T]: U }`
+    fn mapped_type(&self, node: Node, member: Node) -> Option<AstValue> {
+        if member.kind() != "index_signature" {
+            return None;
+        }
+        let clause = named_children(member)
+            .into_iter()
+            .find(|child| child.kind() == "mapped_type_clause")?;
+        let annotation = member.child_by_field_name("type");
+        let optional = annotation.map(|annotation| match annotation.kind() {
+            "opting_type_annotation" => AstValue::Bool(true),
+            "adding_type_annotation" => "+".into(),
+            "omitting_type_annotation" => "-".into(),
+            _ => AstValue::Bool(false),
+        });
+        let sign = member.child_by_field_name("sign").map(|sign| sign.kind());
+        let readonly = match (has_token(member, "readonly"), sign) {
+            (true, Some(sign)) => sign.into(),
+            (readonly, _) => readonly.into(),
+        };
+        let type_annotation = annotation
+            .and_then(|annotation| named_children(annotation).into_iter().next())
+            .map_or(AstValue::Null, |ty| self.ts_type(ty));
+        Some(self.make(
+            "TSMappedType",
+            node,
+            vec![
+                (
+                    "key",
+                    clause
+                        .child_by_field_name("name")
+                        .map_or(AstValue::Null, |name| self.identifier(name)),
+                ),
+                (
+                    "constraint",
+                    clause
+                        .child_by_field_name("type")
+                        .map_or(AstValue::Null, |ty| self.ts_type(ty)),
+                ),
+                (
+                    "nameType",
+                    clause
+                        .child_by_field_name("alias")
+                        .map_or(AstValue::Null, |ty| self.ts_type(ty)),
+                ),
+                ("typeAnnotation", type_annotation),
+                ("optional", optional.unwrap_or(AstValue::Bool(false))),
+                ("readonly", readonly),
+            ],
+        ))
+    }
+
+    /// A member of an interface body or object type
+    fn type_member(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        match node.kind() {
+            "property_signature" => {
+                let (key, computed) = self.property_key(field("name"));
+                let mut props = vec![
+                    ("computed", computed.into()),
+                    ("key", key),
+                    ("optional", has_token(node, "?").into()),
+                    ("readonly", has_token(node, "readonly").into()),
+                    ("static", false.into()),
+                ];
+                if let Some(annotation) = field("type") {
+                    props.push(("typeAnnotation", self.type_annotation(annotation)));
+                }
+                self.make("TSPropertySignature", without_semicolon(node), props)
+            }
+            "method_signature" => {
+                let (key, computed) = self.property_key(field("name"));
+                let kind = if has_token(node, "get") {
+                    "get"
+                } else if has_token(node, "set") {
+                    "set"
+                } else {
+                    "method"
+                };
+                let mut props = vec![
+                    ("computed", computed.into()),
+                    ("key", key),
+                    ("kind", kind.into()),
+                    ("optional", has_token(node, "?").into()),
+                    ("params", self.params(node)),
+                    ("readonly", false.into()),
+                    ("static", false.into()),
+                ];
+                if let Some(return_type) = field("return_type") {
+                    props.push(("returnType", self.type_annotation(return_type)));
+                }
+                self.push_type_parameters(&mut props, field("type_parameters"));
+                self.make("TSMethodSignature", without_semicolon(node), props)
+            }
+            "call_signature" | "construct_signature" => {
+                let mut props = vec![("params", self.params(node))];
+                if let Some(return_type) = field("return_type").or_else(|| field("type")) {
+                    props.push(("returnType", self.type_annotation(return_type)));
+                }
+                self.push_type_parameters(&mut props, field("type_parameters"));
+                let ty = if node.kind() == "call_signature" {
+                    "TSCallSignatureDeclaration"
+                } else {
+                    "TSConstructSignatureDeclaration"
+                };
+                self.make(ty, without_semicolon(node), props)
+            }
+            "index_signature" => self.index_signature(node),
+            _ => self.convert(node),
+        }
+    }
+
+    fn index_signature(&self, node: Node) -> AstValue {
+        let name = node.child_by_field_name("name");
+        let index_type = node.child_by_field_name("index_type");
+        let parameters = match (name, index_type) {
+            (Some(name), Some(index_type)) => {
+                let colon = children(node)
+                    .into_iter()
+                    .find(|token| token.kind() == ":" && token.start_byte() > name.start_byte());
+                let annotation = self.make(
+                    "TSTypeAnnotation",
+                    colon
+                        .map_or(Span::from(index_type), Span::from)
+                        .to(index_type),
+                    vec![("typeAnnotation", self.ts_type(index_type))],
+                );
+                vec![self.make(
+                    "Identifier",
+                    Span::from(name).to(index_type),
+                    vec![
+                        ("name", self.text(name).into()),
+                        ("typeAnnotation", annotation),
+                    ],
+                )]
+            }
+            _ => Vec::new(),
+        };
+        let mut props = vec![
+            ("parameters", parameters.into()),
+            ("readonly", has_token(node, "readonly").into()),
+            ("static", has_token(node, "static").into()),
+        ];
+        if let Some(annotation) = node.child_by_field_name("type") {
+            props.push(("typeAnnotation", self.type_annotation(annotation)));
+        }
+        self.make("TSIndexSignature", without_semicolon(node), props)
+    }
+
+    /// A type or namespace name: an `Identifier`, or a `TSQualifiedName` for `a.b.c`
+    fn entity_name(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        match node.kind() {
+            "nested_type_identifier" | "nested_identifier" | "member_expression" => {
+                let left = field("module").or_else(|| field("object"));
+                let right = field("name").or_else(|| field("property"));
+                self.make(
+                    "TSQualifiedName",
+                    node,
+                    vec![
+                        (
+                            "left",
+                            left.map_or(AstValue::Null, |left| self.entity_name(left)),
+                        ),
+                        (
+                            "right",
+                            right.map_or(AstValue::Null, |right| self.identifier(right)),
+                        ),
+                    ],
+                )
+            }
+            "this" => self.make("ThisExpression", node, vec![]),
+            "identifier" | "type_identifier" | "property_identifier" => self.identifier(node),
+            _ => self.convert(node),
+        }
+    }
+
+    fn heritage(&self, ty: &'static str, node: Node) -> AstValue {
+        let (name, arguments) = match node.kind() {
+            "generic_type" => (
+                node.child_by_field_name("name"),
+                node.child_by_field_name("type_arguments"),
+            ),
+            _ => (Some(node), None),
+        };
+        let expression = name.map_or(AstValue::Null, |name| match name.kind() {
+            "nested_type_identifier" | "nested_identifier" => self.heritage_expression(name),
+            _ => self.identifier(name),
+        });
+        let mut props = vec![("expression", expression)];
+        self.push_type_arguments(&mut props, arguments);
+        self.make(ty, node, props)
+    }
+
+    /// `a.b.C` in `extends` and `implements` clauses, which typescript-estree writes as member
+    /// expressions
+    fn heritage_expression(&self, node: Node) -> AstValue {
+        let left = node
+            .child_by_field_name("module")
+            .or_else(|| node.child_by_field_name("object"));
+        let right = node
+            .child_by_field_name("name")
+            .or_else(|| node.child_by_field_name("property"));
+        match (left, right) {
+            (Some(left), Some(right)) => self.make(
+                "MemberExpression",
+                node,
+                vec![
+                    ("object", self.heritage_expression(left)),
+                    ("property", self.identifier(right)),
+                    ("computed", false.into()),
+                    ("optional", false.into()),
+                ],
+            ),
+            _ => self.identifier(node),
+        }
+    }
+
+    fn interface(&self, node: Node) -> AstValue {
+        let field = |name| node.child_by_field_name(name);
+        let extends = named_children(node)
+            .into_iter()
+            .filter(|child| child.kind() == "extends_type_clause")
+            .flat_map(named_children)
+            .map(|ty| self.heritage("TSInterfaceHeritage", ty))
+            .collect::<Vec<_>>();
+        let body = field("body").map_or(AstValue::Null, |body| {
+            self.make(
+                "TSInterfaceBody",
+                body,
+                vec![(
+                    "body",
+                    named_children(body)
+                        .into_iter()
+                        .map(|member| self.type_member(member))
+                        .collect::<Vec<_>>()
+                        .into(),
+                )],
+            )
+        });
+        let mut props = vec![
+            ("id", self.optional(field("name"))),
+            ("extends", extends.into()),
+            ("body", body),
+            ("declare", false.into()),
+        ];
+        self.push_type_parameters(&mut props, field("type_parameters"));
+        self.make("TSInterfaceDeclaration", node, props)
+    }
+
+    fn enum_declaration(&self, node: Node) -> AstValue {
+        let body = node.child_by_field_name("body");
+        let members = body.map(named_children).unwrap_or_default();
+        let members = members
+            .into_iter()
+            .map(|member| match member.kind() {
+                "enum_assignment" => {
+                    let (id, computed) = self.property_key(member.child_by_field_name("name"));
+                    self.make(
+                        "TSEnumMember",
+                        member,
+                        vec![
+                            ("computed", computed.into()),
+                            ("id", id),
+                            (
+                                "initializer",
+                                self.optional(member.child_by_field_name("value")),
+                            ),
+                        ],
+                    )
+                }
+                _ => {
+                    let (id, computed) = self.property_key(Some(member));
+                    self.make(
+                        "TSEnumMember",
+                        member,
+                        vec![("computed", computed.into()), ("id", id)],
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        let body = body.map_or(AstValue::Null, |body| {
+            self.make("TSEnumBody", body, vec![("members", members.into())])
+        });
+        self.make(
+            "TSEnumDeclaration",
+            node,
+            vec![
+                ("const", has_token(node, "const").into()),
+                ("declare", false.into()),
+                ("id", self.optional(node.child_by_field_name("name"))),
+                ("body", body),
+            ],
+        )
+    }
+
+    fn module(&self, node: Node) -> AstValue {
+        let name = node.child_by_field_name("name");
+        let kind = if node.kind() == "internal_module" {
+            "namespace"
+        } else if name
+            .is_some_and(|name| name.kind() == "identifier" && self.text(name) == "global")
+        {
+            "global"
+        } else {
+            "module"
+        };
+        let mut props = vec![(
+            "id",
+            name.map_or(AstValue::Null, |name| match name.kind() {
+                "nested_identifier" => self.entity_name(name),
+                _ => self.convert(name),
+            }),
+        )];
+        if let Some(body) = node.child_by_field_name("body") {
+            props.push((
+                "body",
+                self.make(
+                    "TSModuleBlock",
+                    body,
+                    vec![("body", self.statements(&named_children(body), false))],
+                ),
+            ));
+        }
+        props.push(("kind", kind.into()));
+        props.push(("declare", false.into()));
+        props.push(("global", (kind == "global").into()));
+        self.make("TSModuleDeclaration", node, props)
+    }
+
+    fn ambient_declaration(&self, node: Node) -> AstValue {
+        let parts = named_children(node);
+        let mut declaration = match parts.as_slice() {
+            // `declare global { ... }`
+            [block] if block.kind() == "statement_block" => {
+                let global = children(node)
+                    .into_iter()
+                    .find(|token| token.kind() == "global")
+                    .unwrap_or(node);
+                self.make(
+                    "TSModuleDeclaration",
+                    node,
+                    vec![
+                        (
+                            "id",
+                            self.make("Identifier", global, vec![("name", "global".into())]),
+                        ),
+                        (
+                            "body",
+                            self.make(
+                                "TSModuleBlock",
+                                *block,
+                                vec![("body", self.statements(&named_children(*block), false))],
+                            ),
+                        ),
+                        ("kind", "global".into()),
+                        ("declare", false.into()),
+                        ("global", true.into()),
+                    ],
+                )
+            }
+            // typescript-estree starts the declaration at `declare`
+            [declaration, ..] => self.rebase(self.convert(*declaration), node),
+            [] => return self.unknown(node),
+        };
+        set(&mut declaration, "declare", true.into());
+        declaration
+    }
+
+    /// Move the start of a converted node to the start of `node`
+    fn rebase(&self, mut value: AstValue, node: Node) -> AstValue {
+        let start = self.offset(node.start_byte(), node.start_position());
+        set(&mut value, "start", start.into());
+        if let AstValue::Map(entries) = &mut value {
+            for (key, entry) in entries.iter_mut() {
+                match (*key, entry) {
+                    ("loc", loc) => set(
+                        loc,
+                        "start",
+                        self.location(node.start_byte(), node.start_position()),
+                    ),
+                    ("range", AstValue::List(range)) => range[0] = start.into(),
+                    _ => {}
+                }
+            }
+        }
+        value
+    }
+}
+
+/// Named children, without comments and other extras; syntax errors Tree-sitter skipped over
+/// as extras are kept
+fn named_children(node: Node) -> Vec<Node> {
+    node.named_children(&mut node.walk())
+        .filter(|child| !child.is_extra() || child.is_error())
+        .collect()
+}
+
+/// All children, named or not, without comments and other extras
+fn children(node: Node) -> Vec<Node> {
+    node.children(&mut node.walk())
+        .filter(|child| !child.is_extra() || child.is_error())
+        .collect()
+}
+
+/// `node` has the anonymous token `token` as a direct child
+fn has_token(node: Node, token: &str) -> bool {
+    node.children(&mut node.walk())
+        .any(|child| !child.is_named() && child.kind() == token)
+}
+
+fn is_method(node: Node) -> bool {
+    matches!(
+        node.kind(),
+        "method_definition" | "method_signature" | "abstract_method_signature"
+    )
+}
+
+fn type_or_value(node: Node) -> &'static str {
+    if has_token(node, "type") || has_token(node, "typeof") {
+        "type"
+    } else {
+        "value"
+    }
+}
+
+/// The range of `node` without a trailing `;`
+fn without_semicolon(node: Node) -> Span {
+    let tokens = children(node);
+    match tokens.as_slice() {
+        [.., before, last] if last.kind() == ";" => Span::from(node).to(*before),
+        _ => node.into(),
+    }
+}
+
+/// Set `key` on a map, replacing an existing entry
+fn set(value: &mut AstValue, key: &'static str, new: AstValue) {
+    if let AstValue::Map(entries) = value {
+        match entries.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, existing)) => *existing = new,
+            None => entries.push((key, new)),
+        }
+    }
+}
+
+/// The value of a numeric literal as a JSON number, with integers written without a fraction
+fn number_value(raw: &str) -> AstValue {
+    let digits = raw.replace('_', "");
+    let radix = |prefix: &[&str]| prefix.iter().find_map(|prefix| digits.strip_prefix(prefix));
+    let value = if let Some(hex) = radix(&["0x", "0X"]) {
+        u64::from_str_radix(hex, 16).ok().map(|n| n as f64)
+    } else if let Some(octal) = radix(&["0o", "0O"]) {
+        u64::from_str_radix(octal, 8).ok().map(|n| n as f64)
+    } else if let Some(binary) = radix(&["0b", "0B"]) {
+        u64::from_str_radix(binary, 2).ok().map(|n| n as f64)
+    } else if digits.len() > 1
+        && digits.starts_with('0')
+        && digits.bytes().all(|b| (b'0'..=b'7').contains(&b))
+    {
+        // Legacy octal, e.g. `0755`
+        u64::from_str_radix(&digits[1..], 8).ok().map(|n| n as f64)
+    } else {
+        digits.parse::<f64>().ok()
+    };
+    match value {
+        Some(value) if value.fract() == 0.0 && value.abs() < 9_007_199_254_740_992.0 => {
+            AstValue::Number((value as i64).into())
+        }
+        Some(value) => serde_json::Number::from_f64(value).map_or(AstValue::Null, AstValue::Number),
+        None => AstValue::Null,
+    }
+}
+
+/// The decimal digits of a BigInt literal, which ESTree gives as `bigint`
+fn bigint_digits(digits: &str) -> String {
+    let digits = digits.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => return digits,
+    };
+    // Digits beyond u128 are left as written
+    u128::from_str_radix(&digits[2..], radix).map_or(digits, |value| value.to_string())
+}
+
+/// The value of a string or template literal's text, with escape sequences resolved, or `None`
+/// if it holds an invalid escape (templates) or an escape Rust strings cannot hold
+fn cook(raw: &str, allow_octal: bool) -> Option<String> {
+    let mut cooked = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    let mut pending_high_surrogate: Option<u32> = None;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if pending_high_surrogate.take().is_some() {
+                cooked.push(char::REPLACEMENT_CHARACTER);
+            }
+            // Template literals normalize line endings
+            if c == '\r' && !allow_octal {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                cooked.push('\n');
+            } else {
+                cooked.push(c);
+            }
+            continue;
+        }
+        let escape = chars.next()?;
+        let code_point = match escape {
+            'n' => '\n' as u32,
+            'r' => '\r' as u32,
+            't' => '\t' as u32,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '0' if !chars.peek().is_some_and(char::is_ascii_digit) => 0,
+            '1'..='7' | '0' if allow_octal => {
+                let mut value = escape.to_digit(8)?;
+                let max_digits = if escape <= '3' { 3 } else { 2 };
+                for _ in 1..max_digits {
+                    match chars.peek().and_then(|d| d.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                value
+            }
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                (hex.len() == 2).then(|| u32::from_str_radix(&hex, 16).ok())??
+            }
+            'u' => {
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    let hex: String = chars.by_ref().take_while(|&d| d != '}').collect();
+                    u32::from_str_radix(&hex, 16).ok()?
+                } else {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    (hex.len() == 4).then(|| u32::from_str_radix(&hex, 16).ok())??
+                }
+            }
+            // Line continuations
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => continue,
+            '1'..='9' => return None,
+            other => other as u32,
+        };
+        match (pending_high_surrogate.take(), code_point) {
+            (Some(high), 0xDC00..=0xDFFF) => {
+                let combined = 0x10000 + ((high - 0xD800) << 10) + (code_point - 0xDC00);
+                cooked.push(char::from_u32(combined)?);
+            }
+            (high, 0xD800..=0xDBFF) => {
+                if high.is_some() {
+                    cooked.push(char::REPLACEMENT_CHARACTER);
+                }
+                pending_high_surrogate = Some(code_point);
+            }
+            (high, code_point) => {
+                if high.is_some() {
+                    cooked.push(char::REPLACEMENT_CHARACTER);
+                }
+                cooked.push(char::from_u32(code_point).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+        }
+    }
+    if pending_high_surrogate.is_some() {
+        cooked.push(char::REPLACEMENT_CHARACTER);
+    }
+    Some(cooked)
+}
+
+/// Resolve the HTML character references JSX text and attribute strings may contain
+fn decode_entities(raw: &str) -> String {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| {
+                let name = &rest[1..end + 1];
+                let c = match name {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    "copy" => Some('©'),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    _ => name
+                        .strip_prefix("#x")
+                        .or_else(|| name.strip_prefix("#X"))
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .or_else(|| name.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                        .and_then(char::from_u32),
+                };
+                c.map(|c| (c, end + 2))
+            });
+        match entity {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use tree_sitter::Parser;
+
+    fn estree(filename: &str, source: &str, options: &TreeOptions) -> AstValue {
+        let encodings = create_encodings();
+        let encoding = encodings.match_file(filename).unwrap();
+        let mut parser = Parser::new();
+        parser.set_language(encoding.language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        to_estree(encoding.name(), source, tree.root_node(), options)
+    }
+
+    #[test]
+    fn test_number_values() {
+        let number = |raw| serde_json::to_string(&number_value(raw)).unwrap();
+        assert_eq!(number("42"), "42");
+        assert_eq!(number("1_000"), "1000");
+        assert_eq!(number("0x1F"), "31");
+        assert_eq!(number("0o17"), "15");
+        assert_eq!(number("0b101"), "5");
+        assert_eq!(number("0755"), "493");
+        assert_eq!(number("089"), "89");
+        assert_eq!(number("1.5e3"), "1500");
+        assert_eq!(number(".25"), "0.25");
+        assert_eq!(bigint_digits("0xff"), "255");
+        assert_eq!(bigint_digits("1_0"), "10");
+    }
+
+    #[test]
+    fn test_cooked_strings() {
+        assert_eq!(cook(r"a\tb\n", true).unwrap(), "a\tb\n");
+        assert_eq!(cook(r"\x41B\u{43}", true).unwrap(), "ABC");
+        assert_eq!(cook(r"😀", true).unwrap(), "😀");
+        assert_eq!(cook(r"\uD83D", true).unwrap(), "\u{FFFD}");
+        assert_eq!(cook(r"\101\0", true).unwrap(), "A\0");
+        assert_eq!(cook("a\\\nb", true).unwrap(), "ab");
+        assert_eq!(cook(r"\q", true).unwrap(), "q");
+        // Templates reject legacy octal escapes and normalize line endings
+        assert_eq!(cook(r"\101", false), None);
+        assert_eq!(cook("a\r\nb", false).unwrap(), "a\nb");
+        assert_eq!(cook(r"\u{zz}", false), None);
+    }
+
+    #[test]
+    fn test_jsx_entities() {
+        assert_eq!(decode_entities("a &amp; b &lt;c&gt;"), "a & b <c>");
+        assert_eq!(decode_entities("&#65;&#x42;&nbsp;"), "AB\u{a0}");
+        assert_eq!(decode_entities("AT&T &unknown; &"), "AT&T &unknown; &");
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        let source = "'é😀'; x";
+        let utf8 = estree("a.js", source, &TreeOptions::default());
+        let utf16 = estree(
+            "a.js",
+            source,
+            &TreeOptions {
+                column_encoding: ColumnEncoding::Utf16,
+                ..TreeOptions::default()
+            },
+        );
+        let identifier_start = |program: &AstValue| {
+            let AstValue::List(body) = program.get("body").unwrap() else {
+                panic!("Program has no body");
+            };
+            body[1].get("expression").unwrap().get("start").cloned()
+        };
+        assert_eq!(identifier_start(&utf8), Some(AstValue::from(10)));
+        assert_eq!(identifier_start(&utf16), Some(AstValue::from(7)));
+    }
+
+    #[test]
+    fn test_syntax_errors_become_unknown_nodes() {
+        let program = estree("a.js", "let x = 1;\n)\n", &TreeOptions::default());
+        let json = serde_json::to_string(&program).unwrap();
+        assert!(json.contains(r#""type":"Unknown""#), "{}", json);
+        assert!(json.contains(r#""kind":"ERROR""#), "{}", json);
+        assert!(json.contains(r#""type":"VariableDeclaration""#), "{}", json);
+    }
+}
@@ -0,0 +1,298 @@
+//! Graphviz DOT and Mermaid diagrams of syntax trees
+//!
+//! Each node becomes a box labeled with its kind, plus the source text for leaves, and field
+//! names label the edges to the children that fill them. Trees get large quickly, so
+//! `TreeOptions::graph_root` and `max_depth` are the usual way to keep a diagram readable.
+use crate::error::Result;
+use crate::json::TreeOptions;
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use std::io::Write;
+use tree_sitter::{Node, Point};
+
+/// Leaf text longer than this many characters is cut short in labels
+const MAX_LABEL_TEXT_CHARS: usize = 24;
+
+/// Diagram language to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphStyle {
+    Dot,
+    Mermaid,
+}
+
+/// Write the tree under `root` as a top-down diagram
+///
+/// With `graph_root` set, only the smallest named node containing that position and its
+/// descendants are drawn. Children pruned by depth limits or size budgets are summarized by a
+/// single dashed node.
+pub fn write_graph(
+    out: &mut dyn Write,
+    style: GraphStyle,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<()> {
+    let root = match options.graph_root {
+        Some(position) => {
+            let point = Point::new(position.row, position.column);
+            root.named_descendant_for_point_range(point, point)
+                .unwrap_or(root)
+        }
+        None => root,
+    };
+
+    match style {
+        GraphStyle::Dot => {
+            writeln!(out, "digraph tree {{")?;
+            writeln!(out, "  node [shape=box, fontname=\"monospace\"];")?;
+            writeln!(out, "  edge [fontname=\"monospace\", fontsize=10];")?;
+        }
+        GraphStyle::Mermaid => writeln!(out, "graph TD")?,
+    }
+    let mut writer = GraphWriter {
+        out: &mut *out,
+        style,
+        source_code,
+        parents: Vec::new(),
+        next_id: 0,
+    };
+    walk_tree(root, options, &mut writer)?;
+    if style == GraphStyle::Dot {
+        writeln!(out, "}}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+struct GraphWriter<'a> {
+    out: &'a mut dyn Write,
+    style: GraphStyle,
+    source_code: &'a str,
+    /// Ids of the nodes whose children are being visited
+    parents: Vec<usize>,
+    next_id: usize,
+}
+
+impl GraphWriter<'_> {
+    fn add_node(&mut self, label: &str, class: NodeClass) -> Result<usize> {
+        let id = self.next_id;
+        self.next_id += 1;
+        match self.style {
+            GraphStyle::Dot => {
+                let attributes = match class {
+                    NodeClass::Normal => "",
+                    NodeClass::Error => ", color=red, fontcolor=red",
+                    NodeClass::Omitted => ", style=dashed",
+                };
+                writeln!(
+                    self.out,
+                    "  n{} [label=\"{}\"{}];",
+                    id,
+                    escape_dot(label),
+                    attributes
+                )?;
+            }
+            GraphStyle::Mermaid => {
+                writeln!(self.out, "  n{}[\"{}\"]", id, escape_mermaid(label))?;
+                match class {
+                    NodeClass::Normal => {}
+                    NodeClass::Error => writeln!(self.out, "  style n{} stroke:red,color:red", id)?,
+                    NodeClass::Omitted => writeln!(self.out, "  style n{} stroke-dasharray:4", id)?,
+                }
+            }
+        }
+        Ok(id)
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, label: Option<&str>) -> Result<()> {
+        match (self.style, label) {
+            (GraphStyle::Dot, Some(label)) => writeln!(
+                self.out,
+                "  n{} -> n{} [label=\"{}\"];",
+                from,
+                to,
+                escape_dot(label)
+            )?,
+            (GraphStyle::Dot, None) => writeln!(self.out, "  n{} -> n{};", from, to)?,
+            (GraphStyle::Mermaid, Some(label)) => writeln!(
+                self.out,
+                "  n{} -->|\"{}\"| n{}",
+                from,
+                escape_mermaid(label),
+                to
+            )?,
+            (GraphStyle::Mermaid, None) => writeln!(self.out, "  n{} --> n{}", from, to)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum NodeClass {
+    Normal,
+    Error,
+    Omitted,
+}
+
+impl<'tree> TreeVisitor<'tree> for GraphWriter<'_> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        let node = visited.node;
+        let mut label = if node.is_missing() {
+            format!("MISSING {}", node.kind())
+        } else {
+            node.kind().to_string()
+        };
+        // Anonymous leaves such as `(` are labeled by their kind, which already is their text
+        if visited.is_leaf && node.is_named() {
+            let text = &self.source_code[node.start_byte()..node.end_byte()];
+            label.push('\n');
+            label.push_str(&shorten(text));
+        }
+        let class = if node.is_error() || node.is_missing() {
+            NodeClass::Error
+        } else {
+            NodeClass::Normal
+        };
+
+        let id = self.add_node(&label, class)?;
+        if let Some(&parent) = self.parents.last() {
+            self.add_edge(parent, id, visited.field_name)?;
+        }
+        self.parents.push(id);
+        Ok(())
+    }
+
+    fn leave_node(&mut self, _visited: &VisitedNode<'tree>, omitted: usize) -> Result<()> {
+        let id = self.parents.pop().expect("entered nodes are on the stack");
+        if omitted > 0 {
+            let label = format!(
+                "{} more node{}",
+                omitted,
+                if omitted == 1 { "" } else { "s" }
+            );
+            let omitted_id = self.add_node(&label, NodeClass::Omitted)?;
+            self.add_edge(id, omitted_id, None)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote `text` like a string literal, cut to `MAX_LABEL_TEXT_CHARS` characters
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(MAX_LABEL_TEXT_CHARS) {
+        Some((end, _)) => format!("{:?}…", &text[..end]),
+        None => format!("{:?}", text),
+    }
+}
+
+/// Escape a label for a double-quoted DOT string, keeping line breaks as `\n`
+fn escape_dot(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape a label for a double-quoted Mermaid string, using Mermaid's `#code;` entities
+fn escape_mermaid(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("#quot;"),
+            '#' => escaped.push_str("#35;"),
+            '<' => escaped.push_str("#lt;"),
+            '>' => escaped.push_str("#gt;"),
+            '\n' => escaped.push_str("<br/>"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Position;
+    use tree_sitter::{Parser, Tree};
+
+    fn parse_rust(code: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    fn graph(code: &str, style: GraphStyle, options: &TreeOptions) -> String {
+        let tree = parse_rust(code);
+        let mut out = Vec::new();
+        write_graph(&mut out, style, code, tree.root_node(), options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_dot_labels_nodes_and_field_edges() {
+        let options = TreeOptions {
+            named_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            graph("fn main() {}", GraphStyle::Dot, &options),
+            r#"digraph tree {
+  node [shape=box, fontname="monospace"];
+  edge [fontname="monospace", fontsize=10];
+  n0 [label="source_file"];
+  n1 [label="function_item"];
+  n0 -> n1;
+  n2 [label="identifier\n\"main\""];
+  n1 -> n2 [label="name"];
+  n3 [label="parameters\n\"()\""];
+  n1 -> n3 [label="parameters"];
+  n4 [label="block\n\"{}\""];
+  n1 -> n4 [label="body"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_mermaid_selects_subtree_and_summarizes_pruned_children() {
+        let code = "fn main() {}\nfn other(a: u8) {}\n";
+        let options = TreeOptions {
+            named_only: true,
+            max_depth: Some(1),
+            graph_root: Some(Position { row: 1, column: 0 }),
+            ..Default::default()
+        };
+        assert_eq!(
+            graph(code, GraphStyle::Mermaid, &options),
+            r#"graph TD
+  n0["function_item"]
+  n1["identifier<br/>#quot;other#quot;"]
+  n0 -->|"name"| n1
+  n2["parameters"]
+  n0 -->|"parameters"| n2
+  n3["3 more nodes"]
+  style n3 stroke-dasharray:4
+  n2 --> n3
+  n4["block<br/>#quot;{}#quot;"]
+  n0 -->|"body"| n4
+"#
+        );
+    }
+
+    #[test]
+    fn test_shorten_cuts_long_text_at_char_boundary() {
+        let text = "é".repeat(MAX_LABEL_TEXT_CHARS + 1);
+        assert_eq!(
+            shorten(&text),
+            format!("{:?}…", "é".repeat(MAX_LABEL_TEXT_CHARS))
+        );
+        assert_eq!(shorten("a\nb"), "\"a\\nb\"");
+    }
+}
@@ -1,36 +1,296 @@
-use serde::Serialize;
-use tree_sitter::Node;
-
-#[derive(Serialize)]
+use crate::error::Result;
+use crate::schema::AstSchema;
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tree_sitter::{Node, Point};
+
+/// A serializable syntax tree node converted from a Tree-sitter node
+///
+/// Serde recurses once per level of nesting, so children are serialized on a stack that grows
+/// on demand and trees of any depth can be serialized on threads with small stacks.
+#[derive(Serialize, Debug)]
 #[serde(rename = "node")]
-pub(crate) struct JsonNode {
+pub struct JsonNode {
     pub kind: String,
     pub start_byte: usize,
     pub end_byte: usize,
+    pub start_point: Position,
+    pub end_point: Position,
+    /// Grammar field this node fills in its parent, e.g. `name` or `body`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_name: Option<String>,
+    /// Whether the grammar names this node, as opposed to an anonymous token (only with `include_is_named`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_named: Option<bool>,
+    /// Set on `ERROR` nodes that wrap source Tree-sitter could not parse
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+    /// Set on zero-width nodes Tree-sitter inserted in place of an expected token
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_missing: bool,
+    /// Set when some or all of this node's children were pruned by a size budget
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Number of descendants left out of a truncated node
+    #[serde(skip_serializing_if = "is_zero")]
+    pub omitted_descendants: usize,
+    /// Children that fill a grammar field, keyed by field name (only with `group_fields`); a
+    /// field always holds an array, even when a node has a single child in it
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_nested"
+    )]
+    pub fields: Option<BTreeMap<String, Vec<JsonNode>>>,
+    #[serde(serialize_with = "serialize_nested")]
     pub children: Option<Vec<JsonNode>>,
     pub text: Option<String>,
 }
 
-pub(crate) fn node_to_json(source_code: &str, node: Node) -> JsonNode {
-    let mut children = Vec::new();
-    for i in 0..node.child_count() {
-        children.push(node_to_json(source_code, node.child(i).unwrap()));
+/// Zero-based row and column of a location in the source
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// Unit used to count columns in `Position`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColumnEncoding {
+    /// Columns are UTF-8 byte offsets from the start of the line (Tree-sitter's native unit)
+    #[default]
+    Utf8,
+    /// Columns are UTF-16 code units from the start of the line, as used by LSP editors
+    Utf16,
+}
+
+/// Options controlling how Tree-sitter nodes are converted into `JsonNode`s
+#[derive(Clone, Debug, Default)]
+pub struct TreeOptions {
+    pub column_encoding: ColumnEncoding,
+    /// Move children that fill a grammar field out of `children` and into `fields`
+    pub group_fields: bool,
+    /// Drop anonymous tokens such as `(`, `;` and keywords, keeping only named nodes
+    pub named_only: bool,
+    /// Record `is_named` on every node
+    pub include_is_named: bool,
+    /// Prune children of nodes at this depth (the root is at depth 0)
+    pub max_depth: Option<usize>,
+    /// Stop adding nodes once this many have been converted
+    pub max_nodes: Option<usize>,
+    /// Stop adding nodes that would take the tree's compact JSON past about this many bytes
+    pub max_output_bytes: Option<usize>,
+    /// Label S-expression children with their field names, as in `name: (identifier)`
+    pub sexp_fields: bool,
+    /// Add `[row, column] - [row, column]` ranges to S-expression nodes
+    pub sexp_ranges: bool,
+    /// Draw only the smallest named node containing this position (in UTF-8 byte columns) in
+    /// graph output
+    pub graph_root: Option<Position>,
+    /// Write the tree in this language-specific schema instead of as Tree-sitter nodes; ignored
+    /// by formats that render Tree-sitter trees directly
+    pub schema: AstSchema,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+/// Stack that must be left before serializing the next level of nodes
+pub(crate) const STACK_RED_ZONE_BYTES: usize = 128 * 1024;
+/// Size of each extra stack segment allocated once the red zone is reached
+pub(crate) const STACK_SEGMENT_BYTES: usize = 4 * 1024 * 1024;
+
+fn serialize_nested<T: Serialize, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+        value.serialize(serializer)
+    })
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    /// Parse a zero-based `ROW:COLUMN` pair
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let (row, column) = value
+            .split_once(':')
+            .ok_or_else(|| format!("expected ROW:COLUMN, got '{}'", value))?;
+        let parse = |part: &str| {
+            part.trim().parse::<usize>().map_err(|_| {
+                format!(
+                    "expected ROW:COLUMN with zero-based numbers, got '{}'",
+                    value
+                )
+            })
+        };
+        Ok(Position {
+            row: parse(row)?,
+            column: parse(column)?,
+        })
     }
-    let text_value = source_code[node.start_byte()..node.end_byte()].to_string();
-    JsonNode {
-        kind: node.kind().to_string(),
-        start_byte: node.start_byte(),
-        end_byte: node.end_byte(),
-        text: if children.is_empty() && !text_value.is_empty() {
-            Some(text_value)
-        } else {
-            None
-        },
-        children: if children.is_empty() {
-            None
-        } else {
-            Some(children)
-        },
+}
+
+impl Position {
+    pub(crate) fn from_point(
+        source_code: &str,
+        point: Point,
+        byte: usize,
+        encoding: ColumnEncoding,
+    ) -> Self {
+        let column = match encoding {
+            ColumnEncoding::Utf8 => point.column,
+            ColumnEncoding::Utf16 => source_code
+                .get(byte - point.column..byte)
+                .map_or(point.column, |line| line.encode_utf16().count()),
+        };
+        Self {
+            row: point.row,
+            column,
+        }
+    }
+}
+
+/// Convert a Tree-sitter node and all of its descendants into a `JsonNode` tree
+pub fn node_to_json(source_code: &str, node: Node) -> JsonNode {
+    node_to_json_with_options(source_code, node, &TreeOptions::default())
+}
+
+/// Convert a Tree-sitter node into a `JsonNode` tree using the given options
+///
+/// The conversion keeps its own stack, so trees of any depth can be converted on threads with
+/// small stacks.
+pub fn node_to_json_with_options(source_code: &str, node: Node, options: &TreeOptions) -> JsonNode {
+    let mut builder = JsonNodeBuilder {
+        source_code,
+        options,
+        stack: Vec::new(),
+        root: None,
+    };
+    walk_tree(node, options, &mut builder).expect("building JsonNodes cannot fail");
+    builder.root.expect("the root node was visited")
+}
+
+/// A node whose children are still being converted
+struct PartialNode {
+    node: JsonNode,
+    children: Vec<JsonNode>,
+    fields: BTreeMap<String, Vec<JsonNode>>,
+    /// The grouped field currently being filled
+    field: Option<&'static str>,
+}
+
+struct JsonNodeBuilder<'s> {
+    source_code: &'s str,
+    options: &'s TreeOptions,
+    stack: Vec<PartialNode>,
+    root: Option<JsonNode>,
+}
+
+impl JsonNodeBuilder<'_> {
+    fn position(&self, point: Point, byte: usize) -> Position {
+        Position::from_point(self.source_code, point, byte, self.options.column_encoding)
+    }
+}
+
+impl<'tree> TreeVisitor<'tree> for JsonNodeBuilder<'_> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        let node = visited.node;
+        let text = (visited.is_leaf && node.end_byte() > node.start_byte())
+            .then(|| self.source_code[node.start_byte()..node.end_byte()].to_string());
+        self.stack.push(PartialNode {
+            node: JsonNode {
+                kind: node.kind().to_string(),
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                start_point: self.position(node.start_position(), node.start_byte()),
+                end_point: self.position(node.end_position(), node.end_byte()),
+                field_name: visited.field_name.map(str::to_string),
+                is_named: self.options.include_is_named.then(|| node.is_named()),
+                is_error: node.is_error(),
+                is_missing: node.is_missing(),
+                truncated: false,
+                omitted_descendants: 0,
+                fields: None,
+                children: None,
+                text,
+            },
+            children: Vec::new(),
+            fields: BTreeMap::new(),
+            field: None,
+        });
+        Ok(())
+    }
+
+    fn enter_field(&mut self, name: &'static str) -> Result<()> {
+        if let Some(parent) = self.stack.last_mut() {
+            parent.field = Some(name);
+        }
+        Ok(())
+    }
+
+    fn leave_field(&mut self) -> Result<()> {
+        if let Some(parent) = self.stack.last_mut() {
+            parent.field = None;
+        }
+        Ok(())
+    }
+
+    fn leave_node(
+        &mut self,
+        _visited: &VisitedNode<'tree>,
+        omitted_descendants: usize,
+    ) -> Result<()> {
+        let partial = self.stack.pop().expect("node was entered");
+        let mut node = partial.node;
+        node.truncated = omitted_descendants > 0;
+        node.omitted_descendants = omitted_descendants;
+        node.children = (!partial.children.is_empty()).then_some(partial.children);
+        node.fields = (!partial.fields.is_empty()).then_some(partial.fields);
+
+        let Some(parent) = self.stack.last_mut() else {
+            self.root = Some(node);
+            return Ok(());
+        };
+        match parent.field {
+            Some(name) => parent
+                .fields
+                .entry(name.to_string())
+                .or_default()
+                .push(node),
+            None => parent.children.push(node),
+        }
+        Ok(())
+    }
+}
+
+impl JsonNode {
+    /// Move this node's children and field children out, leaving it a leaf
+    fn take_descendants(&mut self, into: &mut Vec<JsonNode>) {
+        into.extend(self.children.take().into_iter().flatten());
+        for field in self
+            .fields
+            .take()
+            .into_iter()
+            .flat_map(BTreeMap::into_values)
+        {
+            into.extend(field);
+        }
+    }
+}
+
+/// Dropping a deeply nested tree recursively would overflow the stack just like converting it,
+/// so descendants are detached onto a heap-allocated list first
+impl Drop for JsonNode {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_descendants(&mut pending);
+        while let Some(mut node) = pending.pop() {
+            node.take_descendants(&mut pending);
+        }
     }
 }
 
@@ -51,6 +311,15 @@ mod tests {
             kind: "source_file".to_string(),
             start_byte: 0,
             end_byte: 10,
+            start_point: Position { row: 0, column: 0 },
+            end_point: Position { row: 0, column: 10 },
+            field_name: None,
+            is_named: None,
+            is_error: false,
+            is_missing: false,
+            truncated: false,
+            omitted_descendants: 0,
+            fields: None,
             children: None,
             text: Some("test".to_string()),
         };
@@ -60,6 +329,8 @@ mod tests {
         assert!(serialized.contains("test"));
         assert!(serialized.contains("start_byte"));
         assert!(serialized.contains("end_byte"));
+        assert!(serialized.contains("\"start_point\":{\"row\":0,\"column\":0}"));
+        assert!(serialized.contains("\"end_point\":{\"row\":0,\"column\":10}"));
     }
 
     #[test]
@@ -188,4 +459,339 @@ mod tests {
             assert!(leaf_node.children.is_none());
         }
     }
+
+    #[test]
+    fn test_node_to_json_reports_row_and_column() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() {\n    let x = 1;\n}";
+        let tree = parser.parse(code, None).unwrap();
+        let json_node = node_to_json(code, tree.root_node());
+
+        assert_eq!(json_node.start_point, Position { row: 0, column: 0 });
+        assert_eq!(json_node.end_point, Position { row: 2, column: 1 });
+
+        let body = tree
+            .root_node()
+            .child(0)
+            .unwrap()
+            .child_by_field_name("body");
+        let statement = body.unwrap().named_child(0).unwrap();
+        let json_statement = node_to_json(code, statement);
+        assert_eq!(json_statement.kind, "let_declaration");
+        assert_eq!(json_statement.start_point, Position { row: 1, column: 4 });
+        assert_eq!(json_statement.end_point, Position { row: 1, column: 14 });
+    }
+
+    #[test]
+    fn test_node_to_json_utf16_columns() {
+        let js_language = tree_sitter_javascript::LANGUAGE.into();
+        let mut parser = setup_parser(&js_language);
+
+        // "é" is 2 UTF-8 bytes but 1 UTF-16 unit; "😀" is 4 UTF-8 bytes but 2 UTF-16 units
+        let code = "'é😀'; x;";
+        let tree = parser.parse(code, None).unwrap();
+        let statement = tree.root_node().named_child(1).unwrap();
+
+        let utf8 = node_to_json(code, statement);
+        assert_eq!(utf8.start_point.column, 10);
+
+        let options = TreeOptions {
+            column_encoding: ColumnEncoding::Utf16,
+            ..Default::default()
+        };
+        let utf16 = node_to_json_with_options(code, statement, &options);
+        assert_eq!(utf16.start_point.column, 7);
+        assert_eq!(utf16.end_point.column, 9);
+        assert_eq!(utf16.start_byte, 10);
+    }
+
+    #[test]
+    fn test_node_to_json_includes_field_names() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() {}";
+        let tree = parser.parse(code, None).unwrap();
+        let json_node = node_to_json(code, tree.root_node());
+
+        assert!(json_node.field_name.is_none());
+        let function = &json_node.children.as_ref().unwrap()[0];
+        let field_names: Vec<Option<&str>> = function
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|child| child.field_name.as_deref())
+            .collect();
+        assert_eq!(
+            field_names,
+            vec![None, Some("name"), Some("parameters"), Some("body")]
+        );
+        assert!(function.fields.is_none());
+    }
+
+    #[test]
+    fn test_node_to_json_groups_fields() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() {}";
+        let tree = parser.parse(code, None).unwrap();
+        let options = TreeOptions {
+            group_fields: true,
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+
+        let function = &json_node.children.as_ref().unwrap()[0];
+        let fields = function.fields.as_ref().unwrap();
+        assert_eq!(fields["name"].len(), 1);
+        assert_eq!(fields["name"][0].text.as_deref(), Some("main"));
+        assert!(fields.contains_key("parameters"));
+        assert!(fields.contains_key("body"));
+
+        // Only the unfielded `fn` keyword is left in children
+        let children = function.children.as_ref().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].kind, "fn");
+
+        let serialized = serde_json::to_value(function).unwrap();
+        assert_eq!(serialized["fields"]["name"][0]["kind"], "identifier");
+    }
+
+    #[test]
+    fn test_node_to_json_named_only() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() { let s = \"hi\"; }";
+        let tree = parser.parse(code, None).unwrap();
+        let options = TreeOptions {
+            named_only: true,
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+
+        fn collect_kinds(node: &JsonNode, kinds: &mut Vec<String>) {
+            kinds.push(node.kind.clone());
+            for child in node.children.iter().flatten() {
+                collect_kinds(child, kinds);
+            }
+        }
+        let mut kinds = Vec::new();
+        collect_kinds(&json_node, &mut kinds);
+        for anonymous in ["fn", "(", ")", "{", "}", "let", "=", ";", "\""] {
+            assert!(
+                !kinds.contains(&anonymous.to_string()),
+                "found {}",
+                anonymous
+            );
+        }
+        assert!(kinds.contains(&"function_item".to_string()));
+        assert!(kinds.contains(&"let_declaration".to_string()));
+
+        let function = &json_node.children.as_ref().unwrap()[0];
+        let field_names: Vec<Option<&str>> = function
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|child| child.field_name.as_deref())
+            .collect();
+        assert_eq!(
+            field_names,
+            vec![Some("name"), Some("parameters"), Some("body")]
+        );
+        // `parameters` has only anonymous children, so it becomes a leaf with text
+        assert_eq!(
+            function.children.as_ref().unwrap()[1].text.as_deref(),
+            Some("()")
+        );
+    }
+
+    #[test]
+    fn test_node_to_json_is_named_flag() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() {}";
+        let tree = parser.parse(code, None).unwrap();
+
+        let json_node = node_to_json(code, tree.root_node());
+        assert!(json_node.is_named.is_none());
+
+        let options = TreeOptions {
+            include_is_named: true,
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+        assert_eq!(json_node.is_named, Some(true));
+        let function = &json_node.children.as_ref().unwrap()[0];
+        let fn_keyword = &function.children.as_ref().unwrap()[0];
+        assert_eq!(fn_keyword.kind, "fn");
+        assert_eq!(fn_keyword.is_named, Some(false));
+    }
+
+    #[test]
+    fn test_node_to_json_flags_error_and_missing_nodes() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() { let x = 1 }";
+        let tree = parser.parse(code, None).unwrap();
+        let json_node = node_to_json(code, tree.root_node());
+
+        fn find_missing(node: &JsonNode) -> Option<&JsonNode> {
+            if node.is_missing {
+                return Some(node);
+            }
+            node.children.iter().flatten().find_map(find_missing)
+        }
+        let missing = find_missing(&json_node).unwrap();
+        assert_eq!(missing.kind, ";");
+        assert_eq!(missing.start_byte, missing.end_byte);
+        assert!(serde_json::to_string(missing)
+            .unwrap()
+            .contains("\"is_missing\":true"));
+
+        let code = "fn main() { @@ }";
+        let tree = parser.parse(code, None).unwrap();
+        let json_node = node_to_json(code, tree.root_node());
+        fn has_error(node: &JsonNode) -> bool {
+            node.is_error || node.children.iter().flatten().any(has_error)
+        }
+        assert!(has_error(&json_node));
+
+        // Clean nodes do not serialize the flags at all
+        let serialized = serde_json::to_string(&node_to_json(
+            "fn main() {}",
+            parser.parse("fn main() {}", None).unwrap().root_node(),
+        ))
+        .unwrap();
+        assert!(!serialized.contains("is_error"));
+        assert!(!serialized.contains("is_missing"));
+    }
+
+    fn count_json_nodes(node: &JsonNode) -> usize {
+        1 + node
+            .children
+            .iter()
+            .flatten()
+            .map(count_json_nodes)
+            .sum::<usize>()
+    }
+
+    fn total_nodes(node: &JsonNode) -> usize {
+        count_json_nodes(node)
+            + node.omitted_descendants
+            + node
+                .children
+                .iter()
+                .flatten()
+                .map(|child| total_nodes(child) - count_json_nodes(child))
+                .sum::<usize>()
+    }
+
+    #[test]
+    fn test_node_to_json_max_depth() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn main() { let x = 1; }";
+        let tree = parser.parse(code, None).unwrap();
+        let options = TreeOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+
+        let function = &json_node.children.as_ref().unwrap()[0];
+        assert_eq!(function.kind, "function_item");
+        assert!(function.children.is_none());
+        assert!(function.text.is_none());
+        assert!(function.truncated);
+        assert_eq!(
+            function.omitted_descendants,
+            tree.root_node().child(0).unwrap().descendant_count() - 1
+        );
+        assert!(!json_node.truncated);
+
+        let serialized = serde_json::to_value(&json_node).unwrap();
+        assert_eq!(serialized["children"][0]["truncated"], true);
+    }
+
+    #[test]
+    fn test_node_to_json_max_nodes() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn a() { let x = 1; }\nfn b() { let y = 2; }";
+        let tree = parser.parse(code, None).unwrap();
+        let options = TreeOptions {
+            max_nodes: Some(5),
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+
+        assert_eq!(count_json_nodes(&json_node), 5);
+        assert!(json_node.truncated);
+        // Every node is either emitted or accounted for in an omitted count
+        assert_eq!(total_nodes(&json_node), tree.root_node().descendant_count());
+    }
+
+    #[test]
+    fn test_node_to_json_max_nodes_named_only_counts_named_descendants() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = setup_parser(&rust_language);
+
+        let code = "fn a() { let x = 1; }";
+        let tree = parser.parse(code, None).unwrap();
+        let options = TreeOptions {
+            named_only: true,
+            max_nodes: Some(1),
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(code, tree.root_node(), &options);
+
+        assert!(json_node.children.is_none());
+        // function_item, identifier, parameters, block, let_declaration, identifier, integer_literal
+        assert_eq!(json_node.omitted_descendants, 7);
+    }
+
+    #[test]
+    fn test_node_to_json_max_output_bytes() {
+        let js_language = tree_sitter_javascript::LANGUAGE.into();
+        let mut parser = setup_parser(&js_language);
+
+        let code = "let a = 1;\n".repeat(200);
+        let tree = parser.parse(&code, None).unwrap();
+        let unlimited = serde_json::to_string(&node_to_json(&code, tree.root_node())).unwrap();
+
+        let options = TreeOptions {
+            max_output_bytes: Some(2_000),
+            ..Default::default()
+        };
+        let json_node = node_to_json_with_options(&code, tree.root_node(), &options);
+        let limited = serde_json::to_string(&json_node).unwrap();
+
+        assert!(json_node.truncated);
+        assert!(limited.len() < unlimited.len() / 10);
+        // Only the truncation markers are left out of the count
+        let markers = limited
+            .match_indices(r#","truncated":true,"omitted_descendants":"#)
+            .map(|(start, marker)| {
+                let digits = limited[start + marker.len()..]
+                    .bytes()
+                    .take_while(u8::is_ascii_digit)
+                    .count();
+                marker.len() + digits
+            })
+            .sum::<usize>();
+        assert!((1_800..=2_000).contains(&(limited.len() - markers)));
+        assert_eq!(total_nodes(&json_node), tree.root_node().descendant_count());
+        // Still a complete, valid JSON document
+        let _: serde_json::Value = serde_json::from_str(&limited).unwrap();
+    }
 }
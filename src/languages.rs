@@ -64,7 +64,7 @@ pub fn supported_languages() -> Vec<LanguageInfo> {
         },
         LanguageInfo {
             name: "TypeScript",
-            extensions: &[".ts"],
+            extensions: &[".ts", ".mts", ".cts"],
             version: TREE_SITTER_TYPESCRIPT_VERSION,
         },
         LanguageInfo {
@@ -74,7 +74,7 @@ pub fn supported_languages() -> Vec<LanguageInfo> {
         },
         LanguageInfo {
             name: "JavaScript",
-            extensions: &[".js"],
+            extensions: &[".js", ".mjs", ".cjs"],
             version: TREE_SITTER_JAVASCRIPT_VERSION,
         },
         LanguageInfo {
@@ -90,22 +90,22 @@ pub fn supported_languages() -> Vec<LanguageInfo> {
         },
         LanguageInfo {
             name: "C++",
-            extensions: &[".cpp", ".cc", ".cxx", ".hpp", ".hh"],
+            extensions: &[".cpp", ".cc", ".cxx", ".hpp", ".hh", ".h"],
             version: TREE_SITTER_CPP_VERSION,
         },
         LanguageInfo {
             name: "Bash",
-            extensions: &[".sh", ".bash"],
+            extensions: &[".sh", ".bash", ".ksh"],
             version: TREE_SITTER_BASH_VERSION,
         },
         LanguageInfo {
             name: "JSON",
-            extensions: &[".json"],
+            extensions: &[".json", ".geojson", ".topojson"],
             version: TREE_SITTER_JSON_VERSION,
         },
         LanguageInfo {
             name: "HTML",
-            extensions: &[".html", ".htm"],
+            extensions: &[".html", ".htm", ".xhtml", ".shtml"],
             version: TREE_SITTER_HTML_VERSION,
         },
         LanguageInfo {
@@ -176,31 +176,33 @@ pub fn create_encodings() -> encodings::Encodings<'static> {
     let graphql_lang = GRAPHQL_LANGUAGE.get_or_init(|| tree_sitter_graphql::LANGUAGE.into());
 
     let mut enc = encodings::Encodings::new();
-    enc.add("rs$", rust_lang, "Rust")
-        .add("java$", java_lang, "Java")
-        .add("cs$", csharp_lang, "C#")
-        .add("go$", go_lang, "Go")
-        .add("py$", python_lang, "Python")
-        .add("ts$", typescript_lang, "TypeScript")
-        .add("tsx$", tsx_lang, "TSX")
-        .add("js$", javascript_lang, "JavaScript")
-        .add("rb$", ruby_lang, "Ruby")
+    // Patterns match the whole extension, or the whole name of a file without one
+    enc.add("^rs$", rust_lang, "Rust")
+        .add("^java$", java_lang, "Java")
+        .add("^cs$", csharp_lang, "C#")
+        .add("^go$", go_lang, "Go")
+        .add("^py$", python_lang, "Python")
+        .add("^(ts|mts|cts)$", typescript_lang, "TypeScript")
+        .add("^tsx$", tsx_lang, "TSX")
+        .add("^(js|mjs|cjs)$", javascript_lang, "JavaScript")
+        .add("^rb$", ruby_lang, "Ruby")
         // High priority new encodings
-        .add("c$", c_lang, "C")
-        .add("h$", c_lang, "C")
-        .add("(cpp|cc|cxx)$", cpp_lang, "C++")
-        .add("(hpp|hh|hxx)$", cpp_lang, "C++")
-        .add("(sh|bash)$", bash_lang, "Bash")
-        .add("json$", json_lang, "JSON")
-        .add("(html|htm)$", html_lang, "HTML")
-        .add("css$", css_lang, "CSS")
-        .add("(ya?ml)$", yaml_lang, "YAML")
+        .add("^c$", c_lang, "C")
+        .add("^h$", c_lang, "C")
+        .add("^(cpp|cc|cxx)$", cpp_lang, "C++")
+        // `.h` is both C and C++; `Encodings::detect_with_source` chooses between them by content
+        .add("^(hpp|hh|hxx|h)$", cpp_lang, "C++")
+        .add("^(sh|bash|ksh)$", bash_lang, "Bash")
+        .add("^(json|geojson|topojson)$", json_lang, "JSON")
+        .add("^(html|htm|xhtml|shtml)$", html_lang, "HTML")
+        .add("^css$", css_lang, "CSS")
+        .add("^(ya?ml)$", yaml_lang, "YAML")
         // Medium priority new encodings
-        .add("swift$", swift_lang, "Swift")
-        .add("scala$", scala_lang, "Scala")
-        .add("lua$", lua_lang, "Lua")
-        .add("(hcl|tf|tfvars)$", hcl_lang, "HCL")
-        .add("(graphql|gql)$", graphql_lang, "GraphQL");
+        .add("^swift$", swift_lang, "Swift")
+        .add("^scala$", scala_lang, "Scala")
+        .add("^lua$", lua_lang, "Lua")
+        .add("^(hcl|tf|tfvars)$", hcl_lang, "HCL")
+        .add("^(graphql|gql)$", graphql_lang, "GraphQL");
 
     enc
 }
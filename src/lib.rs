@@ -0,0 +1,62 @@
+//! astgen parses source code with Tree-sitter grammars and produces typed,
+//! serializable abstract syntax trees.
+//!
+//! The `astgen` binary is a thin command line front end over this library, so
+//! anything the CLI can do is also available to Rust callers:
+//!
+//! ```no_run
+//! use astgen::{create_encodings, parse_file, TreeOptions};
+//!
+//! let encodings = create_encodings();
+//! let encoding = encodings.match_file("src/main.rs").expect("Rust is supported");
+//! let parsed =
+//!     parse_file("src/main.rs".as_ref(), encoding, 10_000_000, &TreeOptions::default()).unwrap();
+//! println!("{} has a {} root", parsed.filename, parsed.ast.kind);
+//! ```
+
+pub mod binary;
+pub mod config;
+pub mod detection;
+pub mod diagnostics;
+pub mod disambiguation;
+pub mod encoding;
+pub mod encodings;
+pub mod error;
+pub mod estree;
+pub mod graph;
+pub mod json;
+pub mod languages;
+pub mod node_table;
+pub mod output;
+pub mod parser_pool;
+pub mod parsing;
+pub mod python_ast;
+pub mod query;
+pub mod schema;
+pub mod sexp;
+pub mod sqlite;
+pub mod srcml;
+pub mod tree_walk;
+pub mod versions;
+
+pub use detection::Detection;
+pub use diagnostics::{Diagnostic, DiagnosticKind};
+pub use encoding::Encoding;
+pub use encodings::Encodings;
+pub use error::{AstgenError, Result};
+pub use json::{node_to_json, node_to_json_with_options, ColumnEncoding, JsonNode, TreeOptions};
+pub use languages::create_encodings;
+pub use output::OutputFormat;
+pub use parsing::{
+    parse_file, parse_file_tree, parse_source, parse_source_tree, query_file, query_source,
+    ParsedFile, QueriedFile, SourceTree,
+};
+pub use query::{QueryCapture, QuerySet};
+pub use schema::{AstSchema, AstValue};
+
+/// Full astgen version: the crate version followed by the git revision it was built from
+pub static VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    ".",
+    include_str!(concat!(env!("OUT_DIR"), "/version.txt"))
+);
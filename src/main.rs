@@ -1,26 +1,14 @@
 mod cli_types;
-mod config;
-mod encoding;
-mod encodings;
-mod error;
-mod json;
-mod languages;
-mod parsing;
-mod versions; // Add new module
-mod walk; // new module
+mod walk;
 
+use astgen::config;
+use astgen::error::{AstgenError, Result};
+use astgen::languages::{create_encodings, print_supported_languages};
+use astgen::output::RunSummary;
 use clap::Parser;
 use cli_types::Args;
-use error::{AstgenError, Result};
-use languages::{create_encodings, print_supported_languages};
 use std::fs;
 
-static VERSION: &str = concat!(
-    env!("CARGO_PKG_VERSION"),
-    ".",
-    include_str!(concat!(env!("OUT_DIR"), "/version.txt"))
-);
-
 fn main() -> Result<()> {
     // Initialize logging
     env_logger::Builder::from_default_env()
@@ -37,6 +25,9 @@ fn main() -> Result<()> {
 
     // Validate arguments
     args.validate()?;
+    if args.truncate.is_some() && !args.quiet {
+        log::warn!("--truncate no longer cuts the output at a length; it is read as --max-output-bytes, which prunes the tree at an approximate size.");
+    }
 
     // Load configuration
     let config = if let Some(config_path) = &args.config {
@@ -45,6 +36,11 @@ fn main() -> Result<()> {
         config::Config::load_default()?
     };
 
+    // Bound the number of parsers each worker thread keeps for reuse
+    if let Some(pool_size) = config.performance.as_ref().and_then(|p| p.parser_pool_size) {
+        astgen::parser_pool::set_parser_pool_size(pool_size);
+    }
+
     // Set up thread pool
     let num_threads = args
         .parallel
@@ -62,57 +58,75 @@ fn main() -> Result<()> {
         log::info!("Using {} threads for parallel processing", num_threads);
     }
 
-    // Set up encodings
-    let encodings = create_encodings();
+    // Set up encodings, with file patterns mapped on the command line taking precedence over
+    // those in the configuration
+    let mut encodings = create_encodings();
+    for (pattern, language) in args.language_map().into_iter().chain(config.language_map()) {
+        encodings.map_pattern(&pattern, &language)?;
+    }
+    let queries = args.query_set()?;
 
     // Process files
     if args.files.is_empty() {
         return Err(AstgenError::InvalidInput(
-            "No input files specified.\n\nUsage: astgen <files...>\nExample: astgen src/main.rs\nExample: astgen src/\n\nUse --help for more options.".to_string(),
+            "No input files specified.\n\nUsage: astgen <files...>\nExample: astgen src/main.rs\nExample: astgen src/\nExample: astgen --language python - < script.py\n\nUse --help for more options.".to_string(),
         ));
     }
 
     let total_start_time = std::time::Instant::now();
-    let mut total_files = 0;
-    let mut total_errors = 0;
+    let mut output = walk::Output::open(&args)?;
+    let mut summary = RunSummary::default();
 
     for file_arg in &args.files {
+        if file_arg.as_os_str() == "-" {
+            let status = walk::process_stdin(&encodings, &args, queries.as_ref(), &mut output)?;
+            summary.record(status.succeeded, status.has_syntax_errors);
+            continue;
+        }
         match fs::metadata(file_arg) {
             Ok(metadata) => {
                 if metadata.is_dir() {
                     if args.verbose && !args.quiet {
                         log::info!("Processing directory: {}", file_arg.display());
                     }
-                    let (files, errors) = walk::process_directory(file_arg, &encodings, &args)?;
-                    total_files += files;
-                    total_errors += errors;
+                    let directory_summary = walk::process_directory(
+                        file_arg,
+                        &encodings,
+                        &args,
+                        queries.as_ref(),
+                        &mut output,
+                    )?;
+                    summary.merge(&directory_summary);
                 } else {
-                    let result = walk::process_single_file(file_arg, &encodings, &args)?;
-                    if result {
-                        total_files += 1;
-                    } else {
-                        total_errors += 1;
-                    }
+                    let status = walk::process_single_file(
+                        file_arg,
+                        &encodings,
+                        &args,
+                        queries.as_ref(),
+                        &mut output,
+                    )?;
+                    summary.record(status.succeeded, status.has_syntax_errors);
                 }
             }
             Err(e) => {
                 log::error!("Cannot access {}: {}", file_arg.display(), e);
-                total_errors += 1;
+                summary.record(false, false);
             }
         }
     }
+    output.finish(&summary)?;
 
     let duration = total_start_time.elapsed();
     if args.verbose && !args.quiet {
         log::info!(
             "Processed {} files with {} errors in {:?}",
-            total_files,
-            total_errors,
+            summary.succeeded,
+            summary.failed,
             duration
         );
     }
 
-    if total_errors > 0 {
+    if summary.failed > 0 {
         std::process::exit(1);
     }
 
@@ -146,4 +160,31 @@ mod tests {
         assert!(encodings.match_file("test.txt").is_none());
         assert!(encodings.match_file("test").is_none());
     }
+
+    #[test]
+    fn test_create_encodings_matches_whole_extensions() {
+        let encodings = create_encodings();
+        let name = |path| encodings.match_file(path).map(|encoding| encoding.name());
+        assert_eq!(name("test.sh"), Some("Bash"));
+        assert_eq!(name("test.cc"), Some("C++"));
+        assert_eq!(name("test.h"), Some("C"));
+        assert_eq!(encodings.candidates("test.h").len(), 2);
+        assert_eq!(name("test.mjs"), Some("JavaScript"));
+        assert_eq!(name("deploy-rpc"), None);
+        assert_eq!(name(".bashrc"), Some("Bash"));
+    }
+
+    #[test]
+    fn test_create_encodings_keeps_longer_extensions_of_the_same_language() {
+        let encodings = create_encodings();
+        let name = |path| encodings.match_file(path).map(|encoding| encoding.name());
+        assert_eq!(name("map.geojson"), Some("JSON"));
+        assert_eq!(name("map.topojson"), Some("JSON"));
+        assert_eq!(name("setup.ksh"), Some("Bash"));
+        assert_eq!(name("page.xhtml"), Some("HTML"));
+        assert_eq!(name("page.shtml"), Some("HTML"));
+        // Dialects the grammars do not parse only shared a suffix and are left unmatched
+        assert_eq!(name("init.zsh"), None);
+        assert_eq!(name("theme.scss"), None);
+    }
 }
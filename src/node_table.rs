@@ -0,0 +1,340 @@
+//! Flat node tables in Apache Arrow IPC and Parquet files
+//!
+//! Every node becomes one row, linked to its parent by `parent_id`, so trees can be loaded into
+//! analytics engines and queried with SQL instead of walking nested documents. Rows from every
+//! file of a run go into the same table; `(file, node_id)` identifies a node.
+use crate::error::{AstgenError, Result};
+use crate::json::{Position, TreeOptions};
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use arrow_array::builder::{BooleanBuilder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::io::Write;
+use std::sync::{Arc, LazyLock};
+use tree_sitter::Node;
+
+/// File layout for node tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    /// The Arrow IPC file format (Feather v2)
+    Arrow,
+    /// Parquet with Snappy compression
+    Parquet,
+}
+
+static SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let u64_column = |name| Field::new(name, DataType::UInt64, false);
+    Arc::new(Schema::new(vec![
+        Field::new("file", DataType::Utf8, false),
+        u64_column("node_id"),
+        Field::new("parent_id", DataType::UInt64, true),
+        Field::new("child_index", DataType::UInt32, true),
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("field_name", DataType::Utf8, true),
+        u64_column("start_byte"),
+        u64_column("end_byte"),
+        u64_column("start_row"),
+        u64_column("start_column"),
+        u64_column("end_row"),
+        u64_column("end_column"),
+        Field::new("is_named", DataType::Boolean, false),
+        Field::new("text", DataType::Utf8, true),
+    ]))
+});
+
+/// Columns of the node table
+///
+/// `node_id` numbers a file's nodes in depth-first order from 0 at the root, `parent_id` and
+/// `child_index` are null for the root, and `text` is only set on leaves. Rows follow
+/// `TreeOptions` such as `named_only` and the depth and size limits.
+pub fn node_table_schema() -> SchemaRef {
+    SCHEMA.clone()
+}
+
+/// Flatten the tree under `root` into one row per node
+pub fn node_rows(
+    filename: &str,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<RecordBatch> {
+    // Child indexes count children in source order
+    let walk_options = TreeOptions {
+        group_fields: false,
+        ..options.clone()
+    };
+    let mut builder = RowBuilder {
+        source_code,
+        options,
+        parents: Vec::new(),
+        next_id: 0,
+        columns: Columns::default(),
+    };
+    walk_tree(root, &walk_options, &mut builder)?;
+    builder.columns.finish(filename, builder.next_id)
+}
+
+#[derive(Default)]
+struct Columns {
+    node_id: UInt64Builder,
+    parent_id: UInt64Builder,
+    child_index: UInt32Builder,
+    kind: StringBuilder,
+    field_name: StringBuilder,
+    start_byte: UInt64Builder,
+    end_byte: UInt64Builder,
+    start_row: UInt64Builder,
+    start_column: UInt64Builder,
+    end_row: UInt64Builder,
+    end_column: UInt64Builder,
+    is_named: BooleanBuilder,
+    text: StringBuilder,
+}
+
+impl Columns {
+    fn finish(mut self, filename: &str, rows: u64) -> Result<RecordBatch> {
+        let mut file = StringBuilder::new();
+        for _ in 0..rows {
+            file.append_value(filename);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(file.finish()),
+            Arc::new(self.node_id.finish()),
+            Arc::new(self.parent_id.finish()),
+            Arc::new(self.child_index.finish()),
+            Arc::new(self.kind.finish()),
+            Arc::new(self.field_name.finish()),
+            Arc::new(self.start_byte.finish()),
+            Arc::new(self.end_byte.finish()),
+            Arc::new(self.start_row.finish()),
+            Arc::new(self.start_column.finish()),
+            Arc::new(self.end_row.finish()),
+            Arc::new(self.end_column.finish()),
+            Arc::new(self.is_named.finish()),
+            Arc::new(self.text.finish()),
+        ];
+        RecordBatch::try_new(node_table_schema(), columns).map_err(table_error)
+    }
+}
+
+struct RowBuilder<'a> {
+    source_code: &'a str,
+    options: &'a TreeOptions,
+    /// Ids of the nodes whose children are being visited, with the number of children so far
+    parents: Vec<(u64, u32)>,
+    next_id: u64,
+    columns: Columns,
+}
+
+impl<'tree> TreeVisitor<'tree> for RowBuilder<'_> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        let node = visited.node;
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let columns = &mut self.columns;
+        columns.node_id.append_value(id);
+        match self.parents.last_mut() {
+            Some((parent_id, children)) => {
+                columns.parent_id.append_value(*parent_id);
+                columns.child_index.append_value(*children);
+                *children += 1;
+            }
+            None => {
+                columns.parent_id.append_null();
+                columns.child_index.append_null();
+            }
+        }
+        columns.kind.append_value(node.kind());
+        columns.field_name.append_option(visited.field_name);
+        columns.start_byte.append_value(node.start_byte() as u64);
+        columns.end_byte.append_value(node.end_byte() as u64);
+        let encoding = self.options.column_encoding;
+        let start = Position::from_point(
+            self.source_code,
+            node.start_position(),
+            node.start_byte(),
+            encoding,
+        );
+        let end = Position::from_point(
+            self.source_code,
+            node.end_position(),
+            node.end_byte(),
+            encoding,
+        );
+        columns.start_row.append_value(start.row as u64);
+        columns.start_column.append_value(start.column as u64);
+        columns.end_row.append_value(end.row as u64);
+        columns.end_column.append_value(end.column as u64);
+        columns.is_named.append_value(node.is_named());
+        columns.text.append_option(
+            (visited.is_leaf && node.end_byte() > node.start_byte())
+                .then(|| &self.source_code[node.start_byte()..node.end_byte()]),
+        );
+
+        self.parents.push((id, 0));
+        Ok(())
+    }
+
+    fn leave_node(&mut self, _visited: &VisitedNode<'tree>, _omitted: usize) -> Result<()> {
+        self.parents.pop();
+        Ok(())
+    }
+}
+
+/// Writes node rows from any number of files into a single table
+pub struct NodeTableWriter<W: Write + Send> {
+    inner: TableWriter<W>,
+}
+
+enum TableWriter<W: Write + Send> {
+    Arrow(arrow_ipc::writer::FileWriter<W>),
+    Parquet(ArrowWriter<W>),
+}
+
+impl<W: Write + Send> NodeTableWriter<W> {
+    pub fn new(out: W, format: TableFormat) -> Result<Self> {
+        let schema = node_table_schema();
+        let inner = match format {
+            TableFormat::Arrow => TableWriter::Arrow(
+                arrow_ipc::writer::FileWriter::try_new(out, &schema).map_err(table_error)?,
+            ),
+            TableFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                TableWriter::Parquet(
+                    ArrowWriter::try_new(out, schema, Some(properties)).map_err(table_error)?,
+                )
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Append rows produced by `node_rows`
+    pub fn write(&mut self, rows: &RecordBatch) -> Result<()> {
+        match &mut self.inner {
+            TableWriter::Arrow(writer) => writer.write(rows).map_err(table_error),
+            TableWriter::Parquet(writer) => writer.write(rows).map_err(table_error),
+        }
+    }
+
+    /// Write the file footer; the table is not readable until this has been called
+    pub fn finish(self) -> Result<()> {
+        match self.inner {
+            TableWriter::Arrow(mut writer) => {
+                writer.finish().map_err(table_error)?;
+                writer.into_inner().map_err(table_error)?.flush()?;
+            }
+            TableWriter::Parquet(writer) => {
+                writer.into_inner().map_err(table_error)?.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn table_error(error: impl std::fmt::Display) -> AstgenError {
+    AstgenError::SerializationError(format!("Cannot write node table: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{UInt32Type, UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tree_sitter::{Parser, Tree};
+
+    fn parse_rust(code: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    fn rows(code: &str, options: &TreeOptions) -> RecordBatch {
+        let tree = parse_rust(code);
+        node_rows("main.rs", code, tree.root_node(), options).unwrap()
+    }
+
+    #[test]
+    fn test_rows_link_children_to_parents() {
+        let options = TreeOptions {
+            named_only: true,
+            ..Default::default()
+        };
+        let batch = rows("fn main() {}", &options);
+        assert_eq!(batch.num_rows(), 5);
+
+        let kinds: Vec<_> = batch["kind"].as_string::<i32>().iter().flatten().collect();
+        assert_eq!(
+            kinds,
+            [
+                "source_file",
+                "function_item",
+                "identifier",
+                "parameters",
+                "block"
+            ]
+        );
+        let parents: Vec<_> = batch["parent_id"]
+            .as_primitive::<UInt64Type>()
+            .iter()
+            .collect();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(1), Some(1)]);
+        let indexes: Vec<_> = batch["child_index"]
+            .as_primitive::<UInt32Type>()
+            .iter()
+            .collect();
+        assert_eq!(indexes, [None, Some(0), Some(0), Some(1), Some(2)]);
+        let fields: Vec<_> = batch["field_name"].as_string::<i32>().iter().collect();
+        assert_eq!(
+            fields,
+            [None, None, Some("name"), Some("parameters"), Some("body")]
+        );
+        let texts: Vec<_> = batch["text"].as_string::<i32>().iter().collect();
+        assert_eq!(texts, [None, None, Some("main"), Some("()"), Some("{}")]);
+    }
+
+    #[test]
+    fn test_parquet_table_holds_rows_from_every_file() {
+        let file = tempfile::tempfile().unwrap();
+        let mut writer = NodeTableWriter::new(&file, TableFormat::Parquet).unwrap();
+        writer
+            .write(&rows("fn a() {}", &TreeOptions::default()))
+            .unwrap();
+        writer
+            .write(&rows("fn b() {}", &TreeOptions::default()))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let total: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(
+            total,
+            2 * rows("fn a() {}", &TreeOptions::default()).num_rows()
+        );
+    }
+
+    #[test]
+    fn test_arrow_table_round_trips() {
+        let mut out = Vec::new();
+        let mut writer = NodeTableWriter::new(&mut out, TableFormat::Arrow).unwrap();
+        let batch = rows("fn main() {}", &TreeOptions::default());
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+
+        let reader =
+            arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(out), None).unwrap();
+        let read: Vec<_> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(read, [batch]);
+    }
+}
@@ -0,0 +1,1257 @@
+//! Streaming output: write parsed trees straight to an `io::Write` sink in each `OutputFormat`
+//!
+//! Trees are serialized while `walk_tree` moves over them, without building `JsonNode`s or an
+//! intermediate string, so memory use follows the depth of the tree rather than its size.
+use crate::binary::{self, BinaryEncoding};
+use crate::detection::Detection;
+use crate::diagnostics::Diagnostic;
+use crate::disambiguation::Disambiguation;
+use crate::error::{AstgenError, Result};
+use crate::graph::{self, GraphStyle};
+use crate::json::{
+    node_to_json_with_options, Position, TreeOptions, STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES,
+};
+use crate::node_table::{self, NodeTableWriter, TableFormat};
+use crate::schema::{self, AstSchema, AstValue};
+use crate::sexp;
+use crate::srcml;
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use crate::versions::TREE_SITTER_PARSERS;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tree_sitter::Node;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    PrettyJson,
+    Yaml,
+    /// JSON Lines: exactly one compact document per line, flushed as each file completes
+    Jsonl,
+    /// The indented S-expression `tree-sitter parse` prints; the tree only, without the envelope
+    Sexp,
+    /// A Graphviz digraph of the tree, for `dot -Tsvg`
+    Dot,
+    /// A Mermaid flowchart of the tree, for Markdown renderers that support Mermaid
+    Mermaid,
+    /// srcML-style XML that keeps every byte of the source between the element tags
+    Xml,
+    /// CBOR documents, each preceded by its length as a big-endian u32
+    Cbor,
+    /// MessagePack documents, each preceded by its length as a big-endian u32
+    #[value(name = "msgpack")]
+    MsgPack,
+    /// One row per node in an Apache Arrow IPC file, with every file of a run in one table
+    Arrow,
+    /// One row per node in a Parquet file, with every file of a run in one table
+    Parquet,
+    /// A SQLite database of files, nodes and syntax errors, updated in place on later runs
+    Sqlite,
+}
+
+impl OutputFormat {
+    /// The format renders a syntax tree on its own, without the envelope, so it cannot hold
+    /// query captures or aggregate documents
+    pub fn is_tree_only(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Sexp
+                | OutputFormat::Dot
+                | OutputFormat::Mermaid
+                | OutputFormat::Xml
+                | OutputFormat::Arrow
+                | OutputFormat::Parquet
+                | OutputFormat::Sqlite
+        )
+    }
+
+    /// The table layout for the formats that write one row per node
+    pub fn table_format(self) -> Option<TableFormat> {
+        match self {
+            OutputFormat::Arrow => Some(TableFormat::Arrow),
+            OutputFormat::Parquet => Some(TableFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    /// The binary encoding for CBOR and MessagePack, whose documents are framed rather than
+    /// separated by newlines
+    pub fn binary_encoding(self) -> Option<BinaryEncoding> {
+        match self {
+            OutputFormat::Cbor => Some(BinaryEncoding::Cbor),
+            OutputFormat::MsgPack => Some(BinaryEncoding::MsgPack),
+            _ => None,
+        }
+    }
+}
+
+/// Envelope metadata written ahead of a streamed tree
+#[derive(Serialize)]
+pub struct Envelope<'a> {
+    pub version: &'a str,
+    pub filename: &'a str,
+    pub language: &'a str,
+    /// How the language was chosen, when astgen chose it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Detection>,
+    /// How a language shared by the file's extension was chosen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disambiguation: Option<Disambiguation>,
+    pub has_error: bool,
+    #[serde(skip_serializing_if = "<[Diagnostic]>::is_empty")]
+    pub diagnostics: &'a [Diagnostic],
+}
+
+/// The envelope and converted tree, serialized together for the binary formats
+#[derive(Serialize)]
+struct TreeDocument<'a, T> {
+    #[serde(flatten)]
+    envelope: &'a Envelope<'a>,
+    ast: T,
+}
+
+/// Where a document sits in the output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// A document of its own, ending with a newline
+    #[default]
+    Standalone,
+    /// An entry in a document holding several files, laid out to nest inside it: the `files` list
+    /// of an aggregate document, a unit of an srcML archive, or an S-expression headed by its
+    /// filename
+    AggregateEntry,
+}
+
+/// Write the envelope and the tree under `root` as one document
+pub fn write_tree(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    placement: Placement,
+    envelope: &Envelope,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<()> {
+    match format {
+        OutputFormat::Sexp => {
+            return sexp::write_sexp(
+                out,
+                placement,
+                envelope.filename,
+                source_code,
+                root,
+                options,
+            )
+        }
+        OutputFormat::Dot => {
+            return graph::write_graph(out, GraphStyle::Dot, source_code, root, options)
+        }
+        OutputFormat::Mermaid => {
+            return graph::write_graph(out, GraphStyle::Mermaid, source_code, root, options)
+        }
+        OutputFormat::Xml => {
+            return srcml::write_srcml(
+                out,
+                placement,
+                envelope.language,
+                envelope.filename,
+                source_code,
+                root,
+                options,
+            )
+        }
+        OutputFormat::Sqlite => {
+            return Err(AstgenError::InvalidInput(
+                "SQLite output is written to a database with sqlite::SqliteStore, not a stream"
+                    .to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if let Some(table_format) = format.table_format() {
+        // The table writers need a `Send` output, which `out` may not be
+        let rows = node_table::node_rows(envelope.filename, source_code, root, options)?;
+        let mut table = Vec::new();
+        let mut writer = NodeTableWriter::new(&mut table, table_format)?;
+        writer.write(&rows)?;
+        writer.finish()?;
+        out.write_all(&table)?;
+        out.flush()?;
+        return Ok(());
+    }
+    let converted = match options.schema {
+        AstSchema::TreeSitter => None,
+        schema => Some(schema::convert_tree(
+            schema,
+            envelope.language,
+            source_code,
+            root,
+            options,
+        )?),
+    };
+    if let Some(encoding) = format.binary_encoding() {
+        return match converted {
+            Some(ast) => binary::write_frame(out, encoding, &TreeDocument { envelope, ast }),
+            None => {
+                let ast = node_to_json_with_options(source_code, root, options);
+                binary::write_frame(out, encoding, &TreeDocument { envelope, ast })
+            }
+        };
+    }
+    let mut writer = FormatWriter::new(out, format, placement);
+    write_document_with(&mut writer, envelope, |writer| match &converted {
+        Some(ast) => Ok(write_ast_value(writer, ast)?),
+        None => walk_tree(
+            root,
+            options,
+            &mut StreamVisitor {
+                writer,
+                source_code,
+                options,
+                frames: Vec::new(),
+            },
+        ),
+    })
+}
+
+/// Write any serializable value as one document
+///
+/// Meant for small values such as query results; trees should go through `write_tree`.
+pub fn write_value(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    placement: Placement,
+    value: &impl Serialize,
+) -> Result<()> {
+    if format.is_tree_only() {
+        return Err(AstgenError::InvalidInput(format!(
+            "{:?} output can only hold syntax trees",
+            format
+        )));
+    }
+    if let Some(encoding) = format.binary_encoding() {
+        return binary::write_frame(out, encoding, value);
+    }
+    let mut writer = FormatWriter::new(out, format, placement);
+    write_json_value(&mut writer, &serde_json::to_value(value)?)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Run metadata written at the top of an aggregate document
+#[derive(Serialize, Debug, Clone)]
+pub struct RunHeader {
+    /// astgen version, including the git revision
+    pub version: String,
+    /// Version of each Tree-sitter grammar crate, keyed by crate name
+    pub parsers: BTreeMap<String, String>,
+    /// UTC start time in RFC 3339 format
+    pub started_at: String,
+    /// Command line arguments, excluding the program name
+    pub arguments: Vec<String>,
+}
+
+impl RunHeader {
+    /// A header for a run starting now with the given arguments
+    pub fn new(arguments: Vec<String>) -> Self {
+        Self {
+            version: crate::VERSION.to_string(),
+            parsers: TREE_SITTER_PARSERS
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+            started_at: rfc3339_utc(SystemTime::now()),
+            arguments,
+        }
+    }
+}
+
+/// Counts of processed files, written at the end of an aggregate document
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    pub files: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Files Tree-sitter had to recover from syntax errors in, whether or not that failed them
+    pub with_syntax_errors: usize,
+}
+
+impl RunSummary {
+    pub fn record(&mut self, succeeded: bool, has_syntax_errors: bool) {
+        self.files += 1;
+        if succeeded {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        if has_syntax_errors {
+            self.with_syntax_errors += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &RunSummary) {
+        self.files += other.files;
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.with_syntax_errors += other.with_syntax_errors;
+    }
+}
+
+/// Write the closing `{"summary": ...}` record of a JSON Lines stream
+pub fn write_summary_record(out: &mut dyn Write, summary: &RunSummary) -> Result<()> {
+    serde_json::to_writer(&mut *out, &serde_json::json!({ "summary": summary }))?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes one document holding a run header, the document of every file, and a summary
+///
+/// File documents must be written with `Placement::AggregateEntry` in the same format.
+pub struct AggregateWriter<W: Write> {
+    writer: FormatWriter<W>,
+}
+
+impl<W: Write> AggregateWriter<W> {
+    /// Write the header and open the `files` list
+    pub fn begin(out: W, format: OutputFormat, header: &RunHeader) -> Result<Self> {
+        if format.is_tree_only() || format.binary_encoding().is_some() {
+            return Err(AstgenError::InvalidInput(format!(
+                "{:?} output cannot hold an aggregate document",
+                format
+            )));
+        }
+        let mut writer = FormatWriter::new(out, format, Placement::Standalone);
+        writer.begin_map()?;
+        writer.key("run")?;
+        write_json_value(&mut writer, &serde_json::to_value(header)?)?;
+        writer.key("files")?;
+        writer.begin_seq()?;
+        Ok(Self { writer })
+    }
+
+    /// Add one file's document, which `write` renders with `Placement::AggregateEntry`
+    pub fn write_entry(&mut self, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+        self.writer.begin_raw_entry()?;
+        write(self.writer.inner())?;
+        self.writer.inner().flush()?;
+        Ok(())
+    }
+
+    /// Close the `files` list and write the summary
+    pub fn finish(mut self, summary: &RunSummary) -> Result<()> {
+        self.writer.end_seq()?;
+        self.writer.key("summary")?;
+        write_json_value(&mut self.writer, &serde_json::to_value(summary)?)?;
+        self.writer.end_map()?;
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Format `time` as an RFC 3339 UTC timestamp with second precision
+fn rfc3339_utc(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60
+    )
+}
+
+/// Write the envelope, then the `ast` entry with `write_ast`
+fn write_document_with<W: StructureWriter>(
+    writer: &mut W,
+    envelope: &Envelope,
+    write_ast: impl FnOnce(&mut W) -> Result<()>,
+) -> Result<()> {
+    writer.begin_map()?;
+    writer.key("version")?;
+    writer.string(envelope.version)?;
+    writer.key("filename")?;
+    writer.string(envelope.filename)?;
+    writer.key("language")?;
+    writer.string(envelope.language)?;
+    if let Some(detection) = envelope.detection {
+        writer.key("detection")?;
+        writer.string(detection.as_str())?;
+    }
+    if let Some(disambiguation) = &envelope.disambiguation {
+        writer.key("disambiguation")?;
+        write_json_value(writer, &serde_json::to_value(disambiguation)?)?;
+    }
+    writer.key("has_error")?;
+    writer.bool(envelope.has_error)?;
+    if !envelope.diagnostics.is_empty() {
+        writer.key("diagnostics")?;
+        write_json_value(writer, &serde_json::to_value(envelope.diagnostics)?)?;
+    }
+    writer.key("ast")?;
+    write_ast(writer)?;
+    writer.end_map()?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Write a small value through a `StructureWriter` so it matches the surrounding layout
+fn write_json_value<W: StructureWriter>(writer: &mut W, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Null => writer.null(),
+        Value::Bool(b) => writer.bool(*b),
+        Value::Number(n) => match n.as_u64() {
+            Some(n) => writer.unsigned(n),
+            None => writer.raw_number(&n.to_string()),
+        },
+        Value::String(s) => writer.string(s),
+        Value::Array(items) => {
+            writer.begin_seq()?;
+            for item in items {
+                write_json_value(writer, item)?;
+            }
+            writer.end_seq()
+        }
+        Value::Object(entries) => {
+            writer.begin_map()?;
+            for (key, item) in entries {
+                writer.key(key)?;
+                write_json_value(writer, item)?;
+            }
+            writer.end_map()
+        }
+    }
+}
+
+/// Write a tree converted into another schema, keeping the order of its keys
+fn write_ast_value<W: StructureWriter>(writer: &mut W, value: &AstValue) -> io::Result<()> {
+    stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || match value {
+        AstValue::Null => writer.null(),
+        AstValue::Bool(b) => writer.bool(*b),
+        AstValue::Number(n) => match n.as_u64() {
+            Some(n) => writer.unsigned(n),
+            None => writer.raw_number(&n.to_string()),
+        },
+        AstValue::String(s) => writer.string(s),
+        AstValue::List(items) => {
+            writer.begin_seq()?;
+            for item in items {
+                write_ast_value(writer, item)?;
+            }
+            writer.end_seq()
+        }
+        AstValue::Map(entries) => {
+            writer.begin_map()?;
+            for (key, item) in entries {
+                writer.key(key)?;
+                write_ast_value(writer, item)?;
+            }
+            writer.end_map()
+        }
+    })
+}
+
+/// Per-node state while its children are streamed
+#[derive(Default)]
+struct NodeFrame {
+    fields_open: bool,
+    /// Inside a grouped field, whose children are written as a sequence
+    in_field: bool,
+    children_open: bool,
+}
+
+/// Writes each node as `walk_tree` reaches it, with the same keys as a serialized `JsonNode`
+///
+/// `truncated` and `omitted_descendants` are only known once a node's children have been
+/// visited, so they follow `children` and `text` instead of preceding them.
+struct StreamVisitor<'w, 's, W> {
+    writer: &'w mut W,
+    source_code: &'s str,
+    options: &'s TreeOptions,
+    frames: Vec<NodeFrame>,
+}
+
+impl<W: StructureWriter> StreamVisitor<'_, '_, W> {
+    fn position(&mut self, key: &str, position: Position) -> io::Result<()> {
+        self.writer.key(key)?;
+        self.writer.begin_map()?;
+        self.writer.key("row")?;
+        self.writer.unsigned(position.row as u64)?;
+        self.writer.key("column")?;
+        self.writer.unsigned(position.column as u64)?;
+        self.writer.end_map()
+    }
+
+    fn point(&self, node: Node, start: bool) -> Position {
+        let (point, byte) = if start {
+            (node.start_position(), node.start_byte())
+        } else {
+            (node.end_position(), node.end_byte())
+        };
+        Position::from_point(self.source_code, point, byte, self.options.column_encoding)
+    }
+}
+
+impl<'tree, W: StructureWriter> TreeVisitor<'tree> for StreamVisitor<'_, '_, W> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        if let Some(parent) = self.frames.last_mut() {
+            if !parent.in_field {
+                if parent.fields_open {
+                    parent.fields_open = false;
+                    self.writer.end_map()?;
+                }
+                if !parent.children_open {
+                    parent.children_open = true;
+                    self.writer.key("children")?;
+                    self.writer.begin_seq()?;
+                }
+            }
+        }
+        self.frames.push(NodeFrame::default());
+
+        let node = visited.node;
+        self.writer.begin_map()?;
+        self.writer.key("kind")?;
+        self.writer.string(node.kind())?;
+        self.writer.key("start_byte")?;
+        self.writer.unsigned(node.start_byte() as u64)?;
+        self.writer.key("end_byte")?;
+        self.writer.unsigned(node.end_byte() as u64)?;
+        let start = self.point(node, true);
+        self.position("start_point", start)?;
+        let end = self.point(node, false);
+        self.position("end_point", end)?;
+        if let Some(field_name) = visited.field_name {
+            self.writer.key("field_name")?;
+            self.writer.string(field_name)?;
+        }
+        if self.options.include_is_named {
+            self.writer.key("is_named")?;
+            self.writer.bool(node.is_named())?;
+        }
+        if node.is_error() {
+            self.writer.key("is_error")?;
+            self.writer.bool(true)?;
+        }
+        if node.is_missing() {
+            self.writer.key("is_missing")?;
+            self.writer.bool(true)?;
+        }
+        Ok(())
+    }
+
+    fn enter_field(&mut self, name: &'static str) -> Result<()> {
+        let frame = self.frames.last_mut().expect("field inside a node");
+        if !frame.fields_open {
+            frame.fields_open = true;
+            self.writer.key("fields")?;
+            self.writer.begin_map()?;
+        }
+        frame.in_field = true;
+        self.writer.key(name)?;
+        self.writer.begin_seq()?;
+        Ok(())
+    }
+
+    fn leave_field(&mut self) -> Result<()> {
+        let frame = self.frames.last_mut().expect("field inside a node");
+        frame.in_field = false;
+        self.writer.end_seq()?;
+        Ok(())
+    }
+
+    fn leave_node(
+        &mut self,
+        visited: &VisitedNode<'tree>,
+        omitted_descendants: usize,
+    ) -> Result<()> {
+        let frame = self.frames.pop().expect("node was entered");
+        if frame.fields_open {
+            self.writer.end_map()?;
+        }
+        if frame.children_open {
+            self.writer.end_seq()?;
+        } else {
+            self.writer.key("children")?;
+            self.writer.null()?;
+        }
+
+        let node = visited.node;
+        self.writer.key("text")?;
+        if visited.is_leaf && node.end_byte() > node.start_byte() {
+            self.writer
+                .string(&self.source_code[node.start_byte()..node.end_byte()])?;
+        } else {
+            self.writer.null()?;
+        }
+        if omitted_descendants > 0 {
+            self.writer.key("truncated")?;
+            self.writer.bool(true)?;
+            self.writer.key("omitted_descendants")?;
+            self.writer.unsigned(omitted_descendants as u64)?;
+        }
+        self.writer.end_map()?;
+        Ok(())
+    }
+}
+
+/// Low-level event sink for nested maps, sequences and scalars
+///
+/// Implementations keep one small entry per open container, never the values themselves.
+trait StructureWriter {
+    fn begin_map(&mut self) -> io::Result<()>;
+    fn key(&mut self, key: &str) -> io::Result<()>;
+    fn end_map(&mut self) -> io::Result<()>;
+    fn begin_seq(&mut self) -> io::Result<()>;
+    fn end_seq(&mut self) -> io::Result<()>;
+    fn string(&mut self, value: &str) -> io::Result<()>;
+    fn unsigned(&mut self, value: u64) -> io::Result<()>;
+    /// A number that is not an unsigned integer, already formatted
+    fn raw_number(&mut self, value: &str) -> io::Result<()>;
+    fn bool(&mut self, value: bool) -> io::Result<()>;
+    fn null(&mut self) -> io::Result<()>;
+    /// Start an entry in the current sequence whose value the caller writes pre-rendered
+    fn begin_raw_entry(&mut self) -> io::Result<()>;
+    /// End the document (with a newline when it stands alone) and flush
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// The `StructureWriter` for an `OutputFormat`
+enum FormatWriter<W> {
+    Json(JsonWriter<W>),
+    Yaml(YamlWriter<W>),
+}
+
+impl<W: Write> FormatWriter<W> {
+    fn new(out: W, format: OutputFormat, placement: Placement) -> Self {
+        match format {
+            OutputFormat::Json | OutputFormat::Jsonl => {
+                Self::Json(JsonWriter::new(out, false, placement))
+            }
+            OutputFormat::PrettyJson => Self::Json(JsonWriter::new(out, true, placement)),
+            OutputFormat::Yaml => Self::Yaml(YamlWriter::new(out, placement)),
+            OutputFormat::Sexp
+            | OutputFormat::Dot
+            | OutputFormat::Mermaid
+            | OutputFormat::Xml
+            | OutputFormat::Cbor
+            | OutputFormat::MsgPack
+            | OutputFormat::Arrow
+            | OutputFormat::Parquet
+            | OutputFormat::Sqlite => {
+                unreachable!("tree-only and binary formats have their own writers")
+            }
+        }
+    }
+
+    fn inner(&mut self) -> &mut W {
+        match self {
+            Self::Json(writer) => &mut writer.out,
+            Self::Yaml(writer) => &mut writer.out,
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(fn $name(&mut self, $($arg: $ty),*) -> io::Result<()> {
+            match self {
+                Self::Json(writer) => writer.$name($($arg),*),
+                Self::Yaml(writer) => writer.$name($($arg),*),
+            }
+        })*
+    };
+}
+
+impl<W: Write> StructureWriter for FormatWriter<W> {
+    delegate! {
+        begin_map();
+        key(key: &str);
+        end_map();
+        begin_seq();
+        end_seq();
+        string(value: &str);
+        unsigned(value: u64);
+        raw_number(value: &str);
+        bool(value: bool);
+        null();
+        begin_raw_entry();
+        finish();
+    }
+}
+
+/// JSON in the same layout as `serde_json::to_writer` or `to_writer_pretty`
+struct JsonWriter<W> {
+    out: W,
+    pretty: bool,
+    placement: Placement,
+    /// Whether each open container already holds an entry
+    open: Vec<bool>,
+    after_key: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    fn new(out: W, pretty: bool, placement: Placement) -> Self {
+        Self {
+            out,
+            pretty,
+            placement,
+            open: Vec::new(),
+            after_key: false,
+        }
+    }
+
+    fn new_line(&mut self) -> io::Result<()> {
+        if self.pretty {
+            self.out.write_all(b"\n")?;
+            // An aggregate entry sits inside the top-level map and its `files` list
+            let nesting = match self.placement {
+                Placement::Standalone => 0,
+                Placement::AggregateEntry => 2,
+            };
+            for _ in 0..self.open.len() + nesting {
+                self.out.write_all(b"  ")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the separator and indentation that precede an entry in the current container
+    fn begin_entry(&mut self) -> io::Result<()> {
+        if let Some(has_entries) = self.open.last_mut() {
+            let separator = std::mem::replace(has_entries, true);
+            if separator {
+                self.out.write_all(b",")?;
+            }
+            self.new_line()?;
+        }
+        Ok(())
+    }
+
+    fn begin_value(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.after_key) {
+            Ok(())
+        } else {
+            self.begin_entry()
+        }
+    }
+
+    fn end_container(&mut self, close: &[u8]) -> io::Result<()> {
+        let had_entries = self.open.pop().unwrap_or(false);
+        if had_entries {
+            self.new_line()?;
+        }
+        self.out.write_all(close)
+    }
+}
+
+impl<W: Write> StructureWriter for JsonWriter<W> {
+    fn begin_map(&mut self) -> io::Result<()> {
+        self.begin_value()?;
+        self.open.push(false);
+        self.out.write_all(b"{")
+    }
+
+    fn key(&mut self, key: &str) -> io::Result<()> {
+        self.begin_entry()?;
+        serde_json::to_writer(&mut self.out, key)?;
+        self.after_key = true;
+        self.out.write_all(if self.pretty { b": " } else { b":" })
+    }
+
+    fn end_map(&mut self) -> io::Result<()> {
+        self.end_container(b"}")
+    }
+
+    fn begin_seq(&mut self) -> io::Result<()> {
+        self.begin_value()?;
+        self.open.push(false);
+        self.out.write_all(b"[")
+    }
+
+    fn end_seq(&mut self) -> io::Result<()> {
+        self.end_container(b"]")
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.begin_value()?;
+        serde_json::to_writer(&mut self.out, value)?;
+        Ok(())
+    }
+
+    fn unsigned(&mut self, value: u64) -> io::Result<()> {
+        self.begin_value()?;
+        write!(self.out, "{}", value)
+    }
+
+    fn raw_number(&mut self, value: &str) -> io::Result<()> {
+        self.begin_value()?;
+        self.out.write_all(value.as_bytes())
+    }
+
+    fn bool(&mut self, value: bool) -> io::Result<()> {
+        self.begin_value()?;
+        self.out.write_all(if value { b"true" } else { b"false" })
+    }
+
+    fn null(&mut self) -> io::Result<()> {
+        self.begin_value()?;
+        self.out.write_all(b"null")
+    }
+
+    fn begin_raw_entry(&mut self) -> io::Result<()> {
+        self.begin_entry()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.placement == Placement::Standalone {
+            self.out.write_all(b"\n")?;
+        }
+        self.out.flush()
+    }
+}
+
+/// An open YAML container
+struct YamlContainer {
+    is_map: bool,
+    /// Column at which this container's entries start
+    indent: usize,
+    entries: usize,
+    /// Opened as the value of a key, so entries start on the next line (or `{}`/`[]` follows the key)
+    after_key: bool,
+    /// Opened as a sequence item, so the first entry continues the `- ` line
+    inline_first: bool,
+}
+
+/// Block-style YAML in the same layout as `serde_yaml`
+///
+/// Strings are left plain when that is unambiguous and written as JSON-escaped double-quoted
+/// scalars otherwise, which YAML reads back identically.
+struct YamlWriter<W> {
+    out: W,
+    placement: Placement,
+    open: Vec<YamlContainer>,
+    after_key: bool,
+}
+
+impl<W: Write> YamlWriter<W> {
+    fn new(out: W, placement: Placement) -> Self {
+        Self {
+            out,
+            placement,
+            open: Vec::new(),
+            after_key: false,
+        }
+    }
+
+    fn indent(&mut self, columns: usize) -> io::Result<()> {
+        for _ in 0..columns {
+            self.out.write_all(b" ")?;
+        }
+        Ok(())
+    }
+
+    /// Start a new entry in the innermost container: a `- ` for sequences, nothing for maps
+    fn begin_entry(&mut self) -> io::Result<()> {
+        let Some(container) = self.open.last_mut() else {
+            return Ok(());
+        };
+        let first = container.entries == 0;
+        container.entries += 1;
+        let (is_map, indent, after_key, inline_first) = (
+            container.is_map,
+            container.indent,
+            container.after_key,
+            container.inline_first,
+        );
+        if first && after_key {
+            self.out.write_all(b"\n")?;
+        }
+        if !(first && inline_first) {
+            self.indent(indent)?;
+        }
+        if !is_map {
+            self.out.write_all(b"- ")?;
+        }
+        Ok(())
+    }
+
+    fn begin_container(&mut self, is_map: bool) -> io::Result<()> {
+        let after_key = std::mem::take(&mut self.after_key);
+        let parent_indent = self.open.last().map(|parent| parent.indent);
+        let (indent, inline_first) = match parent_indent {
+            // An aggregate entry continues the `- ` its `files` list item starts with
+            None => match self.placement {
+                Placement::Standalone => (0, false),
+                Placement::AggregateEntry => (2, true),
+            },
+            // serde_yaml does not indent a sequence that is the value of a key
+            Some(parent) if after_key => (parent + if is_map { 2 } else { 0 }, false),
+            Some(parent) => {
+                self.begin_entry()?;
+                (parent + 2, true)
+            }
+        };
+        self.open.push(YamlContainer {
+            is_map,
+            indent,
+            entries: 0,
+            after_key,
+            inline_first,
+        });
+        Ok(())
+    }
+
+    fn end_container(&mut self, empty: &[u8]) -> io::Result<()> {
+        let container = self.open.pop().expect("container is open");
+        if container.entries == 0 {
+            if container.after_key {
+                self.out.write_all(b" ")?;
+            }
+            self.out.write_all(empty)?;
+            self.out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn scalar(&mut self, text: &[u8]) -> io::Result<()> {
+        if std::mem::take(&mut self.after_key) {
+            self.out.write_all(b" ")?;
+        } else {
+            self.begin_entry()?;
+        }
+        self.out.write_all(text)?;
+        self.out.write_all(b"\n")
+    }
+
+    fn quoted(value: &str) -> io::Result<Vec<u8>> {
+        if is_plain_yaml(value) {
+            Ok(value.as_bytes().to_vec())
+        } else {
+            Ok(serde_json::to_vec(value)?)
+        }
+    }
+}
+
+impl<W: Write> StructureWriter for YamlWriter<W> {
+    fn begin_map(&mut self) -> io::Result<()> {
+        self.begin_container(true)
+    }
+
+    fn key(&mut self, key: &str) -> io::Result<()> {
+        self.begin_entry()?;
+        let key = Self::quoted(key)?;
+        self.out.write_all(&key)?;
+        self.out.write_all(b":")?;
+        self.after_key = true;
+        Ok(())
+    }
+
+    fn end_map(&mut self) -> io::Result<()> {
+        self.end_container(b"{}")
+    }
+
+    fn begin_seq(&mut self) -> io::Result<()> {
+        self.begin_container(false)
+    }
+
+    fn end_seq(&mut self) -> io::Result<()> {
+        self.end_container(b"[]")
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        let text = Self::quoted(value)?;
+        self.scalar(&text)
+    }
+
+    fn unsigned(&mut self, value: u64) -> io::Result<()> {
+        self.scalar(value.to_string().as_bytes())
+    }
+
+    fn raw_number(&mut self, value: &str) -> io::Result<()> {
+        self.scalar(value.as_bytes())
+    }
+
+    fn bool(&mut self, value: bool) -> io::Result<()> {
+        self.scalar(if value { b"true" } else { b"false" })
+    }
+
+    fn null(&mut self) -> io::Result<()> {
+        self.scalar(b"null")
+    }
+
+    fn begin_raw_entry(&mut self) -> io::Result<()> {
+        self.begin_entry()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Whether `value` reads back as the same string when written as a plain YAML scalar
+fn is_plain_yaml(value: &str) -> bool {
+    const RESERVED: &[&str] = &["true", "false", "yes", "no", "on", "off", "null", "y", "n"];
+    let mut chars = value.chars();
+    let starts_with_letter = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    starts_with_letter
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+        && !RESERVED.iter().any(|r| value.eq_ignore_ascii_case(r))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disambiguation::{Confidence, Strategy};
+    use crate::json::{node_to_json_with_options, ColumnEncoding};
+    use tree_sitter::{Parser, Tree};
+
+    fn parse(code: &str, language: tree_sitter::Language) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_language(&language).unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    fn streamed(code: &str, tree: &Tree, format: OutputFormat, options: &TreeOptions) -> String {
+        let root = tree.root_node();
+        let diagnostics =
+            crate::diagnostics::collect_diagnostics(code, root, options.column_encoding);
+        let envelope = Envelope {
+            version: "test",
+            filename: "input",
+            language: "Test",
+            detection: None,
+            disambiguation: None,
+            has_error: root.has_error(),
+            diagnostics: &diagnostics,
+        };
+        let mut out = Vec::new();
+        write_tree(
+            &mut out,
+            format,
+            Placement::Standalone,
+            &envelope,
+            code,
+            root,
+            options,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The streamed document must hold exactly the values `JsonNode` serialization produces
+    fn assert_matches_json_node(
+        code: &str,
+        language: tree_sitter::Language,
+        options: &TreeOptions,
+    ) {
+        let tree = parse(code, language);
+        let expected =
+            serde_json::to_value(node_to_json_with_options(code, tree.root_node(), options))
+                .unwrap();
+
+        for format in [OutputFormat::Json, OutputFormat::PrettyJson] {
+            let text = streamed(code, &tree, format, options);
+            let document: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(document["ast"], expected, "format {:?}", format);
+        }
+        let text = streamed(code, &tree, OutputFormat::Yaml, options);
+        let document: Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(document["ast"], expected, "YAML:\n{}", text);
+    }
+
+    #[test]
+    fn test_streamed_tree_matches_json_node() {
+        let code = "fn main() {\n    let s = \"h\\u{e9}llo: \\\"yes\\\"\";\n}\n";
+        assert_matches_json_node(
+            code,
+            tree_sitter_rust::LANGUAGE.into(),
+            &TreeOptions::default(),
+        );
+    }
+
+    #[test]
+    fn test_streamed_tree_matches_json_node_with_options() {
+        let code = "def f(a, b):\n    return {'no': None, 'yes': True}\n";
+        let options = TreeOptions {
+            column_encoding: ColumnEncoding::Utf16,
+            group_fields: true,
+            named_only: true,
+            include_is_named: true,
+            ..Default::default()
+        };
+        assert_matches_json_node(code, tree_sitter_python::LANGUAGE.into(), &options);
+    }
+
+    #[test]
+    fn test_streamed_tree_matches_json_node_when_truncated() {
+        let code = "fn main() { let x = [1, 2, 3, 4, 5]; }";
+        let options = TreeOptions {
+            max_nodes: Some(6),
+            ..Default::default()
+        };
+        assert_matches_json_node(code, tree_sitter_rust::LANGUAGE.into(), &options);
+        let options = TreeOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_matches_json_node(code, tree_sitter_rust::LANGUAGE.into(), &options);
+    }
+
+    #[test]
+    fn test_streamed_envelope_includes_diagnostics() {
+        let code = "fn main() {\n    let x = 1\n}";
+        let tree = parse(code, tree_sitter_rust::LANGUAGE.into());
+        let text = streamed(code, &tree, OutputFormat::Yaml, &TreeOptions::default());
+        let document: Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(document["has_error"], true);
+        assert_eq!(document["diagnostics"][0]["message"], "Missing ;");
+    }
+
+    #[test]
+    fn test_pretty_json_layout_matches_serde_json() {
+        let value = serde_json::json!({"a": [], "b": {}, "c": [1, {"d": null}], "e": "x\ny"});
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(&mut out, true, Placement::Standalone);
+        write_json_value(&mut writer, &value).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            serde_json::to_string_pretty(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_yaml_layout_matches_serde_yaml() {
+        let value = serde_json::json!({
+            "a": [],
+            "b": {},
+            "c": [1, {"d": null, "e": [true]}],
+            "f": {"g": "h"},
+        });
+        let mut out = Vec::new();
+        let mut writer = YamlWriter::new(&mut out, Placement::Standalone);
+        write_json_value(&mut writer, &value).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            serde_yaml::to_string(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_aggregate_document_wraps_entries_in_every_format() {
+        let code = "fn main() {}";
+        let tree = parse(code, tree_sitter_rust::LANGUAGE.into());
+        let root = tree.root_node();
+        let envelope = Envelope {
+            version: "test",
+            filename: "main.rs",
+            language: "Rust",
+            detection: Some(Detection::Extension),
+            disambiguation: Some(Disambiguation {
+                strategy: Strategy::Tokens,
+                confidence: Confidence::High,
+            }),
+            has_error: false,
+            diagnostics: &[],
+        };
+        let header = RunHeader::new(vec!["src".to_string()]);
+        let mut summary = RunSummary::default();
+        summary.record(true, false);
+        summary.record(false, true);
+
+        for format in [
+            OutputFormat::Json,
+            OutputFormat::PrettyJson,
+            OutputFormat::Yaml,
+        ] {
+            let mut out = Vec::new();
+            let mut aggregate = AggregateWriter::begin(&mut out, format, &header).unwrap();
+            for _ in 0..2 {
+                aggregate
+                    .write_entry(|entry| {
+                        write_tree(
+                            entry,
+                            format,
+                            Placement::AggregateEntry,
+                            &envelope,
+                            code,
+                            root,
+                            &TreeOptions::default(),
+                        )
+                    })
+                    .unwrap();
+            }
+            aggregate.finish(&summary).unwrap();
+
+            let text = String::from_utf8(out).unwrap();
+            let document: Value = match format {
+                OutputFormat::Yaml => serde_yaml::from_str(&text).unwrap(),
+                _ => serde_json::from_str(&text).unwrap(),
+            };
+            let standalone: Value = serde_json::from_str(&streamed(
+                code,
+                &tree,
+                OutputFormat::Json,
+                &TreeOptions::default(),
+            ))
+            .unwrap();
+            assert_eq!(document["run"]["arguments"][0], "src", "{}", text);
+            assert!(document["run"]["parsers"]["tree-sitter-rust"].is_string());
+            assert_eq!(document["files"].as_array().unwrap().len(), 2);
+            assert_eq!(document["files"][0]["detection"], "extension", "{}", text);
+            assert_eq!(
+                document["files"][0]["disambiguation"]["confidence"], "high",
+                "{}",
+                text
+            );
+            assert_eq!(document["files"][1]["ast"], standalone["ast"], "{}", text);
+            assert_eq!(document["summary"]["files"], 2);
+            assert_eq!(document["summary"]["with_syntax_errors"], 1);
+        }
+    }
+
+    #[test]
+    fn test_rfc3339_utc() {
+        assert_eq!(rfc3339_utc(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + std::time::Duration::from_secs(951_827_696);
+        assert_eq!(rfc3339_utc(leap_day), "2000-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn test_yaml_quotes_ambiguous_strings() {
+        assert!(is_plain_yaml("function_item"));
+        assert!(is_plain_yaml("astgen-0.1"));
+        for ambiguous in ["", "(", "yes", "Null", "1.0", "a: b", "#x", " lead"] {
+            assert!(!is_plain_yaml(ambiguous), "{:?}", ambiguous);
+        }
+    }
+}
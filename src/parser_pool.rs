@@ -0,0 +1,126 @@
+//! Per-thread cache of Tree-sitter parsers, so worker threads reuse a configured parser
+//! for each language instead of creating one for every file
+use crate::error::Result;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tree_sitter::{Language, Parser};
+
+/// Number of parsers each thread keeps when `parser_pool_size` is not configured
+pub const DEFAULT_PARSER_POOL_SIZE: usize = 8;
+
+static POOL_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_PARSER_POOL_SIZE);
+
+thread_local! {
+    // Least recently used parser first
+    static PARSERS: RefCell<Vec<(Language, Parser)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Set how many parsers (one per language) each thread may keep; 0 disables reuse
+pub fn set_parser_pool_size(size: usize) {
+    POOL_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// The current per-thread parser limit
+pub fn parser_pool_size() -> usize {
+    POOL_SIZE.load(Ordering::Relaxed)
+}
+
+/// Run `f` with a parser for `language`, reusing this thread's cached parser when there is one
+///
+/// When the thread already holds `parser_pool_size` parsers, the least recently used one is
+/// dropped to make room.
+pub fn with_parser<T>(language: &Language, f: impl FnOnce(&mut Parser) -> T) -> Result<T> {
+    with_pooled_parser(language, parser_pool_size(), f)
+}
+
+fn with_pooled_parser<T>(
+    language: &Language,
+    capacity: usize,
+    f: impl FnOnce(&mut Parser) -> T,
+) -> Result<T> {
+    let cached = PARSERS.with_borrow_mut(|parsers| {
+        parsers
+            .iter()
+            .position(|(cached_language, _)| cached_language == language)
+            .map(|index| parsers.remove(index).1)
+    });
+
+    let mut parser = match cached {
+        Some(parser) => parser,
+        None => {
+            let mut parser = Parser::new();
+            parser.set_language(language)?;
+            parser
+        }
+    };
+
+    let result = f(&mut parser);
+
+    if capacity > 0 {
+        parser.reset();
+        PARSERS.with_borrow_mut(|parsers| {
+            parsers.push((language.clone(), parser));
+            if parsers.len() > capacity {
+                let excess = parsers.len() - capacity;
+                parsers.drain(..excess);
+            }
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test runs on its own thread, so the thread-local cache starts out empty
+    fn cached_languages() -> Vec<Language> {
+        PARSERS.with_borrow(|parsers| parsers.iter().map(|(l, _)| l.clone()).collect())
+    }
+
+    #[test]
+    fn test_parser_is_reused_per_language() {
+        let rust: Language = tree_sitter_rust::LANGUAGE.into();
+        let tree = with_parser(&rust, |parser| parser.parse("fn a() {}", None)).unwrap();
+        assert_eq!(tree.unwrap().root_node().kind(), "source_file");
+        with_parser(&rust, |parser| parser.parse("fn b() {}", None)).unwrap();
+
+        assert_eq!(cached_languages(), vec![rust]);
+    }
+
+    #[test]
+    fn test_pool_evicts_least_recently_used() {
+        let rust: Language = tree_sitter_rust::LANGUAGE.into();
+        let python: Language = tree_sitter_python::LANGUAGE.into();
+        let go: Language = tree_sitter_go::LANGUAGE.into();
+
+        with_pooled_parser(&rust, 2, |_| ()).unwrap();
+        with_pooled_parser(&python, 2, |_| ()).unwrap();
+        with_pooled_parser(&rust, 2, |_| ()).unwrap();
+        with_pooled_parser(&go, 2, |_| ()).unwrap();
+
+        assert_eq!(cached_languages(), vec![rust, go]);
+    }
+
+    #[test]
+    fn test_pool_size_zero_disables_caching() {
+        let rust: Language = tree_sitter_rust::LANGUAGE.into();
+        let tree = with_pooled_parser(&rust, 0, |parser| parser.parse("fn a() {}", None)).unwrap();
+        assert!(tree.is_some());
+        assert!(cached_languages().is_empty());
+    }
+
+    #[test]
+    fn test_reused_parser_switches_languages_correctly() {
+        let rust: Language = tree_sitter_rust::LANGUAGE.into();
+        let python: Language = tree_sitter_python::LANGUAGE.into();
+
+        for _ in 0..2 {
+            let rust_tree = with_pooled_parser(&rust, 1, |p| p.parse("fn a() {}", None)).unwrap();
+            assert_eq!(rust_tree.unwrap().root_node().kind(), "source_file");
+            let python_tree = with_pooled_parser(&python, 1, |p| p.parse("a = 1", None)).unwrap();
+            assert_eq!(python_tree.unwrap().root_node().kind(), "module");
+        }
+    }
+}
@@ -1,19 +1,164 @@
+use crate::detection::Detection;
+use crate::diagnostics::{collect_diagnostics, Diagnostic};
+use crate::disambiguation::Disambiguation;
 use crate::encoding::Encoding;
 use crate::error::{AstgenError, Result};
-use crate::json::JsonNode;
-use serde_json::json;
+use crate::json::{JsonNode, TreeOptions};
+use crate::node_table;
+use crate::output::{self, Envelope, OutputFormat, Placement};
+use crate::parser_pool;
+use crate::query::{run_query, QueryCapture};
+use arrow_array::RecordBatch;
+use serde::Serialize;
 use std::fs;
-use std::path::PathBuf;
-use tree_sitter::{Language, Parser};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tree_sitter::{Language, Query, Tree};
+
+/// Version tag written into every parsed file envelope
+pub const ENVELOPE_VERSION: &str = "astgen-0.1";
+
+/// A parsed source file: the AST together with the metadata describing where it came from
+#[derive(Serialize, Debug)]
+pub struct ParsedFile {
+    pub version: String,
+    pub filename: String,
+    pub language: String,
+    /// How the language was chosen, when astgen chose it rather than the caller
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Detection>,
+    /// How a language shared by the file's extension was chosen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disambiguation: Option<Disambiguation>,
+    /// True when Tree-sitter had to recover from at least one syntax error
+    pub has_error: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    pub ast: JsonNode,
+}
 
-pub fn parse_file_safe_with_size_limit(
-    path: PathBuf,
+/// The captures a query produced for one file, with the same metadata as `ParsedFile`
+#[derive(Serialize, Debug)]
+pub struct QueriedFile {
+    pub version: String,
+    pub filename: String,
+    pub language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Detection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disambiguation: Option<Disambiguation>,
+    pub has_error: bool,
+    pub captures: Vec<QueryCapture>,
+}
+
+/// A parsed file that still holds its Tree-sitter tree, so it can be streamed to a writer
+/// without first being converted into `JsonNode`s
+pub struct SourceTree {
+    pub filename: String,
+    pub language: String,
+    pub detection: Option<Detection>,
+    pub disambiguation: Option<Disambiguation>,
+    pub source: String,
+    pub tree: Tree,
+}
+
+impl SourceTree {
+    /// True when Tree-sitter had to recover from at least one syntax error
+    pub fn has_error(&self) -> bool {
+        self.tree.root_node().has_error()
+    }
+
+    pub fn diagnostics(&self, options: &TreeOptions) -> Vec<Diagnostic> {
+        collect_diagnostics(&self.source, self.tree.root_node(), options.column_encoding)
+    }
+
+    /// Write the same document `ParsedFile` serializes to, directly from the tree
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        format: OutputFormat,
+        options: &TreeOptions,
+    ) -> Result<()> {
+        self.write_placed(out, format, Placement::Standalone, options)
+    }
+
+    /// One node-table row per node, as in `node_table::node_rows`
+    pub fn node_rows(&self, options: &TreeOptions) -> Result<RecordBatch> {
+        node_table::node_rows(&self.filename, &self.source, self.tree.root_node(), options)
+    }
+
+    /// Like `write`, laid out for `placement`, e.g. as an entry in an aggregate document
+    pub fn write_placed(
+        &self,
+        out: &mut dyn Write,
+        format: OutputFormat,
+        placement: Placement,
+        options: &TreeOptions,
+    ) -> Result<()> {
+        let diagnostics = self.diagnostics(options);
+        let envelope = Envelope {
+            version: ENVELOPE_VERSION,
+            filename: &self.filename,
+            language: &self.language,
+            detection: self.detection,
+            disambiguation: self.disambiguation,
+            has_error: self.has_error(),
+            diagnostics: &diagnostics,
+        };
+        output::write_tree(
+            out,
+            format,
+            placement,
+            &envelope,
+            &self.source,
+            self.tree.root_node(),
+            options,
+        )
+    }
+}
+
+/// Read and parse a file into a `SourceTree`, refusing files larger than `max_size_bytes`
+pub fn parse_file_tree(
+    path: &Path,
     encoding: &Encoding,
-    truncate: Option<usize>,
     max_size_bytes: usize,
-) -> Result<String> {
+) -> Result<SourceTree> {
+    let content = read_source(path, max_size_bytes)?;
+    parse_source_tree(content, &path.to_string_lossy(), encoding)
+}
+
+/// Parse source code that has already been loaded into memory into a `SourceTree`
+pub fn parse_source_tree(
+    content: String,
+    filename: &str,
+    encoding: &Encoding,
+) -> Result<SourceTree> {
+    let tree = parse_tree(&content, encoding.language)?;
+    Ok(SourceTree {
+        filename: filename.to_string(),
+        language: encoding.name.clone(),
+        detection: None,
+        disambiguation: None,
+        source: content,
+        tree,
+    })
+}
+
+/// Read and parse a file, refusing files larger than `max_size_bytes`
+pub fn parse_file(
+    path: &Path,
+    encoding: &Encoding,
+    max_size_bytes: usize,
+    options: &TreeOptions,
+) -> Result<ParsedFile> {
+    let content = read_source(path, max_size_bytes)?;
+    parse_source(&content, &path.to_string_lossy(), encoding, options)
+}
+
+/// Read a file as UTF-8 text, refusing files larger than `max_size_bytes`
+pub fn read_source(path: &Path, max_size_bytes: usize) -> Result<String> {
     // Check file size before reading
-    let metadata = fs::metadata(&path)?;
+    let metadata = fs::metadata(path)?;
     let file_size = metadata.len() as usize;
 
     if file_size > max_size_bytes {
@@ -24,7 +169,7 @@ pub fn parse_file_safe_with_size_limit(
         });
     }
 
-    let content = fs::read_to_string(&path).map_err(|e| {
+    let content = fs::read_to_string(path).map_err(|e| {
         if e.kind() == std::io::ErrorKind::InvalidData {
             AstgenError::InvalidInput(format!(
                 "File contains invalid UTF-8: {}\nTry converting the file to UTF-8 encoding first.",
@@ -35,45 +180,110 @@ pub fn parse_file_safe_with_size_limit(
         }
     })?;
 
-    let json_tree = build_parse_tree_safe(&content, encoding.language)?;
-
-    let wrapped_json = json!({
-        "version": "astgen-0.1",
-        "filename": path.to_string_lossy(),
-        "language": encoding.name,
-        "ast": json_tree
-    });
-
-    let json_output = match truncate {
-        Some(len) => {
-            let full_output = serde_json::to_string(&wrapped_json)?;
-            if full_output.len() > len {
-                let mut truncated = full_output[..len].to_string();
-                // Try to end at a reasonable boundary
-                if let Some(last_brace) = truncated.rfind('}') {
-                    truncated.truncate(last_brace + 1);
-                }
-                truncated
-            } else {
-                full_output
-            }
-        }
-        None => serde_json::to_string(&wrapped_json)?,
-    };
+    Ok(content)
+}
 
-    Ok(json_output)
+/// Read UTF-8 text from a stream such as stdin, refusing streams longer than `max_size_bytes`;
+/// `name` stands for the stream in errors
+pub fn read_source_from(
+    mut reader: impl Read,
+    name: &str,
+    max_size_bytes: usize,
+) -> Result<String> {
+    let mut bytes = Vec::new();
+    (&mut reader)
+        .take(max_size_bytes as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > max_size_bytes {
+        // Count the rest without keeping it, to report the full size
+        let rest = std::io::copy(&mut reader, &mut std::io::sink())?;
+        return Err(AstgenError::FileTooLarge {
+            path: name.to_string(),
+            size: bytes.len() + rest as usize,
+            limit: max_size_bytes,
+        });
+    }
+    String::from_utf8(bytes).map_err(|_| {
+        AstgenError::InvalidInput(format!(
+            "{} contains invalid UTF-8\nTry converting the input to UTF-8 encoding first.",
+            name
+        ))
+    })
 }
 
-fn build_parse_tree_safe(content: &str, lang: &Language) -> Result<JsonNode> {
-    let mut parser = Parser::new();
-    parser.set_language(lang)?;
+/// Parse source code that has already been loaded into memory
+pub fn parse_source(
+    content: &str,
+    filename: &str,
+    encoding: &Encoding,
+    options: &TreeOptions,
+) -> Result<ParsedFile> {
+    let tree = parse_tree(content, encoding.language)?;
+    let root_node = tree.root_node();
 
-    let tree = parser
-        .parse(content, None)
-        .ok_or_else(|| AstgenError::ParseError("Failed to parse content".to_string()))?;
+    Ok(ParsedFile {
+        version: ENVELOPE_VERSION.to_string(),
+        filename: filename.to_string(),
+        language: encoding.name.clone(),
+        detection: None,
+        disambiguation: None,
+        has_error: root_node.has_error(),
+        diagnostics: collect_diagnostics(content, root_node, options.column_encoding),
+        ast: crate::json::node_to_json_with_options(content, root_node, options),
+    })
+}
+
+/// Parse a file and run a compiled query over it, keeping only the captures
+pub fn query_file(
+    path: &Path,
+    encoding: &Encoding,
+    max_size_bytes: usize,
+    query: &Query,
+    options: &TreeOptions,
+) -> Result<QueriedFile> {
+    let content = read_source(path, max_size_bytes)?;
+    query_source(&content, &path.to_string_lossy(), encoding, query, options)
+}
 
+/// Run a compiled query over source code that has already been loaded into memory
+pub fn query_source(
+    content: &str,
+    filename: &str,
+    encoding: &Encoding,
+    query: &Query,
+    options: &TreeOptions,
+) -> Result<QueriedFile> {
+    let tree = parse_tree(content, encoding.language)?;
     let root_node = tree.root_node();
-    Ok(crate::json::node_to_json(content, root_node))
+
+    Ok(QueriedFile {
+        version: ENVELOPE_VERSION.to_string(),
+        filename: filename.to_string(),
+        language: encoding.name.clone(),
+        detection: None,
+        disambiguation: None,
+        has_error: root_node.has_error(),
+        captures: run_query(query, root_node, content, options.column_encoding),
+    })
+}
+
+/// Parse a file and serialize the result to a single line of JSON
+pub fn parse_file_safe_with_size_limit(
+    path: PathBuf,
+    encoding: &Encoding,
+    max_size_bytes: usize,
+    options: &TreeOptions,
+) -> Result<String> {
+    let source_tree = parse_file_tree(&path, encoding, max_size_bytes)?;
+    let mut json = Vec::new();
+    source_tree.write(&mut json, OutputFormat::Json, options)?;
+    json.pop(); // trailing newline
+    String::from_utf8(json).map_err(|e| AstgenError::SerializationError(e.to_string()))
+}
+
+pub(crate) fn parse_tree(content: &str, lang: &Language) -> Result<Tree> {
+    parser_pool::with_parser(lang, |parser| parser.parse(content, None))?
+        .ok_or_else(|| AstgenError::ParseError("Failed to parse content".to_string()))
 }
 
 #[cfg(test)]
@@ -82,6 +292,19 @@ mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    fn build_parse_tree_safe(
+        content: &str,
+        lang: &Language,
+        options: &TreeOptions,
+    ) -> Result<JsonNode> {
+        let tree = parse_tree(content, lang)?;
+        Ok(crate::json::node_to_json_with_options(
+            content,
+            tree.root_node(),
+            options,
+        ))
+    }
+
     fn create_temp_file(content: &str, extension: &str) -> NamedTempFile {
         let mut file = NamedTempFile::new().unwrap();
         let _file_path = file.path().to_str().unwrap();
@@ -107,8 +330,8 @@ mod tests {
         let result = parse_file_safe_with_size_limit(
             temp_file.path().to_path_buf(),
             &encoding,
-            None,
             10_000_000,
+            &TreeOptions::default(),
         );
         assert!(result.is_ok());
     }
@@ -119,14 +342,19 @@ mod tests {
         let rust_language = tree_sitter_rust::LANGUAGE.into();
         let encoding = Encoding::new("rs$", &rust_language, "Rust");
 
-        // Test with truncation - should still succeed
+        // Test with truncation - should still succeed and produce valid JSON
+        let options = TreeOptions {
+            max_output_bytes: Some(100),
+            ..Default::default()
+        };
         let result = parse_file_safe_with_size_limit(
             temp_file.path().to_path_buf(),
             &encoding,
-            Some(100),
             10_000_000,
+            &options,
         );
-        assert!(result.is_ok());
+        let json: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+        assert_eq!(json["ast"]["truncated"], true);
     }
 
     #[test]
@@ -138,8 +366,8 @@ mod tests {
         let result = parse_file_safe_with_size_limit(
             temp_file.path().to_path_buf(),
             &encoding,
-            None,
             10_000_000,
+            &TreeOptions::default(),
         );
         assert!(result.is_ok());
     }
@@ -153,8 +381,8 @@ mod tests {
         let result = parse_file_safe_with_size_limit(
             temp_file.path().to_path_buf(),
             &encoding,
-            None,
             10_000_000,
+            &TreeOptions::default(),
         );
         assert!(result.is_ok());
     }
@@ -168,18 +396,137 @@ mod tests {
         let result = parse_file_safe_with_size_limit(
             temp_file.path().to_path_buf(),
             &encoding,
-            None,
             10_000_000,
+            &TreeOptions::default(),
         );
         assert!(result.is_ok()); // Empty files should parse successfully
     }
 
+    #[test]
+    fn test_parse_file_returns_typed_envelope() {
+        let temp_file = create_temp_file("fn main() {}", "rs");
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let encoding = Encoding::new("rs$", &rust_language, "Rust");
+
+        let parsed = parse_file(
+            temp_file.path(),
+            &encoding,
+            10_000_000,
+            &TreeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(parsed.version, ENVELOPE_VERSION);
+        assert_eq!(parsed.language, "Rust");
+        assert_eq!(parsed.filename, temp_file.path().to_string_lossy());
+        assert_eq!(parsed.ast.kind, "source_file");
+    }
+
+    #[test]
+    fn test_parse_file_rejects_oversized_file() {
+        let temp_file = create_temp_file("fn main() {}", "rs");
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let encoding = Encoding::new("rs$", &rust_language, "Rust");
+
+        let err = parse_file(temp_file.path(), &encoding, 4, &TreeOptions::default()).unwrap_err();
+        match err {
+            AstgenError::FileTooLarge { size, limit, .. } => {
+                assert_eq!(size, 12);
+                assert_eq!(limit, 4);
+            }
+            _ => panic!("Expected FileTooLarge, got: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_read_source_from_stream() {
+        let source = read_source_from("fn main() {}".as_bytes(), "<stdin>", 100).unwrap();
+        assert_eq!(source, "fn main() {}");
+
+        let err = read_source_from("fn main() {}".as_bytes(), "<stdin>", 4).unwrap_err();
+        match err {
+            AstgenError::FileTooLarge { path, size, limit } => {
+                assert_eq!(path, "<stdin>");
+                assert_eq!(size, 12);
+                assert_eq!(limit, 4);
+            }
+            _ => panic!("Expected FileTooLarge, got: {:?}", err),
+        }
+
+        let err = read_source_from(&[0xff, 0xfe][..], "<stdin>", 100).unwrap_err();
+        assert!(err.to_string().contains("<stdin> contains invalid UTF-8"));
+    }
+
+    #[test]
+    fn test_parse_source_uses_given_filename() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let encoding = Encoding::new("rs$", &rust_language, "Rust");
+
+        let parsed = parse_source(
+            "fn main() {}",
+            "buffer.rs",
+            &encoding,
+            &TreeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(parsed.filename, "buffer.rs");
+        assert_eq!(parsed.ast.end_byte, 12);
+    }
+
+    #[test]
+    fn test_parse_source_reports_syntax_errors() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let encoding = Encoding::new("rs$", &rust_language, "Rust");
+        let options = TreeOptions::default();
+
+        let clean = parse_source("fn main() {}", "ok.rs", &encoding, &options).unwrap();
+        assert!(!clean.has_error);
+        assert!(clean.diagnostics.is_empty());
+        let json = serde_json::to_value(&clean).unwrap();
+        assert_eq!(json["has_error"], false);
+        assert!(json.get("diagnostics").is_none());
+
+        let broken =
+            parse_source("fn main() { let x = 1 }", "bad.rs", &encoding, &options).unwrap();
+        assert!(broken.has_error);
+        assert_eq!(broken.diagnostics.len(), 1);
+        let json = serde_json::to_value(&broken).unwrap();
+        assert_eq!(json["has_error"], true);
+        assert_eq!(json["diagnostics"][0]["kind"], "missing");
+        assert_eq!(json["diagnostics"][0]["snippet"], "fn main() { let x = 1 }");
+    }
+
+    #[test]
+    fn test_query_source_returns_captures() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let encoding = Encoding::new("rs$", &rust_language, "Rust");
+        let query = Query::new(&rust_language, "(function_item name: (identifier) @name)").unwrap();
+
+        let queried = query_source(
+            "fn main() {}",
+            "main.rs",
+            &encoding,
+            &query,
+            &TreeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(queried.language, "Rust");
+        assert_eq!(queried.captures.len(), 1);
+        let json = serde_json::to_value(&queried).unwrap();
+        assert_eq!(json["captures"][0]["name"], "name");
+        assert_eq!(json["captures"][0]["text"], "main");
+        assert!(json.get("ast").is_none());
+    }
+
     #[test]
     fn test_build_parse_tree_rust() {
         let _temp_file = create_temp_file("fn main() {}", "rs");
         let rust_language = tree_sitter_rust::LANGUAGE.into();
 
-        let json_node = build_parse_tree_safe("fn main() {}", &rust_language).unwrap();
+        let json_node =
+            build_parse_tree_safe("fn main() {}", &rust_language, &TreeOptions::default()).unwrap();
 
         assert_eq!(json_node.kind, "source_file");
         assert!(json_node.children.is_some());
@@ -191,7 +538,8 @@ mod tests {
         let _temp_file = create_temp_file(content, "rs");
         let rust_language = tree_sitter_rust::LANGUAGE.into();
 
-        let json_node = build_parse_tree_safe(content, &rust_language).unwrap();
+        let json_node =
+            build_parse_tree_safe(content, &rust_language, &TreeOptions::default()).unwrap();
 
         assert_eq!(json_node.start_byte, 0);
         assert_eq!(json_node.end_byte, content.len());
@@ -212,7 +560,8 @@ mod tests {
         ];
 
         for (content, _ext, language) in test_cases {
-            let json_node = build_parse_tree_safe(content, &language).unwrap();
+            let json_node =
+                build_parse_tree_safe(content, &language, &TreeOptions::default()).unwrap();
 
             assert_eq!(json_node.start_byte, 0);
             assert_eq!(json_node.end_byte, content.len());
@@ -0,0 +1,2489 @@
+//! Python `ast` output for Python files
+//!
+//! Nodes have the layout `ast2json` gives the classes of CPython 3.13's `ast` module: `_type`
+//! holds the class name (`Module`, `FunctionDef`, `Name`, ...), followed by the class's fields in
+//! `_fields` order and, for classes with a position, `lineno`, `col_offset`, `end_lineno` and
+//! `end_col_offset`. Lines are 1-based and columns count UTF-8 bytes as CPython's do, or UTF-16
+//! code units with `ColumnEncoding::Utf16`. Operators and expression contexts are nodes with only
+//! a `_type`, e.g. `{"_type": "Load"}`.
+//!
+//! `Constant` values are JSON scalars, except that complex numbers are written as their `str()`
+//! (`"2j"`), `...` as `"..."`, infinite floats as `"inf"`, bytes as their UTF-8 text (or hex
+//! digits when they are not valid UTF-8) and integers that do not fit 64 bits as decimal strings.
+//! Without CPython's Unicode database, `\N{...}` escapes are kept as written, lone surrogates
+//! become U+FFFD and identifiers are not NFKC-normalized.
+//!
+//! Files CPython cannot parse still convert: `ERROR` nodes and Python 2 statements become
+//! `Unknown` nodes that record the Tree-sitter `kind`.
+use crate::json::{
+    ColumnEncoding, Position, TreeOptions, STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES,
+};
+use crate::schema::{AstValue, Span};
+use std::cell::Cell;
+use tree_sitter::{Node, Point};
+
+/// Encodings whose trees can be converted
+pub const LANGUAGES: &[&str] = &["Python"];
+
+type Props = Vec<(&'static str, AstValue)>;
+
+/// Statements that contain blocks
+const COMPOUND_STATEMENTS: &[&str] = &[
+    "class_definition",
+    "decorated_definition",
+    "for_statement",
+    "function_definition",
+    "if_statement",
+    "match_statement",
+    "try_statement",
+    "while_statement",
+    "with_statement",
+];
+
+/// How an expression is used, CPython's `expr_context`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Load,
+    Store,
+    Del,
+}
+
+impl Context {
+    fn value(self) -> AstValue {
+        tag(match self {
+            Context::Load => "Load",
+            Context::Store => "Store",
+            Context::Del => "Del",
+        })
+    }
+}
+
+/// Convert the tree of a Python file into an `ast.Module`
+pub fn to_python_ast(source_code: &str, root: Node, options: &TreeOptions) -> AstValue {
+    let converter = Converter {
+        source: source_code,
+        encoding: options.column_encoding,
+        unwrapped_splat: Cell::new(None),
+    };
+    converter.module(root)
+}
+
+struct Converter<'a> {
+    source: &'a str,
+    encoding: ColumnEncoding,
+    /// Id of the `list_splat` whose `Starred` was moved outside the call or attribute around it
+    unwrapped_splat: Cell<Option<usize>>,
+}
+
+/// Literal text of an f-string waiting to be merged with the text that follows it
+struct PendingLiteral {
+    value: String,
+    span: Span,
+}
+
+impl Converter<'_> {
+    fn text(&self, node: Node) -> &str {
+        &self.source[node.start_byte()..node.end_byte()]
+    }
+
+    fn column(&self, byte: usize, point: Point) -> usize {
+        Position::from_point(self.source, point, byte, self.encoding).column
+    }
+
+    /// A node of class `ty` covering `span`
+    fn make(&self, ty: &'static str, span: impl Into<Span>, props: Props) -> AstValue {
+        let span = span.into();
+        let mut entries = vec![("_type", ty.into())];
+        entries.extend(props);
+        entries.extend([
+            ("lineno", (span.start.row + 1).into()),
+            (
+                "col_offset",
+                self.column(span.start_byte, span.start).into(),
+            ),
+            ("end_lineno", (span.end.row + 1).into()),
+            (
+                "end_col_offset",
+                self.column(span.end_byte, span.end).into(),
+            ),
+        ]);
+        AstValue::Map(entries)
+    }
+
+    /// A compound statement or clause, which ends with its last nested statement
+    fn compound(&self, ty: &'static str, node: Node, props: Props) -> AstValue {
+        self.make(ty, Span::from(node).to(compound_end(node)), props)
+    }
+
+    fn unknown(&self, node: Node) -> AstValue {
+        self.make("Unknown", node, vec![("kind", node.kind().into())])
+    }
+
+    fn name(&self, node: Node, ctx: Context) -> AstValue {
+        self.make(
+            "Name",
+            node,
+            vec![("id", self.text(node).into()), ("ctx", ctx.value())],
+        )
+    }
+
+    fn optional_text(&self, node: Option<Node>) -> AstValue {
+        node.map_or(AstValue::Null, |node| self.text(node).into())
+    }
+
+    fn optional(&self, node: Option<Node>, ctx: Context) -> AstValue {
+        node.map_or(AstValue::Null, |node| self.expr(node, ctx))
+    }
+
+    fn list<'tree>(&self, nodes: impl IntoIterator<Item = Node<'tree>>, ctx: Context) -> AstValue {
+        nodes
+            .into_iter()
+            .map(|node| self.expr(node, ctx))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// A `Tuple` of `nodes`, which are the elements of a tuple written without parentheses
+    fn tuple(&self, nodes: &[Node], span: impl Into<Span>, ctx: Context) -> AstValue {
+        self.make(
+            "Tuple",
+            span,
+            vec![
+                ("elts", self.list(nodes.iter().copied(), ctx)),
+                ("ctx", ctx.value()),
+            ],
+        )
+    }
+
+    /// The one expression in `nodes`, or a `Tuple` of several
+    fn expr_or_tuple(&self, nodes: &[Node], ctx: Context) -> AstValue {
+        match nodes {
+            [] => AstValue::Null,
+            [node] => self.expr(*node, ctx),
+            [first, .., last] => self.tuple(nodes, Span::from(*first).to(*last), ctx),
+        }
+    }
+
+    fn module(&self, root: Node) -> AstValue {
+        node_value(
+            "Module",
+            vec![
+                ("body", self.body(Some(root))),
+                ("type_ignores", AstValue::List(Vec::new())),
+            ],
+        )
+    }
+
+    /// The statements of a module or block
+    fn body(&self, node: Option<Node>) -> AstValue {
+        node.map_or_else(Vec::new, named_children)
+            .into_iter()
+            .map(|child| self.statement(child))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn field_body(&self, node: Node, field: &str) -> AstValue {
+        self.body(node.child_by_field_name(field))
+    }
+
+    /// The body of the `else` clause in the `alternative` field of a loop
+    fn else_body(&self, node: Node) -> AstValue {
+        self.body(
+            node.child_by_field_name("alternative")
+                .and_then(|clause| clause.child_by_field_name("body")),
+        )
+    }
+
+    fn statement(&self, node: Node) -> AstValue {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.statement_node(node)
+        })
+    }
+
+    fn statement_node(&self, node: Node) -> AstValue {
+        match node.kind() {
+            "expression_statement" => self.expression_statement(node),
+            "return_statement" => self.make(
+                "Return",
+                node,
+                vec![(
+                    "value",
+                    self.optional(named_children(node).first().copied(), Context::Load),
+                )],
+            ),
+            "delete_statement" => {
+                let targets = match named_children(node).first() {
+                    Some(list) if list.kind() == "expression_list" => {
+                        self.list(named_children(*list), Context::Del)
+                    }
+                    target => self.list(target.copied(), Context::Del),
+                };
+                self.make("Delete", node, vec![("targets", targets)])
+            }
+            "pass_statement" => self.make("Pass", node, Vec::new()),
+            "break_statement" => self.make("Break", node, Vec::new()),
+            "continue_statement" => self.make("Continue", node, Vec::new()),
+            "global_statement" | "nonlocal_statement" => {
+                let names = named_children(node)
+                    .into_iter()
+                    .map(|name| self.text(name).into())
+                    .collect::<Vec<_>>();
+                let ty = if node.kind() == "global_statement" {
+                    "Global"
+                } else {
+                    "Nonlocal"
+                };
+                self.make(ty, node, vec![("names", names.into())])
+            }
+            "assert_statement" => {
+                let children = named_children(node);
+                self.make(
+                    "Assert",
+                    node,
+                    vec![
+                        (
+                            "test",
+                            self.optional(children.first().copied(), Context::Load),
+                        ),
+                        (
+                            "msg",
+                            self.optional(children.get(1).copied(), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            "raise_statement" => {
+                let cause = node.child_by_field_name("cause");
+                let exc = named_children(node)
+                    .into_iter()
+                    .find(|child| Some(*child) != cause);
+                self.make(
+                    "Raise",
+                    node,
+                    vec![
+                        ("exc", self.optional(exc, Context::Load)),
+                        ("cause", self.optional(cause, Context::Load)),
+                    ],
+                )
+            }
+            "import_statement" => {
+                self.make("Import", node, vec![("names", self.aliases(node).into())])
+            }
+            "import_from_statement" => self.import_from(node),
+            "future_import_statement" => self.make(
+                "ImportFrom",
+                node,
+                vec![
+                    ("module", "__future__".into()),
+                    ("names", self.aliases(node).into()),
+                    ("level", 0.into()),
+                ],
+            ),
+            "if_statement" => self.if_statement(node),
+            "for_statement" => {
+                let ty = if has_token(node, "async") {
+                    "AsyncFor"
+                } else {
+                    "For"
+                };
+                self.compound(
+                    ty,
+                    node,
+                    vec![
+                        (
+                            "target",
+                            self.optional(node.child_by_field_name("left"), Context::Store),
+                        ),
+                        (
+                            "iter",
+                            self.optional(node.child_by_field_name("right"), Context::Load),
+                        ),
+                        ("body", self.field_body(node, "body")),
+                        ("orelse", self.else_body(node)),
+                        ("type_comment", AstValue::Null),
+                    ],
+                )
+            }
+            "while_statement" => self.compound(
+                "While",
+                node,
+                vec![
+                    (
+                        "test",
+                        self.optional(node.child_by_field_name("condition"), Context::Load),
+                    ),
+                    ("body", self.field_body(node, "body")),
+                    ("orelse", self.else_body(node)),
+                ],
+            ),
+            "try_statement" => self.try_statement(node),
+            "with_statement" => self.with_statement(node),
+            "function_definition" => self.function(node, Vec::new()),
+            "class_definition" => self.class(node, Vec::new()),
+            "decorated_definition" => {
+                let decorators = named_children(node)
+                    .into_iter()
+                    .filter(|child| child.kind() == "decorator")
+                    .map(|decorator| {
+                        self.optional(named_children(decorator).first().copied(), Context::Load)
+                    })
+                    .collect();
+                match node.child_by_field_name("definition") {
+                    Some(definition) if definition.kind() == "class_definition" => {
+                        self.class(definition, decorators)
+                    }
+                    Some(definition) => self.function(definition, decorators),
+                    None => self.unknown(node),
+                }
+            }
+            "match_statement" => self.match_statement(node),
+            "type_alias_statement" => self.type_alias(node),
+            _ => self.unknown(node),
+        }
+    }
+
+    fn expression_statement(&self, node: Node) -> AstValue {
+        let children = named_children(node);
+        match children.as_slice() {
+            [child] if child.kind() == "assignment" => self.assignment(node, *child),
+            [child] if child.kind() == "augmented_assignment" => {
+                let operator = child
+                    .child_by_field_name("operator")
+                    .map_or("", |operator| operator.kind().trim_end_matches('='));
+                self.make(
+                    "AugAssign",
+                    node,
+                    vec![
+                        (
+                            "target",
+                            self.optional(child.child_by_field_name("left"), Context::Store),
+                        ),
+                        ("op", tag(binary_operator(operator))),
+                        (
+                            "value",
+                            self.optional(child.child_by_field_name("right"), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            [child] if !has_token(node, ",") => self.make(
+                "Expr",
+                node,
+                vec![("value", self.expr(*child, Context::Load))],
+            ),
+            _ => self.make(
+                "Expr",
+                node,
+                vec![("value", self.tuple(&children, node, Context::Load))],
+            ),
+        }
+    }
+
+    /// `Assign` for `a = b = value`, or `AnnAssign` for an annotated target
+    fn assignment(&self, statement: Node, node: Node) -> AstValue {
+        let left = node.child_by_field_name("left");
+        let right = node.child_by_field_name("right");
+        if let Some(annotation) = node.child_by_field_name("type") {
+            let simple = left.is_some_and(|left| left.kind() == "identifier");
+            return self.make(
+                "AnnAssign",
+                statement,
+                vec![
+                    ("target", self.optional(left, Context::Store)),
+                    ("annotation", self.type_expr(annotation)),
+                    ("value", self.optional(right, Context::Load)),
+                    ("simple", usize::from(simple).into()),
+                ],
+            );
+        }
+        let mut targets = vec![self.optional(left, Context::Store)];
+        let mut value = right;
+        while let Some(chained) = value.filter(|value| {
+            value.kind() == "assignment" && value.child_by_field_name("type").is_none()
+        }) {
+            targets.push(self.optional(chained.child_by_field_name("left"), Context::Store));
+            value = chained.child_by_field_name("right");
+        }
+        self.make(
+            "Assign",
+            statement,
+            vec![
+                ("targets", targets.into()),
+                ("value", self.optional(value, Context::Load)),
+                ("type_comment", AstValue::Null),
+            ],
+        )
+    }
+
+    fn aliases(&self, node: Node) -> Vec<AstValue> {
+        field_children(node, "name")
+            .into_iter()
+            .map(|name| self.alias(name))
+            .collect()
+    }
+
+    fn alias(&self, node: Node) -> AstValue {
+        let (name, asname) = match node.kind() {
+            "aliased_import" => (
+                node.child_by_field_name("name"),
+                node.child_by_field_name("alias"),
+            ),
+            _ => (Some(node), None),
+        };
+        self.make(
+            "alias",
+            node,
+            vec![
+                (
+                    "name",
+                    name.map_or(AstValue::Null, |name| self.dotted(name).into()),
+                ),
+                ("asname", self.optional_text(asname)),
+            ],
+        )
+    }
+
+    /// A dotted module name, without any whitespace or comments between its parts
+    fn dotted(&self, node: Node) -> String {
+        named_children(node)
+            .into_iter()
+            .map(|part| self.text(part))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn import_from(&self, node: Node) -> AstValue {
+        let (module, level) = match node.child_by_field_name("module_name") {
+            Some(relative) if relative.kind() == "relative_import" => {
+                let level = children(relative)
+                    .into_iter()
+                    .filter(|child| child.kind() == "import_prefix")
+                    .map(|prefix| self.text(prefix).matches('.').count())
+                    .sum::<usize>();
+                let module = named_children(relative)
+                    .into_iter()
+                    .find(|child| child.kind() == "dotted_name")
+                    .map_or(AstValue::Null, |name| self.dotted(name).into());
+                (module, level)
+            }
+            Some(name) => (self.dotted(name).into(), 0),
+            None => (AstValue::Null, 0),
+        };
+        let mut names = self.aliases(node);
+        if let Some(wildcard) = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "wildcard_import")
+        {
+            names.push(self.make(
+                "alias",
+                wildcard,
+                vec![("name", "*".into()), ("asname", AstValue::Null)],
+            ));
+        }
+        self.make(
+            "ImportFrom",
+            node,
+            vec![
+                ("module", module),
+                ("names", names.into()),
+                ("level", level.into()),
+            ],
+        )
+    }
+
+    /// `If`, with every `elif` clause as an `If` in the `orelse` of the one before it
+    fn if_statement(&self, node: Node) -> AstValue {
+        let mut orelse = AstValue::List(Vec::new());
+        for clause in field_children(node, "alternative").into_iter().rev() {
+            orelse = match clause.kind() {
+                "else_clause" => self.field_body(clause, "body"),
+                _ => vec![self.make(
+                    "If",
+                    Span::from(clause).to(compound_end(node)),
+                    vec![
+                        (
+                            "test",
+                            self.optional(clause.child_by_field_name("condition"), Context::Load),
+                        ),
+                        ("body", self.field_body(clause, "consequence")),
+                        ("orelse", orelse),
+                    ],
+                )]
+                .into(),
+            };
+        }
+        self.compound(
+            "If",
+            node,
+            vec![
+                (
+                    "test",
+                    self.optional(node.child_by_field_name("condition"), Context::Load),
+                ),
+                ("body", self.field_body(node, "consequence")),
+                ("orelse", orelse),
+            ],
+        )
+    }
+
+    fn try_statement(&self, node: Node) -> AstValue {
+        let mut handlers = Vec::new();
+        let mut orelse = AstValue::List(Vec::new());
+        let mut finalbody = AstValue::List(Vec::new());
+        let mut star = false;
+        for clause in named_children(node) {
+            match clause.kind() {
+                "except_clause" | "except_group_clause" => {
+                    star |= clause.kind() == "except_group_clause" || has_token(clause, "*");
+                    handlers.push(self.except_handler(clause));
+                }
+                "else_clause" => orelse = self.field_body(clause, "body"),
+                "finally_clause" => {
+                    finalbody = self.body(
+                        named_children(clause)
+                            .into_iter()
+                            .find(|child| child.kind() == "block"),
+                    )
+                }
+                _ => {}
+            }
+        }
+        self.compound(
+            if star { "TryStar" } else { "Try" },
+            node,
+            vec![
+                ("body", self.field_body(node, "body")),
+                ("handlers", handlers.into()),
+                ("orelse", orelse),
+                ("finalbody", finalbody),
+            ],
+        )
+    }
+
+    fn except_handler(&self, node: Node) -> AstValue {
+        let values = field_children(node, "value");
+        let (ty, name) = match values.as_slice() {
+            [value] if value.kind() == "as_pattern" => {
+                let children = named_children(*value);
+                let alias = value
+                    .child_by_field_name("alias")
+                    .and_then(|target| named_children(target).first().copied());
+                (
+                    self.optional(children.first().copied(), Context::Load),
+                    self.optional_text(alias),
+                )
+            }
+            // Python 2's `except Error, name:`
+            _ => (
+                self.expr_or_tuple(&values, Context::Load),
+                self.optional_text(node.child_by_field_name("alias")),
+            ),
+        };
+        let body = named_children(node)
+            .into_iter()
+            .find(|child| child.kind() == "block");
+        self.compound(
+            "ExceptHandler",
+            node,
+            vec![("type", ty), ("name", name), ("body", self.body(body))],
+        )
+    }
+
+    fn with_statement(&self, node: Node) -> AstValue {
+        let items = named_children(node)
+            .into_iter()
+            .filter(|child| child.kind() == "with_clause")
+            .flat_map(named_children)
+            .filter(|item| item.kind() == "with_item")
+            .map(|item| self.with_item(item))
+            .collect::<Vec<_>>();
+        let ty = if has_token(node, "async") {
+            "AsyncWith"
+        } else {
+            "With"
+        };
+        self.compound(
+            ty,
+            node,
+            vec![
+                ("items", items.into()),
+                ("body", self.field_body(node, "body")),
+                ("type_comment", AstValue::Null),
+            ],
+        )
+    }
+
+    fn with_item(&self, node: Node) -> AstValue {
+        // `with (f() as x):` reads as a parenthesized `as` pattern
+        let value =
+            node.child_by_field_name("value")
+                .map(|value| match named_children(value).as_slice() {
+                    [pattern]
+                        if value.kind() == "parenthesized_expression"
+                            && pattern.kind() == "as_pattern" =>
+                    {
+                        *pattern
+                    }
+                    _ => value,
+                });
+        let (context_expr, optional_vars) = match value {
+            Some(pattern) if pattern.kind() == "as_pattern" => {
+                let target = pattern
+                    .child_by_field_name("alias")
+                    .and_then(|target| named_children(target).first().copied());
+                (
+                    named_children(pattern).first().copied(),
+                    self.optional(target, Context::Store),
+                )
+            }
+            value => (value, AstValue::Null),
+        };
+        node_value(
+            "withitem",
+            vec![
+                ("context_expr", self.optional(context_expr, Context::Load)),
+                ("optional_vars", optional_vars),
+            ],
+        )
+    }
+
+    fn function(&self, node: Node, decorators: Vec<AstValue>) -> AstValue {
+        let ty = if has_token(node, "async") {
+            "AsyncFunctionDef"
+        } else {
+            "FunctionDef"
+        };
+        self.compound(
+            ty,
+            node,
+            vec![
+                ("name", self.optional_text(node.child_by_field_name("name"))),
+                (
+                    "args",
+                    self.arguments(node.child_by_field_name("parameters")),
+                ),
+                ("body", self.field_body(node, "body")),
+                ("decorator_list", decorators.into()),
+                (
+                    "returns",
+                    node.child_by_field_name("return_type")
+                        .map_or(AstValue::Null, |returns| self.type_expr(returns)),
+                ),
+                ("type_comment", AstValue::Null),
+                (
+                    "type_params",
+                    self.type_params(node.child_by_field_name("type_parameters")),
+                ),
+            ],
+        )
+    }
+
+    fn class(&self, node: Node, decorators: Vec<AstValue>) -> AstValue {
+        let (bases, keywords) = node
+            .child_by_field_name("superclasses")
+            .map_or_else(Default::default, |arguments| self.call_arguments(arguments));
+        self.compound(
+            "ClassDef",
+            node,
+            vec![
+                ("name", self.optional_text(node.child_by_field_name("name"))),
+                ("bases", bases.into()),
+                ("keywords", keywords.into()),
+                ("body", self.field_body(node, "body")),
+                ("decorator_list", decorators.into()),
+                (
+                    "type_params",
+                    self.type_params(node.child_by_field_name("type_parameters")),
+                ),
+            ],
+        )
+    }
+
+    /// The `arguments` of a function or lambda from its parameter list
+    fn arguments(&self, node: Option<Node>) -> AstValue {
+        let mut posonlyargs = Vec::new();
+        let mut args = Vec::new();
+        let mut vararg = AstValue::Null;
+        let mut kwonlyargs = Vec::new();
+        let mut kw_defaults = Vec::new();
+        let mut kwarg = AstValue::Null;
+        let mut defaults = Vec::new();
+        let mut keyword_only = false;
+        for parameter in node.map_or_else(Vec::new, named_children) {
+            let (name, annotation, default) = match parameter.kind() {
+                "positional_separator" => {
+                    posonlyargs.append(&mut args);
+                    continue;
+                }
+                "keyword_separator" => {
+                    keyword_only = true;
+                    continue;
+                }
+                "typed_parameter" => (
+                    named_children(parameter)
+                        .into_iter()
+                        .find(|child| child.kind() != "type"),
+                    parameter.child_by_field_name("type"),
+                    None,
+                ),
+                "default_parameter" | "typed_default_parameter" => (
+                    parameter.child_by_field_name("name"),
+                    parameter.child_by_field_name("type"),
+                    parameter.child_by_field_name("value"),
+                ),
+                _ => (Some(parameter), None, None),
+            };
+            let Some(name) = name else { continue };
+            let arg = self.arg(name, annotation);
+            match name.kind() {
+                "list_splat_pattern" => {
+                    vararg = arg;
+                    keyword_only = true;
+                }
+                "dictionary_splat_pattern" => kwarg = arg,
+                _ if keyword_only => {
+                    kwonlyargs.push(arg);
+                    kw_defaults.push(self.optional(default, Context::Load));
+                }
+                _ => {
+                    args.push(arg);
+                    defaults.extend(default.map(|default| self.expr(default, Context::Load)));
+                }
+            }
+        }
+        node_value(
+            "arguments",
+            vec![
+                ("posonlyargs", posonlyargs.into()),
+                ("args", args.into()),
+                ("vararg", vararg),
+                ("kwonlyargs", kwonlyargs.into()),
+                ("kw_defaults", kw_defaults.into()),
+                ("kwarg", kwarg),
+                ("defaults", defaults.into()),
+            ],
+        )
+    }
+
+    fn arg(&self, name: Node, annotation: Option<Node>) -> AstValue {
+        let name = match name.kind() {
+            "list_splat_pattern" | "dictionary_splat_pattern" => {
+                named_children(name).first().copied().unwrap_or(name)
+            }
+            _ => name,
+        };
+        let span = annotation.map_or(Span::from(name), |annotation| {
+            Span::from(name).to(annotation)
+        });
+        self.make(
+            "arg",
+            span,
+            vec![
+                ("arg", self.text(name).into()),
+                (
+                    "annotation",
+                    annotation.map_or(AstValue::Null, |annotation| self.type_expr(annotation)),
+                ),
+                ("type_comment", AstValue::Null),
+            ],
+        )
+    }
+
+    /// The positional arguments and `keyword`s of a call or class definition
+    fn call_arguments(&self, node: Node) -> (Vec<AstValue>, Vec<AstValue>) {
+        let mut args = Vec::new();
+        let mut keywords = Vec::new();
+        for argument in named_children(node) {
+            match argument.kind() {
+                "keyword_argument" => keywords.push(self.make(
+                    "keyword",
+                    argument,
+                    vec![
+                        (
+                            "arg",
+                            self.optional_text(argument.child_by_field_name("name")),
+                        ),
+                        (
+                            "value",
+                            self.optional(argument.child_by_field_name("value"), Context::Load),
+                        ),
+                    ],
+                )),
+                "dictionary_splat" => keywords.push(self.make(
+                    "keyword",
+                    argument,
+                    vec![
+                        ("arg", AstValue::Null),
+                        (
+                            "value",
+                            self.optional(named_children(argument).first().copied(), Context::Load),
+                        ),
+                    ],
+                )),
+                _ => args.push(self.expr(argument, Context::Load)),
+            }
+        }
+        (args, keywords)
+    }
+
+    fn match_statement(&self, node: Node) -> AstValue {
+        let subjects = field_children(node, "subject");
+        let subject = match subjects.as_slice() {
+            [subject] if !has_token(node, ",") => self.expr(*subject, Context::Load),
+            [] => AstValue::Null,
+            [first, .., last] | [first @ last] => self.tuple(
+                &subjects,
+                Span::from(*first).to(with_trailing_comma(*last)),
+                Context::Load,
+            ),
+        };
+        let cases = node
+            .child_by_field_name("body")
+            .map_or_else(Vec::new, |body| field_children(body, "alternative"))
+            .into_iter()
+            .map(|case| self.match_case(case))
+            .collect::<Vec<_>>();
+        self.compound(
+            "Match",
+            node,
+            vec![("subject", subject), ("cases", cases.into())],
+        )
+    }
+
+    fn match_case(&self, node: Node) -> AstValue {
+        let patterns = named_children(node)
+            .into_iter()
+            .filter(|child| child.kind() == "case_pattern")
+            .collect::<Vec<_>>();
+        let pattern = match patterns.as_slice() {
+            [] => AstValue::Null,
+            [pattern] if !has_token(node, ",") => self.pattern(*pattern),
+            [first, .., last] | [first @ last] => self.make(
+                "MatchSequence",
+                Span::from(*first).to(with_trailing_comma(*last)),
+                vec![(
+                    "patterns",
+                    patterns
+                        .iter()
+                        .map(|pattern| self.pattern(*pattern))
+                        .collect::<Vec<_>>()
+                        .into(),
+                )],
+            ),
+        };
+        let guard = node
+            .child_by_field_name("guard")
+            .and_then(|guard| named_children(guard).first().copied());
+        node_value(
+            "match_case",
+            vec![
+                ("pattern", pattern),
+                ("guard", self.optional(guard, Context::Load)),
+                ("body", self.field_body(node, "consequence")),
+            ],
+        )
+    }
+
+    fn type_alias(&self, node: Node) -> AstValue {
+        let left = node
+            .child_by_field_name("left")
+            .and_then(|left| named_children(left).first().copied());
+        let keyword = children(node)
+            .into_iter()
+            .find(|child| child.kind() == "type");
+        if let (Some(target), Some(keyword)) = (left, keyword) {
+            if target.kind() == "attribute" {
+                return self.make(
+                    "Assign",
+                    node,
+                    vec![
+                        (
+                            "targets",
+                            vec![self.type_call_target(target, keyword, Context::Store)].into(),
+                        ),
+                        (
+                            "value",
+                            node.child_by_field_name("right")
+                                .map_or(AstValue::Null, |value| self.type_expr(value)),
+                        ),
+                        ("type_comment", AstValue::Null),
+                    ],
+                );
+            }
+        }
+        let (name, parameters) = match left {
+            Some(generic) if generic.kind() == "generic_type" => {
+                let children = named_children(generic);
+                (
+                    children.first().copied(),
+                    children
+                        .into_iter()
+                        .find(|child| child.kind() == "type_parameter"),
+                )
+            }
+            name => (name, None),
+        };
+        self.make(
+            "TypeAlias",
+            node,
+            vec![
+                ("name", self.optional(name, Context::Store)),
+                ("type_params", self.type_params(parameters)),
+                (
+                    "value",
+                    node.child_by_field_name("right")
+                        .map_or(AstValue::Null, |value| self.type_expr(value)),
+                ),
+            ],
+        )
+    }
+
+    /// The target of `type(obj).attr = value`, which Tree-sitter reads as a type alias named
+    /// `(obj).attr`: the parenthesized expression at the start of `node` holds the arguments of
+    /// a call to the `type` keyword before it
+    fn type_call_target(&self, node: Node, keyword: Node, ctx: Context) -> AstValue {
+        let span = Span::from(keyword).to(node);
+        match node.kind() {
+            "attribute" => self.make(
+                "Attribute",
+                span,
+                vec![
+                    (
+                        "value",
+                        node.child_by_field_name("object")
+                            .map_or(AstValue::Null, |object| {
+                                self.type_call_target(object, keyword, Context::Load)
+                            }),
+                    ),
+                    (
+                        "attr",
+                        self.optional_text(node.child_by_field_name("attribute")),
+                    ),
+                    ("ctx", ctx.value()),
+                ],
+            ),
+            "parenthesized_expression" | "tuple" => self.make(
+                "Call",
+                span,
+                vec![
+                    ("func", self.name(keyword, Context::Load)),
+                    ("args", self.list(named_children(node), Context::Load)),
+                    ("keywords", AstValue::List(Vec::new())),
+                ],
+            ),
+            _ => self.unknown(node),
+        }
+    }
+
+    /// The `TypeVar`, `TypeVarTuple` and `ParamSpec` declarations in `[...]` after a name
+    fn type_params(&self, node: Option<Node>) -> AstValue {
+        node.map_or_else(Vec::new, named_children)
+            .into_iter()
+            .map(|parameter| {
+                let inner = named_children(parameter)
+                    .first()
+                    .copied()
+                    .unwrap_or(parameter);
+                match inner.kind() {
+                    "constrained_type" => {
+                        let types = named_children(inner);
+                        let name = types
+                            .first()
+                            .and_then(|name| named_children(*name).first().copied());
+                        self.make(
+                            "TypeVar",
+                            parameter,
+                            vec![
+                                ("name", self.optional_text(name)),
+                                (
+                                    "bound",
+                                    types
+                                        .get(1)
+                                        .map_or(AstValue::Null, |bound| self.type_expr(*bound)),
+                                ),
+                                ("default_value", AstValue::Null),
+                            ],
+                        )
+                    }
+                    "splat_type" => {
+                        let ty = if self.text(inner).starts_with("**") {
+                            "ParamSpec"
+                        } else {
+                            "TypeVarTuple"
+                        };
+                        let name = named_children(inner).first().copied();
+                        self.make(
+                            ty,
+                            parameter,
+                            vec![
+                                ("name", self.optional_text(name)),
+                                ("default_value", AstValue::Null),
+                            ],
+                        )
+                    }
+                    _ => self.make(
+                        "TypeVar",
+                        parameter,
+                        vec![
+                            ("name", self.text(inner).into()),
+                            ("bound", AstValue::Null),
+                            ("default_value", AstValue::Null),
+                        ],
+                    ),
+                }
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn expr(&self, node: Node, ctx: Context) -> AstValue {
+        stacker::maybe_grow(
+            STACK_RED_ZONE_BYTES,
+            STACK_SEGMENT_BYTES,
+            || match leading_splat(node) {
+                Some((splat, path)) if self.unwrapped_splat.get() != Some(splat.id()) => {
+                    self.hoist_splat(node, splat, &path, ctx)
+                }
+                _ => self.expr_node(node, ctx),
+            },
+        )
+    }
+
+    /// `*f(x)` or `*a.b`, which Tree-sitter reads as a call or attribute of `*f` or `*a`: the
+    /// `Starred` goes around the whole expression, and the nodes along `path` from it to the
+    /// starred operand start after the `*`
+    fn hoist_splat(&self, node: Node, splat: Node, path: &[&str], ctx: Context) -> AstValue {
+        let outer = self.unwrapped_splat.replace(Some(splat.id()));
+        let mut value = self.expr_node(node, ctx);
+        self.unwrapped_splat.set(outer);
+        let mut operand = &value;
+        for key in path {
+            operand = operand.get(key).unwrap_or(operand);
+        }
+        let start = (
+            operand.get("lineno").cloned(),
+            operand.get("col_offset").cloned(),
+        );
+        if let (Some(line), Some(column)) = start {
+            let mut current = &mut value;
+            for key in path {
+                set(current, "lineno", line.clone());
+                set(current, "col_offset", column.clone());
+                let Some(next) = get_mut(current, key) else {
+                    break;
+                };
+                current = next;
+            }
+        }
+        self.make(
+            "Starred",
+            node,
+            vec![("value", value), ("ctx", ctx.value())],
+        )
+    }
+
+    fn expr_node(&self, node: Node, ctx: Context) -> AstValue {
+        match node.kind() {
+            "identifier" => self.name(node, ctx),
+            "true" | "false" => self.constant(node, (node.kind() == "true").into()),
+            "none" => self.constant(node, AstValue::Null),
+            "ellipsis" => self.constant(node, "...".into()),
+            "integer" | "float" => self.constant(node, number(self.text(node))),
+            "string" | "concatenated_string" => self.string(node),
+            "attribute" => self.make(
+                "Attribute",
+                node,
+                vec![
+                    (
+                        "value",
+                        self.optional(node.child_by_field_name("object"), Context::Load),
+                    ),
+                    (
+                        "attr",
+                        self.optional_text(node.child_by_field_name("attribute")),
+                    ),
+                    ("ctx", ctx.value()),
+                ],
+            ),
+            "subscript" => {
+                let subscripts = field_children(node, "subscript");
+                let slice = match subscripts.as_slice() {
+                    [subscript] if !has_token(node, ",") && !is_starred(*subscript) => {
+                        self.expr(*subscript, Context::Load)
+                    }
+                    [] => AstValue::Null,
+                    [first, ..] => {
+                        let last = children(node)
+                            .into_iter()
+                            .rev()
+                            .find(|child| child.kind() != "]")
+                            .unwrap_or(*first);
+                        self.tuple(&subscripts, Span::from(*first).to(last), Context::Load)
+                    }
+                };
+                self.make(
+                    "Subscript",
+                    node,
+                    vec![
+                        (
+                            "value",
+                            self.optional(node.child_by_field_name("value"), Context::Load),
+                        ),
+                        ("slice", slice),
+                        ("ctx", ctx.value()),
+                    ],
+                )
+            }
+            "slice" => {
+                let mut parts = [None, None, None];
+                let mut index = 0;
+                for child in children(node) {
+                    if child.kind() == ":" {
+                        index = (index + 1).min(2);
+                    } else if child.is_named() {
+                        parts[index] = Some(child);
+                    }
+                }
+                self.make(
+                    "Slice",
+                    node,
+                    vec![
+                        ("lower", self.optional(parts[0], Context::Load)),
+                        ("upper", self.optional(parts[1], Context::Load)),
+                        ("step", self.optional(parts[2], Context::Load)),
+                    ],
+                )
+            }
+            "call" => {
+                let (args, keywords) = match node.child_by_field_name("arguments") {
+                    Some(generator) if generator.kind() == "generator_expression" => {
+                        (vec![self.expr(generator, Context::Load)], Vec::new())
+                    }
+                    Some(arguments) => self.call_arguments(arguments),
+                    None => Default::default(),
+                };
+                self.make(
+                    "Call",
+                    node,
+                    vec![
+                        (
+                            "func",
+                            self.optional(node.child_by_field_name("function"), Context::Load),
+                        ),
+                        ("args", args.into()),
+                        ("keywords", keywords.into()),
+                    ],
+                )
+            }
+            "binary_operator" => {
+                let operator = node
+                    .child_by_field_name("operator")
+                    .map_or("", |operator| operator.kind());
+                self.make(
+                    "BinOp",
+                    node,
+                    vec![
+                        (
+                            "left",
+                            self.optional(node.child_by_field_name("left"), Context::Load),
+                        ),
+                        ("op", tag(binary_operator(operator))),
+                        (
+                            "right",
+                            self.optional(node.child_by_field_name("right"), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            "unary_operator" | "not_operator" => {
+                let operator = match node.child_by_field_name("operator").map(|op| op.kind()) {
+                    Some("+") => "UAdd",
+                    Some("-") => "USub",
+                    Some("~") => "Invert",
+                    _ => "Not",
+                };
+                self.make(
+                    "UnaryOp",
+                    node,
+                    vec![
+                        ("op", tag(operator)),
+                        (
+                            "operand",
+                            self.optional(node.child_by_field_name("argument"), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            "boolean_operator" => self.bool_op(node),
+            "comparison_operator" => {
+                let operands = named_children(node);
+                let ops = field_children(node, "operators")
+                    .into_iter()
+                    .map(|operator| tag(compare_operator(operator.kind())))
+                    .collect::<Vec<_>>();
+                self.make(
+                    "Compare",
+                    node,
+                    vec![
+                        (
+                            "left",
+                            self.optional(operands.first().copied(), Context::Load),
+                        ),
+                        ("ops", ops.into()),
+                        (
+                            "comparators",
+                            self.list(operands.into_iter().skip(1), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            "conditional_expression" => {
+                let children = named_children(node);
+                self.make(
+                    "IfExp",
+                    node,
+                    vec![
+                        (
+                            "test",
+                            self.optional(children.get(1).copied(), Context::Load),
+                        ),
+                        (
+                            "body",
+                            self.optional(children.first().copied(), Context::Load),
+                        ),
+                        (
+                            "orelse",
+                            self.optional(children.get(2).copied(), Context::Load),
+                        ),
+                    ],
+                )
+            }
+            "named_expression" => self.make(
+                "NamedExpr",
+                node,
+                vec![
+                    (
+                        "target",
+                        self.optional(node.child_by_field_name("name"), Context::Store),
+                    ),
+                    (
+                        "value",
+                        self.optional(node.child_by_field_name("value"), Context::Load),
+                    ),
+                ],
+            ),
+            "lambda" => self.make(
+                "Lambda",
+                node,
+                vec![
+                    (
+                        "args",
+                        self.arguments(node.child_by_field_name("parameters")),
+                    ),
+                    (
+                        "body",
+                        self.optional(node.child_by_field_name("body"), Context::Load),
+                    ),
+                ],
+            ),
+            "await" => self.make(
+                "Await",
+                node,
+                vec![(
+                    "value",
+                    self.optional(named_children(node).first().copied(), Context::Load),
+                )],
+            ),
+            "yield" => {
+                let ty = if has_token(node, "from") {
+                    "YieldFrom"
+                } else {
+                    "Yield"
+                };
+                self.make(
+                    ty,
+                    node,
+                    vec![(
+                        "value",
+                        self.optional(named_children(node).first().copied(), Context::Load),
+                    )],
+                )
+            }
+            "list" | "list_pattern" => self.make(
+                "List",
+                node,
+                vec![
+                    ("elts", self.list(named_children(node), ctx)),
+                    ("ctx", ctx.value()),
+                ],
+            ),
+            "tuple_pattern" if !has_token(node, ",") && named_children(node).len() == 1 => {
+                // `(target)` is a parenthesized target rather than a tuple
+                self.expr(named_children(node)[0], ctx)
+            }
+            "tuple" | "tuple_pattern" | "pattern_list" | "expression_list" => {
+                self.tuple(&named_children(node), node, ctx)
+            }
+            "set" => self.make(
+                "Set",
+                node,
+                vec![("elts", self.list(named_children(node), Context::Load))],
+            ),
+            "dictionary" => {
+                let mut keys = Vec::new();
+                let mut values = Vec::new();
+                for entry in named_children(node) {
+                    if entry.kind() == "pair" {
+                        keys.push(self.optional(entry.child_by_field_name("key"), Context::Load));
+                        values
+                            .push(self.optional(entry.child_by_field_name("value"), Context::Load));
+                    } else {
+                        keys.push(AstValue::Null);
+                        values.push(
+                            self.optional(named_children(entry).first().copied(), Context::Load),
+                        );
+                    }
+                }
+                self.make(
+                    "Dict",
+                    node,
+                    vec![("keys", keys.into()), ("values", values.into())],
+                )
+            }
+            "list_comprehension" => self.comprehension("ListComp", node),
+            "set_comprehension" => self.comprehension("SetComp", node),
+            "generator_expression" => self.comprehension("GeneratorExp", node),
+            "dictionary_comprehension" => self.comprehension("DictComp", node),
+            "parenthesized_expression" | "parenthesized_list_splat" => {
+                match named_children(node).first() {
+                    Some(inner) => self.expr(*inner, ctx),
+                    None => self.unknown(node),
+                }
+            }
+            "list_splat" if self.unwrapped_splat.get() == Some(node.id()) => {
+                self.optional(named_children(node).first().copied(), ctx)
+            }
+            "list_splat" | "list_splat_pattern" => self.make(
+                "Starred",
+                node,
+                vec![
+                    (
+                        "value",
+                        self.optional(named_children(node).first().copied(), ctx),
+                    ),
+                    ("ctx", ctx.value()),
+                ],
+            ),
+            "type" => self.type_expr(node),
+            _ => self.unknown(node),
+        }
+    }
+
+    fn constant(&self, node: Node, value: AstValue) -> AstValue {
+        self.make(
+            "Constant",
+            node,
+            vec![("value", value), ("kind", AstValue::Null)],
+        )
+    }
+
+    /// `BoolOp`, with the operands of a chain of the same operator in one list
+    fn bool_op(&self, node: Node) -> AstValue {
+        let operator = |node: Node| {
+            node.child_by_field_name("operator")
+                .map_or("", |operator| operator.kind())
+        };
+        let op = operator(node);
+        let mut operands = Vec::new();
+        let mut left = Some(node);
+        while let Some(chain) =
+            left.filter(|left| left.kind() == "boolean_operator" && operator(*left) == op)
+        {
+            operands.extend(chain.child_by_field_name("right"));
+            left = chain.child_by_field_name("left");
+        }
+        operands.extend(left);
+        operands.reverse();
+        self.make(
+            "BoolOp",
+            node,
+            vec![
+                ("op", tag(if op == "and" { "And" } else { "Or" })),
+                ("values", self.list(operands, Context::Load)),
+            ],
+        )
+    }
+
+    fn comprehension(&self, ty: &'static str, node: Node) -> AstValue {
+        let mut generators: Vec<Props> = Vec::new();
+        for clause in named_children(node) {
+            match clause.kind() {
+                "for_in_clause" => {
+                    let iter = field_children(clause, "right")
+                        .into_iter()
+                        .filter(|right| right.is_named())
+                        .collect::<Vec<_>>();
+                    generators.push(vec![
+                        (
+                            "target",
+                            self.optional(clause.child_by_field_name("left"), Context::Store),
+                        ),
+                        ("iter", self.expr_or_tuple(&iter, Context::Load)),
+                        ("ifs", AstValue::List(Vec::new())),
+                        ("is_async", usize::from(has_token(clause, "async")).into()),
+                    ]);
+                }
+                "if_clause" => {
+                    let condition =
+                        self.optional(named_children(clause).first().copied(), Context::Load);
+                    if let Some((_, AstValue::List(ifs))) = generators
+                        .last_mut()
+                        .and_then(|generator| generator.get_mut(2))
+                    {
+                        ifs.push(condition);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let generators = generators
+            .into_iter()
+            .map(|generator| node_value("comprehension", generator))
+            .collect::<Vec<_>>();
+        let body = node.child_by_field_name("body");
+        let mut props = match body {
+            Some(pair) if ty == "DictComp" => vec![
+                (
+                    "key",
+                    self.optional(pair.child_by_field_name("key"), Context::Load),
+                ),
+                (
+                    "value",
+                    self.optional(pair.child_by_field_name("value"), Context::Load),
+                ),
+            ],
+            body => vec![("elt", self.optional(body, Context::Load))],
+        };
+        props.push(("generators", generators.into()));
+        self.make(ty, node, props)
+    }
+
+    /// A `Constant` for a string or bytes literal, or a `JoinedStr` when any part of it is an
+    /// f-string
+    fn string(&self, node: Node) -> AstValue {
+        let parts = match node.kind() {
+            "concatenated_string" => named_children(node)
+                .into_iter()
+                .filter(|part| part.kind() == "string")
+                .collect(),
+            _ => vec![node],
+        };
+        let prefixes = parts
+            .iter()
+            .map(|part| self.prefix(*part))
+            .collect::<Vec<_>>();
+        if prefixes.iter().any(|prefix| prefix.contains('f')) {
+            return self.joined_string(node, &parts, &prefixes);
+        }
+        let bytes = prefixes.iter().any(|prefix| prefix.contains('b'));
+        let mut value = String::new();
+        for (part, prefix) in parts.iter().zip(&prefixes) {
+            let (start, end) = self.content(*part);
+            value.push_str(&unescape(
+                &self.source[start.start_byte..end.start_byte],
+                prefix.contains('r'),
+                bytes,
+            ));
+        }
+        let kind = match prefixes.first() {
+            Some(prefix) if prefix.contains('u') => "u".into(),
+            _ => AstValue::Null,
+        };
+        let value = if bytes {
+            bytes_value(&value)
+        } else {
+            value.into()
+        };
+        self.make("Constant", node, vec![("value", value), ("kind", kind)])
+    }
+
+    /// The lowercase prefix of a string literal, such as `rb` or `f`
+    fn prefix(&self, part: Node) -> String {
+        children(part)
+            .into_iter()
+            .find(|child| child.kind() == "string_start")
+            .map_or(String::new(), |start| {
+                self.text(start)
+                    .trim_end_matches(['"', '\''])
+                    .to_ascii_lowercase()
+            })
+    }
+
+    /// Empty spans at the start and the end of the text between a string's quotes
+    fn content(&self, part: Node) -> (Span, Span) {
+        let children = children(part);
+        let Some(start) = children.iter().find(|child| child.kind() == "string_start") else {
+            return (Span::from(part).start(), Span::from(part).end());
+        };
+        let quotes = self
+            .text(*start)
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .len();
+        let start = Span::from(*start).end();
+        // The closing token can take in backslashes before the quotes, as in `r'\\'`, so the
+        // text ends where the quotes begin rather than where the token does
+        let Some(end) = children.iter().find(|child| child.kind() == "string_end") else {
+            return (start, Span::from(part).end());
+        };
+        let end = Span::from(*end).end();
+        let end = match end.end.column.checked_sub(quotes) {
+            Some(column) if end.end_byte - quotes >= start.end_byte => {
+                let point = Point::new(end.end.row, column);
+                Span {
+                    start_byte: end.end_byte - quotes,
+                    end_byte: end.end_byte - quotes,
+                    start: point,
+                    end: point,
+                }
+            }
+            _ => start,
+        };
+        (start, end)
+    }
+
+    fn joined_string(&self, node: Node, parts: &[Node], prefixes: &[String]) -> AstValue {
+        let mut values = Vec::new();
+        let mut pending = None;
+        for (part, prefix) in parts.iter().zip(prefixes) {
+            let raw = prefix.contains('r');
+            let (start, end) = self.content(*part);
+            if !prefix.contains('f') {
+                let text = &self.source[start.start_byte..end.start_byte];
+                push_literal(&mut pending, unescape(text, raw, false), Span::from(*part));
+                continue;
+            }
+            let mut literal_start = start;
+            for interpolation in named_children(*part)
+                .into_iter()
+                .filter(|child| child.kind() == "interpolation")
+            {
+                let interpolation_start = Span::from(interpolation).start();
+                self.push_fstring_literal(&mut pending, literal_start.to(interpolation_start), raw);
+                self.push_formatted_value(&mut values, &mut pending, interpolation, raw);
+                literal_start = Span::from(interpolation).end();
+            }
+            self.push_fstring_literal(&mut pending, literal_start.to(end), raw);
+        }
+        values.extend(self.take_literal(&mut pending));
+        self.make("JoinedStr", node, vec![("values", values.into())])
+    }
+
+    /// Add the f-string text in `span`, with its escapes and doubled braces decoded
+    fn push_fstring_literal(&self, pending: &mut Option<PendingLiteral>, span: Span, raw: bool) {
+        let text = &self.source[span.start_byte..span.end_byte];
+        let value = unescape(text, raw, false)
+            .replace("{{", "{")
+            .replace("}}", "}");
+        push_literal(pending, value, span);
+    }
+
+    fn take_literal(&self, pending: &mut Option<PendingLiteral>) -> Option<AstValue> {
+        pending.take().map(|literal| {
+            self.make(
+                "Constant",
+                literal.span,
+                vec![("value", literal.value.into()), ("kind", AstValue::Null)],
+            )
+        })
+    }
+
+    /// Add a `FormattedValue` for an f-string replacement field to `values`, after the pending
+    /// text before it and the expression text that a `=` after the expression adds to that text
+    fn push_formatted_value(
+        &self,
+        values: &mut Vec<AstValue>,
+        pending: &mut Option<PendingLiteral>,
+        node: Node,
+        raw: bool,
+    ) {
+        let tokens = children(node);
+        let debug = tokens
+            .iter()
+            .position(|child| !child.is_named() && child.kind() == "=");
+        if let (Some(index), Some(open)) = (debug, tokens.first()) {
+            let end = tokens
+                .get(index + 1)
+                .map_or(Span::from(node).end(), |next| Span::from(*next).start());
+            let span = Span::from(*open).end().to(end);
+            let text = self.source[span.start_byte..span.end_byte].to_string();
+            push_literal(pending, text, span);
+        }
+        values.extend(self.take_literal(pending));
+        let format_spec = node.child_by_field_name("format_specifier");
+        let mut expression = node.child_by_field_name("expression");
+        // Tree-sitter reads `{x:=10}` as an assignment expression rather than `x` with the
+        // format specification `=10`
+        let walrus = expression
+            .filter(|expression| expression.kind() == "named_expression" && format_spec.is_none());
+        let walrus_spec = walrus.map(|walrus| {
+            expression = walrus.child_by_field_name("name");
+            let spec = children(walrus)
+                .into_iter()
+                .find(|child| child.kind() == ":=")
+                .map_or(Span::from(walrus).end(), |operator| {
+                    Span::from(operator).start()
+                })
+                .to(walrus);
+            let mut literal = spec;
+            literal.start_byte += 1;
+            literal.start.column += 1;
+            let mut values = Vec::new();
+            let mut pending = None;
+            self.push_fstring_literal(&mut pending, literal, raw);
+            values.extend(self.take_literal(&mut pending));
+            self.make("JoinedStr", spec, vec![("values", values.into())])
+        });
+        let conversion: i64 = match node.child_by_field_name("type_conversion") {
+            Some(conversion) => self
+                .text(conversion)
+                .chars()
+                .last()
+                .map_or(-1, |c| u32::from(c).into()),
+            None if debug.is_some() && format_spec.is_none() && walrus.is_none() => {
+                u32::from('r').into()
+            }
+            None => -1,
+        };
+        values.push(self.make(
+            "FormattedValue",
+            node,
+            vec![
+                ("value", self.optional(expression, Context::Load)),
+                ("conversion", AstValue::Number(conversion.into())),
+                (
+                    "format_spec",
+                    match format_spec {
+                        Some(spec) => self.format_spec(spec, raw),
+                        None => walrus_spec.into(),
+                    },
+                ),
+            ],
+        ));
+    }
+
+    /// The `JoinedStr` of the format specification after the `:` of a replacement field
+    fn format_spec(&self, node: Node, raw: bool) -> AstValue {
+        let mut values = Vec::new();
+        let mut pending = None;
+        let mut literal_start = Span::from(node).start();
+        for child in children(node) {
+            match child.kind() {
+                ":" => literal_start = Span::from(child).end(),
+                "format_expression" => {
+                    let span = literal_start.to(Span::from(child).start());
+                    self.push_fstring_literal(&mut pending, span, raw);
+                    self.push_formatted_value(&mut values, &mut pending, child, raw);
+                    literal_start = Span::from(child).end();
+                }
+                _ => {}
+            }
+        }
+        self.push_fstring_literal(&mut pending, literal_start.to(Span::from(node).end()), raw);
+        values.extend(self.take_literal(&mut pending));
+        self.make("JoinedStr", node, vec![("values", values.into())])
+    }
+
+    /// An annotation or other type expression, which Tree-sitter parses with its own node kinds
+    fn type_expr(&self, node: Node) -> AstValue {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.type_expr_node(node)
+        })
+    }
+
+    fn type_expr_node(&self, node: Node) -> AstValue {
+        let children = named_children(node);
+        match node.kind() {
+            "type" => match children.first() {
+                Some(inner) => self.type_expr(*inner),
+                None => self.unknown(node),
+            },
+            "generic_type" => {
+                let arguments = children
+                    .iter()
+                    .find(|child| child.kind() == "type_parameter")
+                    .copied();
+                let slice = arguments.map_or(AstValue::Null, |arguments| {
+                    let types = named_children(arguments);
+                    match types.as_slice() {
+                        [ty] if !has_token(arguments, ",")
+                            && !named_children(*ty)
+                                .first()
+                                .is_some_and(|inner| is_starred(*inner)) =>
+                        {
+                            self.type_expr(*ty)
+                        }
+                        _ => self.make(
+                            "Tuple",
+                            inner_span(arguments),
+                            vec![
+                                (
+                                    "elts",
+                                    types
+                                        .iter()
+                                        .map(|ty| self.type_expr(*ty))
+                                        .collect::<Vec<_>>()
+                                        .into(),
+                                ),
+                                ("ctx", Context::Load.value()),
+                            ],
+                        ),
+                    }
+                });
+                self.make(
+                    "Subscript",
+                    node,
+                    vec![
+                        (
+                            "value",
+                            self.optional(children.first().copied(), Context::Load),
+                        ),
+                        ("slice", slice),
+                        ("ctx", Context::Load.value()),
+                    ],
+                )
+            }
+            "union_type" => {
+                let mut types = children.into_iter();
+                let Some(first) = types.next() else {
+                    return self.unknown(node);
+                };
+                types.fold(self.type_expr(first), |left, right| {
+                    self.make(
+                        "BinOp",
+                        Span::from(first).to(right),
+                        vec![
+                            ("left", left),
+                            ("op", tag("BitOr")),
+                            ("right", self.type_expr(right)),
+                        ],
+                    )
+                })
+            }
+            "member_type" => self.make(
+                "Attribute",
+                node,
+                vec![
+                    (
+                        "value",
+                        children
+                            .first()
+                            .map_or(AstValue::Null, |value| self.type_expr(*value)),
+                    ),
+                    ("attr", self.optional_text(children.get(1).copied())),
+                    ("ctx", Context::Load.value()),
+                ],
+            ),
+            "splat_type" => self.make(
+                "Starred",
+                node,
+                vec![
+                    (
+                        "value",
+                        self.optional(children.first().copied(), Context::Load),
+                    ),
+                    ("ctx", Context::Load.value()),
+                ],
+            ),
+            "constrained_type" => self.unknown(node),
+            _ => self.expr(node, Context::Load),
+        }
+    }
+
+    fn pattern(&self, node: Node) -> AstValue {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.pattern_node(node)
+        })
+    }
+
+    fn pattern_node(&self, node: Node) -> AstValue {
+        match node.kind() {
+            "case_pattern" => {
+                let mut patterns = self.patterns(&children(node));
+                match patterns.len() {
+                    1 => patterns.remove(0),
+                    _ => self.unknown(node),
+                }
+            }
+            "dotted_name" => {
+                let parts = named_children(node);
+                match parts.as_slice() {
+                    [name] => self.make(
+                        "MatchAs",
+                        node,
+                        vec![
+                            ("pattern", AstValue::Null),
+                            ("name", self.text(*name).into()),
+                        ],
+                    ),
+                    _ => self.make("MatchValue", node, vec![("value", self.dotted_value(node))]),
+                }
+            }
+            "none" | "true" | "false" => {
+                let value = match node.kind() {
+                    "none" => AstValue::Null,
+                    kind => (kind == "true").into(),
+                };
+                self.make("MatchSingleton", node, vec![("value", value)])
+            }
+            "list_pattern" | "tuple_pattern" => {
+                let mut patterns = self.patterns(&children(node));
+                if node.kind() == "tuple_pattern" && patterns.len() == 1 && !has_token(node, ",") {
+                    return patterns.remove(0);
+                }
+                self.make("MatchSequence", node, vec![("patterns", patterns.into())])
+            }
+            "union_pattern" => self.make(
+                "MatchOr",
+                node,
+                vec![("patterns", self.patterns(&children(node)).into())],
+            ),
+            "splat_pattern" => self.make(
+                "MatchStar",
+                node,
+                vec![(
+                    "name",
+                    self.optional_text(named_children(node).first().copied()),
+                )],
+            ),
+            "as_pattern" => {
+                let children = named_children(node);
+                self.make(
+                    "MatchAs",
+                    node,
+                    vec![
+                        (
+                            "pattern",
+                            children
+                                .first()
+                                .map_or(AstValue::Null, |pattern| self.pattern(*pattern)),
+                        ),
+                        ("name", self.optional_text(children.get(1).copied())),
+                    ],
+                )
+            }
+            "dict_pattern" => {
+                let mut keys = Vec::new();
+                let mut patterns = Vec::new();
+                let mut rest = AstValue::Null;
+                let mut minus = None;
+                for (field, child) in fields(node) {
+                    if child.kind() == "-" {
+                        minus = Some(child);
+                    } else if child.kind() == "splat_pattern" {
+                        rest = self.optional_text(named_children(child).first().copied());
+                    } else if child.is_named() && !child.is_extra() {
+                        match field {
+                            Some("key") => keys.push(self.value_pattern(child, minus.take())),
+                            Some("value") => patterns.push(self.pattern(child)),
+                            _ => {}
+                        }
+                    }
+                }
+                self.make(
+                    "MatchMapping",
+                    node,
+                    vec![
+                        ("keys", keys.into()),
+                        ("patterns", patterns.into()),
+                        ("rest", rest),
+                    ],
+                )
+            }
+            "class_pattern" => {
+                let children = named_children(node);
+                let mut patterns = Vec::new();
+                let mut kwd_attrs = Vec::new();
+                let mut kwd_patterns = Vec::new();
+                for argument in children.iter().skip(1) {
+                    let keyword = named_children(*argument)
+                        .into_iter()
+                        .find(|child| child.kind() == "keyword_pattern");
+                    match keyword {
+                        Some(keyword) => {
+                            let parts = children_after(keyword, "=");
+                            kwd_attrs
+                                .push(self.optional_text(named_children(keyword).first().copied()));
+                            kwd_patterns.extend(self.patterns(&parts).into_iter().next());
+                        }
+                        None => patterns.push(self.pattern(*argument)),
+                    }
+                }
+                self.make(
+                    "MatchClass",
+                    node,
+                    vec![
+                        (
+                            "cls",
+                            children
+                                .first()
+                                .map_or(AstValue::Null, |cls| self.dotted_value(*cls)),
+                        ),
+                        ("patterns", patterns.into()),
+                        ("kwd_attrs", kwd_attrs.into()),
+                        ("kwd_patterns", kwd_patterns.into()),
+                    ],
+                )
+            }
+            "integer" | "float" | "string" | "concatenated_string" | "complex_pattern" => self
+                .make(
+                    "MatchValue",
+                    node,
+                    vec![("value", self.value_pattern(node, None))],
+                ),
+            _ => self.unknown(node),
+        }
+    }
+
+    /// The patterns among `nodes`, where `_` is the wildcard and a `-` token negates the
+    /// number after it
+    fn patterns(&self, nodes: &[Node]) -> Vec<AstValue> {
+        let mut patterns = Vec::new();
+        let mut minus = None;
+        for node in nodes {
+            match node.kind() {
+                "-" => minus = Some(*node),
+                "_" => patterns.push(self.make(
+                    "MatchAs",
+                    *node,
+                    vec![("pattern", AstValue::Null), ("name", AstValue::Null)],
+                )),
+                _ if !node.is_named() => {}
+                _ => match minus.take() {
+                    Some(minus) => patterns.push(self.make(
+                        "MatchValue",
+                        Span::from(minus).to(*node),
+                        vec![("value", self.value_pattern(*node, Some(minus)))],
+                    )),
+                    None => patterns.push(self.pattern(*node)),
+                },
+            }
+        }
+        patterns
+    }
+
+    /// The expression of a literal in a pattern, negated when preceded by `minus`
+    fn value_pattern(&self, node: Node, minus: Option<Node>) -> AstValue {
+        let value = match node.kind() {
+            "complex_pattern" => {
+                let mut parts = Vec::new();
+                let mut sign = None;
+                let mut operator = "Add";
+                for child in children(node) {
+                    match child.kind() {
+                        "-" if parts.is_empty() => sign = Some(child),
+                        "-" => operator = "Sub",
+                        "+" => operator = "Add",
+                        _ if child.is_named() => {
+                            parts.push(self.value_pattern(child, sign.take()));
+                        }
+                        _ => {}
+                    }
+                }
+                let mut parts = parts.into_iter();
+                let left = parts.next().unwrap_or(AstValue::Null);
+                let right = parts.next().unwrap_or(AstValue::Null);
+                self.make(
+                    "BinOp",
+                    node,
+                    vec![("left", left), ("op", tag(operator)), ("right", right)],
+                )
+            }
+            "dotted_name" => self.dotted_value(node),
+            _ => self.expr(node, Context::Load),
+        };
+        match minus {
+            Some(minus) => self.make(
+                "UnaryOp",
+                Span::from(minus).to(node),
+                vec![("op", tag("USub")), ("operand", value)],
+            ),
+            None => value,
+        }
+    }
+
+    /// `Name` or `Attribute` for a dotted name in a pattern
+    fn dotted_value(&self, node: Node) -> AstValue {
+        let parts = named_children(node);
+        let Some(first) = parts.first() else {
+            return self.unknown(node);
+        };
+        parts
+            .iter()
+            .skip(1)
+            .fold(self.name(*first, Context::Load), |value, attr| {
+                self.make(
+                    "Attribute",
+                    Span::from(*first).to(*attr),
+                    vec![
+                        ("value", value),
+                        ("attr", self.text(*attr).into()),
+                        ("ctx", Context::Load.value()),
+                    ],
+                )
+            })
+    }
+}
+
+/// A node without a position, such as `arguments` or `comprehension`
+fn node_value(ty: &'static str, props: Props) -> AstValue {
+    let mut entries = vec![("_type", ty.into())];
+    entries.extend(props);
+    AstValue::Map(entries)
+}
+
+/// An operator or expression context, which has no fields
+fn tag(ty: &'static str) -> AstValue {
+    node_value(ty, Vec::new())
+}
+
+fn set(value: &mut AstValue, key: &'static str, new: AstValue) {
+    if let AstValue::Map(entries) = value {
+        if let Some((_, existing)) = entries.iter_mut().find(|(name, _)| *name == key) {
+            *existing = new;
+        }
+    }
+}
+
+fn get_mut<'v>(value: &'v mut AstValue, key: &str) -> Option<&'v mut AstValue> {
+    match value {
+        AstValue::Map(entries) => entries
+            .iter_mut()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+/// The `list_splat` at the start of a call, attribute or subscript chain, with the keys that
+/// lead from the converted chain to the converted operand of the splat
+fn leading_splat(node: Node) -> Option<(Node, Vec<&'static str>)> {
+    let mut path = Vec::new();
+    let mut current = node;
+    loop {
+        let (field, key) = match current.kind() {
+            "call" => ("function", "func"),
+            "attribute" => ("object", "value"),
+            "subscript" => ("value", "value"),
+            "list_splat" if !path.is_empty() => return Some((current, path)),
+            _ => return None,
+        };
+        path.push(key);
+        current = current.child_by_field_name(field)?;
+    }
+}
+
+/// `node` and the `,` after it, if there is one
+fn with_trailing_comma(node: Node) -> Node {
+    node.next_sibling()
+        .filter(|next| next.kind() == ",")
+        .unwrap_or(node)
+}
+
+/// Whether an expression is starred, which CPython wraps in a `Tuple` even when it is the only
+/// subscript
+fn is_starred(node: Node) -> bool {
+    matches!(node.kind(), "list_splat" | "splat_type") || leading_splat(node).is_some()
+}
+
+/// The end of a compound statement or clause: the end of its last nested statement and of a `;`
+/// after it, as CPython counts it, but not of the comments Tree-sitter may add to the last block
+fn compound_end(node: Node) -> Span {
+    let mut current = node;
+    loop {
+        let Some(last) = named_children(current).pop() else {
+            return Span::from(current);
+        };
+        let nested = last.kind() == "block"
+            || last.kind().ends_with("_clause")
+            || COMPOUND_STATEMENTS.contains(&last.kind());
+        if nested {
+            current = last;
+        } else if current.kind() == "block" {
+            return match last.next_sibling().filter(|next| next.kind() == ";") {
+                Some(semicolon) => Span::from(last).to(semicolon),
+                None => Span::from(last),
+            };
+        } else {
+            return Span::from(current);
+        }
+    }
+}
+
+fn push_literal(pending: &mut Option<PendingLiteral>, value: String, span: Span) {
+    if value.is_empty() {
+        return;
+    }
+    match pending {
+        Some(literal) => {
+            literal.value.push_str(&value);
+            literal.span = literal.span.to(span);
+        }
+        None => *pending = Some(PendingLiteral { value, span }),
+    }
+}
+
+/// The span of `node` without its first and last tokens, such as the brackets around type
+/// arguments
+fn inner_span(node: Node) -> Span {
+    let children = named_children(node);
+    match (children.first(), children.last()) {
+        (Some(first), Some(last)) => Span::from(*first).to(*last),
+        _ => Span::from(node),
+    }
+}
+
+fn named_children(node: Node) -> Vec<Node> {
+    node.named_children(&mut node.walk())
+        .filter(|child| !child.is_extra() || child.is_error())
+        .collect()
+}
+
+/// All children, named or not, without comments and other extras
+fn children(node: Node) -> Vec<Node> {
+    node.children(&mut node.walk())
+        .filter(|child| !child.is_extra() || child.is_error())
+        .collect()
+}
+
+/// All children with the names of the fields they fill, without comments and other extras
+fn fields(node: Node) -> Vec<(Option<&'static str>, Node)> {
+    let mut cursor = node.walk();
+    let mut fields = Vec::new();
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if !child.is_extra() || child.is_error() {
+                fields.push((cursor.field_name(), child));
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+    fields
+}
+
+/// The children of `node` after its first `token`
+fn children_after<'tree>(node: Node<'tree>, token: &str) -> Vec<Node<'tree>> {
+    children(node)
+        .into_iter()
+        .skip_while(|child| child.kind() != token)
+        .skip(1)
+        .collect()
+}
+
+fn field_children<'tree>(node: Node<'tree>, field: &str) -> Vec<Node<'tree>> {
+    node.children_by_field_name(field, &mut node.walk())
+        .filter(|child| !child.is_extra() || child.is_error())
+        .collect()
+}
+
+/// `node` has the anonymous token `token` as a direct child
+fn has_token(node: Node, token: &str) -> bool {
+    node.children(&mut node.walk())
+        .any(|child| !child.is_named() && child.kind() == token)
+}
+
+fn binary_operator(operator: &str) -> &'static str {
+    match operator {
+        "+" => "Add",
+        "-" => "Sub",
+        "*" => "Mult",
+        "@" => "MatMult",
+        "/" => "Div",
+        "%" => "Mod",
+        "**" => "Pow",
+        "<<" => "LShift",
+        ">>" => "RShift",
+        "|" => "BitOr",
+        "^" => "BitXor",
+        "&" => "BitAnd",
+        _ => "FloorDiv",
+    }
+}
+
+fn compare_operator(operator: &str) -> &'static str {
+    match operator {
+        "==" => "Eq",
+        "!=" | "<>" => "NotEq",
+        "<" => "Lt",
+        "<=" => "LtE",
+        ">" => "Gt",
+        ">=" => "GtE",
+        "is" => "Is",
+        "is not" => "IsNot",
+        "in" => "In",
+        _ => "NotIn",
+    }
+}
+
+/// The value of an integer, float or imaginary literal
+fn number(raw: &str) -> AstValue {
+    let digits = raw.replace('_', "").to_ascii_lowercase();
+    if let Some(imaginary) = digits.strip_suffix('j') {
+        return imaginary.parse::<f64>().map_or(AstValue::Null, |value| {
+            format!("{}j", float_repr(value)).into()
+        });
+    }
+    let radix = match digits.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ if digits.contains(['.', 'e']) => {
+            return digits.parse::<f64>().map_or(AstValue::Null, |value| {
+                serde_json::Number::from_f64(value)
+                    .map_or_else(|| float_repr(value).into(), AstValue::Number)
+            })
+        }
+        _ => 10,
+    };
+    let digits = if radix == 10 {
+        digits.trim_end_matches('l')
+    } else {
+        &digits[2..]
+    };
+    match u64::from_str_radix(digits, radix) {
+        Ok(value) => AstValue::Number(value.into()),
+        Err(_) => big_integer(digits, radix).map_or(AstValue::Null, AstValue::String),
+    }
+}
+
+/// The decimal digits of an integer too large for `u64`
+fn big_integer(digits: &str, radix: u32) -> Option<String> {
+    // Little-endian limbs of nine decimal digits each
+    let mut limbs: Vec<u64> = vec![0];
+    for digit in digits.chars() {
+        let mut carry = u64::from(digit.to_digit(radix)?);
+        for limb in &mut limbs {
+            let value = *limb * u64::from(radix) + carry;
+            *limb = value % 1_000_000_000;
+            carry = value / 1_000_000_000;
+        }
+        if carry > 0 {
+            limbs.push(carry);
+        }
+    }
+    let mut limbs = limbs.iter().rev();
+    let mut decimal = limbs.next().map_or(String::new(), u64::to_string);
+    for limb in limbs {
+        decimal.push_str(&format!("{:09}", limb));
+    }
+    Some(decimal)
+}
+
+/// A float the way Python's `repr` writes it: the shortest digits that read back the same, in
+/// exponent notation below 1e-4 and from 1e16
+fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-4..1e16).contains(&magnitude) {
+        let formatted = format!("{:e}", value);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+        let (sign, exponent) = match exponent.strip_prefix('-') {
+            Some(exponent) => ('-', exponent),
+            None => ('+', exponent),
+        };
+        format!("{}e{}{:0>2}", mantissa, sign, exponent)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Decode the escape sequences in the text of a string literal, or of a bytes literal into
+/// characters below U+0100 that stand for its bytes
+fn unescape(text: &str, raw: bool, bytes: bool) -> String {
+    if raw || !text.contains('\\') {
+        return text.to_string();
+    }
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            value.push('\\');
+            break;
+        };
+        match escape {
+            '\n' => {}
+            '\r' => {
+                chars.next_if_eq(&'\n');
+            }
+            '\\' | '\'' | '"' => value.push(escape),
+            'a' => value.push('\x07'),
+            'b' => value.push('\x08'),
+            'f' => value.push('\x0c'),
+            'n' => value.push('\n'),
+            'r' => value.push('\r'),
+            't' => value.push('\t'),
+            'v' => value.push('\x0b'),
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match chars.peek().and_then(|next| next.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                let code = if bytes { code & 0xff } else { code };
+                value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            'x' | 'u' | 'U' if escape == 'x' || !bytes => {
+                let width = match escape {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let hex = chars.clone().take(width).collect::<String>();
+                match u32::from_str_radix(&hex, 16) {
+                    Ok(code) if hex.len() == width => {
+                        chars.nth(width - 1);
+                        value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    _ => {
+                        value.push('\\');
+                        value.push(escape);
+                    }
+                }
+            }
+            _ => {
+                value.push('\\');
+                value.push(escape);
+            }
+        }
+    }
+    value
+}
+
+/// The value of a bytes literal: its UTF-8 text, or its hex digits when it is not valid UTF-8
+fn bytes_value(text: &str) -> AstValue {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match u8::try_from(u32::from(c)) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => text.into(),
+        Err(error) => error
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+            .into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use tree_sitter::Parser;
+
+    fn python_ast(source: &str, options: &TreeOptions) -> AstValue {
+        let encodings = create_encodings();
+        let encoding = encodings.match_file("a.py").unwrap();
+        let mut parser = Parser::new();
+        parser.set_language(encoding.language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        to_python_ast(source, tree.root_node(), options)
+    }
+
+    fn statements(module: &AstValue) -> &[AstValue] {
+        match module.get("body") {
+            Some(AstValue::List(body)) => body,
+            _ => panic!("Module has no body"),
+        }
+    }
+
+    #[test]
+    fn test_number_values() {
+        let number = |raw| serde_json::to_string(&number(raw)).unwrap();
+        assert_eq!(number("42"), "42");
+        assert_eq!(number("1_000"), "1000");
+        assert_eq!(number("0xFF"), "255");
+        assert_eq!(number("0o17"), "15");
+        assert_eq!(number("0b101"), "5");
+        assert_eq!(number("1.5e3"), "1500.0");
+        assert_eq!(number("2j"), r#""2j""#);
+        assert_eq!(number("1.5J"), r#""1.5j""#);
+        assert_eq!(number("1e400"), r#""inf""#);
+        assert_eq!(
+            number("0x1_0000_0000_0000_0000"),
+            r#""18446744073709551616""#
+        );
+        assert_eq!(float_repr(1e16), "1e+16");
+        assert_eq!(float_repr(0.0001), "0.0001");
+        assert_eq!(float_repr(0.00001), "1e-05");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r"a\tb\n", false, false), "a\tb\n");
+        assert_eq!(unescape(r"\x41\101\u00e9\U0001F600", false, false), "AAé😀");
+        assert_eq!(unescape("a\\\nb", false, false), "ab");
+        assert_eq!(unescape(r"\d\N{BULLET}", false, false), r"\d\N{BULLET}");
+        assert_eq!(unescape(r"a\tb", true, false), r"a\tb");
+        // Bytes literals have no \u escapes, and octal escapes wrap at 256
+        assert_eq!(unescape(r"\u0041\777", false, true), "\\u0041\u{ff}");
+        assert_eq!(bytes_value("\u{ff}"), AstValue::from("ff"));
+        assert_eq!(bytes_value("abc"), AstValue::from("abc"));
+    }
+
+    #[test]
+    fn test_syntax_errors_become_unknown_nodes() {
+        let module = python_ast("x = 1\nprint 'hello'\n", &TreeOptions::default());
+        let body = statements(&module);
+        assert_eq!(
+            body[0].get("_type").and_then(AstValue::as_str),
+            Some("Assign")
+        );
+        assert_eq!(
+            body[1].get("_type").and_then(AstValue::as_str),
+            Some("Unknown")
+        );
+        assert_eq!(
+            body[1].get("kind").and_then(AstValue::as_str),
+            Some("print_statement")
+        );
+        assert_eq!(body[1].get("lineno"), Some(&AstValue::from(2)));
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let source = "s = '😀'; x";
+        let column = |options: &TreeOptions| {
+            statements(&python_ast(source, options))[1]
+                .get("col_offset")
+                .cloned()
+        };
+        assert_eq!(column(&TreeOptions::default()), Some(AstValue::from(12)));
+        let utf16 = TreeOptions {
+            column_encoding: ColumnEncoding::Utf16,
+            ..TreeOptions::default()
+        };
+        assert_eq!(column(&utf16), Some(AstValue::from(10)));
+    }
+}
@@ -0,0 +1,284 @@
+//! Tree-sitter query support: run S-expression queries against parsed trees and report captures
+use crate::encoding::Encoding;
+use crate::error::{AstgenError, Result};
+use crate::json::{ColumnEncoding, Position};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::sync::Arc;
+use tree_sitter::{Node, Query, QueryCursor, QueryError, QueryErrorKind, StreamingIterator};
+
+/// A node captured by a query pattern
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryCapture {
+    /// Index of the pattern that matched, in query source order
+    pub pattern_index: usize,
+    /// Capture name without the leading `@`
+    pub name: String,
+    pub kind: String,
+    pub text: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_point: Position,
+    pub end_point: Position,
+}
+
+/// Query sources, each optionally scoped to one language, compiled lazily per language
+///
+/// Queries are written against a specific grammar, so a query only applies to files whose
+/// encoding name matches its language scope (case-insensitively). Unscoped queries apply to
+/// every language whose grammar has the node kinds and fields they name; for other languages
+/// they are left out, and `take_skipped` reports why. All queries that apply to a language are
+/// compiled together, once.
+#[derive(Default)]
+pub struct QuerySet {
+    sources: Vec<(Option<String>, String)>,
+    compiled: DashMap<String, std::result::Result<Option<Arc<Query>>, String>>,
+    /// Why unscoped queries were left out for each language, until reported
+    skipped: DashMap<String, String>,
+}
+
+impl QuerySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a query source, scoped to `language` if given
+    pub fn add(&mut self, language: Option<&str>, source: &str) -> &mut Self {
+        self.sources
+            .push((language.map(str::to_string), source.to_string()));
+        self.compiled.clear();
+        self.skipped.clear();
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// The compiled query for files matched by `encoding`, or `None` if no query applies to it
+    pub fn for_encoding(&self, encoding: &Encoding) -> Result<Option<Arc<Query>>> {
+        let compiled = self
+            .compiled
+            .entry(encoding.name().to_string())
+            .or_insert_with(|| self.compile(encoding))
+            .clone();
+        compiled.map_err(AstgenError::QueryError)
+    }
+
+    /// Why unscoped queries do not apply to files matched by `encoding`, the first time this is
+    /// asked after they were compiled for it
+    pub fn take_skipped(&self, encoding: &Encoding) -> Option<String> {
+        self.skipped
+            .remove(encoding.name())
+            .map(|(_, reason)| reason)
+    }
+
+    fn compile(&self, encoding: &Encoding) -> std::result::Result<Option<Arc<Query>>, String> {
+        let mut applicable = Vec::new();
+        let mut skipped = Vec::new();
+        for (language, source) in &self.sources {
+            match language {
+                Some(language) if !language.eq_ignore_ascii_case(encoding.name()) => {}
+                Some(_) => applicable.push(source.as_str()),
+                // An unscoped query naming another grammar's nodes was not written for this one
+                None => match Query::new(encoding.language(), source) {
+                    Err(e) if is_grammar_mismatch(&e) => skipped.push(describe(encoding, &e)),
+                    _ => applicable.push(source.as_str()),
+                },
+            }
+        }
+        if !skipped.is_empty() {
+            self.skipped
+                .insert(encoding.name().to_string(), skipped.join("; "));
+        }
+        if applicable.is_empty() {
+            return Ok(None);
+        }
+
+        Query::new(encoding.language(), &applicable.join("\n"))
+            .map(|query| Some(Arc::new(query)))
+            .map_err(|e| describe(encoding, &e))
+    }
+}
+
+/// Whether a query failed only because it names node kinds, fields or structure that
+/// `encoding`'s grammar does not have, rather than being malformed
+fn is_grammar_mismatch(error: &QueryError) -> bool {
+    matches!(
+        error.kind,
+        QueryErrorKind::NodeType | QueryErrorKind::Field | QueryErrorKind::Structure
+    )
+}
+
+fn describe(encoding: &Encoding, error: &QueryError) -> String {
+    format!(
+        "Query is not valid for {} at row {}, column {}: {}",
+        encoding.name(),
+        error.row + 1,
+        error.column + 1,
+        error.message
+    )
+}
+
+/// Run `query` over the tree rooted at `root` and return every capture in match order
+///
+/// Text predicates such as `#eq?` and `#match?` are applied. Captures whose name starts with
+/// `_` are treated as private helpers for predicates and are not reported.
+pub fn run_query(
+    query: &Query,
+    root: Node,
+    source_code: &str,
+    column_encoding: ColumnEncoding,
+) -> Vec<QueryCapture> {
+    let capture_names = query.capture_names();
+    let mut captures = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, root, source_code.as_bytes());
+    while let Some(query_match) = matches.next() {
+        for capture in query_match.captures {
+            let name = capture_names[capture.index as usize];
+            if name.starts_with('_') {
+                continue;
+            }
+            let node = capture.node;
+            captures.push(QueryCapture {
+                pattern_index: query_match.pattern_index,
+                name: name.to_string(),
+                kind: node.kind().to_string(),
+                text: source_code[node.start_byte()..node.end_byte()].to_string(),
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                start_point: Position::from_point(
+                    source_code,
+                    node.start_position(),
+                    node.start_byte(),
+                    column_encoding,
+                ),
+                end_point: Position::from_point(
+                    source_code,
+                    node.end_position(),
+                    node.end_byte(),
+                    column_encoding,
+                ),
+            });
+        }
+    }
+    captures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Parser;
+
+    fn captures_for(code: &str, query_source: &str) -> Vec<QueryCapture> {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let mut parser = Parser::new();
+        parser.set_language(&rust_language).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let query = Query::new(&rust_language, query_source).unwrap();
+        run_query(&query, tree.root_node(), code, ColumnEncoding::Utf8)
+    }
+
+    #[test]
+    fn test_run_query_reports_captures() {
+        let captures = captures_for(
+            "fn first() {}\nfn second() {}",
+            "(function_item name: (identifier) @function.name)",
+        );
+        let names: Vec<&str> = captures.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert_eq!(captures[0].name, "function.name");
+        assert_eq!(captures[0].kind, "identifier");
+        assert_eq!(captures[1].start_point, Position { row: 1, column: 3 });
+    }
+
+    #[test]
+    fn test_run_query_applies_eq_predicate() {
+        let captures = captures_for(
+            "fn main() {}\nfn helper() {}",
+            r#"((function_item name: (identifier) @name) (#eq? @name "main"))"#,
+        );
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].text, "main");
+    }
+
+    #[test]
+    fn test_run_query_applies_match_predicate_and_hides_private_captures() {
+        let captures = captures_for(
+            "fn test_one() {}\nfn helper() {}\nfn test_two() {}",
+            r#"((function_item name: (identifier) @_name) @test (#match? @_name "^test_"))"#,
+        );
+        assert_eq!(captures.len(), 2);
+        assert!(captures.iter().all(|c| c.name == "test"));
+        assert!(captures[0].text.starts_with("fn test_one"));
+    }
+
+    #[test]
+    fn test_query_set_scopes_queries_by_language() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let python_language = tree_sitter_python::LANGUAGE.into();
+        let rust = Encoding::new("rs$", &rust_language, "Rust");
+        let python = Encoding::new("py$", &python_language, "Python");
+
+        let mut queries = QuerySet::new();
+        queries.add(Some("rust"), "(function_item) @function");
+
+        assert!(queries.for_encoding(&rust).unwrap().is_some());
+        assert!(queries.for_encoding(&python).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_query_set_reports_invalid_query_for_language() {
+        let python_language = tree_sitter_python::LANGUAGE.into();
+        let python = Encoding::new("py$", &python_language, "Python");
+
+        let mut queries = QuerySet::new();
+        queries.add(Some("python"), "(function_item) @function");
+
+        match queries.for_encoding(&python) {
+            Err(AstgenError::QueryError(message)) => assert!(message.contains("Python")),
+            other => panic!("Expected QueryError, got: {:?}", other.map(|q| q.is_some())),
+        }
+
+        // Malformed queries fail whatever their scope
+        let mut queries = QuerySet::new();
+        queries.add(None, "(function_definition");
+        assert!(queries.for_encoding(&python).is_err());
+    }
+
+    #[test]
+    fn test_query_set_skips_unscoped_queries_for_other_grammars() {
+        let rust_language = tree_sitter_rust::LANGUAGE.into();
+        let python_language = tree_sitter_python::LANGUAGE.into();
+        let rust = Encoding::new("rs$", &rust_language, "Rust");
+        let python = Encoding::new("py$", &python_language, "Python");
+
+        let mut queries = QuerySet::new();
+        queries
+            .add(None, "(function_item) @function")
+            .add(None, "(identifier) @name");
+
+        assert_eq!(
+            queries
+                .for_encoding(&rust)
+                .unwrap()
+                .unwrap()
+                .pattern_count(),
+            2
+        );
+        assert!(queries.take_skipped(&rust).is_none());
+        assert_eq!(
+            queries
+                .for_encoding(&python)
+                .unwrap()
+                .unwrap()
+                .pattern_count(),
+            1
+        );
+        assert!(queries.for_encoding(&python).unwrap().is_some());
+        // Reported once per language
+        assert!(queries.take_skipped(&python).unwrap().contains("Python"));
+        assert!(queries.take_skipped(&python).is_none());
+    }
+}
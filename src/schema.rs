@@ -0,0 +1,269 @@
+//! Language-specific AST schemas that syntax trees can be converted into
+//!
+//! Tools built around a language's own AST format (ESTree for JavaScript, the `ast` module for
+//! Python) cannot read Tree-sitter kinds, so `TreeOptions::schema` can replace the `ast` of every
+//! document with the tree converted into that format. The envelope stays the same.
+use crate::error::{AstgenError, Result};
+use crate::estree;
+use crate::json::{TreeOptions, STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES};
+use crate::python_ast;
+use clap::ValueEnum;
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use tree_sitter::{Node, Point};
+
+/// Shape of the `ast` written for each file
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AstSchema {
+    /// Tree-sitter nodes with their grammar's kinds, for every language
+    #[default]
+    TreeSitter,
+    /// ESTree, with the typescript-estree extensions for TypeScript and TSX
+    Estree,
+    /// CPython's `ast` module classes in `ast2json`'s layout, for Python
+    PythonAst,
+}
+
+impl AstSchema {
+    /// Languages the schema can convert, or `None` for all of them
+    pub fn languages(self) -> Option<&'static [&'static str]> {
+        match self {
+            AstSchema::TreeSitter => None,
+            AstSchema::Estree => Some(estree::LANGUAGES),
+            AstSchema::PythonAst => Some(python_ast::LANGUAGES),
+        }
+    }
+}
+
+/// A converted tree: JSON-like data whose maps keep their keys in the order the schema
+/// defines, e.g. `type` first
+///
+/// Values nest once per level of the tree, so serializing and dropping them grows the stack on
+/// demand, as for `JsonNode`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AstValue {
+    Null,
+    Bool(bool),
+    Number(serde_json::Number),
+    String(String),
+    List(Vec<AstValue>),
+    Map(Vec<(&'static str, AstValue)>),
+}
+
+impl AstValue {
+    /// The value of `key` in a map
+    pub fn get(&self, key: &str) -> Option<&AstValue> {
+        match self {
+            AstValue::Map(entries) => entries
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AstValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for AstValue {
+    fn drop(&mut self) {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || match self {
+            AstValue::List(items) => drop(std::mem::take(items)),
+            AstValue::Map(entries) => drop(std::mem::take(entries)),
+            _ => {}
+        })
+    }
+}
+
+impl Serialize for AstValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || match self {
+            AstValue::Null => serializer.serialize_unit(),
+            AstValue::Bool(value) => serializer.serialize_bool(*value),
+            AstValue::Number(value) => value.serialize(serializer),
+            AstValue::String(value) => serializer.serialize_str(value),
+            AstValue::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            AstValue::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        })
+    }
+}
+
+impl From<bool> for AstValue {
+    fn from(value: bool) -> Self {
+        AstValue::Bool(value)
+    }
+}
+
+impl From<usize> for AstValue {
+    fn from(value: usize) -> Self {
+        AstValue::Number(value.into())
+    }
+}
+
+impl From<&str> for AstValue {
+    fn from(value: &str) -> Self {
+        AstValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AstValue {
+    fn from(value: String) -> Self {
+        AstValue::String(value)
+    }
+}
+
+impl From<Vec<AstValue>> for AstValue {
+    fn from(items: Vec<AstValue>) -> Self {
+        AstValue::List(items)
+    }
+}
+
+impl<T: Into<AstValue>> From<Option<T>> for AstValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(AstValue::Null, Into::into)
+    }
+}
+
+/// Source range of a converted node, which may differ from the range of any Tree-sitter node
+#[derive(Clone, Copy)]
+pub(crate) struct Span {
+    pub(crate) start_byte: usize,
+    pub(crate) end_byte: usize,
+    pub(crate) start: Point,
+    pub(crate) end: Point,
+}
+
+impl Span {
+    /// From the start of `self` to the end of `other`
+    pub(crate) fn to(self, other: impl Into<Span>) -> Span {
+        let other = other.into();
+        Span {
+            end_byte: other.end_byte,
+            end: other.end,
+            ..self
+        }
+    }
+
+    /// The empty range at the start of `self`
+    pub(crate) fn start(self) -> Span {
+        Span {
+            end_byte: self.start_byte,
+            end: self.start,
+            ..self
+        }
+    }
+
+    /// The empty range at the end of `self`
+    pub(crate) fn end(self) -> Span {
+        Span {
+            start_byte: self.end_byte,
+            start: self.end,
+            ..self
+        }
+    }
+}
+
+impl From<Node<'_>> for Span {
+    fn from(node: Node) -> Self {
+        Span {
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            start: node.start_position(),
+            end: node.end_position(),
+        }
+    }
+}
+
+/// Convert the tree under `root` into `schema`, or fail if the schema does not cover `language`
+///
+/// `TreeOptions` settings that shape Tree-sitter output, such as `named_only` and the size
+/// budgets, do not apply; `column_encoding` does.
+pub fn convert_tree(
+    schema: AstSchema,
+    language: &str,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<AstValue> {
+    if let Some(languages) = schema.languages() {
+        if !languages.contains(&language) {
+            return Err(AstgenError::InvalidInput(format!(
+                "{} output is only available for {}, not {}",
+                schema_name(schema),
+                languages.join(", "),
+                language
+            )));
+        }
+    }
+    match schema {
+        AstSchema::TreeSitter => Err(AstgenError::InvalidInput(
+            "Tree-sitter trees are written directly rather than converted".to_string(),
+        )),
+        AstSchema::Estree => Ok(estree::to_estree(language, source_code, root, options)),
+        AstSchema::PythonAst => Ok(python_ast::to_python_ast(source_code, root, options)),
+    }
+}
+
+fn schema_name(schema: AstSchema) -> String {
+    schema.to_possible_value().map_or_else(
+        || format!("{:?}", schema),
+        |value| value.get_name().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use tree_sitter::Parser;
+
+    #[test]
+    fn test_maps_keep_their_key_order() {
+        let value = AstValue::Map(vec![
+            ("type", "Identifier".into()),
+            ("name", "x".into()),
+            ("optional", AstValue::Null),
+            ("range", vec![AstValue::from(0), AstValue::from(1)].into()),
+        ]);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"type":"Identifier","name":"x","optional":null,"range":[0,1]}"#
+        );
+        assert_eq!(value.get("name").and_then(AstValue::as_str), Some("x"));
+    }
+
+    #[test]
+    fn test_convert_tree_rejects_other_languages() {
+        let encodings = create_encodings();
+        let encoding = encodings.match_file("main.rs").unwrap();
+        let mut parser = Parser::new();
+        parser.set_language(encoding.language()).unwrap();
+        let tree = parser.parse("fn main() {}", None).unwrap();
+        let error = convert_tree(
+            AstSchema::Estree,
+            "Rust",
+            "fn main() {}",
+            tree.root_node(),
+            &TreeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("only available for JavaScript"));
+    }
+}
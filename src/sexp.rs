@@ -0,0 +1,189 @@
+//! S-expression output in the layout `tree-sitter parse` prints, for pasting into corpus tests
+use crate::error::Result;
+use crate::json::{Position, TreeOptions};
+use crate::output::Placement;
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use std::io::Write;
+use tree_sitter::Node;
+
+/// Write the tree under `root` as an indented S-expression, one named node per line
+///
+/// Like `tree-sitter parse`, only named nodes are shown, plus `(MISSING ...)` for tokens
+/// Tree-sitter inserted during error recovery. Field names and `[row, column] - [row, column]`
+/// ranges are added when `sexp_fields` and `sexp_ranges` are set. Nodes pruned by depth limits
+/// or size budgets are left out. When several files are written, each tree is an entry headed by
+/// a `;; filename` comment line and followed by a blank line.
+pub fn write_sexp(
+    out: &mut dyn Write,
+    placement: Placement,
+    filename: &str,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<()> {
+    // Children must stay in source order, and anonymous tokens are filtered here rather than by
+    // the walk so that MISSING tokens can still be shown
+    let walk_options = TreeOptions {
+        group_fields: false,
+        named_only: false,
+        ..options.clone()
+    };
+    let entry = placement == Placement::AggregateEntry;
+    if entry {
+        writeln!(out, ";; {}", filename)?;
+    }
+    let mut writer = SexpWriter {
+        out: &mut *out,
+        source_code,
+        options,
+        shown: Vec::new(),
+        depth: 0,
+    };
+    walk_tree(root, &walk_options, &mut writer)?;
+    writeln!(out)?;
+    if entry {
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+struct SexpWriter<'a> {
+    out: &'a mut dyn Write,
+    source_code: &'a str,
+    options: &'a TreeOptions,
+    /// Whether each entered node was printed, so `leave_node` knows whether to close it
+    shown: Vec<bool>,
+    /// Number of printed ancestors of the next node, which sets its indentation
+    depth: usize,
+}
+
+impl SexpWriter<'_> {
+    fn position(&self, node: Node, start: bool) -> Position {
+        let (point, byte) = if start {
+            (node.start_position(), node.start_byte())
+        } else {
+            (node.end_position(), node.end_byte())
+        };
+        Position::from_point(self.source_code, point, byte, self.options.column_encoding)
+    }
+}
+
+impl<'tree> TreeVisitor<'tree> for SexpWriter<'_> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        let node = visited.node;
+        let shown = node.is_named() || node.is_missing();
+        self.shown.push(shown);
+        if !shown {
+            return Ok(());
+        }
+
+        if self.depth > 0 {
+            write!(self.out, "\n{}", "  ".repeat(self.depth))?;
+        }
+        if let Some(field_name) = visited.field_name.filter(|_| self.options.sexp_fields) {
+            write!(self.out, "{}: ", field_name)?;
+        }
+        match (node.is_missing(), node.is_named()) {
+            (true, true) => write!(self.out, "(MISSING {}", node.kind())?,
+            (true, false) => write!(self.out, "(MISSING \"{}\"", node.kind())?,
+            (false, _) => write!(self.out, "({}", node.kind())?,
+        }
+        if self.options.sexp_ranges {
+            let (start, end) = (self.position(node, true), self.position(node, false));
+            write!(
+                self.out,
+                " [{}, {}] - [{}, {}]",
+                start.row, start.column, end.row, end.column
+            )?;
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_node(&mut self, _visited: &VisitedNode<'tree>, _omitted: usize) -> Result<()> {
+        if self.shown.pop() == Some(true) {
+            self.depth -= 1;
+            write!(self.out, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::{Parser, Tree};
+
+    fn parse_rust(code: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    fn sexp(code: &str, options: &TreeOptions) -> String {
+        placed_sexp(code, options, Placement::Standalone)
+    }
+
+    fn placed_sexp(code: &str, options: &TreeOptions, placement: Placement) -> String {
+        let tree = parse_rust(code);
+        let mut out = Vec::new();
+        write_sexp(
+            &mut out,
+            placement,
+            "src/main.rs",
+            code,
+            tree.root_node(),
+            options,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sexp_with_fields_matches_tree_sitter() {
+        let code = "fn main() {\n    let x = foo(1, \"a\");\n    let y = 2\n}\n";
+        let options = TreeOptions {
+            sexp_fields: true,
+            ..Default::default()
+        };
+        let printed = sexp(code, &options);
+        let collapsed = printed.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert_eq!(collapsed, parse_rust(code).root_node().to_sexp());
+        assert!(printed.contains("(MISSING \";\")"));
+    }
+
+    #[test]
+    fn test_sexp_layout_without_fields() {
+        assert_eq!(
+            sexp("fn main() {}", &TreeOptions::default()),
+            "(source_file\n  (function_item\n    (identifier)\n    (parameters)\n    (block)))\n"
+        );
+    }
+
+    #[test]
+    fn test_sexp_with_ranges() {
+        let options = TreeOptions {
+            sexp_fields: true,
+            sexp_ranges: true,
+            ..Default::default()
+        };
+        let printed = sexp("fn main() {}", &options);
+        assert!(printed.starts_with("(source_file [0, 0] - [0, 12]\n"));
+        assert!(printed.contains("\n    name: (identifier [0, 3] - [0, 7])\n"));
+    }
+
+    #[test]
+    fn test_sexp_entries_are_headed_by_their_filename() {
+        assert_eq!(
+            placed_sexp(
+                "fn main() {}",
+                &TreeOptions::default(),
+                Placement::AggregateEntry
+            ),
+            ";; src/main.rs\n(source_file\n  (function_item\n    (identifier)\n    (parameters)\n    (block)))\n\n"
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! SQLite databases of parsed files that are updated incrementally
+//!
+//! A database holds a `files` row per source file, its nodes in `nodes` (the same columns as the
+//! node tables in `node_table`), its syntax errors in `errors`, and a `runs` row per run with
+//! the grammar versions used. Files are keyed by path and store a hash of their content, so
+//! re-running over the same tree only replaces the rows of files that changed, and removes the
+//! rows of files a directory walk no longer finds.
+use crate::diagnostics::Diagnostic;
+use crate::error::{AstgenError, Result};
+use crate::json::{ColumnEncoding, TreeOptions};
+use crate::output::{RunHeader, RunSummary};
+use crate::parsing::SourceTree;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{UInt32Type, UInt64Type};
+use arrow_array::{Array, RecordBatch};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    astgen_version TEXT NOT NULL,
+    parsers TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    arguments TEXT NOT NULL,
+    files INTEGER,
+    succeeded INTEGER,
+    failed INTEGER,
+    with_syntax_errors INTEGER,
+    files_replaced INTEGER
+);
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    language TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    settings TEXT NOT NULL,
+    has_error INTEGER NOT NULL,
+    run_id INTEGER NOT NULL REFERENCES runs(id)
+);
+CREATE TABLE IF NOT EXISTS nodes (
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    node_id INTEGER NOT NULL,
+    parent_id INTEGER,
+    child_index INTEGER,
+    kind TEXT NOT NULL,
+    field_name TEXT,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    start_row INTEGER NOT NULL,
+    start_column INTEGER NOT NULL,
+    end_row INTEGER NOT NULL,
+    end_column INTEGER NOT NULL,
+    is_named INTEGER NOT NULL,
+    text TEXT,
+    PRIMARY KEY (file_id, node_id)
+) WITHOUT ROWID;
+CREATE INDEX IF NOT EXISTS nodes_kind ON nodes(kind);
+CREATE TABLE IF NOT EXISTS errors (
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    start_row INTEGER NOT NULL,
+    start_column INTEGER NOT NULL,
+    end_row INTEGER NOT NULL,
+    end_column INTEGER NOT NULL,
+    snippet TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS errors_file ON errors(file_id);
+";
+
+/// Hex SHA-256 of a file's content, used to decide whether its rows are out of date
+pub fn content_hash(source: &str) -> String {
+    Sha256::digest(source.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Everything stored for one parsed file
+pub struct StoredFile {
+    pub path: String,
+    pub language: String,
+    pub content_hash: String,
+    pub has_error: bool,
+    /// Rows in the layout of `node_table::node_table_schema`
+    pub nodes: RecordBatch,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl StoredFile {
+    pub fn new(tree: &SourceTree, content_hash: String, options: &TreeOptions) -> Result<Self> {
+        Ok(Self {
+            path: tree.filename.clone(),
+            language: tree.language.clone(),
+            content_hash,
+            has_error: tree.has_error(),
+            nodes: tree.node_rows(options)?,
+            diagnostics: tree.diagnostics(options),
+        })
+    }
+}
+
+/// Content hashes of the files already in a database, which can be shared between threads
+pub struct StoredHashes {
+    files: HashMap<String, (String, bool)>,
+}
+
+impl StoredHashes {
+    /// Whether `path` is stored with this content hash, and if so whether it had syntax errors
+    pub fn unchanged(&self, path: &str, content_hash: &str) -> Option<bool> {
+        self.files
+            .get(path)
+            .filter(|(stored_hash, _)| stored_hash == content_hash)
+            .map(|&(_, has_error)| has_error)
+    }
+}
+
+/// An open database; everything a run writes is committed together by `finish`
+pub struct SqliteStore {
+    connection: Connection,
+    run_id: i64,
+    /// Identifies the astgen build and tree options rows were produced with; files stored with
+    /// other settings are replaced even if their content is unchanged
+    settings: String,
+    files_replaced: usize,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path` and record the start of a run
+    pub fn open(path: &Path, header: &RunHeader, options: &TreeOptions) -> Result<Self> {
+        let connection = Connection::open(path).map_err(database_error)?;
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(database_error)?;
+        connection.execute_batch(SCHEMA).map_err(database_error)?;
+        connection.execute_batch("BEGIN").map_err(database_error)?;
+        connection
+            .execute(
+                "INSERT INTO runs (astgen_version, parsers, started_at, arguments)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    header.version,
+                    serde_json::to_string(&header.parsers)?,
+                    header.started_at,
+                    serde_json::to_string(&header.arguments)?,
+                ],
+            )
+            .map_err(database_error)?;
+        let run_id = connection.last_insert_rowid();
+        Ok(Self {
+            connection,
+            run_id,
+            settings: settings_key(options),
+            files_replaced: 0,
+        })
+    }
+
+    /// Content hashes of the files stored with this run's settings
+    pub fn stored_hashes(&self) -> Result<StoredHashes> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT path, content_hash, has_error FROM files WHERE settings = ?1")
+            .map_err(database_error)?;
+        let files = statement
+            .query_map([&self.settings], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<_>>())
+            .map_err(database_error)?;
+        Ok(StoredHashes { files })
+    }
+
+    /// Replace whatever is stored for `file.path` with `file`
+    pub fn store(&mut self, file: &StoredFile) -> Result<()> {
+        let connection = &self.connection;
+        let previous: Option<i64> = connection
+            .query_row(
+                "SELECT id FROM files WHERE path = ?1",
+                [&file.path],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(database_error)?;
+        if let Some(file_id) = previous {
+            delete_file(connection, file_id)?;
+        }
+        connection
+            .execute(
+                "INSERT INTO files (path, language, content_hash, settings, has_error, run_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    file.path,
+                    file.language,
+                    file.content_hash,
+                    self.settings,
+                    file.has_error,
+                    self.run_id
+                ],
+            )
+            .map_err(database_error)?;
+        let file_id = connection.last_insert_rowid();
+        insert_nodes(connection, file_id, &file.nodes)?;
+        insert_errors(connection, file_id, &file.diagnostics)?;
+        self.files_replaced += 1;
+        Ok(())
+    }
+
+    /// Remove the files stored under directory `root` that are not in `walked`, the paths a walk
+    /// of it just found, so files deleted, renamed or excluded since an earlier run do not linger;
+    /// returns how many were removed
+    pub fn remove_missing(&mut self, root: &Path, walked: &HashSet<String>) -> Result<usize> {
+        let connection = &self.connection;
+        let mut statement = connection
+            .prepare("SELECT id, path FROM files WHERE substr(path, 1, length(?1)) = ?1")
+            .map_err(database_error)?;
+        let missing = statement
+            .query_map([root.to_string_lossy()], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(database_error)?
+            .into_iter()
+            .filter(|(_, path)| Path::new(path).starts_with(root) && !walked.contains(path))
+            .collect::<Vec<_>>();
+        for (file_id, _) in &missing {
+            delete_file(connection, *file_id)?;
+        }
+        Ok(missing.len())
+    }
+
+    /// Record the run's counts and commit everything it stored
+    pub fn finish(self, summary: &RunSummary) -> Result<()> {
+        self.connection
+            .execute(
+                "UPDATE runs SET files = ?1, succeeded = ?2, failed = ?3, with_syntax_errors = ?4,
+                 files_replaced = ?5 WHERE id = ?6",
+                params![
+                    summary.files,
+                    summary.succeeded,
+                    summary.failed,
+                    summary.with_syntax_errors,
+                    self.files_replaced,
+                    self.run_id
+                ],
+            )
+            .and_then(|_| self.connection.execute_batch("COMMIT"))
+            .map_err(database_error)
+    }
+}
+
+fn delete_file(connection: &Connection, file_id: i64) -> Result<()> {
+    connection
+        .execute("DELETE FROM nodes WHERE file_id = ?1", [file_id])
+        .and_then(|_| connection.execute("DELETE FROM errors WHERE file_id = ?1", [file_id]))
+        .and_then(|_| connection.execute("DELETE FROM files WHERE id = ?1", [file_id]))
+        .map_err(database_error)?;
+    Ok(())
+}
+
+fn insert_nodes(connection: &Connection, file_id: i64, nodes: &RecordBatch) -> Result<()> {
+    let mut statement = connection
+        .prepare_cached(
+            "INSERT INTO nodes (file_id, node_id, parent_id, child_index, kind, field_name,
+             start_byte, end_byte, start_row, start_column, end_row, end_column, is_named, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )
+        .map_err(database_error)?;
+    let u64_column = |name: &str| nodes[name].as_primitive::<UInt64Type>();
+    let (node_id, parent_id) = (u64_column("node_id"), u64_column("parent_id"));
+    let child_index = nodes["child_index"].as_primitive::<UInt32Type>();
+    let (kind, field_name) = (
+        nodes["kind"].as_string::<i32>(),
+        nodes["field_name"].as_string::<i32>(),
+    );
+    let (start_byte, end_byte) = (u64_column("start_byte"), u64_column("end_byte"));
+    let (start_row, start_column) = (u64_column("start_row"), u64_column("start_column"));
+    let (end_row, end_column) = (u64_column("end_row"), u64_column("end_column"));
+    let is_named = nodes["is_named"].as_boolean();
+    let text = nodes["text"].as_string::<i32>();
+    for row in 0..nodes.num_rows() {
+        statement
+            .execute(params![
+                file_id,
+                node_id.value(row),
+                parent_id.is_valid(row).then(|| parent_id.value(row)),
+                child_index.is_valid(row).then(|| child_index.value(row)),
+                kind.value(row),
+                field_name.is_valid(row).then(|| field_name.value(row)),
+                start_byte.value(row),
+                end_byte.value(row),
+                start_row.value(row),
+                start_column.value(row),
+                end_row.value(row),
+                end_column.value(row),
+                is_named.value(row),
+                text.is_valid(row).then(|| text.value(row)),
+            ])
+            .map_err(database_error)?;
+    }
+    Ok(())
+}
+
+fn insert_errors(connection: &Connection, file_id: i64, diagnostics: &[Diagnostic]) -> Result<()> {
+    let mut statement = connection
+        .prepare_cached(
+            "INSERT INTO errors (file_id, kind, message, start_byte, end_byte, start_row,
+             start_column, end_row, end_column, snippet)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )
+        .map_err(database_error)?;
+    for diagnostic in diagnostics {
+        let kind = serde_json::to_value(diagnostic.kind)?;
+        statement
+            .execute(params![
+                file_id,
+                kind.as_str(),
+                diagnostic.message,
+                diagnostic.start_byte,
+                diagnostic.end_byte,
+                diagnostic.start_point.row,
+                diagnostic.start_point.column,
+                diagnostic.end_point.row,
+                diagnostic.end_point.column,
+                diagnostic.snippet,
+            ])
+            .map_err(database_error)?;
+    }
+    Ok(())
+}
+
+/// The astgen build and the options that shape the `nodes` and `errors` rows, e.g.
+/// `astgen 0.8.0.abc123; columns=utf8; named_only=false; ...`
+///
+/// Options that only change other output formats are left out, so they do not invalidate the
+/// store. `include_is_named` counts because it changes the size `max_output_bytes` measures.
+fn settings_key(options: &TreeOptions) -> String {
+    // Destructured in full so that a new option has to be classified here
+    let TreeOptions {
+        column_encoding,
+        group_fields: _,
+        named_only,
+        include_is_named,
+        max_depth,
+        max_nodes,
+        max_output_bytes,
+        sexp_fields: _,
+        sexp_ranges: _,
+        graph_root: _,
+        schema: _,
+    } = options;
+    let columns = match column_encoding {
+        ColumnEncoding::Utf8 => "utf8",
+        ColumnEncoding::Utf16 => "utf16",
+    };
+    let limit = |limit: &Option<usize>| limit.map_or("none".to_string(), |n| n.to_string());
+    format!(
+        "astgen {}; columns={}; named_only={}; include_is_named={}; max_depth={}; max_nodes={}; max_output_bytes={}",
+        crate::VERSION,
+        columns,
+        named_only,
+        include_is_named,
+        limit(max_depth),
+        limit(max_nodes),
+        limit(max_output_bytes)
+    )
+}
+
+fn database_error(error: rusqlite::Error) -> AstgenError {
+    AstgenError::SerializationError(format!("Cannot update SQLite database: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use crate::json::Position;
+    use crate::parsing::parse_source_tree;
+    use crate::schema::AstSchema;
+
+    fn stored_file(path: &str, source: &str) -> StoredFile {
+        let encodings = create_encodings();
+        let tree = parse_source_tree(
+            source.to_string(),
+            path,
+            encodings.match_file(path).unwrap(),
+        )
+        .unwrap();
+        StoredFile::new(&tree, content_hash(source), &TreeOptions::default()).unwrap()
+    }
+
+    fn count(connection: &Connection, sql: &str) -> i64 {
+        connection.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_store_replaces_previous_rows_for_a_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.db");
+        let options = TreeOptions::default();
+        let header = RunHeader::new(Vec::new());
+
+        let mut store = SqliteStore::open(&path, &header, &options).unwrap();
+        store.store(&stored_file("a.rs", "fn a() {}")).unwrap();
+        store.store(&stored_file("b.rs", "fn b( {}")).unwrap();
+        store.finish(&RunSummary::default()).unwrap();
+
+        let mut store = SqliteStore::open(&path, &header, &options).unwrap();
+        let hashes = store.stored_hashes().unwrap();
+        assert_eq!(
+            hashes.unchanged("a.rs", &content_hash("fn a() {}")),
+            Some(false)
+        );
+        assert_eq!(
+            hashes.unchanged("b.rs", &content_hash("fn b( {}")),
+            Some(true)
+        );
+        assert_eq!(hashes.unchanged("a.rs", &content_hash("fn a2() {}")), None);
+        let changed = stored_file("a.rs", "fn a2() { let x = 1; }");
+        store.store(&changed).unwrap();
+        store.finish(&RunSummary::default()).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "SELECT count(*) FROM files"), 2);
+        assert_eq!(count(&connection, "SELECT count(*) FROM runs"), 2);
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT count(*) FROM nodes JOIN files ON files.id = nodes.file_id WHERE path = 'a.rs'"
+            ),
+            changed.nodes.num_rows() as i64
+        );
+        assert!(count(&connection, "SELECT count(*) FROM errors") > 0);
+    }
+
+    #[test]
+    fn test_changed_settings_invalidate_stored_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.db");
+        let header = RunHeader::new(Vec::new());
+
+        let mut store = SqliteStore::open(&path, &header, &TreeOptions::default()).unwrap();
+        store.store(&stored_file("a.rs", "fn a() {}")).unwrap();
+        store.finish(&RunSummary::default()).unwrap();
+
+        let named_only = TreeOptions {
+            named_only: true,
+            ..Default::default()
+        };
+        let store = SqliteStore::open(&path, &header, &named_only).unwrap();
+        let hashes = store.stored_hashes().unwrap();
+        assert_eq!(hashes.unchanged("a.rs", &content_hash("fn a() {}")), None);
+    }
+
+    #[test]
+    fn test_options_of_other_formats_keep_stored_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.db");
+        let header = RunHeader::new(Vec::new());
+
+        let mut store = SqliteStore::open(&path, &header, &TreeOptions::default()).unwrap();
+        store.store(&stored_file("a.rs", "fn a() {}")).unwrap();
+        store.finish(&RunSummary::default()).unwrap();
+
+        let other_formats = TreeOptions {
+            group_fields: true,
+            sexp_fields: true,
+            sexp_ranges: true,
+            graph_root: Some(Position { row: 0, column: 3 }),
+            schema: AstSchema::Estree,
+            ..Default::default()
+        };
+        let store = SqliteStore::open(&path, &header, &other_formats).unwrap();
+        let hashes = store.stored_hashes().unwrap();
+        assert_eq!(
+            hashes.unchanged("a.rs", &content_hash("fn a() {}")),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_remove_missing_keeps_walked_files_and_other_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.db");
+        let header = RunHeader::new(Vec::new());
+
+        let mut store = SqliteStore::open(&path, &header, &TreeOptions::default()).unwrap();
+        for file in [
+            "src/a.rs",
+            "src/old.rs",
+            "src/nested/b.rs",
+            "srcgen/c.rs",
+            "d.rs",
+        ] {
+            store.store(&stored_file(file, "fn f() {}")).unwrap();
+        }
+        let walked = HashSet::from(["src/a.rs".to_string(), "src/nested/b.rs".to_string()]);
+        assert_eq!(store.remove_missing(Path::new("src"), &walked).unwrap(), 1);
+        store.finish(&RunSummary::default()).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let paths: Vec<String> = connection
+            .prepare("SELECT path FROM files ORDER BY path")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            paths,
+            ["d.rs", "src/a.rs", "src/nested/b.rs", "srcgen/c.rs"]
+        );
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT count(*) FROM nodes WHERE file_id NOT IN (SELECT id FROM files)"
+            ),
+            0
+        );
+    }
+}
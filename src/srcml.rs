@@ -0,0 +1,546 @@
+//! srcML-style XML, where markup is interleaved with the complete source text
+//!
+//! Every byte of the file is written, including the whitespace and comments between tokens, so
+//! stripping the tags gives back the original source. For C, C++, Java and C# node kinds are
+//! mapped to srcML element names and nodes without a srcML counterpart contribute only their
+//! text; other languages use the Tree-sitter kind of every named node as its element name.
+use crate::error::Result;
+use crate::json::TreeOptions;
+use crate::output::Placement;
+use crate::tree_walk::{walk_tree, TreeVisitor, VisitedNode};
+use std::io::Write;
+use tree_sitter::Node;
+
+const SRC_NAMESPACE: &str = "http://www.srcML.org/srcML/src";
+const CPP_NAMESPACE: &str = "http://www.srcML.org/srcML/cpp";
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>";
+
+/// Fields holding the type of a declaration, function or expression, wrapped in `<type>`
+const TYPE_FIELDS: &[&str] = &["type", "returns"];
+
+/// Kinds shared by the C and C++ grammars
+const C_ELEMENTS: &[(&str, &str)] = &[
+    ("function_definition", "function"),
+    ("declaration", "decl_stmt"),
+    ("init_declarator", "decl"),
+    ("parameter_list", "parameter_list"),
+    ("parameter_declaration", "parameter"),
+    ("compound_statement", "block"),
+    ("identifier", "name"),
+    ("field_identifier", "name"),
+    ("type_identifier", "name"),
+    ("primitive_type", "name"),
+    ("sized_type_specifier", "name"),
+    ("statement_identifier", "name"),
+    ("expression_statement", "expr_stmt"),
+    ("return_statement", "return"),
+    ("if_statement", "if"),
+    ("else_clause", "else"),
+    ("while_statement", "while"),
+    ("do_statement", "do"),
+    ("for_statement", "for"),
+    ("switch_statement", "switch"),
+    ("case_statement", "case"),
+    ("break_statement", "break"),
+    ("continue_statement", "continue"),
+    ("goto_statement", "goto"),
+    ("labeled_statement", "label"),
+    ("call_expression", "call"),
+    ("argument_list", "argument_list"),
+    ("number_literal", "literal type=\"number\""),
+    ("string_literal", "literal type=\"string\""),
+    ("char_literal", "literal type=\"char\""),
+    ("true", "literal type=\"boolean\""),
+    ("false", "literal type=\"boolean\""),
+    ("null", "literal type=\"null\""),
+    ("comment", "comment"),
+    ("struct_specifier", "struct"),
+    ("union_specifier", "union"),
+    ("enum_specifier", "enum"),
+    ("field_declaration_list", "block"),
+    ("field_declaration", "decl_stmt"),
+    ("enumerator", "decl"),
+    ("type_definition", "typedef"),
+    ("preproc_include", "cpp:include"),
+    ("preproc_def", "cpp:define"),
+    ("preproc_function_def", "cpp:define"),
+    ("preproc_if", "cpp:if"),
+    ("preproc_ifdef", "cpp:ifdef"),
+    ("system_lib_string", "cpp:file"),
+];
+
+/// Kinds only the C++ grammar has
+const CPP_ELEMENTS: &[(&str, &str)] = &[
+    ("class_specifier", "class"),
+    ("namespace_definition", "namespace"),
+    ("namespace_identifier", "name"),
+    ("qualified_identifier", "name"),
+    ("template_declaration", "template"),
+    ("template_argument_list", "argument_list type=\"template\""),
+    ("using_declaration", "using"),
+    ("access_specifier", "specifier"),
+    ("lambda_expression", "lambda"),
+    ("try_statement", "try"),
+    ("catch_clause", "catch"),
+    ("throw_statement", "throw"),
+    ("for_range_loop", "for"),
+    ("nullptr", "literal type=\"null\""),
+    ("this", "name"),
+];
+
+const JAVA_ELEMENTS: &[(&str, &str)] = &[
+    ("package_declaration", "package"),
+    ("import_declaration", "import"),
+    ("class_declaration", "class"),
+    ("interface_declaration", "interface"),
+    ("enum_declaration", "enum"),
+    ("class_body", "block"),
+    ("interface_body", "block"),
+    ("method_declaration", "function"),
+    ("constructor_declaration", "constructor"),
+    ("formal_parameters", "parameter_list"),
+    ("formal_parameter", "parameter"),
+    ("block", "block"),
+    ("field_declaration", "decl_stmt"),
+    ("local_variable_declaration", "decl_stmt"),
+    ("variable_declarator", "decl"),
+    ("identifier", "name"),
+    ("type_identifier", "name"),
+    ("integral_type", "name"),
+    ("floating_point_type", "name"),
+    ("boolean_type", "name"),
+    ("void_type", "name"),
+    ("expression_statement", "expr_stmt"),
+    ("return_statement", "return"),
+    ("if_statement", "if"),
+    ("while_statement", "while"),
+    ("do_statement", "do"),
+    ("for_statement", "for"),
+    ("enhanced_for_statement", "foreach"),
+    ("switch_expression", "switch"),
+    ("break_statement", "break"),
+    ("continue_statement", "continue"),
+    ("try_statement", "try"),
+    ("catch_clause", "catch"),
+    ("finally_clause", "finally"),
+    ("throw_statement", "throw"),
+    ("method_invocation", "call"),
+    ("argument_list", "argument_list"),
+    ("lambda_expression", "lambda"),
+    ("annotation", "annotation"),
+    ("marker_annotation", "annotation"),
+    ("decimal_integer_literal", "literal type=\"number\""),
+    ("hex_integer_literal", "literal type=\"number\""),
+    ("decimal_floating_point_literal", "literal type=\"number\""),
+    ("string_literal", "literal type=\"string\""),
+    ("character_literal", "literal type=\"char\""),
+    ("true", "literal type=\"boolean\""),
+    ("false", "literal type=\"boolean\""),
+    ("null_literal", "literal type=\"null\""),
+    ("line_comment", "comment type=\"line\""),
+    ("block_comment", "comment type=\"block\""),
+];
+
+const CSHARP_ELEMENTS: &[(&str, &str)] = &[
+    ("using_directive", "using"),
+    ("namespace_declaration", "namespace"),
+    ("class_declaration", "class"),
+    ("struct_declaration", "struct"),
+    ("interface_declaration", "interface"),
+    ("enum_declaration", "enum"),
+    ("declaration_list", "block"),
+    ("method_declaration", "function"),
+    ("constructor_declaration", "constructor"),
+    ("property_declaration", "property"),
+    ("parameter_list", "parameter_list"),
+    ("parameter", "parameter"),
+    ("block", "block"),
+    ("field_declaration", "decl_stmt"),
+    ("local_declaration_statement", "decl_stmt"),
+    ("variable_declarator", "decl"),
+    ("identifier", "name"),
+    ("predefined_type", "name"),
+    ("expression_statement", "expr_stmt"),
+    ("return_statement", "return"),
+    ("if_statement", "if"),
+    ("while_statement", "while"),
+    ("do_statement", "do"),
+    ("for_statement", "for"),
+    ("foreach_statement", "foreach"),
+    ("switch_statement", "switch"),
+    ("break_statement", "break"),
+    ("continue_statement", "continue"),
+    ("try_statement", "try"),
+    ("catch_clause", "catch"),
+    ("finally_clause", "finally"),
+    ("throw_statement", "throw"),
+    ("invocation_expression", "call"),
+    ("argument_list", "argument_list"),
+    ("lambda_expression", "lambda"),
+    ("attribute_list", "attribute"),
+    ("integer_literal", "literal type=\"number\""),
+    ("real_literal", "literal type=\"number\""),
+    ("string_literal", "literal type=\"string\""),
+    ("character_literal", "literal type=\"char\""),
+    ("boolean_literal", "literal type=\"boolean\""),
+    ("null_literal", "literal type=\"null\""),
+    ("comment", "comment"),
+];
+
+/// The srcML element tables for a language, most specific first, or `None` if srcML does not
+/// cover it
+fn element_tables(language: &str) -> Option<&'static [&'static [(&'static str, &'static str)]]> {
+    match language {
+        "C" => Some(&[C_ELEMENTS]),
+        "C++" => Some(&[CPP_ELEMENTS, C_ELEMENTS]),
+        "Java" => Some(&[JAVA_ELEMENTS]),
+        "C#" => Some(&[CSHARP_ELEMENTS]),
+        _ => None,
+    }
+}
+
+/// Write the tree under `root` as one srcML `unit` document for `filename`
+///
+/// `root` must be the root of the tree parsed from `source_code`, so that the whole file is
+/// covered.
+pub fn write_srcml(
+    out: &mut dyn Write,
+    placement: Placement,
+    language: &str,
+    filename: &str,
+    source_code: &str,
+    root: Node,
+    options: &TreeOptions,
+) -> Result<()> {
+    let tables = element_tables(language);
+    let archived = placement == Placement::AggregateEntry;
+    if !archived {
+        writeln!(out, "{}", XML_DECLARATION)?;
+    }
+    write!(out, "<unit")?;
+    if tables.is_some() {
+        // Units in an archive take the srcML namespace from the archive's unit
+        if !archived {
+            write!(out, " xmlns=\"{}\"", SRC_NAMESPACE)?;
+        }
+        if matches!(language, "C" | "C++") {
+            write!(out, " xmlns:cpp=\"{}\"", CPP_NAMESPACE)?;
+        }
+        write!(out, " revision=\"1.0.0\"")?;
+    } else if archived {
+        write!(out, " xmlns=\"\"")?;
+    }
+    write!(
+        out,
+        " language=\"{}\" filename=\"{}\">",
+        escape(language),
+        escape(filename)
+    )?;
+
+    // Text is written in source order, so fields must not be regrouped
+    let walk_options = TreeOptions {
+        group_fields: false,
+        ..options.clone()
+    };
+    let mut writer = SrcmlWriter {
+        out: &mut *out,
+        source_code,
+        tables,
+        written: 0,
+        open: Vec::new(),
+    };
+    walk_tree(root, &walk_options, &mut writer)?;
+    writer.write_source_to(source_code.len())?;
+    writeln!(out, "</unit>")?;
+    if archived {
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Open an srcML archive, the `<unit>` holding one `<unit>` per file when several files are
+/// written as one document
+pub fn write_archive_start(out: &mut dyn Write) -> Result<()> {
+    write!(
+        out,
+        "{}\n<unit xmlns=\"{}\" revision=\"1.0.0\">\n\n",
+        XML_DECLARATION, SRC_NAMESPACE
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Close an srcML archive opened with `write_archive_start`
+pub fn write_archive_end(out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "</unit>")?;
+    out.flush()?;
+    Ok(())
+}
+
+struct SrcmlWriter<'a> {
+    out: &'a mut dyn Write,
+    source_code: &'a str,
+    tables: Option<&'static [&'static [(&'static str, &'static str)]]>,
+    /// Source up to this byte offset has been written
+    written: usize,
+    /// Element opened for each entered node, if any, and whether a `<type>` was opened around it
+    open: Vec<(Option<String>, bool)>,
+}
+
+impl SrcmlWriter<'_> {
+    /// Start tag contents (name and attributes) for a node, or `None` to write only its text
+    fn element(&self, visited: &VisitedNode) -> Option<String> {
+        let node = visited.node;
+        if visited.depth == 0 || node.is_missing() {
+            return None;
+        }
+        match self.tables {
+            Some(tables) => tables
+                .iter()
+                .find_map(|table| table.iter().find(|(kind, _)| *kind == node.kind()))
+                .map(|(_, element)| element.to_string()),
+            None => node.is_named().then(|| element_name(node.kind())),
+        }
+    }
+
+    /// Whether a node is a type not already inside a `<type>` element; srcML wraps the names
+    /// and specifiers of a type in one `<type>`
+    fn starts_type(&self, visited: &VisitedNode) -> bool {
+        self.tables.is_some()
+            && visited.depth > 0
+            && visited
+                .field_name
+                .is_some_and(|field| TYPE_FIELDS.contains(&field))
+            && !self.open.iter().any(|(_, typed)| *typed)
+    }
+
+    fn write_source_to(&mut self, end: usize) -> Result<()> {
+        if end > self.written {
+            write_text(self.out, &self.source_code[self.written..end])?;
+            self.written = end;
+        }
+        Ok(())
+    }
+}
+
+impl<'tree> TreeVisitor<'tree> for SrcmlWriter<'_> {
+    fn enter_node(&mut self, visited: &VisitedNode<'tree>) -> Result<()> {
+        self.write_source_to(visited.node.start_byte())?;
+        let typed = self.starts_type(visited);
+        if typed {
+            write!(self.out, "<type>")?;
+        }
+        let element = self.element(visited);
+        if let Some(element) = &element {
+            write!(self.out, "<{}>", element)?;
+        }
+        self.open.push((element, typed));
+        Ok(())
+    }
+
+    fn leave_node(&mut self, visited: &VisitedNode<'tree>, _omitted: usize) -> Result<()> {
+        self.write_source_to(visited.node.end_byte())?;
+        let (element, typed) = self.open.pop().unwrap_or_default();
+        if let Some(element) = element {
+            let name = element.split(' ').next().unwrap_or(&element);
+            write!(self.out, "</{}>", name)?;
+        }
+        if typed {
+            write!(self.out, "</type>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Turn a Tree-sitter kind into a valid XML element name
+fn element_name(kind: &str) -> String {
+    let name: String = kind
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name
+    } else {
+        format!("_{}", name)
+    }
+}
+
+/// Write source text as character data; characters XML cannot hold become srcML `escape`
+/// elements, and carriage returns are escaped so XML parsers do not normalize them away
+fn write_text(out: &mut dyn Write, text: &str) -> Result<()> {
+    for c in text.chars() {
+        match c {
+            '&' => out.write_all(b"&amp;")?,
+            '<' => out.write_all(b"&lt;")?,
+            '>' => out.write_all(b"&gt;")?,
+            '\r' => out.write_all(b"&#xD;")?,
+            '\t' | '\n' => write!(out, "{}", c)?,
+            c if c.is_control() && (c as u32) < 0x20 => {
+                write!(out, "<escape char=\"0x{:02x}\"/>", c as u32)?
+            }
+            c => write!(out, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_encodings;
+    use tree_sitter::Parser;
+
+    fn srcml(filename: &str, source: &str) -> String {
+        placed_srcml(filename, source, Placement::Standalone)
+    }
+
+    fn placed_srcml(filename: &str, source: &str, placement: Placement) -> String {
+        let encodings = create_encodings();
+        let encoding = encodings.match_file(filename).unwrap();
+        let mut parser = Parser::new();
+        parser.set_language(encoding.language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut out = Vec::new();
+        write_srcml(
+            &mut out,
+            placement,
+            encoding.name(),
+            filename,
+            source,
+            tree.root_node(),
+            &TreeOptions::default(),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn strip_tags(xml: &str) -> String {
+        let body = &xml[xml.find("?>\n").unwrap() + 3..xml.rfind("</unit>").unwrap()];
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in body.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&#xD;", "\r")
+            .replace("&amp;", "&")
+    }
+
+    #[test]
+    fn test_c_maps_kinds_to_srcml_elements() {
+        let xml = srcml("main.c", "int main() {\n  return 0; // done\n}\n");
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <unit xmlns=\"http://www.srcML.org/srcML/src\" xmlns:cpp=\"http://www.srcML.org/srcML/cpp\" \
+             revision=\"1.0.0\" language=\"C\" filename=\"main.c\">\
+             <function><type><name>int</name></type> <name>main</name><parameter_list>()</parameter_list> \
+             <block>{\n  <return>return <literal type=\"number\">0</literal>;</return> \
+             <comment>// done</comment>\n}</block></function>\n</unit>\n"
+        );
+    }
+
+    #[test]
+    fn test_stripping_tags_recovers_the_source() {
+        let samples = [
+            (
+                "a.cpp",
+                "#include <vector>\n\nnamespace n {\r\n  class A { int x = 1 & 2; };\n}\n",
+            ),
+            ("A.java", "class A {\n\tvoid f() { g(\"<x>\"); }\n}\n"),
+            (
+                "A.cs",
+                "using System;\nclass A { void F() { if (a < b) { } } }\n",
+            ),
+            ("broken.c", "int f( {\n"),
+            ("a.py", "def f(x):\n    return x  # done\n"),
+        ];
+        for (filename, source) in samples {
+            assert_eq!(strip_tags(&srcml(filename, source)), source, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_other_languages_use_node_kinds() {
+        let xml = srcml("a.py", "x = 1\n");
+        assert!(xml.contains("<unit language=\"Python\" filename=\"a.py\">"));
+        assert!(xml.contains("<expression_statement><assignment><identifier>x</identifier> = <integer>1</integer></assignment></expression_statement>"));
+    }
+
+    #[test]
+    fn test_types_are_wrapped_in_type_elements() {
+        let xml = srcml(
+            "A.java",
+            "class A { int f(String s) { return (int) 0; } }\n",
+        );
+        assert!(xml.contains("<type><name>int</name></type> <name>f</name>"));
+        assert!(
+            xml.contains("<parameter><type><name>String</name></type> <name>s</name></parameter>")
+        );
+
+        // A type inside a type is not wrapped again
+        let xml = srcml("a.c", "unsigned long n = sizeof(const char *);\n");
+        assert_eq!(xml.matches("<type>").count(), 2);
+        assert_eq!(xml.matches("</type>").count(), 2);
+    }
+
+    #[test]
+    fn test_archive_units_share_the_archive_namespace() {
+        let xml = placed_srcml("main.c", "int x;\n", Placement::AggregateEntry);
+        assert!(xml.starts_with(
+            "<unit xmlns:cpp=\"http://www.srcML.org/srcML/cpp\" revision=\"1.0.0\" language=\"C\" filename=\"main.c\">"
+        ));
+        assert!(xml.ends_with("</unit>\n\n"));
+
+        // Kinds without a srcML counterpart stay out of the srcML namespace
+        let xml = placed_srcml("a.py", "x = 1\n", Placement::AggregateEntry);
+        assert!(xml.starts_with("<unit xmlns=\"\" language=\"Python\" filename=\"a.py\">"));
+
+        let mut archive = Vec::new();
+        write_archive_start(&mut archive).unwrap();
+        archive.extend(placed_srcml("main.c", "int x;\n", Placement::AggregateEntry).bytes());
+        write_archive_end(&mut archive).unwrap();
+        let archive = String::from_utf8(archive).unwrap();
+        assert_eq!(archive.matches("<?xml").count(), 1);
+        assert!(archive.ends_with("</unit>\n\n</unit>\n"));
+    }
+
+    #[test]
+    fn test_mapped_kinds_exist_in_their_grammars() {
+        let encodings = create_encodings();
+        for language in ["C", "C++", "Java", "C#"] {
+            let encoding = encodings.iter().find(|e| e.name() == language).unwrap();
+            let grammar = encoding.language();
+            for (kind, _) in element_tables(language).unwrap().iter().copied().flatten() {
+                assert!(
+                    grammar.id_for_node_kind(kind, true) != 0
+                        || grammar.id_for_node_kind(kind, false) != 0,
+                    "{} has no {} nodes",
+                    language,
+                    kind
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! Depth-first traversal of Tree-sitter trees with an explicit stack
+//!
+//! Every tree consumer (in-memory `JsonNode` conversion and the streaming writers) goes through
+//! `walk_tree`, so `TreeOptions` such as field grouping, named-only mode and size budgets are
+//! applied in one place and traversal depth is never limited by the thread's stack.
+use crate::error::Result;
+use crate::json::TreeOptions;
+use std::collections::BTreeSet;
+use tree_sitter::{Node, TreeCursor};
+
+/// Keys and punctuation every node has in compact JSON, including the comma after it
+const NODE_KEYS_BYTES: usize = r#"{"kind":,"start_byte":,"end_byte":,"start_point":{"row":,"column":},"end_point":{"row":,"column":},"children":,"text":},"#.len();
+
+/// A node reached by `walk_tree`, with what the traversal knows about it
+#[derive(Clone, Copy, Debug)]
+pub struct VisitedNode<'tree> {
+    pub node: Node<'tree>,
+    /// Grammar field this node fills in its parent, e.g. `name` or `body`
+    pub field_name: Option<&'static str>,
+    /// Distance from the root, which is at depth 0
+    pub depth: usize,
+    /// The node has no children to visit (after `named_only` filtering), so its source text is its content
+    pub is_leaf: bool,
+}
+
+/// Receives the nodes of a tree in depth-first order from `walk_tree`
+pub trait TreeVisitor<'tree> {
+    /// Called before any of the node's children are visited
+    fn enter_node(&mut self, node: &VisitedNode<'tree>) -> Result<()>;
+
+    /// Called before the first child filling grammar field `name` is entered (only with
+    /// `group_fields`)
+    fn enter_field(&mut self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the last child filling the current field has been left
+    fn leave_field(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after all of the node's children have been visited, with the number of
+    /// descendants that depth limits or size budgets left out
+    fn leave_node(&mut self, node: &VisitedNode<'tree>, omitted_descendants: usize) -> Result<()>;
+}
+
+/// Visit `root` and its descendants depth-first, applying `options`
+///
+/// With `group_fields`, children that fill a field are visited first, grouped by field name in
+/// sorted order, followed by the children that fill no field.
+pub fn walk_tree<'tree>(
+    root: Node<'tree>,
+    options: &TreeOptions,
+    visitor: &mut impl TreeVisitor<'tree>,
+) -> Result<()> {
+    let mut budget = Budget::new(options);
+    let mut stack = Vec::new();
+
+    // The root is kept whatever the budgets
+    let root = VisitedNode {
+        node: root,
+        field_name: None,
+        depth: 0,
+        is_leaf: !has_children(root, options.named_only),
+    };
+    budget.spend(&root);
+    visit(root, options, visitor, &mut stack)?;
+
+    while let Some(frame) = stack.last_mut() {
+        match frame.advance(options.named_only) {
+            Step::Child(node, field_name) => {
+                let child = VisitedNode {
+                    node,
+                    field_name,
+                    depth: frame.visited.depth + 1,
+                    is_leaf: !has_children(node, options.named_only),
+                };
+                if !budget.admit(&child) {
+                    frame.omitted += count_nodes(node, options.named_only);
+                    continue;
+                }
+                if let Pass::Field(name) = frame.passes[frame.pass] {
+                    if !frame.field_open {
+                        visitor.enter_field(name)?;
+                        frame.field_open = true;
+                    }
+                }
+                visit(child, options, visitor, &mut stack)?;
+            }
+            Step::PassEnd => {
+                if frame.field_open {
+                    frame.field_open = false;
+                    visitor.leave_field()?;
+                }
+            }
+            Step::Done => {
+                let frame = stack.pop().expect("stack is not empty");
+                visitor.leave_node(&frame.visited, frame.omitted)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Enter `visited`, then either leave it straight away or push a frame to visit its children
+fn visit<'tree>(
+    visited: VisitedNode<'tree>,
+    options: &TreeOptions,
+    visitor: &mut impl TreeVisitor<'tree>,
+    stack: &mut Vec<Frame<'tree>>,
+) -> Result<()> {
+    visitor.enter_node(&visited)?;
+    if visited.is_leaf {
+        visitor.leave_node(&visited, 0)
+    } else if options.max_depth.is_some_and(|max| visited.depth >= max) {
+        let omitted = count_nodes(visited.node, options.named_only) - 1;
+        visitor.leave_node(&visited, omitted)
+    } else {
+        stack.push(Frame::new(visited, options));
+        Ok(())
+    }
+}
+
+fn has_children(node: Node, named_only: bool) -> bool {
+    if named_only {
+        node.named_child_count() > 0
+    } else {
+        node.child_count() > 0
+    }
+}
+
+/// Count `node` and its descendants, skipping anonymous nodes if `named_only` is set
+fn count_nodes(node: Node, named_only: bool) -> usize {
+    if !named_only {
+        return node.descendant_count();
+    }
+    let mut count = 0;
+    let mut cursor = node.walk();
+    loop {
+        if cursor.node().is_named() {
+            count += 1;
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return count;
+            }
+        }
+    }
+}
+
+/// One sweep over a node's children
+#[derive(Clone, Copy)]
+enum Pass {
+    /// Every child, in source order
+    All,
+    /// Children filling this field
+    Field(&'static str),
+    /// Children filling no field
+    Unfielded,
+}
+
+enum Step<'tree> {
+    Child(Node<'tree>, Option<&'static str>),
+    PassEnd,
+    Done,
+}
+
+/// A node whose children are being visited; memory per frame does not depend on the child count
+struct Frame<'tree> {
+    visited: VisitedNode<'tree>,
+    cursor: TreeCursor<'tree>,
+    passes: Vec<Pass>,
+    pass: usize,
+    /// The cursor is on a child of the node rather than the node itself
+    in_children: bool,
+    field_open: bool,
+    omitted: usize,
+}
+
+impl<'tree> Frame<'tree> {
+    fn new(visited: VisitedNode<'tree>, options: &TreeOptions) -> Self {
+        let mut cursor = visited.node.walk();
+        let passes = if options.group_fields {
+            let mut field_names = BTreeSet::new();
+            let mut has_unfielded = false;
+            if cursor.goto_first_child() {
+                loop {
+                    if cursor.node().is_named() || !options.named_only {
+                        match cursor.field_name() {
+                            Some(name) => {
+                                field_names.insert(name);
+                            }
+                            None => has_unfielded = true,
+                        }
+                    }
+                    if !cursor.goto_next_sibling() {
+                        break;
+                    }
+                }
+                cursor.reset(visited.node);
+            }
+            field_names
+                .into_iter()
+                .map(Pass::Field)
+                .chain(has_unfielded.then_some(Pass::Unfielded))
+                .collect()
+        } else {
+            vec![Pass::All]
+        };
+
+        Self {
+            visited,
+            cursor,
+            passes,
+            pass: 0,
+            in_children: false,
+            field_open: false,
+            omitted: 0,
+        }
+    }
+
+    fn advance(&mut self, named_only: bool) -> Step<'tree> {
+        let Some(&pass) = self.passes.get(self.pass) else {
+            return Step::Done;
+        };
+        loop {
+            let moved = if self.in_children {
+                self.cursor.goto_next_sibling()
+            } else {
+                self.in_children = true;
+                self.cursor.goto_first_child()
+            };
+            if !moved {
+                self.cursor.reset(self.visited.node);
+                self.in_children = false;
+                self.pass += 1;
+                return Step::PassEnd;
+            }
+
+            let node = self.cursor.node();
+            if named_only && !node.is_named() {
+                continue;
+            }
+            let field_name = self.cursor.field_name();
+            let in_pass = match pass {
+                Pass::All => true,
+                Pass::Field(name) => field_name == Some(name),
+                Pass::Unfielded => field_name.is_none(),
+            };
+            if in_pass {
+                return Step::Child(node, field_name);
+            }
+        }
+    }
+}
+
+/// Tracks how much of the size budgets in `TreeOptions` has been spent
+///
+/// Bytes are counted as the compact JSON of each node without the `truncated` markers, and with
+/// the text of named leaves counted before escaping, since the source is not at hand. Pretty
+/// JSON and YAML add indentation on top.
+struct Budget<'o> {
+    options: &'o TreeOptions,
+    nodes: usize,
+    bytes: usize,
+    /// A node was refused, so later ones are too and the tree has no gaps in the middle
+    exhausted: bool,
+}
+
+impl<'o> Budget<'o> {
+    fn new(options: &'o TreeOptions) -> Self {
+        Self {
+            options,
+            nodes: 0,
+            bytes: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Spend the budget on `visited` if it fits, or refuse it and every node after it
+    fn admit(&mut self, visited: &VisitedNode) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        let bytes = self.bytes + json_bytes(visited, self.options);
+        self.exhausted = self.options.max_nodes.is_some_and(|max| self.nodes >= max)
+            || self.options.max_output_bytes.is_some_and(|max| bytes > max);
+        if !self.exhausted {
+            self.nodes += 1;
+            self.bytes = bytes;
+        }
+        !self.exhausted
+    }
+
+    fn spend(&mut self, visited: &VisitedNode) {
+        self.nodes += 1;
+        self.bytes += json_bytes(visited, self.options);
+    }
+}
+
+/// Size of a node in compact JSON, excluding its children
+fn json_bytes(visited: &VisitedNode, options: &TreeOptions) -> usize {
+    let node = visited.node;
+    let (start, end) = (node.start_position(), node.end_position());
+    let numbers = [
+        node.start_byte(),
+        node.end_byte(),
+        start.row,
+        start.column,
+        end.row,
+        end.column,
+    ];
+    let mut bytes =
+        NODE_KEYS_BYTES + quoted_len(node.kind()) + numbers.into_iter().map(digits).sum::<usize>();
+    if let Some(field_name) = visited.field_name {
+        bytes += r#","field_name":"#.len() + quoted_len(field_name);
+    }
+    if options.include_is_named {
+        bytes += r#","is_named":"#.len() + if node.is_named() { 4 } else { 5 };
+    }
+    if node.is_error() {
+        bytes += r#","is_error":true"#.len();
+    }
+    if node.is_missing() {
+        bytes += r#","is_missing":true"#.len();
+    }
+    if !visited.is_leaf {
+        // `[]` around the children, and `null` for the text
+        return bytes + 2 + 4;
+    }
+    // `null` for the children, and the text, which for anonymous tokens is their kind
+    let text = if node.is_named() {
+        node.end_byte() - node.start_byte() + 2
+    } else {
+        quoted_len(node.kind())
+    };
+    bytes + 4 + text
+}
+
+/// Length of `text` as a JSON string, quotes and escapes included
+fn quoted_len(text: &str) -> usize {
+    2 + text
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+            c if c < ' ' => 6,
+            c => c.len_utf8(),
+        })
+        .sum::<usize>()
+}
+
+fn digits(number: usize) -> usize {
+    number.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::{Parser, Tree};
+
+    fn parse_rust(code: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        parser.parse(code, None).unwrap()
+    }
+
+    /// Records visitor events as indented lines so traversal order is easy to assert on
+    #[derive(Default)]
+    struct EventLog {
+        lines: Vec<String>,
+    }
+
+    impl<'tree> TreeVisitor<'tree> for EventLog {
+        fn enter_node(&mut self, node: &VisitedNode<'tree>) -> Result<()> {
+            let field = node
+                .field_name
+                .map_or(String::new(), |f| format!("{}: ", f));
+            self.lines.push(format!(
+                "{}{}{}",
+                "  ".repeat(node.depth),
+                field,
+                node.node.kind()
+            ));
+            Ok(())
+        }
+
+        fn enter_field(&mut self, name: &'static str) -> Result<()> {
+            self.lines.push(format!("[{}]", name));
+            Ok(())
+        }
+
+        fn leave_node(&mut self, node: &VisitedNode<'tree>, omitted: usize) -> Result<()> {
+            if omitted > 0 {
+                self.lines
+                    .push(format!("{}omitted {}", "  ".repeat(node.depth), omitted));
+            }
+            Ok(())
+        }
+    }
+
+    fn events(code: &str, options: &TreeOptions) -> Vec<String> {
+        let tree = parse_rust(code);
+        let mut log = EventLog::default();
+        walk_tree(tree.root_node(), options, &mut log).unwrap();
+        log.lines
+    }
+
+    #[test]
+    fn test_walk_visits_nodes_depth_first() {
+        let options = TreeOptions {
+            named_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            events("fn main() {}", &options),
+            vec![
+                "source_file",
+                "  function_item",
+                "    name: identifier",
+                "    parameters: parameters",
+                "    body: block",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_groups_fields_before_other_children() {
+        let options = TreeOptions {
+            named_only: true,
+            group_fields: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            events("#[test] fn main() {}", &options),
+            vec![
+                "source_file",
+                "  attribute_item",
+                "    attribute",
+                "      identifier",
+                "  function_item",
+                "[body]",
+                "    body: block",
+                "[name]",
+                "    name: identifier",
+                "[parameters]",
+                "    parameters: parameters",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_reports_descendants_omitted_by_depth_limit() {
+        let options = TreeOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let tree = parse_rust("fn main() {}");
+        let function_nodes = tree.root_node().child(0).unwrap().descendant_count();
+        assert_eq!(
+            events("fn main() {}", &options),
+            vec![
+                "source_file".to_string(),
+                "  function_item".to_string(),
+                format!("  omitted {}", function_nodes - 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_stops_at_node_budget() {
+        let options = TreeOptions {
+            max_nodes: Some(2),
+            ..Default::default()
+        };
+        let tree = parse_rust("fn main() {}");
+        let lines = events("fn main() {}", &options);
+        assert_eq!(lines[..2], ["source_file", "  function_item"]);
+        assert_eq!(
+            lines[2],
+            format!("  omitted {}", tree.root_node().descendant_count() - 2)
+        );
+    }
+}
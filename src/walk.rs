@@ -1,62 +1,560 @@
-use crate::cli_types::{format_output, Args};
-use crate::encodings;
-use crate::error::{AstgenError, Result};
-use crate::parsing;
+use crate::cli_types::Args;
+use arrow_array::RecordBatch;
+use astgen::detection::Detection;
+use astgen::disambiguation::Disambiguation;
+use astgen::encoding::Encoding;
+use astgen::encodings;
+use astgen::error::{AstgenError, Result};
+use astgen::node_table::NodeTableWriter;
+use astgen::output::{self, AggregateWriter, OutputFormat, Placement, RunHeader, RunSummary};
+use astgen::parsing;
+use astgen::query::QuerySet;
+use astgen::sqlite::{self, SqliteStore, StoredFile, StoredHashes};
+use astgen::srcml;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+
+/// Destination for every document a run produces: standalone documents appended to the output,
+/// entries of a single aggregate document, rows of a single node table, or a SQLite database
+pub struct Output<'a> {
+    args: &'a Args,
+    aggregate: Option<AggregateWriter<Box<dyn Write + Send>>>,
+    table: Option<NodeTableWriter<Box<dyn Write + Send>>>,
+    store: Option<(SqliteStore, Arc<StoredHashes>)>,
+    /// Several files written as XML or S-expressions are marked as entries: units of one srcML
+    /// archive, or trees headed by their filename
+    entries: bool,
+}
+
+impl<'a> Output<'a> {
+    /// Prepare the output for a run; in aggregate mode this writes the run header, and for XML
+    /// of several files it opens the srcML archive
+    pub fn open(args: &'a Args) -> Result<Self> {
+        let header = RunHeader::new(std::env::args().skip(1).collect());
+        let aggregate = if args.aggregate {
+            Some(AggregateWriter::begin(
+                create_output(args)?,
+                args.format,
+                &header,
+            )?)
+        } else {
+            None
+        };
+        let table = match args.format.table_format() {
+            Some(table_format) => Some(NodeTableWriter::new(create_output(args)?, table_format)?),
+            None => None,
+        };
+        let store = match (args.format, &args.output) {
+            (OutputFormat::Sqlite, Some(database_path)) => {
+                let store = SqliteStore::open(database_path, &header, &args.tree_options())?;
+                let stored_hashes = Arc::new(store.stored_hashes()?);
+                Some((store, stored_hashes))
+            }
+            (OutputFormat::Sqlite, None) => {
+                return Err(AstgenError::InvalidInput(
+                    "SQLite output needs a database path. Add --output repo.db.".to_string(),
+                ))
+            }
+            _ => None,
+        };
+        let entries = matches!(args.format, OutputFormat::Xml | OutputFormat::Sexp)
+            && args.reads_many_files();
+        if entries && args.format == OutputFormat::Xml {
+            write_output(args, srcml::write_archive_start)?;
+        }
+        Ok(Self {
+            args,
+            aggregate,
+            table,
+            store,
+            entries,
+        })
+    }
+
+    /// Content hashes already in the SQLite database, so unchanged files can be skipped
+    fn stored_hashes(&self) -> Option<Arc<StoredHashes>> {
+        self.store.as_ref().map(|(_, hashes)| hashes.clone())
+    }
+
+    fn placement(&self) -> Placement {
+        if self.aggregate.is_some() || self.entries {
+            Placement::AggregateEntry
+        } else {
+            Placement::Standalone
+        }
+    }
+
+    fn write(
+        &mut self,
+        document: impl FnOnce(&mut dyn Write, Placement) -> Result<()>,
+    ) -> Result<()> {
+        let placement = self.placement();
+        match &mut self.aggregate {
+            Some(aggregate) => aggregate.write_entry(|out| document(out, placement)),
+            None => write_output(self.args, |out| document(out, placement)),
+        }
+    }
+
+    /// Append a file's rows to the node table
+    fn write_rows(&mut self, rows: &RecordBatch) -> Result<()> {
+        match &mut self.table {
+            Some(table) => table.write(rows),
+            None => Err(AstgenError::InvalidInput(
+                "Node rows can only be written with --format arrow or parquet".to_string(),
+            )),
+        }
+    }
+
+    /// Replace a file's rows in the SQLite database
+    fn write_stored(&mut self, file: &StoredFile) -> Result<()> {
+        match &mut self.store {
+            Some((store, _)) => store.store(file),
+            None => Err(AstgenError::InvalidInput(
+                "Files can only be stored with --format sqlite".to_string(),
+            )),
+        }
+    }
+
+    /// Remove the files stored under `dir_path` that are not among `files`, the files a walk of it
+    /// found; only SQLite output keeps files from earlier runs
+    fn remove_unwalked(&mut self, dir_path: &Path, files: &[PathBuf]) -> Result<()> {
+        let Some((store, _)) = &mut self.store else {
+            return Ok(());
+        };
+        if self.args.dry_run {
+            return Ok(());
+        }
+        let walked = files
+            .iter()
+            .map(|file| file.to_string_lossy().into_owned())
+            .collect();
+        let removed = store.remove_missing(dir_path, &walked)?;
+        if removed > 0 && self.args.verbose && !self.args.quiet {
+            log::info!(
+                "Removed {} stored files no longer found in {}",
+                removed,
+                dir_path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Write what a worker rendered with `placement()`; empty documents are skipped
+    fn write_rendered(&mut self, rendered: Rendered) -> Result<()> {
+        if let Some(rows) = &rendered.rows {
+            self.write_rows(rows)?;
+        }
+        if let Some(file) = &rendered.stored {
+            self.write_stored(file)?;
+        }
+        if rendered.document.is_empty() {
+            return Ok(());
+        }
+        self.write(|out, _| Ok(out.write_all(&rendered.document)?))
+    }
+
+    /// Finish the run; in aggregate mode this writes the summary and closes the document, an
+    /// srcML archive is closed, and with `--summary-record` it appends a summary line to the JSON
+    /// Lines stream
+    pub fn finish(self, summary: &RunSummary) -> Result<()> {
+        if let Some(table) = self.table {
+            return table.finish();
+        }
+        if let Some((store, _)) = self.store {
+            return store.finish(summary);
+        }
+        if self.entries && self.args.format == OutputFormat::Xml {
+            return write_output(self.args, srcml::write_archive_end);
+        }
+        match self.aggregate {
+            Some(aggregate) => aggregate.finish(summary),
+            None if self.args.summary_record => {
+                write_output(self.args, |out| output::write_summary_record(out, summary))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a worker produced for one file, held until the ordered writer reaches it
+#[derive(Default)]
+struct Rendered {
+    document: Vec<u8>,
+    rows: Option<RecordBatch>,
+    stored: Option<StoredFile>,
+}
+
+/// Where a file's document goes: straight to the output, or into a buffer for the ordered writer
+enum Sink<'a, 'o> {
+    Direct(&'a mut Output<'o>),
+    Buffer {
+        rendered: &'a mut Rendered,
+        placement: Placement,
+        stored_hashes: Option<&'a StoredHashes>,
+    },
+}
+
+impl Sink<'_, '_> {
+    fn write(
+        &mut self,
+        document: impl FnOnce(&mut dyn Write, Placement) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            Sink::Direct(output) => output.write(document),
+            Sink::Buffer {
+                rendered,
+                placement,
+                ..
+            } => document(&mut rendered.document, *placement),
+        }
+    }
+
+    fn write_rows(&mut self, rows: RecordBatch) -> Result<()> {
+        match self {
+            Sink::Direct(output) => output.write_rows(&rows),
+            Sink::Buffer { rendered, .. } => {
+                rendered.rows = Some(rows);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the SQLite database already holds `path` with this content hash, and if so
+    /// whether it had syntax errors
+    fn unchanged(&self, path: &str, content_hash: &str) -> Option<bool> {
+        match self {
+            Sink::Direct(output) => output
+                .store
+                .as_ref()
+                .and_then(|(_, hashes)| hashes.unchanged(path, content_hash)),
+            Sink::Buffer { stored_hashes, .. } => (*stored_hashes)?.unchanged(path, content_hash),
+        }
+    }
+
+    /// Whether the file was found by walking a directory rather than named on the command line
+    fn is_walk(&self) -> bool {
+        matches!(self, Sink::Buffer { .. })
+    }
+
+    fn write_stored(&mut self, file: StoredFile) -> Result<()> {
+        match self {
+            Sink::Direct(output) => output.write_stored(&file),
+            Sink::Buffer { rendered, .. } => {
+                rendered.stored = Some(file);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// How processing one file went
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStatus {
+    pub succeeded: bool,
+    pub has_syntax_errors: bool,
+    /// Left out of a directory walk, and of its summary, rather than processed
+    pub skipped: bool,
+}
+
+impl FileStatus {
+    const SUCCEEDED: Self = Self {
+        succeeded: true,
+        has_syntax_errors: false,
+        skipped: false,
+    };
+    const FAILED: Self = Self {
+        succeeded: false,
+        has_syntax_errors: false,
+        skipped: false,
+    };
+    const SKIPPED: Self = Self {
+        succeeded: false,
+        has_syntax_errors: false,
+        skipped: true,
+    };
+}
+
+/// The envelope `filename` of source read from stdin without `--stdin-filename`
+const STDIN_FILENAME: &str = "<stdin>";
+
+/// Bytes read from each end of a file without an extension to look for a shebang or modeline
+const SNIFF_BYTES: u64 = 8 * 1024;
+
+/// Where a file's source comes from
+#[derive(Clone, Copy)]
+enum Input<'a> {
+    File(&'a Path),
+    /// Standard input, reported under this file name
+    Stdin(&'a str),
+    /// Source already read to detect its language, under the name it was read from
+    Loaded(&'a str, &'a str),
+}
+
+impl Input<'_> {
+    /// The envelope's `filename`, also used in messages
+    fn name(&self) -> Cow<'_, str> {
+        match self {
+            Input::File(path) => path.to_string_lossy(),
+            Input::Stdin(name) | Input::Loaded(name, _) => Cow::Borrowed(name),
+        }
+    }
+
+    /// Whether the language may have to be sniffed from the content: the name of a file without
+    /// an extension, or of stdin, may not give it away
+    fn is_sniffable(&self) -> bool {
+        match self {
+            Input::File(path) => path.extension().is_none(),
+            Input::Stdin(_) => true,
+            Input::Loaded(..) => false,
+        }
+    }
+
+    fn read(&self, max_size_bytes: usize) -> Result<String> {
+        match self {
+            Input::File(path) => parsing::read_source(path, max_size_bytes),
+            Input::Stdin(name) => {
+                parsing::read_source_from(io::stdin().lock(), name, max_size_bytes)
+            }
+            Input::Loaded(_, source) => Ok(source.to_string()),
+        }
+    }
+
+    /// Text to detect the language from, and whether it is the whole source; of a large file
+    /// only the first and last `SNIFF_BYTES` are read, which hold any shebang or modeline
+    fn sniff(&self, max_size_bytes: usize) -> Result<(String, bool)> {
+        let Input::File(path) = self else {
+            return self.read(max_size_bytes).map(|source| (source, true));
+        };
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        if size <= 2 * SNIFF_BYTES {
+            return self.read(max_size_bytes).map(|source| (source, true));
+        }
+        let mut head = Vec::new();
+        (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+        let mut tail = Vec::new();
+        file.seek(SeekFrom::Start(size - SNIFF_BYTES))?;
+        file.take(SNIFF_BYTES).read_to_end(&mut tail)?;
+        // A cut may fall inside a character or a line, neither of which the checks rely on
+        let ends = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&head),
+            String::from_utf8_lossy(&tail)
+        );
+        Ok((ends, false))
+    }
+}
 
 pub fn process_single_file(
-    file_path: &std::path::Path,
+    file_path: &Path,
+    encodings: &encodings::Encodings,
+    args: &Args,
+    queries: Option<&QuerySet>,
+    output: &mut Output,
+) -> Result<FileStatus> {
+    process_file(
+        Input::File(file_path),
+        encodings,
+        args,
+        queries,
+        &mut Sink::Direct(output),
+    )
+}
+
+/// Parse source piped into stdin, named by `--stdin-filename` and in the language given by
+/// `--language` or detected from that name
+pub fn process_stdin(
     encodings: &encodings::Encodings,
     args: &Args,
-) -> Result<bool> {
+    queries: Option<&QuerySet>,
+    output: &mut Output,
+) -> Result<FileStatus> {
+    let name = args.stdin_filename.as_deref().unwrap_or(STDIN_FILENAME);
+    process_file(
+        Input::Stdin(name),
+        encodings,
+        args,
+        queries,
+        &mut Sink::Direct(output),
+    )
+}
+
+fn process_file(
+    input: Input,
+    encodings: &encodings::Encodings,
+    args: &Args,
+    queries: Option<&QuerySet>,
+    sink: &mut Sink,
+) -> Result<FileStatus> {
     // Check include/exclude patterns
-    if !should_process_file(file_path, args) {
-        return Ok(false);
+    if let Input::File(file_path) = input {
+        if !should_process_file(file_path, args) {
+            return Ok(FileStatus::FAILED);
+        }
+    }
+
+    let name = input.name();
+    // Calculate max file size in bytes
+    let max_size_bytes = args.max_file_size * 1_000_000; // Convert MB to bytes
+    let mut encoding = match &args.language {
+        Some(language) => encodings
+            .match_name(language)
+            .map(|encoding| (encoding, Detection::Override)),
+        None => encodings.detect(&name, None),
+    };
+
+    // Without a hint in the name, look for a shebang or modeline, keeping the source if it was
+    // read in full
+    let mut loaded = None;
+    if encoding.is_none() && input.is_sniffable() {
+        match input.sniff(max_size_bytes) {
+            Ok((source, whole)) => {
+                encoding = encodings.detect(&name, Some(&source));
+                loaded = whole.then_some(source);
+            }
+            Err(e) if matches!(input, Input::Stdin(_)) => {
+                if !args.quiet {
+                    log::error!("Error reading {}: {}", name, e);
+                }
+                return Ok(FileStatus::FAILED);
+            }
+            // Unreadable files without an extension are skipped like other unsupported files
+            Err(_) => {}
+        }
     }
 
-    let file_str = file_path.to_string_lossy();
-    let encoding = encodings.match_file(&file_str);
+    // An extension several languages share, such as `.h`, is settled by the content
+    let mut disambiguation = None;
+    if let Some((_, Detection::Extension)) = encoding {
+        if encodings.candidates(&name).len() > 1 {
+            let source = match loaded.take().map_or_else(|| input.read(max_size_bytes), Ok) {
+                Ok(source) => source,
+                Err(e) => {
+                    if !args.quiet {
+                        log::error!("Error parsing file {}: {}", name, e);
+                    }
+                    return Ok(FileStatus::FAILED);
+                }
+            };
+            if let Some((chosen, detection, how)) = encodings.detect_with_source(&name, &source) {
+                encoding = Some((chosen, detection));
+                disambiguation = how;
+            }
+            loaded = Some(source);
+        }
+    }
 
     match encoding {
-        Some(lang) => {
+        Some((lang, detection)) => {
+            // A directory mixes languages, so files the schema cannot convert are left out
+            // rather than failed; a file named on the command line, or given a language, still
+            // fails
+            if let Some(languages) = args.schema.languages() {
+                if sink.is_walk()
+                    && detection != Detection::Override
+                    && !languages.contains(&lang.name())
+                {
+                    if args.verbose && !args.quiet {
+                        log::info!("Skipping {}: --schema does not cover {}", name, lang.name());
+                    }
+                    return Ok(FileStatus::SKIPPED);
+                }
+            }
+
             if args.dry_run {
                 if !args.quiet {
-                    println!("Would parse: {} ({})", file_path.display(), lang.name);
+                    println!("Would parse: {} ({})", name, lang.name());
                 }
-                return Ok(true);
+                return Ok(FileStatus::SUCCEEDED);
             }
 
-            // Calculate max file size in bytes
-            let max_size_bytes = args.max_file_size * 1_000_000; // Convert MB to bytes
+            let source_input = match &loaded {
+                Some(source) => Input::Loaded(&name, source),
+                None => input,
+            };
+            if let Some(queries) = queries {
+                return query_single_file(
+                    source_input,
+                    (lang, detection, disambiguation),
+                    queries,
+                    max_size_bytes,
+                    args,
+                    sink,
+                );
+            }
+            if args.format == OutputFormat::Sqlite {
+                return store_single_file(source_input, lang, max_size_bytes, args, sink);
+            }
 
-            match parsing::parse_file_safe_with_size_limit(
-                file_path.to_path_buf(),
-                lang,
-                args.truncate,
-                max_size_bytes,
-            ) {
-                Ok(output) => {
-                    let formatted_output = format_output(&output, &args.format)?;
-                    write_output(&formatted_output, args)?;
+            let options = args.tree_options();
+            let parsed = source_input
+                .read(max_size_bytes)
+                .and_then(|source| parsing::parse_source_tree(source, &name, lang))
+                .and_then(|mut source_tree| {
+                    source_tree.detection = Some(detection);
+                    source_tree.disambiguation = disambiguation;
+                    if args.format.table_format().is_some() {
+                        sink.write_rows(source_tree.node_rows(&options)?)?;
+                    } else {
+                        sink.write(|out, placement| {
+                            source_tree.write_placed(out, args.format, placement, &options)
+                        })?;
+                    }
+                    Ok(source_tree)
+                });
+            match parsed {
+                Ok(source_tree) => {
+                    let has_syntax_errors = source_tree.has_error();
+                    if has_syntax_errors {
+                        let error_count = source_tree.diagnostics(&options).len();
+                        if args.fail_on_syntax_error {
+                            if !args.quiet {
+                                log::error!("Syntax errors in {} ({} found)", name, error_count);
+                            }
+                            return Ok(FileStatus {
+                                succeeded: false,
+                                has_syntax_errors,
+                                skipped: false,
+                            });
+                        }
+                        if args.verbose && !args.quiet {
+                            log::warn!("Syntax errors in {} ({} found)", name, error_count);
+                        }
+                    }
 
                     if args.verbose && !args.quiet {
-                        log::info!("Parsed file: {}", file_path.display());
+                        log::info!("Parsed file: {}", name);
                     }
-                    Ok(true)
+                    Ok(FileStatus {
+                        succeeded: true,
+                        has_syntax_errors,
+                        skipped: false,
+                    })
                 }
                 Err(e) => {
                     if !args.quiet {
-                        log::error!("Error parsing file {}: {}", file_path.display(), e);
+                        log::error!("Error parsing file {}: {}", name, e);
                     }
-                    Ok(false)
+                    Ok(FileStatus::FAILED)
                 }
             }
         }
         None => {
+            let Input::File(file_path) = input else {
+                if !args.quiet {
+                    log::error!(
+                        "Cannot detect the language of {}. Add --language or --stdin-filename with a supported extension, or start the source with a shebang.",
+                        name
+                    );
+                }
+                return Ok(FileStatus::FAILED);
+            };
             if args.verbose && !args.quiet {
                 let ext = file_path
                     .extension()
@@ -73,7 +571,131 @@ pub fn process_single_file(
                     file_path.display()
                 );
             }
-            Ok(false)
+            Ok(FileStatus::FAILED)
+        }
+    }
+}
+
+fn query_single_file(
+    input: Input,
+    (lang, detection, disambiguation): (&Encoding, Detection, Option<Disambiguation>),
+    queries: &QuerySet,
+    max_size_bytes: usize,
+    args: &Args,
+    sink: &mut Sink,
+) -> Result<FileStatus> {
+    let name = input.name();
+    let compiled = queries.for_encoding(lang);
+    if let Some(reason) = queries.take_skipped(lang) {
+        if !args.quiet {
+            log::warn!("Skipping a query for {} files. {}", lang.name(), reason);
+        }
+    }
+    let result = compiled.and_then(|query| match query {
+        Some(query) => input
+            .read(max_size_bytes)
+            .and_then(|source| {
+                parsing::query_source(&source, &name, lang, &query, &args.tree_options())
+            })
+            .map(Some),
+        None => Ok(None),
+    });
+
+    match result {
+        Ok(Some(mut queried)) => {
+            queried.detection = Some(detection);
+            queried.disambiguation = disambiguation;
+            sink.write(|out, placement| {
+                output::write_value(out, args.format, placement, &queried)
+            })?;
+
+            let has_syntax_errors = queried.has_error;
+            if has_syntax_errors && args.fail_on_syntax_error {
+                if !args.quiet {
+                    log::error!("Syntax errors in {}", name);
+                }
+                return Ok(FileStatus {
+                    succeeded: false,
+                    has_syntax_errors,
+                    skipped: false,
+                });
+            }
+            if args.verbose && !args.quiet {
+                log::info!(
+                    "Queried file: {} ({} captures)",
+                    name,
+                    queried.captures.len()
+                );
+            }
+            Ok(FileStatus {
+                succeeded: true,
+                has_syntax_errors,
+                skipped: false,
+            })
+        }
+        Ok(None) => {
+            if args.verbose && !args.quiet {
+                log::info!(
+                    "No query applies to {} files, skipping: {}",
+                    lang.name(),
+                    name
+                );
+            }
+            Ok(FileStatus::SUCCEEDED)
+        }
+        Err(e) => {
+            if !args.quiet {
+                log::error!("Error querying file {}: {}", name, e);
+            }
+            Ok(FileStatus::FAILED)
+        }
+    }
+}
+
+/// Parse a file into the SQLite database, unless it is stored with the same content already
+fn store_single_file(
+    input: Input,
+    lang: &Encoding,
+    max_size_bytes: usize,
+    args: &Args,
+    sink: &mut Sink,
+) -> Result<FileStatus> {
+    let path = input.name();
+    let result = input.read(max_size_bytes).and_then(|source| {
+        let content_hash = sqlite::content_hash(&source);
+        if let Some(has_error) = sink.unchanged(&path, &content_hash) {
+            if args.verbose && !args.quiet {
+                log::info!("Unchanged since last stored: {}", path);
+            }
+            return Ok(has_error);
+        }
+        let source_tree = parsing::parse_source_tree(source, &path, lang)?;
+        let file = StoredFile::new(&source_tree, content_hash, &args.tree_options())?;
+        let has_error = file.has_error;
+        sink.write_stored(file)?;
+        if args.verbose && !args.quiet {
+            log::info!("Stored file: {}", path);
+        }
+        Ok(has_error)
+    });
+
+    match result {
+        Ok(has_syntax_errors) => {
+            let succeeded = !(has_syntax_errors && args.fail_on_syntax_error);
+            if !succeeded && !args.quiet {
+                log::error!("Syntax errors in {}", path);
+            }
+            Ok(FileStatus {
+                succeeded,
+                has_syntax_errors,
+                skipped: false,
+            })
+        }
+        Err(e) => {
+            if !args.quiet {
+                log::error!("Error storing file {}: {}", path, e);
+            }
+            Ok(FileStatus::FAILED)
         }
     }
 }
@@ -111,17 +733,31 @@ fn glob_match(pattern: &str, path: &str) -> bool {
     path.contains(pattern)
 }
 
-fn write_output(content: &str, args: &Args) -> Result<()> {
+/// Open the output file for a run that writes a single document, replacing any previous
+/// contents, or stdout
+fn create_output(args: &Args) -> Result<Box<dyn Write + Send>> {
+    Ok(match &args.output {
+        Some(output_path) => Box::new(BufWriter::new(fs::File::create(output_path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+/// Stream documents to the output file (appending) or stdout
+fn write_output(args: &Args, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
     match &args.output {
         Some(output_path) => {
-            let mut file = fs::OpenOptions::new()
+            let file = fs::OpenOptions::new()
                 .create(true)
                 .append(true)
                 .open(output_path)?;
-            writeln!(file, "{}", content)?;
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
         }
         None => {
-            println!("{}", content);
+            let mut out = BufWriter::new(io::stdout());
+            write(&mut out)?;
+            out.flush()?;
         }
     }
     Ok(())
@@ -131,7 +767,9 @@ pub fn process_directory(
     dir_path: &std::path::Path,
     encodings: &encodings::Encodings,
     args: &Args,
-) -> Result<(usize, usize)> {
+    queries: Option<&QuerySet>,
+    output: &mut Output,
+) -> Result<RunSummary> {
     let mut walker_builder = ignore::WalkBuilder::new(dir_path);
     walker_builder
         .add_custom_ignore_filename(".astgenignore")
@@ -144,7 +782,7 @@ pub fn process_directory(
     }
 
     let walker = walker_builder.build();
-    let files: Vec<PathBuf> = walker
+    let mut files: Vec<PathBuf> = walker
         .filter_map(|entry| {
             let entry = entry.ok()?;
             if entry.file_type()?.is_file() {
@@ -160,6 +798,9 @@ pub fn process_directory(
             }
         })
         .collect();
+    files.sort();
+
+    output.remove_unwalked(dir_path, &files)?;
 
     if files.is_empty() {
         if !args.quiet {
@@ -168,7 +809,7 @@ pub fn process_directory(
                 dir_path.display()
             );
         }
-        return Ok((0, 0));
+        return Ok(RunSummary::default());
     }
 
     if args.verbose && !args.quiet {
@@ -190,40 +831,128 @@ pub fn process_directory(
         None
     };
 
-    let results: Vec<Result<bool>> = files
-        .par_iter()
-        .map(|file| {
-            let result = process_single_file(file, encodings, args);
-            if let Some(ref pb) = progress_bar {
-                pb.inc(1);
-                if args.verbose {
-                    pb.set_message(format!(
-                        "Processing {}",
-                        file.file_name().unwrap_or_default().to_string_lossy()
-                    ));
+    // Parsing runs in parallel, but documents are handed to a single writer that emits them in
+    // sorted path order, so output is identical from run to run and never interleaves
+    let (sender, receiver) = mpsc::channel::<(usize, Rendered)>();
+    let placement = output.placement();
+    let stored_hashes = output.stored_hashes();
+    let (results, written) = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || -> Result<()> {
+            let mut ordered = OrderedWriter::default();
+            for (index, document) in receiver {
+                ordered.push(index, document);
+                while let Some(rendered) = ordered.pop_ready() {
+                    output.write_rendered(rendered)?;
                 }
             }
-            result
-        })
-        .collect();
+            Ok(())
+        });
+
+        let results: Vec<Result<FileStatus>> = files
+            .par_iter()
+            .enumerate()
+            .map_with(sender, |sender, (index, file)| {
+                let mut rendered = Rendered::default();
+                let result = process_file(
+                    Input::File(file),
+                    encodings,
+                    args,
+                    queries,
+                    &mut Sink::Buffer {
+                        rendered: &mut rendered,
+                        placement,
+                        stored_hashes: stored_hashes.as_deref(),
+                    },
+                );
+                // The writer only stops early after an output error, which is reported below
+                let _ = sender.send((index, rendered));
+                if let Some(ref pb) = progress_bar {
+                    pb.inc(1);
+                    if args.verbose {
+                        pb.set_message(format!(
+                            "Processing {}",
+                            file.file_name().unwrap_or_default().to_string_lossy()
+                        ));
+                    }
+                }
+                result
+            })
+            .collect();
+        (results, writer.join().expect("output writer panicked"))
+    });
+    written?;
 
     if let Some(pb) = progress_bar {
         pb.finish_with_message("Complete");
     }
 
-    let success_count = results
-        .iter()
-        .filter(|r| r.as_ref().is_ok_and(|&b| b))
-        .count();
-    let error_count = results.len() - success_count;
+    let mut summary = RunSummary::default();
+    for result in &results {
+        let status = result.as_ref().map_or(FileStatus::FAILED, |status| *status);
+        if status.skipped {
+            continue;
+        }
+        summary.record(status.succeeded, status.has_syntax_errors);
+    }
 
     if args.verbose && !args.quiet {
         log::info!(
             "Successfully processed {} files, {} errors",
-            success_count,
-            error_count
+            summary.succeeded,
+            summary.failed
         );
     }
 
-    Ok((success_count, error_count))
+    Ok(summary)
+}
+
+/// Releases documents that arrive out of order in index order, holding back any that arrive early
+struct OrderedWriter<T> {
+    next: usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Default for OrderedWriter<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> OrderedWriter<T> {
+    /// Accept the document for `index` (empty if the file produced no output)
+    fn push(&mut self, index: usize, document: T) {
+        self.pending.insert(index, document);
+    }
+
+    /// The next document in index order, once it has arrived
+    fn pop_ready(&mut self) -> Option<T> {
+        let document = self.pending.remove(&self.next)?;
+        self.next += 1;
+        Some(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ordered_writer_emits_in_index_order() {
+        let mut writer = OrderedWriter::default();
+        let mut out = Vec::new();
+        for (index, document) in [(2, "c"), (0, "a"), (3, ""), (1, "b")] {
+            writer.push(index, document.as_bytes().to_vec());
+            while let Some(document) = writer.pop_ready() {
+                out.push(String::from_utf8(document).unwrap());
+            }
+            if index == 0 {
+                assert_eq!(out, ["a"]);
+            }
+        }
+        assert!(writer.pending.is_empty());
+        assert_eq!(out, ["a", "b", "c", ""]);
+    }
 }
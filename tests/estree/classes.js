@@ -0,0 +1,26 @@
+class Animal {
+  name;
+  static count = 0;
+  #secret = "hidden";
+  static #registry = new Map();
+
+  constructor(name) {
+    this.name = name;
+    Animal.count++;
+  }
+
+  get secret() { return this.#secret; }
+  set secret(value) { this.#secret = value; }
+  static create(name) { return new this(name); }
+  async *stream() {}
+  ["computed" + key]() {}
+  #privateMethod() { return #secret in this; }
+
+  static {
+    Animal.ready = true;
+  }
+}
+
+const Dog = class extends Animal {
+  speak() { return super.speak(); }
+};
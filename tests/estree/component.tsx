@@ -0,0 +1,16 @@
+import React from "react";
+
+interface Props<T> { items: T[]; render?: (item: T) => JSX.Element }
+
+export const List = <T,>({ items, render }: Props<T>): JSX.Element => (
+  <ul className="list">
+    {items.map((item, index) => (
+      <li key={index}>{render?.(item) ?? String(item)}</li>
+    ))}
+  </ul>
+);
+
+export default function App() {
+  const count = React.useState<number>(0)[0];
+  return <List<number> items={[count]} />;
+}
@@ -0,0 +1,42 @@
+import type { Shape } from "./shape";
+import fs = require("fs");
+import Alias = ns.Inner;
+export type { Shape };
+
+export interface Circle<K extends string = "r"> extends Shape, ns.Named<K> {
+  radius: number;
+}
+
+export const enum Direction { Up = 1, Down, "Left" }
+enum Empty {}
+
+declare module "plugin" {
+  export function load(path?: string): void;
+}
+declare global {
+  interface Window { app: unknown }
+}
+namespace outer.inner {
+  export let depth = 2;
+}
+declare const VERSION: string;
+declare function overload(x: number): number;
+
+@sealed
+export abstract class Base<T> extends Model<T> implements Shape, Serializable {
+  private readonly id: number = 0;
+  declare protected label?: string;
+  static instances!: number;
+  abstract area(): number;
+
+  constructor(public name: string, protected readonly size = 1) {
+    super();
+  }
+
+  @memoize()
+  override describe<U>(this: Base<T>, format?: U): string {
+    return `${this.name}` as string;
+  }
+}
+
+export = Base;
@@ -0,0 +1,11 @@
+const element = (
+  <Layout.Page title="Home" disabled data-id={id} {...props}>
+    <h1 className="title">Hello &amp; welcome, {user.name}!</h1>
+    {/* comment */}
+    {items.map(item => <Item key={item.id} {...item} />)}
+    <svg:rect width="10" />
+    <>
+      fragment text
+    </>
+  </Layout.Page>
+);
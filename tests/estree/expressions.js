@@ -0,0 +1,18 @@
+const numbers = [0, 1.5, .5e3, 0x1F, 0o17, 0b101, 017, 1_000_000, 123n, 0xFFn];
+const strings = ['it\'s', "tab\there", "\u0041\u{1F600}\x41", "line\
+continued"];
+const other = [true, false, null, undefined, this, /a[/]b/gu];
+const template = `sum: ${a + b} and ${c}\n`;
+const tagged = String.raw`C:\path`;
+const object = { a, b: 2, "c": 3, 4: d, [e]: f, ...g, get h() { return 1; }, set h(v) {}, *i() {}, async j() {} };
+const ops = (a ?? b) || c && !d, e = typeof f === "undefined" ? -g : +h;
+x += 1; y **= 2; z ??= 3; i++; --j;
+delete obj.prop, void 0, a in b, a instanceof B;
+const chain = a?.b.c, call = fn?.(arg), index = arr?.[0].d, plain = (a?.b).c;
+new Foo;
+new Foo.Bar(1, ...rest);
+const arrow = async (p, q = 1) => { await p; };
+const id = v => v;
+import("./module.js").then(m => m);
+function* gen() { yield; yield 1; yield* other; }
+function meta() { return new.target ?? import.meta; }
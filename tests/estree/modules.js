@@ -0,0 +1,14 @@
+import "./side-effect.js";
+import defaultExport from "module-a";
+import * as namespace from "module-b";
+import { named, other as alias, "string name" as str } from "module-c";
+import defaultToo, { more } from "module-d";
+
+export { named, alias as renamed };
+export * from "module-e";
+export * as grouped from "module-f";
+export { item as default, thing } from "module-g";
+export const value = 1;
+export function helper() {}
+export class Widget {}
+export default class {}
@@ -0,0 +1,36 @@
+"use strict";
+// Statements and declarations
+var a = 1, b;
+let [first, , third = 3, ...others] = list;
+const { x, y: { z }, ...more } = point;
+
+function sum(values) {
+  "use strict";
+  let total = 0;
+  for (let i = 0; i < values.length; i++) total += values[i];
+  for (const key in values) if (!key) continue;
+  for (value of values) {}
+  while (total > 100) total--;
+  do { total++; } while (false);
+  outer: for (;;) { break outer; }
+  return total;
+}
+
+switch (a) {
+  case 1:
+  case 2:
+    b = "small";
+    break;
+  default:
+    b = null;
+}
+
+try {
+  throw new Error("boom");
+} catch ({ message }) {
+  debugger;
+} finally {
+  ;
+}
+
+with (a) {}
@@ -0,0 +1,17 @@
+let primitives: [string, number, boolean, bigint, symbol, any, unknown, never, void, undefined, null, object];
+let literals: "a" | 1 | true | -1 | `prefix-${string}`;
+let references: Map<string, Array<number>> & ns.Inner.Type;
+let functions: (a: number, ...b: string[]) => void;
+let constructors: abstract new () => object;
+let shapes: { readonly name?: string; size(): number; [key: string]: unknown; (x: number): void; new (): Shape };
+let tuples: [a: string, b?: number, ...c: boolean[]];
+let queries: typeof value.field;
+let operators: keyof Shape | (readonly string[]) | Shape["name"];
+type Mapped<T> = { readonly [P in keyof T]?: T[P] };
+type Unwrap<T> = T extends Promise<infer U extends object> ? U : T;
+type Guard = (value: unknown) => value is string;
+function assertIsString(value: unknown): asserts value is string {}
+function isThis(this: Base): this is Derived { return true; }
+let asserted = <any>input!;
+const satisfied = create<string>(input) satisfies Shape as unknown;
+const instantiated = create<number>;
@@ -1,7 +1,8 @@
 use serde_json::Value;
 use std::fs;
 use std::io::Write;
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use tempfile::{NamedTempFile, TempDir};
 
 // Helper function to run astgen command
@@ -14,6 +15,26 @@ fn run_astgen(args: &[&str]) -> std::process::Output {
         .expect("Failed to execute astgen")
 }
 
+// Helper function to run astgen with `input` piped into its stdin
+fn run_astgen_with_stdin(args: &[&str], input: &str) -> std::process::Output {
+    let mut child = Command::new("cargo")
+        .arg("run")
+        .arg("--")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute astgen");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("Failed to wait for astgen")
+}
+
 // Helper function to create a temporary file with content
 fn create_temp_file_with_extension(content: &str, extension: &str) -> NamedTempFile {
     let mut file = NamedTempFile::with_suffix(format!(".{}", extension)).unwrap();
@@ -157,9 +178,60 @@ hello
 fn test_truncate_option() {
     let rust_code = "fn main() { println!(\"This is a long string that should be truncated\"); }";
     let temp_file = create_temp_file_with_extension(rust_code, "rs");
-    let output = run_astgen(&["--truncate", "50", temp_file.path().to_str().unwrap()]);
+    let path = temp_file.path().to_str().unwrap();
+    let output = run_astgen(&["--truncate", "50", path]);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--truncate no longer cuts the output"));
     let stdout = String::from_utf8(output.stdout).unwrap();
-    assert!(stdout.trim().len() <= 50);
+    // Truncation prunes the tree rather than cutting the text, so the output stays valid JSON
+    let json: Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(json["ast"]["truncated"], true);
+    assert!(json["ast"]["children"].is_null());
+    assert!(json["ast"]["omitted_descendants"].as_u64().unwrap() > 0);
+
+    // Apart from the truncation markers, the tree fits in the budget
+    let output = run_astgen(&["--max-output-bytes", "600", path]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(r#""truncated":true"#));
+    let mut json: Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert!(!json["ast"]["children"].as_array().unwrap().is_empty());
+    remove_truncation_markers(&mut json["ast"]);
+    assert!(json["ast"].to_string().len() <= 600);
+}
+
+fn remove_truncation_markers(node: &mut Value) {
+    if let Some(node) = node.as_object_mut() {
+        node.remove("truncated");
+        node.remove("omitted_descendants");
+    }
+    for child in node["children"].as_array_mut().into_iter().flatten() {
+        remove_truncation_markers(child);
+    }
+}
+
+#[test]
+fn test_truncation_budgets_keep_pretty_and_yaml_valid() {
+    let rust_code = "fn a() { let x = 1; }\nfn b() { let y = 2; }\nfn c() { let z = 3; }";
+    let temp_file = create_temp_file_with_extension(rust_code, "rs");
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&["--max-nodes", "6", "--format", "pretty-json", path]);
+    let json: Value = serde_json::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(json["ast"]["truncated"], true);
+
+    let output = run_astgen(&["--max-depth-nodes", "1", "--format", "yaml", path]);
+    let yaml: serde_yaml::Value =
+        serde_yaml::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(
+        yaml["ast"]["children"][0]["truncated"],
+        serde_yaml::Value::Bool(true)
+    );
+
+    let output = run_astgen(&["--max-output-bytes", "300", path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["ast"]["truncated"], true);
 }
 
 #[test]
@@ -181,6 +253,692 @@ fn test_parse_directory() {
     }
 }
 
+#[test]
+fn test_parse_directory_output_is_sorted_and_repeatable() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for name in ["m", "c", "x", "a", "q", "f", "z", "b", "k", "d", "t", "h"] {
+        let body = format!("fn {}() {{}}\n", name).repeat(200);
+        fs::write(temp_path.join(format!("{}.rs", name)), body).unwrap();
+    }
+
+    let first = run_astgen(&["--quiet", temp_path.to_str().unwrap()]);
+    let second = run_astgen(&["--quiet", temp_path.to_str().unwrap()]);
+    assert_eq!(first.stdout, second.stdout);
+
+    let stdout = String::from_utf8(first.stdout).unwrap();
+    let filenames: Vec<String> = stdout
+        .lines()
+        .map(|line| {
+            let json: Value = serde_json::from_str(line).unwrap();
+            json["filename"].as_str().unwrap().to_string()
+        })
+        .collect();
+    let mut sorted = filenames.clone();
+    sorted.sort();
+    assert_eq!(filenames.len(), 12);
+    assert_eq!(filenames, sorted);
+}
+
+#[test]
+fn test_aggregate_output_is_one_valid_document() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_path.join("app.py"), "def main(:\n").unwrap();
+    fs::write(temp_path.join("readme.txt"), "not code").unwrap();
+    let output_path = temp_path.join("out.json");
+
+    run_astgen(&[
+        "--aggregate",
+        "--format",
+        "pretty-json",
+        "--output",
+        output_path.to_str().unwrap(),
+        "--include",
+        "*.rs",
+        "--include",
+        "*.py",
+        temp_path.to_str().unwrap(),
+    ]);
+
+    let document: Value = serde_json::from_str(&fs::read_to_string(&output_path).unwrap()).unwrap();
+    assert!(document["run"]["version"].as_str().unwrap().contains('.'));
+    assert_eq!(document["run"]["parsers"]["tree-sitter-rust"], "0.24.0");
+    assert!(document["run"]["started_at"]
+        .as_str()
+        .unwrap()
+        .ends_with('Z'));
+    assert_eq!(document["run"]["arguments"][0], "--aggregate");
+
+    let files = document["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(files[0]["filename"].as_str().unwrap().ends_with("app.py"));
+    assert_eq!(files[1]["ast"]["kind"], "source_file");
+
+    assert_eq!(document["summary"]["files"], 2);
+    assert_eq!(document["summary"]["succeeded"], 2);
+    assert_eq!(document["summary"]["with_syntax_errors"], 1);
+}
+
+#[test]
+fn test_jsonl_output_with_summary_record() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join("a.py"),
+        "s = \"\"\"line one\nline two\"\"\"\n",
+    )
+    .unwrap();
+    fs::write(temp_path.join("b.rs"), "fn main() {\n    let x = 1\n}\n").unwrap();
+
+    let output = run_astgen(&[
+        "--quiet",
+        "--format",
+        "jsonl",
+        "--summary-record",
+        temp_path.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records: Vec<Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(records.len(), 3);
+    assert!(records[0]["filename"].as_str().unwrap().ends_with("a.py"));
+    assert!(records[1]["filename"].as_str().unwrap().ends_with("b.rs"));
+    assert_eq!(records[2]["summary"]["files"], 2);
+    assert_eq!(records[2]["summary"]["with_syntax_errors"], 1);
+}
+
+#[test]
+fn test_summary_record_requires_jsonl() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&["--summary-record", temp_file.path().to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--format jsonl"));
+}
+
+#[test]
+fn test_sexp_output_matches_tree_sitter_layout() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&[
+        "--format",
+        "sexp",
+        "--sexp-fields",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(source_file\n  (function_item\n    name: (identifier)\n    parameters: (parameters)\n    body: (block)))\n"
+    );
+}
+
+#[test]
+fn test_sexp_output_of_several_files_names_each_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let first = temp_dir.path().join("a.rs");
+    let second = temp_dir.path().join("b.py");
+    fs::write(&first, "fn main() {}").unwrap();
+    fs::write(&second, "x = 1\n").unwrap();
+    let output = run_astgen(&[
+        "--format",
+        "sexp",
+        first.to_str().unwrap(),
+        second.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries = stdout.split_terminator("\n\n").collect::<Vec<_>>();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].starts_with(&format!(";; {}\n(source_file\n", first.display())));
+    assert!(entries[1].starts_with(&format!(";; {}\n(module\n", second.display())));
+}
+
+#[test]
+fn test_sexp_output_rejects_queries() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&[
+        "--format",
+        "sexp",
+        "--query-string",
+        "(identifier) @name",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_dot_output_draws_selected_subtree() {
+    let temp_file =
+        create_temp_file_with_extension("fn main() {}\nfn other() { let x = 1; }\n", "rs");
+    let output = run_astgen(&[
+        "--format",
+        "dot",
+        "--named-only",
+        "--graph-root",
+        "1:3",
+        "--max-depth-nodes",
+        "1",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("digraph tree {\n"));
+    assert!(stdout.contains("n0 [label=\"identifier\\n\\\"other\\\"\"];"));
+    assert!(!stdout.contains("\"main\""));
+    assert!(stdout.ends_with("}\n"));
+}
+
+#[test]
+fn test_graph_root_requires_graph_format() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&["--graph-root", "0:0", temp_file.path().to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--format dot"));
+}
+
+#[test]
+fn test_graph_output_rejects_several_files() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.rs"), "fn a() {}").unwrap();
+    for format in ["dot", "mermaid"] {
+        let output = run_astgen(&["--format", format, temp_dir.path().to_str().unwrap()]);
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("draws one file's tree"));
+    }
+}
+
+#[test]
+fn test_binary_output_is_length_framed() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.rs"), "fn a() {}").unwrap();
+    fs::write(temp_dir.path().join("b.py"), "b = 1").unwrap();
+
+    for format in ["cbor", "msgpack"] {
+        let output = run_astgen(&["--format", format, temp_dir.path().to_str().unwrap()]);
+        assert!(output.status.success());
+
+        let mut input = output.stdout.as_slice();
+        let mut documents = Vec::new();
+        while let Some(frame) = astgen::binary::read_frame(&mut input).unwrap() {
+            let document: Value = match format {
+                "cbor" => ciborium::from_reader(frame.as_slice()).unwrap(),
+                _ => rmp_serde::from_slice(&frame).unwrap(),
+            };
+            documents.push(document);
+        }
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0]["filename"].as_str().unwrap().ends_with("a.rs"));
+        assert_eq!(documents[0]["ast"]["kind"], "source_file");
+        assert_eq!(documents[1]["language"], "Python");
+    }
+}
+
+#[test]
+fn test_table_output_holds_every_file_in_one_table() {
+    use arrow_array::cast::AsArray;
+
+    let temp_dir = TempDir::new().unwrap();
+    let sources = temp_dir.path().join("src");
+    fs::create_dir(&sources).unwrap();
+    fs::write(sources.join("a.rs"), "fn a() {}").unwrap();
+    fs::write(sources.join("b.py"), "b = 1").unwrap();
+    let table_path = temp_dir.path().join("nodes.parquet");
+
+    let output = run_astgen(&[
+        "--format",
+        "parquet",
+        "--named-only",
+        "--output",
+        table_path.to_str().unwrap(),
+        sources.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+        fs::File::open(&table_path).unwrap(),
+    )
+    .unwrap()
+    .build()
+    .unwrap();
+    let mut roots = Vec::new();
+    for batch in reader {
+        let batch = batch.unwrap();
+        let files = batch["file"].as_string::<i32>();
+        let kinds = batch["kind"].as_string::<i32>();
+        for row in 0..batch.num_rows() {
+            if batch["parent_id"].is_null(row) {
+                roots.push((files.value(row).to_string(), kinds.value(row).to_string()));
+            }
+        }
+    }
+    assert_eq!(roots.len(), 2);
+    assert!(roots[0].0.ends_with("a.rs"));
+    assert_eq!(roots[0].1, "source_file");
+    assert!(roots[1].0.ends_with("b.py"));
+    assert_eq!(roots[1].1, "module");
+
+    let output = run_astgen(&["--format", "arrow", sources.join("a.rs").to_str().unwrap()]);
+    assert!(output.status.success());
+    let reader =
+        arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(output.stdout), None).unwrap();
+    let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+    assert!(rows > 5);
+}
+
+#[test]
+fn test_sqlite_output_only_replaces_changed_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let sources = temp_dir.path().join("src");
+    fs::create_dir(&sources).unwrap();
+    fs::write(sources.join("a.rs"), "fn a() {}").unwrap();
+    fs::write(sources.join("b.py"), "def b(:\n").unwrap();
+    let database = temp_dir.path().join("repo.db");
+    let run = || {
+        let output = run_astgen(&[
+            "--format",
+            "sqlite",
+            "--output",
+            database.to_str().unwrap(),
+            sources.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+    };
+
+    run();
+    run();
+    fs::write(sources.join("a.rs"), "fn a() { let changed = 1; }").unwrap();
+    run();
+
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    let replaced: Vec<i64> = connection
+        .prepare("SELECT files_replaced FROM runs ORDER BY id")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(replaced, [2, 0, 1]);
+    let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+    assert_eq!(count("SELECT count(*) FROM files"), 2);
+    assert_eq!(
+        count("SELECT count(*) FROM nodes JOIN files ON files.id = file_id WHERE kind = 'let_declaration'"),
+        1
+    );
+    assert!(
+        count(
+            "SELECT count(*) FROM errors JOIN files ON files.id = file_id WHERE path LIKE '%b.py'"
+        ) > 0
+    );
+}
+
+#[test]
+fn test_sqlite_output_removes_files_no_longer_found() {
+    let temp_dir = TempDir::new().unwrap();
+    let sources = temp_dir.path().join("src");
+    fs::create_dir(&sources).unwrap();
+    fs::write(sources.join("a.rs"), "fn a() {}").unwrap();
+    fs::write(sources.join("old.rs"), "fn old() {}").unwrap();
+    let database = temp_dir.path().join("repo.db");
+    let run = || {
+        let output = run_astgen(&[
+            "--format",
+            "sqlite",
+            "--output",
+            database.to_str().unwrap(),
+            sources.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+    };
+
+    run();
+    fs::rename(sources.join("old.rs"), sources.join("new.rs")).unwrap();
+    run();
+
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    let paths: Vec<String> = connection
+        .prepare("SELECT path FROM files ORDER BY path")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        paths,
+        [
+            sources.join("a.rs").to_string_lossy(),
+            sources.join("new.rs").to_string_lossy()
+        ]
+    );
+    let orphans: i64 = connection
+        .query_row(
+            "SELECT count(*) FROM nodes WHERE file_id NOT IN (SELECT id FROM files)",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(orphans, 0);
+}
+
+#[test]
+fn test_xml_output_keeps_all_source_text() {
+    let source = "int main() {\n  return 0; /* a < b */\n}\n";
+    let temp_file = create_temp_file_with_extension(source, "c");
+    let output = run_astgen(&["--format", "xml", temp_file.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<unit xmlns=\"http://www.srcML.org/srcML/src\""));
+    assert!(stdout.contains("<function><type><name>int</name></type> <name>main</name>"));
+    assert!(stdout.contains("<comment>/* a &lt; b */</comment>"));
+    assert!(stdout.ends_with("</function>\n</unit>\n"));
+}
+
+#[test]
+fn test_xml_output_of_several_files_is_one_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.c"), "int a;\n").unwrap();
+    fs::write(temp_dir.path().join("b.py"), "b = 1\n").unwrap();
+    let output = run_astgen(&["--format", "xml", temp_dir.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("<?xml").count(), 1);
+    assert!(stdout.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<unit xmlns=\"http://www.srcML.org/srcML/src\" revision=\"1.0.0\">\n\n<unit xmlns:cpp="));
+    assert!(stdout.contains("language=\"C\" filename=\""));
+    assert!(stdout.contains("<unit xmlns=\"\" language=\"Python\" filename=\""));
+    assert!(stdout.ends_with("</unit>\n\n</unit>\n"));
+    assert_eq!(stdout.matches("<unit").count(), 3);
+    assert_eq!(stdout.matches("</unit>").count(), 3);
+}
+
+#[test]
+fn test_estree_schema_output() {
+    let temp_file = create_temp_file_with_extension("const answer = 42;\n", "js");
+    let output = run_astgen(&["--schema", "estree", temp_file.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(r#""ast":{"type":"Program","start":0,"end":19,"#));
+    let json: Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["language"], "JavaScript");
+    let declarator = &json["ast"]["body"][0]["declarations"][0];
+    assert_eq!(declarator["id"]["name"], "answer");
+    assert_eq!(declarator["init"]["value"], 42);
+    assert_eq!(declarator["init"]["loc"]["start"]["line"], 1);
+
+    let rust_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&["--schema", "estree", rust_file.path().to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("only available for JavaScript, TypeScript, TSX, not Rust"));
+
+    let output = run_astgen(&[
+        "--schema",
+        "estree",
+        "--format",
+        "sexp",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot use --schema"));
+}
+
+#[test]
+fn test_schema_skips_other_languages_in_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("app.js"), "let n = 1;").unwrap();
+    fs::write(temp_dir.path().join("lib.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("tool.py"), "n = 1\n").unwrap();
+    // Without an extension the language is only known once the shebang is read
+    fs::write(temp_dir.path().join("deploy"), "#!/bin/bash\necho hi\n").unwrap();
+    for (schema, language) in [("estree", "JavaScript"), ("python-ast", "Python")] {
+        let output = run_astgen(&["--schema", schema, temp_dir.path().to_str().unwrap()]);
+        assert!(output.status.success());
+        assert!(!String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("only available"));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let documents = stdout
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["language"], language);
+    }
+}
+
+#[test]
+fn test_python_ast_schema_output() {
+    let temp_file = create_temp_file_with_extension("s = 'é'; n = 1\n", "py");
+    let output = run_astgen(&[
+        "--schema",
+        "python-ast",
+        "--column-encoding",
+        "utf16",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(r#""ast":{"_type":"Module","body":[{"_type":"Assign","targets":"#));
+    let json: Value = serde_json::from_str(&stdout).unwrap();
+    let second = &json["ast"]["body"][1];
+    assert_eq!(second["targets"][0]["id"], "n");
+    assert_eq!(second["value"]["value"], 1);
+    assert_eq!(second["lineno"], 1);
+    assert_eq!(second["col_offset"], 9);
+
+    let js_file = create_temp_file_with_extension("let n = 1;", "js");
+    let output = run_astgen(&["--schema", "python-ast", js_file.path().to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("only available for Python, not JavaScript"));
+}
+
+#[test]
+fn test_stdin_input() {
+    let output = run_astgen_with_stdin(&["--language", "python", "-"], "x = 1\n");
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["filename"], "<stdin>");
+    assert_eq!(json["language"], "Python");
+    assert_eq!(json["ast"]["kind"], "module");
+
+    // --stdin-filename names the document and picks the language from its extension
+    let output = run_astgen_with_stdin(
+        &["--stdin-filename", "src/unsaved.rs", "-"],
+        "fn main() {}\n",
+    );
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["filename"], "src/unsaved.rs");
+    assert_eq!(json["language"], "Rust");
+
+    // --language wins over the extension
+    let output = run_astgen_with_stdin(
+        &["--language", "JavaScript", "--stdin-filename", "a.ts", "-"],
+        "let x = 1;\n",
+    );
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["filename"], "a.ts");
+    assert_eq!(json["language"], "JavaScript");
+
+    // Without either, the language comes from a shebang
+    let output = run_astgen_with_stdin(&["-"], "#!/usr/bin/env python3\nx = 1\n");
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "Python");
+    assert_eq!(json["detection"], "shebang");
+
+    let output = run_astgen_with_stdin(&["-"], "x = 1\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Add --language or --stdin-filename"));
+
+    let output = run_astgen_with_stdin(&["--language", "Cobol", "-"], "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unknown language: Cobol"));
+}
+
+#[test]
+fn test_language_overrides_extension_for_files() {
+    let temp_file = create_temp_file_with_extension("puts 1\n", "txt");
+    let output = run_astgen(&["--language", "ruby", temp_file.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "Ruby");
+}
+
+#[test]
+fn test_language_map_overrides_extension_detection() {
+    let header = create_temp_file_with_extension("namespace app { class Widget {}; }\n", "h");
+    let output = run_astgen(&["--language-map", "*.h=C++", header.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "C++");
+    assert_eq!(json["has_error"], false);
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = temp_dir.path().join("astgenrc.toml");
+    fs::write(
+        &config,
+        "[languages]\n\"*.h\" = \"C++\"\n\n[patterns]\npython = [\"*.cgi\"]\n",
+    )
+    .unwrap();
+    let script = temp_dir.path().join("index.cgi");
+    fs::write(&script, "print('hello')\n").unwrap();
+    let config = config.to_str().unwrap();
+    let output = run_astgen(&["--config", config, script.to_str().unwrap()]);
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "Python");
+
+    // The command line wins over the configuration
+    let output = run_astgen(&[
+        "--config",
+        config,
+        "--language-map",
+        "*.h=C",
+        header.path().to_str().unwrap(),
+    ]);
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "C");
+
+    let output = run_astgen(&[
+        "--language-map",
+        "*.h=Cobol",
+        header.path().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unknown language Cobol for files matching *.h"));
+}
+
+#[test]
+fn test_sniffs_language_of_files_without_an_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    fs::write(bin.join("deploy"), "#!/bin/bash\necho deploying\n").unwrap();
+    fs::write(bin.join("lint"), "x = 1\n# vim: set ft=python:\n").unwrap();
+    fs::write(bin.join("notes"), "plain text\n").unwrap();
+    fs::write(temp_dir.path().join("Rakefile"), "task :default\n").unwrap();
+    fs::write(temp_dir.path().join("app.py"), "x = 1\n").unwrap();
+
+    // `notes` has nothing to detect, so it is skipped as unsupported
+    let output = run_astgen(&[temp_dir.path().to_str().unwrap()]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut detected = stdout
+        .lines()
+        .map(|line| {
+            let json: Value = serde_json::from_str(line).unwrap();
+            let filename = json["filename"].as_str().unwrap().to_string();
+            let name = Path::new(&filename).file_name().unwrap().to_owned();
+            (
+                name.to_string_lossy().to_string(),
+                json["language"].as_str().unwrap().to_string(),
+                json["detection"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    detected.sort();
+    let expected = [
+        ("Rakefile", "Ruby", "filename"),
+        ("app.py", "Python", "extension"),
+        ("deploy", "Bash", "shebang"),
+        ("lint", "Python", "modeline"),
+    ];
+    assert_eq!(
+        detected,
+        expected.map(|(name, language, detection)| (
+            name.to_string(),
+            language.to_string(),
+            detection.to_string()
+        ))
+    );
+
+    let output = run_astgen(&["--language", "ruby", bin.join("deploy").to_str().unwrap()]);
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["detection"], "override");
+
+    // Only the ends of a large file are read to find the hint, but the whole file is parsed
+    let large = temp_dir.path().join("build");
+    let source = format!("{}# vim: set ft=python:\n", "x = 1\n".repeat(10_000));
+    fs::write(&large, &source).unwrap();
+    let output = run_astgen(&[large.to_str().unwrap()]);
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["detection"], "modeline");
+    assert_eq!(json["ast"]["end_byte"], source.len());
+}
+
+#[test]
+fn test_header_files_are_disambiguated_between_c_and_cpp() {
+    let temp_dir = TempDir::new().unwrap();
+    let widget = temp_dir.path().join("widget.h");
+    fs::write(
+        &widget,
+        "namespace ui {\nclass Widget {\n public:\n  Widget();\n};\n}\n",
+    )
+    .unwrap();
+    let output = run_astgen(&[widget.to_str().unwrap()]);
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "C++");
+    assert_eq!(json["has_error"], false);
+    assert_eq!(json["detection"], "extension");
+    assert_eq!(json["disambiguation"]["strategy"], "tokens");
+    assert_eq!(json["disambiguation"]["confidence"], "high");
+
+    // Plain C declarations are valid in both, so the neighbouring sources decide
+    let api = temp_dir.path().join("api.h");
+    fs::write(&api, "int api_version(void);\n").unwrap();
+    fs::write(
+        temp_dir.path().join("api.c"),
+        "int api_version(void) { return 1; }\n",
+    )
+    .unwrap();
+    let output = run_astgen(&[api.to_str().unwrap()]);
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "C");
+    assert_eq!(json["disambiguation"]["strategy"], "siblings");
+
+    // Mapped patterns are not second-guessed
+    let output = run_astgen(&["--language-map", "*.h=C", widget.to_str().unwrap()]);
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "C");
+    assert_eq!(json["detection"], "override");
+    assert!(json.get("disambiguation").is_none());
+}
+
 #[test]
 fn test_parse_directory_ignores_target() {
     let temp_dir = TempDir::new().unwrap();
@@ -254,3 +1012,227 @@ fn test_list_languages_output() {
         assert_eq!(pipe_count, 4, "Line not properly aligned: {}", line);
     }
 }
+
+#[test]
+fn test_nodes_include_row_and_column() {
+    let temp_file = create_temp_file_with_extension("fn main() {\n    let x = 1;\n}", "rs");
+    let output = run_astgen(&[temp_file.path().to_str().unwrap()]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["ast"]["start_point"]["row"], 0);
+    assert_eq!(json["ast"]["end_point"]["row"], 2);
+    assert_eq!(json["ast"]["end_point"]["column"], 1);
+}
+
+#[test]
+fn test_column_encoding_utf16() {
+    let temp_file = create_temp_file_with_extension("'😀'; x;", "js");
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&[path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["ast"]["children"][1]["start_point"]["column"], 8);
+
+    let output = run_astgen(&["--column-encoding", "utf16", path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["ast"]["children"][1]["start_point"]["column"], 6);
+}
+
+#[test]
+fn test_group_fields_option() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&[path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let function = &json["ast"]["children"][0];
+    assert_eq!(function["children"][1]["field_name"], "name");
+    assert!(function.get("fields").is_none());
+
+    let output = run_astgen(&["--group-fields", path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let function = &json["ast"]["children"][0];
+    assert_eq!(function["fields"]["name"][0]["text"], "main");
+    assert_eq!(function["fields"]["body"][0]["kind"], "block");
+}
+
+#[test]
+fn test_group_fields_are_arrays_whatever_the_child_count() {
+    let temp_file = create_temp_file_with_extension("import os\nimport os, sys\n", "py");
+    let output = run_astgen(&["--group-fields", temp_file.path().to_str().unwrap()]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let imports = &json["ast"]["children"];
+    assert_eq!(imports[0]["fields"]["name"].as_array().unwrap().len(), 1);
+    assert_eq!(imports[0]["fields"]["name"][0]["kind"], "dotted_name");
+    assert_eq!(imports[1]["fields"]["name"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_named_only_option() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&["--named-only", path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let function = &json["ast"]["children"][0];
+    let kinds: Vec<&str> = function["children"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|child| child["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["identifier", "parameters", "block"]);
+
+    let output = run_astgen(&["--is-named", path]);
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let function = &json["ast"]["children"][0];
+    assert_eq!(function["is_named"], true);
+    assert_eq!(function["children"][0]["kind"], "fn");
+    assert_eq!(function["children"][0]["is_named"], false);
+}
+
+#[test]
+fn test_syntax_errors_are_reported() {
+    let temp_file = create_temp_file_with_extension("fn main() { let x = 1 }", "rs");
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&[path]);
+    assert!(output.status.success());
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["has_error"], true);
+    assert_eq!(json["diagnostics"][0]["kind"], "missing");
+    assert_eq!(json["diagnostics"][0]["start_point"]["row"], 0);
+
+    let output = run_astgen(&["--fail-on-syntax-error", path]);
+    assert!(!output.status.success());
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["has_error"], true);
+}
+
+#[test]
+fn test_fail_on_syntax_error_passes_clean_files() {
+    let temp_file = create_temp_file_with_extension("fn main() {}", "rs");
+    let output = run_astgen(&["--fail-on-syntax-error", temp_file.path().to_str().unwrap()]);
+    assert!(output.status.success());
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["has_error"], false);
+}
+
+#[test]
+fn test_query_string_outputs_captures() {
+    let temp_file = create_temp_file_with_extension("fn main() {}\nfn helper() {}\n", "rs");
+    let output = run_astgen(&[
+        "--query-string",
+        "((function_item name: (identifier) @name) (#match? @name \"^h\"))",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let json: Value =
+        serde_json::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    assert_eq!(json["language"], "Rust");
+    assert!(json.get("ast").is_none());
+    let captures = json["captures"].as_array().unwrap();
+    assert_eq!(captures.len(), 1);
+    assert_eq!(captures[0]["name"], "name");
+    assert_eq!(captures[0]["kind"], "identifier");
+    assert_eq!(captures[0]["text"], "helper");
+    assert_eq!(captures[0]["start_point"]["row"], 1);
+}
+
+#[test]
+fn test_query_file_scoped_by_language() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_path.join("app.py"), "def main():\n    pass\n").unwrap();
+    let query_path = temp_path.join("python.scm");
+    fs::write(
+        &query_path,
+        "(function_definition name: (identifier) @function)",
+    )
+    .unwrap();
+
+    let output = run_astgen(&[
+        "--query",
+        &format!("Python={}", query_path.display()),
+        temp_path.join("main.rs").to_str().unwrap(),
+        temp_path.join("app.py").to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.trim().lines().collect();
+    // The Rust file has no applicable query and is skipped
+    assert_eq!(lines.len(), 1);
+    let json: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(json["language"], "Python");
+    assert_eq!(json["captures"][0]["text"], "main");
+}
+
+#[test]
+fn test_query_invalid_for_language_fails() {
+    let temp_file = create_temp_file_with_extension("print('hi')", "py");
+    let output = run_astgen(&[
+        "--query-string",
+        "Python=(function_item) @function",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().trim().is_empty());
+
+    let output = run_astgen(&[
+        "--query-string",
+        "(call",
+        temp_file.path().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_unscoped_query_skips_other_grammars() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("a.py"), "x = 1\n").unwrap();
+    fs::write(temp_dir.path().join("b.py"), "y = 2\n").unwrap();
+
+    let output = run_astgen(&[
+        "--query-string",
+        "(function_item name: (identifier) @name)",
+        temp_dir.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let json: Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(json["language"], "Rust");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.matches("Skipping a query for Python files").count(),
+        1,
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_query_fails_on_syntax_error() {
+    let temp_file = create_temp_file_with_extension("fn main( {}\n", "rs");
+    let query = "(function_item) @function";
+    let path = temp_file.path().to_str().unwrap();
+
+    let output = run_astgen(&["--query-string", query, path]);
+    assert!(output.status.success());
+
+    let output = run_astgen(&["--query-string", query, "--fail-on-syntax-error", path]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Syntax errors in"), "{}", stderr);
+}
@@ -0,0 +1,325 @@
+use astgen::binary::read_frame;
+use astgen::{
+    create_encodings, parse_file, parse_source, parse_source_tree, AstSchema, AstgenError,
+    Detection, JsonNode, OutputFormat, TreeOptions,
+};
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+fn find_kind<'a>(node: &'a JsonNode, kind: &str) -> Option<&'a JsonNode> {
+    if node.kind == kind {
+        return Some(node);
+    }
+    node.children
+        .as_ref()?
+        .iter()
+        .find_map(|child| find_kind(child, kind))
+}
+
+#[test]
+fn test_library_parses_file_into_typed_tree() {
+    let mut file = NamedTempFile::with_suffix(".rs").unwrap();
+    file.write_all(b"fn answer() -> u32 { 42 }").unwrap();
+    file.flush().unwrap();
+
+    let encodings = create_encodings();
+    let path = file.path().to_str().unwrap();
+    let encoding = encodings.match_file(path).unwrap();
+    assert_eq!(encoding.name(), "Rust");
+
+    let parsed = parse_file(file.path(), encoding, 10_000_000, &TreeOptions::default()).unwrap();
+    assert_eq!(parsed.language, "Rust");
+    assert_eq!(parsed.ast.kind, "source_file");
+
+    let literal = find_kind(&parsed.ast, "integer_literal").unwrap();
+    assert_eq!(literal.text.as_deref(), Some("42"));
+}
+
+#[test]
+fn test_library_parses_in_memory_source() {
+    let encodings = create_encodings();
+    let encoding = encodings.match_file("script.py").unwrap();
+
+    let parsed = parse_source(
+        "print('hi')",
+        "script.py",
+        encoding,
+        &TreeOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(parsed.ast.kind, "module");
+
+    let json = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(json["language"], "Python");
+    assert_eq!(json["ast"]["kind"], "module");
+}
+
+#[test]
+fn test_library_reports_typed_errors() {
+    let encodings = create_encodings();
+    let encoding = encodings.match_file("missing.rs").unwrap();
+
+    let err = parse_file(
+        "does/not/exist.rs".as_ref(),
+        encoding,
+        10_000_000,
+        &TreeOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, AstgenError::IoError(_)));
+}
+
+#[test]
+fn test_library_settles_shared_extensions_by_content() {
+    let encodings = create_encodings();
+    let (encoding, detection, disambiguation) = encodings
+        .detect_with_source("widget.h", "namespace ui {\nclass Widget {};\n}\n")
+        .unwrap();
+    assert_eq!(encoding.name(), "C++");
+    assert_eq!(detection, Detection::Extension);
+    assert!(disambiguation.is_some());
+
+    let (encoding, _, disambiguation) = encodings
+        .detect_with_source("widget.h", "struct widget { int size; };\n")
+        .unwrap();
+    assert_eq!(encoding.name(), "C");
+    assert!(disambiguation.is_some());
+
+    // An extension only one language has needs no disambiguation
+    let (encoding, _, disambiguation) = encodings
+        .detect_with_source("main.rs", "fn main() {}")
+        .unwrap();
+    assert_eq!(encoding.name(), "Rust");
+    assert!(disambiguation.is_none());
+}
+
+/// Levels of nesting in the synthetic inputs; deep enough to overflow a recursive converter
+const NESTING_DEPTH: usize = 5_000;
+
+/// A file name and a source text nested `depth` levels deep for every registered language
+fn deeply_nested_sample(language: &str, depth: usize) -> Option<(&'static str, String)> {
+    let wrap = |open: &str, inner: &str, close: &str| {
+        format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth))
+    };
+    let sample = match language {
+        "Rust" => (
+            "deep.rs",
+            format!("fn f() {{ let x = {}; }}", wrap("[", "1", "]")),
+        ),
+        "Java" => (
+            "Deep.java",
+            format!("class Deep {{ int x = {}; }}", wrap("(", "1", ")")),
+        ),
+        "C#" => (
+            "Deep.cs",
+            format!("class Deep {{ int x = {}; }}", wrap("(", "1", ")")),
+        ),
+        "Go" => (
+            "deep.go",
+            format!("package main\nvar x = {}\n", wrap("(", "1", ")")),
+        ),
+        "Python" => ("deep.py", format!("x = {}\n", wrap("[", "1", "]"))),
+        "TypeScript" => ("deep.ts", format!("let x = {};\n", wrap("[", "1", "]"))),
+        "TSX" => ("deep.tsx", format!("let x = {};\n", wrap("[", "1", "]"))),
+        "JavaScript" => ("deep.js", format!("let x = {};\n", wrap("[", "1", "]"))),
+        "Ruby" => ("deep.rb", format!("x = {}\n", wrap("[", "1", "]"))),
+        "C" => ("deep.c", format!("int x = {};\n", wrap("(", "1", ")"))),
+        "C++" => ("deep.cpp", format!("int x = {};\n", wrap("(", "1", ")"))),
+        "Bash" => ("deep.sh", format!("echo {}\n", wrap("$(", "true", ")"))),
+        "JSON" => ("deep.json", wrap("[", "1", "]")),
+        "HTML" => ("deep.html", wrap("<div>", "x", "</div>")),
+        "CSS" => (
+            "deep.css",
+            format!("a {{ width: calc{}; }}", wrap("(", "1px", ")")),
+        ),
+        "YAML" => ("deep.yaml", format!("x: {}\n", wrap("[", "1", "]"))),
+        "Swift" => ("deep.swift", format!("let x = {}\n", wrap("[", "1", "]"))),
+        "Scala" => (
+            "deep.scala",
+            format!("object Deep {{ val x = {} }}", wrap("(", "1", ")")),
+        ),
+        "Lua" => ("deep.lua", format!("x = {}\n", wrap("{", "1", "}"))),
+        "HCL" => ("deep.hcl", format!("x = {}\n", wrap("[", "1", "]"))),
+        "GraphQL" => ("deep.graphql", wrap("{ a ", "b", " }")),
+        _ => return None,
+    };
+    Some(sample)
+}
+
+fn max_depth(root: &JsonNode) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(root, 0)];
+    while let Some((node, depth)) = pending.pop() {
+        deepest = deepest.max(depth);
+        for child in node.children.iter().flatten() {
+            pending.push((child, depth + 1));
+        }
+    }
+    deepest
+}
+
+#[test]
+fn test_deeply_nested_input_converts_for_every_language() {
+    let encodings = create_encodings();
+    let mut seen = Vec::new();
+    for encoding in encodings.iter() {
+        let language = encoding.name();
+        if seen.contains(&language) {
+            continue;
+        }
+        seen.push(language);
+        let (filename, source) = deeply_nested_sample(language, NESTING_DEPTH)
+            .unwrap_or_else(|| panic!("No deeply nested sample for {}", language));
+
+        let parsed = parse_source(&source, filename, encoding, &TreeOptions::default()).unwrap();
+        assert!(
+            max_depth(&parsed.ast) >= NESTING_DEPTH,
+            "{} tree is only {} levels deep",
+            language,
+            max_depth(&parsed.ast)
+        );
+        // An ordinary drop must not recurse once per level either
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || drop(parsed))
+            .unwrap()
+            .join()
+            .unwrap_or_else(|_| panic!("Dropping the {} tree overflowed the stack", language));
+
+        let tree = parse_source_tree(source, filename, encoding).unwrap();
+        let mut out = Vec::new();
+        tree.write(&mut out, OutputFormat::Json, &TreeOptions::default())
+            .unwrap();
+        assert!(out.ends_with(b"}\n"));
+        for format in [OutputFormat::Cbor, OutputFormat::MsgPack] {
+            let mut out = Vec::new();
+            tree.write(&mut out, format, &TreeOptions::default())
+                .unwrap();
+            let frame = read_frame(&mut out.as_slice()).unwrap().unwrap();
+            assert_eq!(frame.len() + 4, out.len());
+        }
+    }
+}
+
+/// Every source file in tests/estree is converted with `--schema estree` and compared with the
+/// `ast` in the `.json` file of the same stem
+#[test]
+fn test_estree_corpus() {
+    let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/estree");
+    let encodings = create_encodings();
+    let options = TreeOptions {
+        schema: AstSchema::Estree,
+        ..TreeOptions::default()
+    };
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&corpus).unwrap() {
+        let path = entry.unwrap().path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            continue;
+        }
+        let filename = path.to_str().unwrap();
+        let encoding = encodings.match_file(filename).unwrap();
+        let source = std::fs::read_to_string(&path).unwrap();
+        let tree = parse_source_tree(source, filename, encoding).unwrap();
+        let mut out = Vec::new();
+        tree.write(&mut out, OutputFormat::Json, &options).unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let expected_path = path.with_extension("json");
+        let expected: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&expected_path).unwrap()).unwrap();
+        assert_eq!(
+            document["ast"],
+            expected,
+            "{} does not match {}",
+            path.display(),
+            expected_path.display()
+        );
+        checked += 1;
+    }
+    assert!(checked >= 8, "only {} corpus files found", checked);
+}
+
+#[test]
+fn test_estree_keeps_key_order_and_converts_deep_trees() {
+    let encodings = create_encodings();
+    let options = TreeOptions {
+        schema: AstSchema::Estree,
+        ..TreeOptions::default()
+    };
+    let (filename, source) = deeply_nested_sample("JavaScript", NESTING_DEPTH).unwrap();
+    let encoding = encodings.match_file(filename).unwrap();
+    let tree = parse_source_tree(source, filename, encoding).unwrap();
+    let mut out = Vec::new();
+    tree.write(&mut out, OutputFormat::Json, &options).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains(r#""ast":{"type":"Program","start":0,"end":"#));
+    assert_eq!(text.matches("ArrayExpression").count(), NESTING_DEPTH);
+
+    let mut out = Vec::new();
+    tree.write(&mut out, OutputFormat::Cbor, &options).unwrap();
+    let frame = read_frame(&mut out.as_slice()).unwrap().unwrap();
+    assert_eq!(frame.len() + 4, out.len());
+}
+
+/// Every source file in tests/python_ast is converted with `--schema python-ast` and compared
+/// with the `.json` file of the same stem, which holds CPython 3.13's `ast.parse` of the file
+#[test]
+fn test_python_ast_corpus() {
+    let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/python_ast");
+    let encodings = create_encodings();
+    let options = TreeOptions {
+        schema: AstSchema::PythonAst,
+        ..TreeOptions::default()
+    };
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&corpus).unwrap() {
+        let path = entry.unwrap().path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            continue;
+        }
+        let filename = path.to_str().unwrap();
+        let encoding = encodings.match_file(filename).unwrap();
+        let source = std::fs::read_to_string(&path).unwrap();
+        let tree = parse_source_tree(source, filename, encoding).unwrap();
+        let mut out = Vec::new();
+        tree.write(&mut out, OutputFormat::Json, &options).unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        let expected_path = path.with_extension("json");
+        let expected: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&expected_path).unwrap()).unwrap();
+        assert_eq!(
+            document["ast"],
+            expected,
+            "{} does not match {}",
+            path.display(),
+            expected_path.display()
+        );
+        checked += 1;
+    }
+    assert!(checked >= 4, "only {} corpus files found", checked);
+}
+
+#[test]
+fn test_python_ast_converts_deep_trees() {
+    let encodings = create_encodings();
+    let options = TreeOptions {
+        schema: AstSchema::PythonAst,
+        ..TreeOptions::default()
+    };
+    let (filename, source) = deeply_nested_sample("Python", NESTING_DEPTH).unwrap();
+    let encoding = encodings.match_file(filename).unwrap();
+    let tree = parse_source_tree(source, filename, encoding).unwrap();
+    let mut out = Vec::new();
+    tree.write(&mut out, OutputFormat::Json, &options).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains(r#""ast":{"_type":"Module","body":[{"_type":"Assign""#));
+    assert_eq!(text.matches(r#""_type":"List""#).count(), NESTING_DEPTH);
+}
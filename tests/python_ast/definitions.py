@@ -0,0 +1,63 @@
+def plain():
+    pass
+
+
+def annotated(a: int, b: str = "x", *args: tuple, key: bool = False, **kwargs: dict) -> None:
+    """Docstring."""
+    return None
+
+
+def positional(a, b=1, /, c=2, *, d, e=3):
+    pass
+
+
+def star_only(*, key):
+    pass
+
+
+def unpacking(*args, **kwargs):
+    pass
+
+
+@decorator
+@module.decorator(arg)
+def decorated():
+    pass
+
+
+@dataclass(frozen=True)
+class Point(Base, metaclass=Meta, **extra):
+    """A point."""
+
+    x: int = 0
+    y: int = 0
+
+    def distance(self, other: "Point") -> float:
+        return ((self.x - other.x) ** 2 + (self.y - other.y) ** 2) ** 0.5
+
+    @property
+    def norm(self):
+        return self.distance(Point())
+
+    async def fetch(self):
+        return await self.client.get()
+
+
+class Empty:
+    ...
+
+
+class Generic[T, *Ts, **P]:
+    pass
+
+
+def first[T](items: list[T]) -> T:
+    return items[0]
+
+
+async def main():
+    await first([1])
+
+
+def unpack(*args: *Ts) -> tuple[*Ts]:
+    return args[*args]
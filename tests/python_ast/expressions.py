@@ -0,0 +1,49 @@
+1 + 2 * 3 - 4 / 5 // 6 % 7 ** 8 @ m
+a << 1 >> 2 & 3 | 4 ^ 5
+-x, +y, ~z, not w
+a and b and c
+a or b and c
+(a and b) and c
+a < b <= c == d != e > f >= g
+a in b, a not in b, a is b, a is not b
+value if condition else other
+(total := compute())
+lambda: 0
+lambda a, b=1, *args, c, d=2, **kwargs: a
+lambda x, /, y: x
+f(1, *args, key=2, **kwargs)
+f(x for x in range(3))
+obj.method().attr
+seq[0], seq[1:2], seq[::3], seq[a:b:c], seq[:], seq[1:2, ::3], seq[i, j]
+[1, 2, *rest]
+(1,)
+()
+(1, 2)
+{1, 2}
+{}
+{"a": 1, **others, "b": 2}
+[x * 2 for x in range(10) if x if x > 2]
+{x for x in data}
+{k: v for k, v in pairs for w in v}
+(y async for y in agen())
+[x for x, in rows]
+await thing
+True, False, None, ...
+0, 42, 0x1F, 0o17, 0b101, 1_000_000, 3.14, 1e10, .5, 1., 2j, 1.5J
+'single', "double", '''triple''', """triple double"""
+r'raw\n', b'bytes', rb'raw\bytes', u'unicode'
+"escapes: \n \t \\ \' \" \x41 é \U0001F600 \101 \z"
+b"\x00\xff"
+"implicit" 'concatenation' """across""" "kinds"
+f"plain {name} and {value!r:>{width}} {x=} {y!s} {{braces}}"
+f'{a}' "b" f"{c}"
+"é" + "ü", x.ñ
+def_ = yield_ = 1
+
+def gen():
+    yield
+    yield 1
+    yield from other
+    x = yield 2
+
+padded = f"{x:=10}"
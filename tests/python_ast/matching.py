@@ -0,0 +1,41 @@
+match command:
+    case "quit":
+        quit()
+    case 0 | 1 | 2:
+        pass
+    case -1:
+        pass
+    case 1 + 2j:
+        pass
+    case None:
+        pass
+    case True | False:
+        pass
+    case [x, y, *rest]:
+        pass
+    case (a, b):
+        pass
+    case [*_]:
+        pass
+    case {"key": value, **others}:
+        pass
+    case {}:
+        pass
+    case Point(x=0, y=0):
+        pass
+    case Point(1, 2, z=3):
+        pass
+    case module.Constant:
+        pass
+    case [Point(x, y) as point, *_] if point:
+        pass
+    case str() as text:
+        pass
+    case _:
+        pass
+
+match first, second,:
+    case 0, *rest,:
+        pass
+    case single,:
+        pass
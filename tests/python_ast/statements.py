@@ -0,0 +1,98 @@
+"""Module docstring."""
+import os
+import os.path as osp, sys
+from . import sibling
+from ..pkg.mod import (a, b as c)
+from mod import *
+from __future__ import annotations
+
+x = 1
+x = y = z = 2
+a, b = b, a
+[first, *rest] = items
+(p, q), r = pairs
+obj.attr = value
+table[key] = value
+count: int = 0
+name: str
+(grouped): int = 1
+obj.field: list[int] = []
+total += 1
+mask <<= 2
+del x, table[key], obj.attr
+pass
+
+if x:
+    pass
+elif y:
+    x = 2
+elif z:
+    pass
+else:
+    x = 3
+
+for i in range(10):
+    if i % 2:
+        continue
+    break
+else:
+    done()
+
+for key, value in pairs:
+    print(key, value)
+
+while True:
+    x -= 1
+else:
+    x = 0
+
+try:
+    risky()
+except ValueError:
+    raise
+except (KeyError, IndexError) as error:
+    raise RuntimeError("bad") from error
+except:
+    pass
+else:
+    ok()
+finally:
+    cleanup()
+
+try:
+    risky()
+except* OSError as group:
+    handle(group)
+
+with open(path) as handle, lock:
+    handle.read()
+
+with (
+    open(a) as f,
+    open(b) as g,
+):
+    pass
+
+def scope():
+    global counter
+    nonlocal other, another
+    assert counter > 0, "positive"
+    assert counter
+    return counter
+
+def empty():
+    return
+
+async def worker():
+    async for item in stream:
+        await item
+    async with session as s:
+        pass
+
+type Alias = list[int]
+type Pair[T] = tuple[T, T]
+x; y = 1; z
+with (
+    open(path) as f
+):
+    f.read();